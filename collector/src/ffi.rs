@@ -1,13 +1,27 @@
+//! C entry points called by code instrumented with the miniperf clang plugin.
+//!
+//! None of them unwinds into the instrumented program: panics are caught at
+//! the boundary, and failures are reported instead of aborting. Functions
+//! returning `i32` return [`MPERF_STATUS_OK`] or an [`Error`] discriminant;
+//! the event of a failed call is lost, and the program keeps running.
+//! `mperf_roofline_internal_notify_loop_begin` returns a null handle on
+//! failure, and the other functions reject a null handle with
+//! [`Error::InvalidArgument`]. When profiling is disabled every function is a
+//! successful no-op.
+
 use smallvec::smallvec;
-use std::ffi::CStr;
+use std::{ffi::CStr, panic::AssertUnwindSafe};
 
 use mperf_data::{CallFrame, Event, EventType, Location};
 
 use crate::{
     current_thread_id, get_next_id, get_string_id, get_timestamp, profiling_enabled,
-    roofline_instrumentation_enabled, send_event,
+    roofline_instrumentation_enabled, send_event, Error, Result,
 };
 
+/// Status of a successful call.
+pub const MPERF_STATUS_OK: i32 = 0;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct LoopInfo {
@@ -46,7 +60,8 @@ pub struct LoopHandle {
 }
 
 /// # Safety
-/// Shut up, clippy. There's nothing safe about what we do.
+/// `info` must be null or point to a `LoopInfo` whose strings are null or
+/// NUL-terminated.
 #[no_mangle]
 pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_begin(
     info: *const LoopInfo,
@@ -54,13 +69,23 @@ pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_begin(
     if !profiling_enabled() {
         return std::ptr::null_mut();
     }
+    match guarded(|| unsafe { notify_loop_begin(info) }) {
+        Ok(handle) => Box::leak(handle),
+        Err(err) => {
+            crate::log(|| tracing::warn!("Failed to record a loop start: {err}"));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn notify_loop_begin(info: *const LoopInfo) -> Result<Box<LoopHandle>> {
     let id = crate::get_next_id();
-    let info = unsafe { info.as_ref().unwrap() };
+    let info = unsafe { info.as_ref() }.ok_or(Error::InvalidArgument)?;
 
     let info = SafeLoopInfo {
         line: info.line,
-        filename: CStr::from_ptr(info.filename).to_str().unwrap().to_string(),
-        func_name: CStr::from_ptr(info.func_name).to_str().unwrap().to_string(),
+        filename: unsafe { c_string(info.filename) }?,
+        func_name: unsafe { c_string(info.func_name) }?,
     };
 
     let mut handle = Box::new(LoopHandle {
@@ -72,8 +97,8 @@ pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_begin(
     handle.timestamp = get_timestamp();
 
     // FIXME we should use the full stack frame instead
-    let filename = get_string_id(&handle.info.filename);
-    let func_name = get_string_id(&handle.info.func_name);
+    let filename = get_string_id(&handle.info.filename)?;
+    let func_name = get_string_id(&handle.info.func_name)?;
 
     let start_frame = CallFrame::Location(Location {
         function_name: func_name,
@@ -97,11 +122,12 @@ pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_begin(
         callstack: smallvec![start_frame],
         user_regs: None,
         user_stack: Vec::new(),
+        weight: 0,
     };
 
-    send_event(start_event).expect("failed to send start event");
+    send_event(start_event)?;
 
-    Box::leak(handle)
+    Ok(handle)
 }
 
 #[no_mangle]
@@ -114,14 +140,18 @@ pub extern "C" fn mperf_roofline_internal_is_instrumented_profiling() -> i32 {
 }
 
 /// # Safety
-/// Shut up, clippy. There's nothing safe about what we do.
+/// `handle` must be null or returned by
+/// `mperf_roofline_internal_notify_loop_begin`.
 #[no_mangle]
-pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_end(handle: *mut LoopHandle) {
+pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_end(handle: *mut LoopHandle) -> i32 {
     if !profiling_enabled() {
-        return;
+        return MPERF_STATUS_OK;
     }
+    status("end", guarded(|| unsafe { notify_loop_end(handle) }))
+}
 
-    let handle = unsafe { handle.as_ref() }.unwrap();
+unsafe fn notify_loop_end(handle: *mut LoopHandle) -> Result<()> {
+    let handle = unsafe { handle.as_ref() }.ok_or(Error::InvalidArgument)?;
 
     let timestamp = get_timestamp();
 
@@ -141,24 +171,35 @@ pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_end(handle: *mut Lo
         callstack: smallvec![],
         user_regs: None,
         user_stack: Vec::new(),
+        weight: 0,
     };
 
-    send_event(event).expect("failed to send loop end event");
+    send_event(event)
 }
 
 /// # Safety
-/// Shut up, clippy. There's nothing safe about what we do.
+/// `handle` must be null or returned by
+/// `mperf_roofline_internal_notify_loop_begin`; `stats` must be null or
+/// point to a `LoopStats`.
 #[no_mangle]
 pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_stats(
     handle: *mut LoopHandle,
     stats: *const LoopStats,
-) {
+) -> i32 {
     if !profiling_enabled() {
-        return;
+        return MPERF_STATUS_OK;
     }
+    status(
+        "stats",
+        guarded(|| unsafe { notify_loop_stats(handle, stats) }),
+    )
+}
+
+/// Sends every counter even if one is lost, and reports the first failure.
+unsafe fn notify_loop_stats(handle: *mut LoopHandle, stats: *const LoopStats) -> Result<()> {
     let stats = unsafe { stats.as_ref().cloned().unwrap_or_default() };
 
-    let handle = unsafe { handle.as_ref() }.unwrap();
+    let handle = unsafe { handle.as_ref() }.ok_or(Error::InvalidArgument)?;
 
     let timestamp = get_timestamp();
 
@@ -179,16 +220,70 @@ pub unsafe extern "C" fn mperf_roofline_internal_notify_loop_stats(
             callstack: smallvec![],
             user_regs: None,
             user_stack: Vec::new(),
+            weight: 0,
         };
 
-        send_event(event).expect("failed to send loop end event");
+        send_event(event)
     };
 
-    send_counter_event(EventType::RooflineBytesLoad, stats.bytes_load);
-    send_counter_event(EventType::RooflineBytesStore, stats.bytes_store);
-    send_counter_event(EventType::RooflineScalarIntOps, stats.scalar_int_ops);
-    send_counter_event(EventType::RooflineScalarFloatOps, stats.scalar_float_ops);
-    send_counter_event(EventType::RooflineVectorIntOps, stats.vector_int_ops);
-    send_counter_event(EventType::RooflineVectorFloatOps, stats.vector_float_ops);
-    send_counter_event(EventType::RooflineVectorDoubleOps, stats.vector_double_ops);
+    let mut result = Ok(());
+    for (ty, value) in [
+        (EventType::RooflineBytesLoad, stats.bytes_load),
+        (EventType::RooflineBytesStore, stats.bytes_store),
+        (EventType::RooflineScalarIntOps, stats.scalar_int_ops),
+        (EventType::RooflineScalarFloatOps, stats.scalar_float_ops),
+        (EventType::RooflineVectorIntOps, stats.vector_int_ops),
+        (EventType::RooflineVectorFloatOps, stats.vector_float_ops),
+        (EventType::RooflineVectorDoubleOps, stats.vector_double_ops),
+    ] {
+        result = result.and(send_counter_event(ty, value));
+    }
+    result
+}
+
+/// Runs `body`, turning a panic into [`Error::Internal`] so it never unwinds
+/// into C.
+fn guarded<T>(body: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(Err(Error::Internal))
+}
+
+/// Logs a failed call, since instrumented code cannot do more than drop the
+/// event, and converts it to a status code.
+fn status(what: &str, result: Result<()>) -> i32 {
+    match result {
+        Ok(()) => MPERF_STATUS_OK,
+        Err(err) => {
+            crate::log(|| tracing::warn!("Failed to record a loop {what}: {err}"));
+            err as i32
+        }
+    }
+}
+
+/// # Safety
+/// `ptr` must be null or NUL-terminated.
+unsafe fn c_string(ptr: *const libc::c_char) -> Result<String> {
+    if ptr.is_null() {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(unsafe { CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_become_status_codes() {
+        assert_eq!(status("end", Ok(())), MPERF_STATUS_OK);
+        assert_eq!(
+            status("end", guarded(|| -> Result<()> { panic!("collector bug") })),
+            Error::Internal as i32
+        );
+        assert_eq!(
+            status("end", unsafe { notify_loop_end(std::ptr::null_mut()) }),
+            Error::InvalidArgument as i32
+        );
+    }
 }
@@ -1,51 +1,118 @@
 use lazy_static::lazy_static;
-use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use shmem::proc_channel::Sender;
-use std::{cell::RefCell, collections::HashMap, sync::Mutex};
+use std::{cell::RefCell, collections::HashMap, time::Duration};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
 
 use mperf_data::{Event, IPCMessage, IPCString};
 
 pub mod ffi;
+
+/// Failures of the collector. The discriminants are the status codes the
+/// [`ffi`] functions return and are part of their C ABI.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Error {
+    /// A null pointer or otherwise unusable argument.
+    #[error("invalid argument")]
+    InvalidArgument = 1,
+    /// The shared-memory channel to `mperf` could not be opened.
+    #[error("the channel to mperf is unavailable")]
+    ChannelUnavailable = 2,
+    /// The message could not be written to the channel and is lost.
+    #[error("failed to send a message to mperf")]
+    SendFailed = 3,
+    /// The collector panicked; the panic was stopped at the FFI boundary.
+    #[error("internal collector error")]
+    Internal = 4,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
 const SIZE_16MB: usize = 16 * 1024 * 1024;
+/// How long a blocking send waits for the profiler to drain the ring before
+/// giving up, so an instrumented program never hangs on a dead profiler.
+const BACKPRESSURE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Log level `mperf` runs at; unset in programs it did not start.
+const LOG_ENV: &str = "MPERF_COLLECTOR_LOG";
 
 lazy_static! {
-    static ref SENDER: Mutex<Sender<IPCMessage>> = {
-        let name = std::env::var("MPERF_COLLECTOR_SHMEM_ID")
-            .expect("MPERF_COLLECTOR_SHMEM_ID must be set by the caller");
-
-        let mutex =
-            Mutex::new(Sender::attach(&name, SIZE_16MB).expect("failed to open shared memory"));
+    /// `None` when the program was not started by `mperf` or the shared
+    /// memory could not be attached; every send then fails.
+    static ref SENDER: Option<Mutex<Sender<IPCMessage>>> = {
+        let Ok(name) = std::env::var("MPERF_COLLECTOR_SHMEM_ID") else {
+            log(|| tracing::warn!("MPERF_COLLECTOR_SHMEM_ID is not set; events are not collected"));
+            return None;
+        };
+        let sender = match Sender::attach(&name, SIZE_16MB) {
+            Ok(sender) => sender,
+            Err(err) => {
+                log(|| tracing::error!("Failed to open the shared memory {name}: {err}"));
+                return None;
+            }
+        };
 
         unsafe {
             libc::atexit(close_pipe);
         }
-        mutex
+        Some(Mutex::new(sender))
+    };
+    /// The collector's own subscriber. It is only the default inside [`log`],
+    /// so the program stays free to install a global one at any time.
+    static ref LOG_DISPATCH: tracing::Dispatch = {
+        let filter = EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new("warn"));
+        tracing::Dispatch::new(
+            tracing_subscriber::fmt()
+                .with_env_filter(filter)
+                .with_writer(std::io::stderr)
+                .without_time()
+                .with_target(false)
+                .finish(),
+        )
     };
     static ref STRINGS: RwLock<HashMap<String, u128>> = RwLock::new(HashMap::new());
     static ref PROFILING_ENABLED: bool = std::env::var("MPERF_COLLECTOR_ENABLED").is_ok();
     static ref ROOFLINE_INSTR_ENABLED: bool =
         std::env::var("MPERF_COLLECTOR_ROOFLINE_INSTRUMENTED").is_ok();
+    static ref BACKPRESSURE_ENABLED: bool =
+        std::env::var("MPERF_COLLECTOR_BACKPRESSURE").is_ok_and(|value| value != "0");
+}
+
+/// Runs `event`, a collector log call, so that it goes to the profiled
+/// program's stderr at the level `mperf` runs at, or warnings only, whatever
+/// subscriber the program uses for its own logs.
+pub(crate) fn log(event: impl FnOnce()) {
+    tracing::dispatcher::with_default(&LOG_DISPATCH, event)
 }
 
 thread_local! {
     static LAST_ID: RefCell<u64> = const { RefCell::new(0) };
 }
 
-pub fn send_event(evt: Event) -> Result<(), Box<dyn std::error::Error>> {
-    let sender = SENDER.lock()?;
-    let res = sender.send_sync(IPCMessage::Event(evt));
+pub fn send_event(evt: Event) -> Result<()> {
+    let sender = SENDER.as_ref().ok_or(Error::ChannelUnavailable)?.lock();
+    send_message(&sender, IPCMessage::Event(evt)).map_err(|err| {
+        log(|| tracing::warn!("Lost an event IPC message due to an error {err:?}"));
+        Error::SendFailed
+    })
+}
 
-    if res.is_err() {
-        eprintln!("Lost an event IPC message due to an error {:?}", res.err());
+/// With `MPERF_COLLECTOR_BACKPRESSURE` set, a full ring slows the program down
+/// instead of dropping the message. Roofline captures need every ops event.
+fn send_message(sender: &Sender<IPCMessage>, message: IPCMessage) -> std::io::Result<()> {
+    if *BACKPRESSURE_ENABLED {
+        sender.send_blocking(message, BACKPRESSURE_TIMEOUT)
+    } else {
+        sender.send_sync(message)
     }
-
-    Ok(())
 }
 
-pub fn get_string_id(string: &str) -> u128 {
+/// Interns `string`, sending it to `mperf` the first time it is seen. The id
+/// is returned even if that send fails; the string then resolves to nothing.
+pub fn get_string_id(string: &str) -> Result<u128> {
     let reader = STRINGS.upgradable_read();
     if reader.contains_key(string) {
-        return *reader.get(string).unwrap();
+        return Ok(reader[string]);
     }
 
     let key = {
@@ -53,7 +120,7 @@ pub fn get_string_id(string: &str) -> u128 {
 
         // We now have exclusive lock, double check no one has added our string
         if writer.contains_key(string) {
-            return *writer.get(string).unwrap();
+            return Ok(writer[string]);
         }
 
         let id = uuid::Uuid::now_v7().as_u128();
@@ -63,17 +130,20 @@ pub fn get_string_id(string: &str) -> u128 {
         id
     };
 
-    let sender = SENDER.lock().unwrap();
-    let res = sender.send_sync(IPCMessage::String(IPCString {
-        key,
-        value: string.to_string(),
-    }));
-
-    if res.is_err() {
-        eprintln!("Lost a string IPC message due to an error {:?}", res.err());
+    let sender = SENDER.as_ref().ok_or(Error::ChannelUnavailable)?.lock();
+    let res = send_message(
+        &sender,
+        IPCMessage::String(IPCString {
+            key,
+            value: string.to_string(),
+        }),
+    );
+
+    if let Err(err) = res {
+        log(|| tracing::warn!("Lost a string IPC message due to an error {err:?}"));
     }
 
-    key
+    Ok(key)
 }
 
 pub fn get_next_id() -> u128 {
@@ -111,10 +181,14 @@ pub fn roofline_instrumentation_enabled() -> bool {
 }
 
 extern "C" fn close_pipe() {
-    let sender = SENDER.lock().unwrap();
-    let _ = sender.close();
+    if let Some(sender) = SENDER.as_ref() {
+        let _ = sender.lock().close();
+    }
 }
 
+/// Nanoseconds of `CLOCK_MONOTONIC_RAW`. Samples recorded with
+/// `mperf record --clock monotonic-raw` are stamped with the same clock, so
+/// loop boundaries and samples share one timeline.
 pub(crate) fn get_timestamp() -> u64 {
     let mut ts: libc::timespec = libc::timespec {
         tv_sec: 0,
@@ -42,6 +42,9 @@ pub enum EventType {
     RooflineVectorDoubleOps,
     RooflineLoopStart,
     RooflineLoopEnd,
+    /// A page fault sampled with its call stack, led by the software
+    /// page-fault event rather than cycles.
+    OsPageFaultSample,
 }
 
 #[derive(Encode, Decode, Debug, Clone, Copy, Serialize, Deserialize)]
@@ -85,6 +88,9 @@ pub struct Event {
     /// Raw state used for offline DWARF unwinding. Empty for instrumentation events.
     pub user_regs: Option<UserRegs>,
     pub user_stack: Vec<u8>,
+    /// Hardware-reported sample weight, the load latency in core cycles for
+    /// memory-latency samples. Zero when the sample carries no weight.
+    pub weight: u64,
 }
 
 #[derive(Encode, Decode, Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
@@ -94,6 +100,53 @@ pub struct ProcMapEntry {
     pub size: usize,
     pub offset: usize,
     pub pid: u32,
+    /// Hex GNU build ID of `filename` when the recording ended, used to spot
+    /// binaries rebuilt before post-processing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    /// Number of `exec`s the process made before this mapping, so a wrapper
+    /// and the program it `exec`s keep separate address spaces.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub exec_generation: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Latest `comm` observed for a thread; written to `thread_names.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ThreadName {
+    pub pid: u32,
+    pub tid: u32,
+    pub name: String,
+}
+
+/// A process forked by the profiled target; written to `processes.json`.
+/// Threads are not listed, since they share their parent's `pid`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessFork {
+    pub pid: u32,
+    pub ppid: u32,
+    pub fork_time: u64,
+    /// Unset when the process outlived the recording.
+    pub exit_time: Option<u64>,
+}
+
+/// The arguments a profiled process runs with, read when it is launched,
+/// attached, forked or `exec`s; written to `cmdlines.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessCommandLine {
+    pub pid: u32,
+    pub args: Vec<String>,
+}
+
+/// An `exec` of a profiled process; written to `execs.json`. Samples after it
+/// belong to the mappings of the next [`ProcMapEntry::exec_generation`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessExec {
+    pub pid: u32,
+    pub time: u64,
 }
 
 impl Event {
@@ -182,6 +235,7 @@ impl fmt::Display for EventType {
             EventType::RooflineVectorDoubleOps => f.write_str("roofline_vector_double_ops"),
             EventType::RooflineLoopStart => f.write_str("roofline_loop_start"),
             EventType::RooflineLoopEnd => f.write_str("roofline_loop_end"),
+            EventType::OsPageFaultSample => f.write_str("os_page_fault_sample"),
         }
     }
 }
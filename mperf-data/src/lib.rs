@@ -5,7 +5,10 @@ use serde::{Deserialize, Serialize};
 mod event;
 mod ipc;
 
-pub use event::{CallFrame, Event, EventType, IString, Location, ProcMapEntry, UserRegs};
+pub use event::{
+    CallFrame, Event, EventType, IString, Location, ProcMapEntry, ProcessCommandLine, ProcessExec,
+    ProcessFork, ThreadName, UserRegs,
+};
 pub use ipc::{IPCMessage, IPCString};
 
 /// Version of the on-disk results format written by this build.
@@ -13,7 +16,8 @@ pub use ipc::{IPCMessage, IPCString};
 /// This covers both the JSON metadata and the bincode event stream. Increment
 /// it whenever either layout changes incompatibly.
 /// Version 2 adds the raw user registers and stack bytes used for post-hoc unwinding.
-pub const CURRENT_FORMAT_VERSION: u32 = 2;
+/// Version 3 adds the per-sample weight used by memory-latency sampling.
+pub const CURRENT_FORMAT_VERSION: u32 = 3;
 
 #[derive(Clone, Debug, Copy, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Scenario {
@@ -24,8 +28,31 @@ pub enum Scenario {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotInfo {
+    /// The launched command, or the attached process when there is none.
     pub pid: i32,
+    /// Process attached with `--pid` alongside a launched command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attached_pid: Option<i32>,
+    /// Samples were taken on slow loads and carry their latency.
+    #[serde(default)]
+    pub memory_latency: bool,
     pub counters: Vec<(EventType, String)>,
+    /// The only counter that interrupted the target, chosen with `--leader`.
+    /// Every counter samples on its own when there is none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling_leader: Option<String>,
+    /// Totals counted alongside sampling with `--count-totals`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counted_totals: Option<CountedTotals>,
+}
+
+/// Whole-run counts of the target, read from a counting group that ran next
+/// to the sampling one. Per-function sample shares are scaled by them to
+/// estimate absolute counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountedTotals {
+    pub cycles: Option<u64>,
+    pub instructions: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,9 +108,76 @@ pub struct RecordInfo {
     /// Core clusters on a heterogeneous host (empty on homogeneous systems).
     #[serde(default)]
     pub cores: Vec<CoreCluster>,
+    /// Absolute results directory at record time. Lets tools tell where the
+    /// results were written even when they are opened from another CWD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_directory: Option<std::path::PathBuf>,
+    /// Data pages per sampling ring buffer used for the recording.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmap_pages: Option<usize>,
+    /// CPUs sampling was restricted to with `--sample-cpu`; empty means
+    /// every CPU.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample_cpus: Vec<u32>,
+    /// Absolute split debug info directories passed with `--debug-dir`.
+    /// Postprocessing the results again searches them too.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub debug_dirs: Vec<std::path::PathBuf>,
+    /// How the launched command ended. Missing when only `--pid` was
+    /// attached to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetStatus>,
+    /// cpufreq policies when recording started. Empty when the host does not
+    /// expose cpufreq.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frequency_policies: Vec<FrequencyPolicy>,
+    /// Clock of the sample timestamps. Recordings that predate the option
+    /// used the perf clock.
+    #[serde(default)]
+    pub sample_clock: SampleClock,
     pub scenario_info: ScenarioInfo,
 }
 
+/// Clock the samples of a recording are stamped with.
+#[derive(Clone, Debug, Copy, Default, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleClock {
+    /// The kernel's perf clock.
+    #[default]
+    Perf,
+    /// `CLOCK_MONOTONIC`.
+    Monotonic,
+    /// `CLOCK_MONOTONIC_RAW`, the clock of the collector's roofline events.
+    MonotonicRaw,
+}
+
+/// Frequency scaling of a group of CPUs that share a clock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrequencyPolicy {
+    /// CPUs of the policy, e.g. `"0,1,2,3"`.
+    pub cpus: String,
+    /// Scaling governor, e.g. `"performance"` or `"powersave"`.
+    pub governor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_khz: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_khz: Option<u64>,
+}
+
+/// Exit status and resource usage of the profiled command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetStatus {
+    /// Exit code, or 128 plus the signal number when a signal killed it.
+    pub exit_code: i32,
+    /// Peak resident set size, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rss_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_time_us: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_time_us: Option<u64>,
+}
+
 impl RecordInfo {
     pub fn ensure_supported_format(&self) -> Result<(), UnsupportedFormatVersion> {
         if self.format_version > CURRENT_FORMAT_VERSION {
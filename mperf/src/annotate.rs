@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use pmu_data::{Value, ValueFormat};
+
+use crate::locale;
+use crate::tui::{load_assembly, AssemblyViewState};
+
+/// Writes the annotated disassembly of the `top` functions with the most
+/// sampled cycles to one text file each in `output`.
+pub fn do_annotate(res_dir: &Path, top: usize, output: &Path) -> Result<()> {
+    let connection = crate::schema::open(&res_dir.join("perf.db"))?;
+    let functions = hottest_functions(&connection, top)
+        .context("no disassembly was recorded; annotate needs snapshot or roofline results")?;
+
+    std::fs::create_dir_all(output)
+        .with_context(|| format!("failed to create {}", output.display()))?;
+
+    let mut written = 0;
+    for (rank, func_name) in functions.iter().enumerate() {
+        let view = match load_assembly(&connection, func_name) {
+            Ok(view) => view,
+            Err(err) => {
+                tracing::warn!("skipping {func_name}: {err}");
+                continue;
+            }
+        };
+        let path = output.join(annotation_file_name(rank + 1, func_name));
+        std::fs::write(&path, format_annotation(&view))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        written += 1;
+    }
+
+    tracing::info!(
+        "wrote {written} annotated functions to {}",
+        output.display()
+    );
+    Ok(())
+}
+
+fn hottest_functions(connection: &sqlite::Connection, top: usize) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "SELECT func_name FROM assembly_samples
+         GROUP BY func_name ORDER BY SUM(cycles) DESC, func_name LIMIT ?;",
+    )?;
+    statement.bind((1, top as i64))?;
+    let mut functions = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        functions.push(statement.read::<String, _>(0)?);
+    }
+    Ok(functions)
+}
+
+/// `03-my_namespace__parse.txt`: the rank keeps files in hotness order and
+/// the name is reduced to characters safe on every file system.
+fn annotation_file_name(rank: usize, func_name: &str) -> String {
+    let name = func_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(100)
+        .collect::<String>();
+    format!("{rank:02}-{name}.txt")
+}
+
+fn format_annotation(view: &AssemblyViewState) -> String {
+    let mut lines = vec![
+        format!("Function: {}", view.func_name),
+        format!("Module: {}", view.module_path),
+        format!("Symbol: {}", view.symbol),
+    ];
+    lines.extend(view.build_id_warning.clone());
+    lines.push(String::new());
+    lines.push(format!(
+        "{:<18} {:>10} {:>8} {:>14} {:>14} {:>6}  Instruction",
+        "Address", "Samples", "Share %", "Cycles", "Instructions", "IPC"
+    ));
+    for row in &view.rows {
+        let ipc = if row.cycles > 0 {
+            format!("{:.2}", row.instructions as f64 / row.cycles as f64)
+        } else {
+            "-".to_string()
+        };
+        lines.push(format!(
+            "0x{:016x} {:>10} {:>8.2} {:>14} {:>14} {:>6}  {}",
+            row.address,
+            count(row.samples),
+            row.share * 100.0,
+            count(row.cycles),
+            count(row.instructions),
+            ipc,
+            row.instruction
+        ));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn count(value: u64) -> String {
+    pmu_data::format_localized(
+        &Value::Integer(i64::try_from(value).unwrap_or(i64::MAX)),
+        &ValueFormat::Integer,
+        &locale::current(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_are_ranked_and_sanitized() {
+        assert_eq!(
+            annotation_file_name(3, "ns::parse<int>(char const*)"),
+            "03-ns__parse_int__char_const__.txt"
+        );
+        assert_eq!(annotation_file_name(12, "main"), "12-main.txt");
+    }
+}
@@ -0,0 +1,253 @@
+//! Opening shared results: a `.tar.zst` archive of a result directory, or a
+//! directory whose raw files are zstd-compressed and not postprocessed yet.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use ruzstd::{FrameDecoder, StreamingDecoder};
+
+use crate::{
+    disassembly::AsmSyntax,
+    postprocess::{do_postprocess, is_incomplete, ViewOptions},
+};
+
+/// A result directory ready to be shown. Archives are extracted to a
+/// temporary directory that is removed on drop.
+pub struct Results {
+    path: PathBuf,
+    temporary: Option<PathBuf>,
+}
+
+impl Results {
+    /// A new empty directory, removed on drop, for results that are only
+    /// looked at once.
+    pub fn temporary(prefix: &str) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("{prefix}-{}", uuid::Uuid::now_v7().simple()));
+        std::fs::create_dir(&dir)?;
+        Ok(Results {
+            path: dir.clone(),
+            temporary: Some(dir),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Results {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.temporary {
+            if let Err(err) = std::fs::remove_dir_all(dir) {
+                tracing::warn!("failed to remove {}: {err}", dir.display());
+            }
+        }
+    }
+}
+
+/// Opens `path` for `show`: extracts a `.tar.zst` archive, decompresses
+/// `*.zst` raw files, and postprocesses a recording without `perf.db` or
+/// resumes postprocessing that failed midway.
+pub async fn open_results(path: &Path) -> Result<Results> {
+    let mut results = Results {
+        path: path.to_path_buf(),
+        temporary: None,
+    };
+    if path.is_file() {
+        results = Results::temporary("mperf-show")?;
+        let dir = results.path.clone();
+        tracing::info!("extracting {} to {}", path.display(), dir.display());
+        let archive = File::open(path)?;
+        extract_tar(ZstdFrames::new(BufReader::new(archive))?, &dir)
+            .with_context(|| format!("failed to extract {}", path.display()))?;
+        results.path = find_result_directory(&dir)
+            .with_context(|| format!("{} holds no info.json", path.display()))?;
+    }
+
+    decompress_raw_files(&results.path)?;
+    let db_path = results.path.join("perf.db");
+    let resume = db_path.exists() && is_incomplete(&db_path)?;
+    if (resume || !db_path.exists()) && results.path.join("events.bin").exists() {
+        if !resume {
+            tracing::info!("{} has no perf.db; postprocessing", results.path.display());
+        }
+        do_postprocess(
+            &results.path,
+            false,
+            false,
+            resume,
+            &[],
+            AsmSyntax::default(),
+            &ViewOptions::default(),
+        )
+        .await?;
+    }
+    Ok(results)
+}
+
+/// The directory holding `info.json`: `dir` itself, or the single directory
+/// an archive of `tar -cf - result_dir` unpacks to.
+fn find_result_directory(dir: &Path) -> Option<PathBuf> {
+    if dir.join("info.json").exists() {
+        return Some(dir.to_path_buf());
+    }
+    let mut entries = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir());
+    let single = entries.next()?;
+    (entries.next().is_none() && single.join("info.json").exists()).then_some(single)
+}
+
+/// Decompresses every `name.zst` in `dir` to `name`, unless `name` exists.
+fn decompress_raw_files(dir: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "zst") {
+            continue;
+        }
+        let target = path.with_extension("");
+        if target.exists() {
+            continue;
+        }
+        tracing::info!("decompressing {}", path.display());
+        let mut decoder = ZstdFrames::new(BufReader::new(File::open(&path)?))?;
+        let mut output = File::create(&target)?;
+        if let Err(err) = std::io::copy(&mut decoder, &mut output) {
+            drop(output);
+            let _ = std::fs::remove_file(&target);
+            return Err(err).with_context(|| format!("failed to decompress {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a zstd stream of one or more concatenated frames, as written by
+/// multi-threaded compressors.
+struct ZstdFrames<R: BufRead> {
+    decoder: Option<StreamingDecoder<R, FrameDecoder>>,
+}
+
+impl<R: BufRead> ZstdFrames<R> {
+    fn new(source: R) -> Result<Self> {
+        let decoder = StreamingDecoder::new(source).context("not a zstd stream")?;
+        Ok(ZstdFrames {
+            decoder: Some(decoder),
+        })
+    }
+}
+
+impl<R: BufRead> Read for ZstdFrames<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let Some(decoder) = &mut self.decoder else {
+                return Ok(0);
+            };
+            let read = decoder.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let (mut source, frame_decoder) = self.decoder.take().expect("decoder").into_parts();
+            if source.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            self.decoder = Some(
+                StreamingDecoder::new_with_decoder(source, frame_decoder)
+                    .map_err(std::io::Error::other)?,
+            );
+        }
+    }
+}
+
+/// Extracts the regular files and directories of a tar stream into `dir`.
+/// Links and special files are skipped; paths leaving `dir` are rejected.
+fn extract_tar(reader: impl Read, dir: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        let name = entry.path()?.display().to_string();
+        anyhow::ensure!(
+            entry.unpack_in(dir)?,
+            "refusing to extract '{name}' outside the target directory"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+
+    fn tar_entry(archive: &mut Vec<u8>, name: &str, data: &[u8]) {
+        let mut header = tar::Header::new_ustar();
+        // Written raw so that tests can hold names `set_path` refuses.
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(data.len() as u64);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(BLOCK_SIZE), 0);
+    }
+
+    /// A zstd frame of raw (stored) blocks.
+    fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = 0xFD2F_B528u32.to_le_bytes().to_vec();
+        // No content size or checksum; a 1 MiB window.
+        frame.extend_from_slice(&[0x00, 0x50]);
+        let chunks = data.chunks(64 * 1024).collect::<Vec<_>>();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let last = u32::from(index + 1 == chunks.len());
+            let header = ((chunk.len() as u32) << 3) | last;
+            frame.extend_from_slice(&header.to_le_bytes()[..3]);
+            frame.extend_from_slice(chunk);
+        }
+        frame
+    }
+
+    #[test]
+    fn archives_of_several_frames_are_extracted() {
+        let mut archive = Vec::new();
+        tar_entry(&mut archive, "run/info.json", b"{}");
+        tar_entry(&mut archive, "run/events.bin", &[7u8; 1000]);
+        archive.extend_from_slice(&[0u8; 2 * BLOCK_SIZE]);
+        let (first, second) = archive.split_at(700);
+        let compressed = [zstd_frame(first), zstd_frame(second)].concat();
+
+        let dir = std::env::temp_dir().join(format!("mperf-archive-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+        extract_tar(ZstdFrames::new(compressed.as_slice()).unwrap(), &dir).unwrap();
+
+        let results = find_result_directory(&dir).unwrap();
+        assert_eq!(results, dir.join("run"));
+        assert_eq!(std::fs::read(results.join("info.json")).unwrap(), b"{}");
+        assert_eq!(
+            std::fs::read(results.join("events.bin")).unwrap(),
+            [7u8; 1000]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_outside_the_directory_are_rejected() {
+        let mut archive = Vec::new();
+        tar_entry(&mut archive, "../escape", b"x");
+        let dir = std::env::temp_dir().join(format!("mperf-archive-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+
+        assert!(extract_tar(archive.as_slice(), &dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,11 @@
+use anyhow::Context;
 use mperf_data::Scenario;
 use pmu::Counter;
 use pmu_data::{arith_parser::Expr, TmaScenario};
 use std::collections::BTreeSet;
 
-pub fn get_pmu_counters(scenario: Scenario) -> Vec<Counter> {
-    match scenario {
+pub fn get_pmu_counters(scenario: Scenario) -> anyhow::Result<Vec<Counter>> {
+    Ok(match scenario {
         Scenario::Snapshot | Scenario::Roofline => vec![
             Counter::Cycles,
             Counter::Instructions,
@@ -19,25 +20,90 @@ pub fn get_pmu_counters(scenario: Scenario) -> Vec<Counter> {
             Counter::PageFaults,
             Counter::ContextSwitches,
         ],
-        Scenario::TMA => pmu::host_tma_scenario()
-            .expect("TMA counter selection requires a supported host CPU")
-            .events
+        Scenario::TMA => {
+            let scenario = pmu::host_tma_scenario().context("TMA is not supported on this CPU")?;
+            pmu::host_scenario_counters(&scenario.events)
+                .with_context(|| {
+                    format!("failed to resolve events of scenario '{}'", scenario.name)
+                })?
+                .into_iter()
+                .chain([
+                    Counter::CpuClock,
+                    Counter::CpuMigrations,
+                    Counter::PageFaults,
+                    Counter::ContextSwitches,
+                ])
+                .collect()
+        }
+    })
+}
+
+/// Counters for a recording: the scenario defaults, or the events named with
+/// `--events`. Sampling is always led by cycles, which the snapshot and
+/// roofline views rank by, so it is added when missing.
+pub fn record_counters(scenario: Scenario, events: &[String]) -> anyhow::Result<Vec<Counter>> {
+    if events.is_empty() {
+        return get_pmu_counters(scenario);
+    }
+    if scenario == Scenario::TMA {
+        anyhow::bail!("--events cannot replace the TMA methodology's events");
+    }
+    let mut counters = counters_from_names(events)?;
+    if !counters.contains(&Counter::Cycles) {
+        tracing::info!("adding cycles to the requested events to lead sampling");
+        counters.insert(0, Counter::Cycles);
+    }
+    Ok(counters)
+}
+
+/// Adds the level 1 cache counters of `record --l1-cache` that are not
+/// already recorded.
+pub fn add_l1_cache_counters(counters: &mut Vec<Counter>) {
+    for counter in [
+        Counter::L1DReferences,
+        Counter::L1DMisses,
+        Counter::L1IMisses,
+    ] {
+        if !counters.contains(&counter) {
+            counters.push(counter);
+        }
+    }
+}
+
+/// Resolves the event `--leader` names.
+pub fn leader_counter(name: &str) -> anyhow::Result<Counter> {
+    let mut counters = counters_from_names(std::slice::from_ref(&name.to_string()))?;
+    Ok(counters.remove(0))
+}
+
+/// Resolves user-supplied event names, as listed by `mperf list` or through
+/// the host CPU family's aliases. Duplicates are dropped.
+fn counters_from_names(names: &[String]) -> anyhow::Result<Vec<Counter>> {
+    let supported = pmu::list_supported_counters(pmu::DriverKind::Default);
+    let mut counters = Vec::<Counter>::new();
+    for name in names {
+        let counter = match supported
             .iter()
-            .map(|evt| match evt.as_str() {
-                "cycles" => Counter::Cycles,
-                "instructions" => Counter::Instructions,
-                "stalled_cycles_frontend" => Counter::StalledCyclesFrontend,
-                "stalled_cycles_backend" => Counter::StalledCyclesBackend,
-                _ => Counter::Custom(evt.clone()),
-            })
-            .chain([
-                Counter::CpuClock,
-                Counter::CpuMigrations,
-                Counter::PageFaults,
-                Counter::ContextSwitches,
-            ])
-            .collect(),
+            .find(|counter| counter.name().eq_ignore_ascii_case(name))
+        {
+            Some(counter) => counter.clone(),
+            None => match Counter::parse_raw(name) {
+                Some(counter) => counter,
+                None => pmu::host_scenario_counters(std::slice::from_ref(name))
+                    .ok()
+                    .and_then(|resolved| resolved.into_iter().next())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "unknown event '{name}'; run `mperf list` to see supported names"
+                        )
+                    })?,
+            },
+        };
+        if !counters.iter().any(|known| known.name() == counter.name()) {
+            counters.push(counter);
+        }
     }
+    Ok(counters)
 }
 
 /// Resolves and validates the independent coherent groups used by TMA.
@@ -74,17 +140,8 @@ pub fn get_tma_counter_groups(scenario: &TmaScenario) -> anyhow::Result<Vec<Vec<
             }
         }
         resolved.push(
-            group
-                .events
-                .iter()
-                .map(|event| match event.as_str() {
-                    "cycles" => Counter::Cycles,
-                    "instructions" => Counter::Instructions,
-                    "stalled_cycles_frontend" => Counter::StalledCyclesFrontend,
-                    "stalled_cycles_backend" => Counter::StalledCyclesBackend,
-                    _ => Counter::Custom(event.clone()),
-                })
-                .collect(),
+            pmu::host_scenario_counters(&group.events)
+                .with_context(|| format!("failed to resolve TMA group '{}'", group.name))?,
         );
     }
     for metric in &scenario.metrics {
@@ -144,3 +201,55 @@ fn formula_variables(expression: &Expr, variables: &mut BTreeSet<String>) {
         Expr::Constant(_) | Expr::Num(_) => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn events_override_scenario_defaults() {
+        let counters = record_counters(
+            Scenario::Snapshot,
+            &names(&["LLC_Misses", "cycles", "cycles"]),
+        )
+        .unwrap();
+        assert_eq!(counters, [Counter::LLCMisses, Counter::Cycles]);
+
+        let counters = record_counters(Scenario::Snapshot, &names(&["instructions"])).unwrap();
+        assert_eq!(counters, [Counter::Cycles, Counter::Instructions]);
+
+        assert_eq!(
+            record_counters(Scenario::Snapshot, &[]).unwrap(),
+            get_pmu_counters(Scenario::Snapshot).unwrap()
+        );
+    }
+
+    #[test]
+    fn l1_cache_counters_are_added_once() {
+        let mut counters =
+            record_counters(Scenario::Snapshot, &names(&["cycles", "l1d_misses"])).unwrap();
+        add_l1_cache_counters(&mut counters);
+        assert_eq!(
+            counters,
+            [
+                Counter::Cycles,
+                Counter::L1DMisses,
+                Counter::L1DReferences,
+                Counter::L1IMisses
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_events_and_tma_overrides_are_rejected() {
+        let error = record_counters(Scenario::Snapshot, &names(&["no_such_event"]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no_such_event"), "{error}");
+        assert!(record_counters(Scenario::TMA, &names(&["cycles"])).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! The CPU frequency scaling in effect while recording. Governors other
+//! than `performance` change the clock under the workload, which distorts
+//! cycle counts and IPC between runs.
+
+use std::path::Path;
+
+use mperf_data::FrequencyPolicy;
+
+const CPUFREQ_DIR: &str = "/sys/devices/system/cpu/cpufreq";
+
+/// The cpufreq policies of the host, empty when it has no cpufreq driver.
+pub fn host_frequency_policies() -> Vec<FrequencyPolicy> {
+    read_frequency_policies(Path::new(CPUFREQ_DIR))
+}
+
+fn read_frequency_policies(dir: &Path) -> Vec<FrequencyPolicy> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut policies = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let index = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("policy")?
+                .parse::<u32>()
+                .ok()?;
+            let path = entry.path();
+            let read = |name: &str| {
+                std::fs::read_to_string(path.join(name))
+                    .ok()
+                    .map(|value| value.trim().to_string())
+            };
+            let policy = FrequencyPolicy {
+                cpus: read("related_cpus")?
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(","),
+                governor: read("scaling_governor")?,
+                min_khz: read("scaling_min_freq").and_then(|value| value.parse().ok()),
+                max_khz: read("scaling_max_freq").and_then(|value| value.parse().ok()),
+            };
+            Some((index, policy))
+        })
+        .collect::<Vec<_>>();
+    policies.sort_by_key(|(index, _)| *index);
+    policies.into_iter().map(|(_, policy)| policy).collect()
+}
+
+/// Why the recorded cycles may not be comparable, if any policy let the
+/// clock change.
+pub fn governor_warning(policies: &[FrequencyPolicy]) -> Option<String> {
+    let mut governors = policies
+        .iter()
+        .map(|policy| policy.governor.as_str())
+        .filter(|governor| *governor != "performance")
+        .collect::<Vec<_>>();
+    governors.sort_unstable();
+    governors.dedup();
+    if governors.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "CPU frequency governor was {}; cycles and IPC vary with the clock. \
+         Use the performance governor for benchmarking",
+        governors.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_are_read_from_sysfs() {
+        let dir = std::env::temp_dir().join(format!("mperf-cpufreq-{}", uuid::Uuid::now_v7()));
+        for (policy, cpus, governor) in [
+            ("policy4", "4 5\n", "powersave\n"),
+            ("policy0", "0 1 2 3\n", "performance\n"),
+        ] {
+            let path = dir.join(policy);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("related_cpus"), cpus).unwrap();
+            std::fs::write(path.join("scaling_governor"), governor).unwrap();
+            std::fs::write(path.join("scaling_max_freq"), "4200000\n").unwrap();
+        }
+        std::fs::create_dir_all(dir.join("boost")).unwrap();
+
+        let policies = read_frequency_policies(&dir);
+        assert_eq!(
+            policies,
+            [
+                FrequencyPolicy {
+                    cpus: "0,1,2,3".to_string(),
+                    governor: "performance".to_string(),
+                    min_khz: None,
+                    max_khz: Some(4_200_000),
+                },
+                FrequencyPolicy {
+                    cpus: "4,5".to_string(),
+                    governor: "powersave".to_string(),
+                    min_khz: None,
+                    max_khz: Some(4_200_000),
+                },
+            ]
+        );
+        assert!(governor_warning(&policies).unwrap().contains("powersave"));
+        assert_eq!(governor_warning(&policies[..1]), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! Metrics derived from pairs of counters, shared by `stat` and the hotspots
+//! view so both report the same numbers.
+
+use pmu::Counter;
+
+use crate::schema;
+
+/// How the hotspots view sums counters that are stored scaled by their
+/// multiplexing confidence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregation {
+    /// Descale every row, then sum. Rows whose counter barely ran are
+    /// blown up the most.
+    #[default]
+    Descale,
+    /// Sum the raw values and descale by the mean confidence, so each row
+    /// contributes in proportion to how long its counter ran.
+    Weighted,
+}
+
+/// A counter a derived metric is computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Cycles,
+    Instructions,
+    BranchMisses,
+    BranchInstructions,
+    LlcMisses,
+    LlcReferences,
+}
+
+impl Input {
+    pub fn counter(self) -> Counter {
+        match self {
+            Input::Cycles => Counter::Cycles,
+            Input::Instructions => Counter::Instructions,
+            Input::BranchMisses => Counter::BranchMisses,
+            Input::BranchInstructions => Counter::BranchInstructions,
+            Input::LlcMisses => Counter::LLCMisses,
+            Input::LlcReferences => Counter::LLCReferences,
+        }
+    }
+
+    /// Sum of the input over the rows of `pmu_counters`. Only cycles and
+    /// instructions are stored at their real value; the other counters are
+    /// kept scaled by [`schema::DESCALE_DIVISOR`].
+    fn sql_sum(self, aggregation: Aggregation) -> String {
+        let (column, descaled) = match self {
+            Input::Cycles => ("pmu_cycles", false),
+            Input::Instructions => ("pmu_instructions", false),
+            Input::BranchMisses => ("pmu_branch_misses", true),
+            Input::BranchInstructions => ("pmu_branch_instructions", true),
+            Input::LlcMisses => ("pmu_llc_misses", true),
+            Input::LlcReferences => ("pmu_llc_references", true),
+        };
+        match (descaled, aggregation) {
+            (false, _) => format!("SUM(pmu_counters.{column})"),
+            (true, Aggregation::Descale) => format!(
+                "SUM(pmu_counters.{column} * 1.0 / {})",
+                schema::DESCALE_DIVISOR
+            ),
+            // COUNT * SUM(value) / SUM(confidence): the raw total over the
+            // mean confidence of the rows that have the counter.
+            (true, Aggregation::Weighted) => format!(
+                "(COUNT(pmu_counters.{column}) * SUM(pmu_counters.{column}) * 1.0 / \
+                 SUM(CASE WHEN pmu_counters.{column} IS NOT NULL THEN {} END))",
+                schema::DESCALE_DIVISOR
+            ),
+        }
+    }
+}
+
+/// `numerator / sum(denominator) * scale`.
+#[derive(Debug, Clone, Copy)]
+pub struct DerivedMetric {
+    /// Column name in the hotspots view.
+    pub key: &'static str,
+    pub label: &'static str,
+    pub desc: &'static str,
+    unit: &'static str,
+    numerator: Input,
+    denominator: &'static [Input],
+    scale: f64,
+}
+
+pub const DERIVED_METRICS: &[DerivedMetric] = &[
+    DerivedMetric {
+        key: "ipc",
+        label: "IPC",
+        desc: "Instructions retired per cycle",
+        unit: "inst/cycle",
+        numerator: Input::Instructions,
+        denominator: &[Input::Cycles],
+        scale: 1.0,
+    },
+    DerivedMetric {
+        key: "branch_miss_rate",
+        label: "Branch miss rate",
+        desc: "Share of branches that were mispredicted",
+        unit: "",
+        numerator: Input::BranchMisses,
+        denominator: &[Input::BranchInstructions],
+        scale: 1.0,
+    },
+    DerivedMetric {
+        key: "branch_mpki",
+        label: "Branch MPKI",
+        desc: "Branch misses per thousand instructions",
+        unit: "",
+        numerator: Input::BranchMisses,
+        denominator: &[Input::Instructions],
+        scale: 1000.0,
+    },
+    DerivedMetric {
+        key: "cache_miss_rate",
+        label: "Cache miss rate",
+        desc: "Last-level cache misses relative to misses and references",
+        unit: "",
+        numerator: Input::LlcMisses,
+        denominator: &[Input::LlcMisses, Input::LlcReferences],
+        scale: 1.0,
+    },
+    DerivedMetric {
+        key: "cache_mpki",
+        label: "Cache MPKI",
+        desc: "Last-level cache misses per thousand instructions",
+        unit: "",
+        numerator: Input::LlcMisses,
+        denominator: &[Input::Instructions],
+        scale: 1000.0,
+    },
+];
+
+impl DerivedMetric {
+    /// Computes the metric from counter totals. `None` when an input is
+    /// missing or the denominator is zero.
+    pub fn evaluate(&self, value: impl Fn(Input) -> Option<u64>) -> Option<f64> {
+        let numerator = value(self.numerator)? as f64;
+        let mut denominator = 0.0;
+        for input in self.denominator {
+            denominator += value(*input)? as f64;
+        }
+        (denominator > 0.0).then(|| numerator / denominator * self.scale)
+    }
+
+    /// The metric as an aggregate over `pmu_counters`.
+    pub fn sql_expression(&self, aggregation: Aggregation) -> String {
+        let denominator = self
+            .denominator
+            .iter()
+            .map(|input| input.sql_sum(aggregation))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let denominator = if self.denominator.len() > 1 {
+            format!("({denominator})")
+        } else {
+            denominator
+        };
+        let scale = if self.scale == 1.0 {
+            String::new()
+        } else {
+            format!(" * {}", self.scale)
+        };
+        format!(
+            "({} * 1.0 / {denominator}{scale})",
+            self.numerator.sql_sum(aggregation)
+        )
+    }
+
+    /// Renders a value the way `stat` prints it: rates as percentages.
+    pub fn format(&self, value: f64) -> String {
+        if self.key.ends_with("_rate") {
+            format!("{:.2}%", value * 100.0)
+        } else if self.unit.is_empty() {
+            format!("{value:.2}")
+        } else {
+            format!("{value:.2} {}", self.unit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_computed_from_counter_totals() {
+        let totals = |input| match input {
+            Input::Cycles => Some(2_000),
+            Input::Instructions => Some(4_000),
+            Input::BranchMisses => Some(30),
+            Input::BranchInstructions => Some(300),
+            Input::LlcMisses => Some(10),
+            Input::LlcReferences => None,
+        };
+        let values = DERIVED_METRICS
+            .iter()
+            .map(|metric| (metric.key, metric.evaluate(totals)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                ("ipc", Some(2.0)),
+                ("branch_miss_rate", Some(0.1)),
+                ("branch_mpki", Some(7.5)),
+                ("cache_miss_rate", None),
+                ("cache_mpki", Some(2.5)),
+            ]
+        );
+    }
+}
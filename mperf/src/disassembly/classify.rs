@@ -0,0 +1,262 @@
+/// Coarse data-type class of one disassembled instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+    ScalarInt,
+    ScalarFloat,
+    VectorInt,
+    VectorFloat,
+}
+
+impl InstructionClass {
+    /// Stable name stored in `assembly_lines.instruction_class`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstructionClass::ScalarInt => "scalar_int",
+            InstructionClass::ScalarFloat => "scalar_float",
+            InstructionClass::VectorInt => "vector_int",
+            InstructionClass::VectorFloat => "vector_float",
+        }
+    }
+}
+
+/// Classifies objdump output for x86 (SSE/AVX/AVX-512), AArch64 (NEON/SVE) and
+/// RISC-V (RVV) by mnemonic and register operands. This is a heuristic meant
+/// for weighting hot code, not an exact ISA decoder.
+pub fn classify_instruction(instruction: &str) -> InstructionClass {
+    let (mnemonic, operands) = split_instruction(instruction);
+
+    // RVV mnemonics are dotted (vadd.vv, vfmul.vf, vle32.v); AVX ones are not.
+    if mnemonic.starts_with('v') && mnemonic.contains('.') {
+        return if mnemonic.starts_with("vf") {
+            InstructionClass::VectorFloat
+        } else {
+            InstructionClass::VectorInt
+        };
+    }
+
+    if ["xmm", "ymm", "zmm"]
+        .iter()
+        .any(|register| operands.contains(register))
+    {
+        return classify_x86_simd(&mnemonic);
+    }
+
+    if has_arm_vector_operand(&operands) {
+        return if mnemonic.starts_with('f') || mnemonic.starts_with("scvtf") {
+            InstructionClass::VectorFloat
+        } else {
+            InstructionClass::VectorInt
+        };
+    }
+
+    // x87 (fld, fmulp), AArch64 (fadd s0, fmadd d1) and RISC-V (fadd.d, flw).
+    if mnemonic.starts_with('f') && !mnemonic.starts_with("fence") {
+        return InstructionClass::ScalarFloat;
+    }
+    if has_arm_fp_scalar_operand(&operands) {
+        return InstructionClass::ScalarFloat;
+    }
+
+    InstructionClass::ScalarInt
+}
+
+/// Whether the instruction can transfer control elsewhere, so the next one
+/// starts a new basic block: jumps, branches, calls, returns and traps.
+pub fn ends_basic_block(instruction: &str) -> bool {
+    let (mnemonic, _) = split_instruction(instruction);
+    let mnemonic = mnemonic.as_str();
+    // x86 (jne, jmpq, callq, retq, loop) and RISC-V (j, jal, jalr, ret).
+    if mnemonic.starts_with('j')
+        || mnemonic.starts_with("call")
+        || mnemonic.starts_with("ret")
+        || mnemonic.starts_with("loop")
+    {
+        return true;
+    }
+    // AArch64 conditional branches (b.ne) and RISC-V ones (bnez, bltu).
+    if mnemonic.starts_with("b.") {
+        return true;
+    }
+    matches!(
+        mnemonic,
+        "syscall"
+            | "sysenter"
+            | "int"
+            | "int3"
+            | "ud2"
+            | "hlt"
+            | "b"
+            | "bl"
+            | "br"
+            | "blr"
+            | "braa"
+            | "blraa"
+            | "cbz"
+            | "cbnz"
+            | "tbz"
+            | "tbnz"
+            | "svc"
+            | "brk"
+            | "eret"
+            | "beq"
+            | "bne"
+            | "blt"
+            | "bge"
+            | "bltu"
+            | "bgeu"
+            | "beqz"
+            | "bnez"
+            | "blez"
+            | "bgez"
+            | "bltz"
+            | "bgtz"
+            | "bgt"
+            | "ble"
+            | "bgtu"
+            | "bleu"
+            | "ecall"
+            | "ebreak"
+    )
+}
+
+/// Lowercase mnemonic, with x86 prefixes skipped, and operands.
+fn split_instruction(instruction: &str) -> (String, String) {
+    let mut tokens = instruction.split_whitespace();
+    let mut mnemonic = tokens.next().unwrap_or_default();
+    while matches!(
+        mnemonic,
+        "lock" | "rep" | "repz" | "repnz" | "repe" | "repne" | "bnd" | "notrack" | "data16"
+    ) {
+        mnemonic = tokens.next().unwrap_or_default();
+    }
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let operands = tokens.collect::<Vec<_>>().join(" ").to_ascii_lowercase();
+    (mnemonic, operands)
+}
+
+fn classify_x86_simd(mnemonic: &str) -> InstructionClass {
+    let base = mnemonic.strip_prefix('v').unwrap_or(mnemonic);
+    if base.starts_with('p') && !base.starts_with("pcmpistr") {
+        return InstructionClass::VectorInt;
+    }
+    if base.contains("broadcast") && !base.contains("broadcasti") {
+        return InstructionClass::VectorFloat;
+    }
+    // Conversions between a GPR or packed form and a scalar lane.
+    if ["ss2", "sd2", "2ss", "2sd", "sh2", "2sh"]
+        .iter()
+        .any(|part| base.contains(part))
+    {
+        return InstructionClass::ScalarFloat;
+    }
+    // AT&T suffixes such as `vmovsdx` are not used for SIMD; check the tail.
+    if ["ps", "pd", "ph"]
+        .iter()
+        .any(|suffix| base.ends_with(suffix))
+    {
+        return InstructionClass::VectorFloat;
+    }
+    if ["ss", "sd", "sh"]
+        .iter()
+        .any(|suffix| base.ends_with(suffix))
+    {
+        return InstructionClass::ScalarFloat;
+    }
+    InstructionClass::VectorInt
+}
+
+/// NEON arrangements (`v0.4s`, `{v1.16b}`) and SVE vectors (`z0.s`).
+fn has_arm_vector_operand(operands: &str) -> bool {
+    operands
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .any(|token| {
+            let Some((register, arrangement)) = token.split_once('.') else {
+                return false;
+            };
+            let mut chars = register.chars();
+            matches!(chars.next(), Some('v' | 'z'))
+                && !chars.as_str().is_empty()
+                && chars.as_str().chars().all(|c| c.is_ascii_digit())
+                && !arrangement.is_empty()
+        })
+}
+
+/// AArch64 scalar FP/SIMD registers (`s0`, `d31`, `h2`).
+fn has_arm_fp_scalar_operand(operands: &str) -> bool {
+    operands
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|token| {
+            let mut chars = token.chars();
+            matches!(chars.next(), Some('s' | 'd' | 'h'))
+                && !chars.as_str().is_empty()
+                && chars.as_str().len() <= 2
+                && chars.as_str().chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify_instruction, ends_basic_block, InstructionClass::*};
+
+    #[test]
+    fn classifies_x86_sse_and_avx() {
+        assert_eq!(
+            classify_instruction("vaddps %ymm1,%ymm2,%ymm3"),
+            VectorFloat
+        );
+        assert_eq!(classify_instruction("addsd %xmm1,%xmm0"), ScalarFloat);
+        assert_eq!(classify_instruction("cvtsi2sd %eax,%xmm0"), ScalarFloat);
+        assert_eq!(classify_instruction("paddd %xmm1,%xmm0"), VectorInt);
+        assert_eq!(classify_instruction("vpminsd %ymm1,%ymm2,%ymm0"), VectorInt);
+        assert_eq!(classify_instruction("vmovdqu (%rdi),%ymm0"), VectorInt);
+        assert_eq!(classify_instruction("add %rax,%rbx"), ScalarInt);
+        assert_eq!(classify_instruction("lock cmpxchg %ecx,(%rdx)"), ScalarInt);
+        assert_eq!(classify_instruction("fldl (%rax)"), ScalarFloat);
+    }
+
+    #[test]
+    fn classifies_aarch64_and_riscv() {
+        assert_eq!(
+            classify_instruction("fadd v0.4s, v1.4s, v2.4s"),
+            VectorFloat
+        );
+        assert_eq!(
+            classify_instruction("add v0.16b, v1.16b, v2.16b"),
+            VectorInt
+        );
+        assert_eq!(classify_instruction("ld1 {v0.4s}, [x0]"), VectorInt);
+        assert_eq!(classify_instruction("fmadd d0, d1, d2, d3"), ScalarFloat);
+        assert_eq!(classify_instruction("ldr s0, [x1]"), ScalarFloat);
+        assert_eq!(classify_instruction("add x0, x1, x2"), ScalarInt);
+        assert_eq!(classify_instruction("subs w8, w8, #0x1"), ScalarInt);
+        assert_eq!(classify_instruction("vfmacc.vv v8,v16,v24"), VectorFloat);
+        assert_eq!(classify_instruction("vle32.v v8,(a0)"), VectorInt);
+        assert_eq!(classify_instruction("fadd.d fa0,fa0,fa1"), ScalarFloat);
+        assert_eq!(classify_instruction("addi sp,sp,-16"), ScalarInt);
+    }
+
+    #[test]
+    fn finds_the_end_of_basic_blocks() {
+        for instruction in [
+            "jne 401136 <main+0x16>",
+            "bnd jmpq *%rax",
+            "callq 401020 <foo>",
+            "retq",
+            "b.ne 4005d8 <loop+0x8>",
+            "cbnz w8, 4005d8 <loop+0x8>",
+            "bl 400600 <foo>",
+            "bnez a5,10156 <main+0x1a>",
+            "jalr ra",
+        ] {
+            assert!(ends_basic_block(instruction), "{instruction}");
+        }
+        for instruction in [
+            "add %rax,%rbx",
+            "bic w0, w1, w2",
+            "bfi x0, x1, #4, #8",
+            "nop",
+        ] {
+            assert!(!ends_basic_block(instruction), "{instruction}");
+        }
+    }
+}
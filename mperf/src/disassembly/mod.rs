@@ -1,11 +1,15 @@
 use std::path::PathBuf;
 #[cfg(target_os = "linux")]
-use std::process::Command;
+use std::{path::Path, process::Command};
 
 #[cfg(target_os = "linux")]
 use anyhow::Context;
 use anyhow::{anyhow, Result};
 
+mod classify;
+
+pub use classify::{classify_instruction, ends_basic_block};
+
 #[derive(Debug, Clone)]
 #[cfg_attr(
     not(target_os = "linux"),
@@ -14,6 +18,9 @@ use anyhow::{anyhow, Result};
 pub struct DisassembleRequest {
     pub module_path: PathBuf,
     pub load_bias: i64,
+    /// Split debug information for `module_path`. When present, instructions
+    /// are annotated with source lines from this file.
+    pub debug_file: Option<PathBuf>,
     pub targets: Vec<DisassembleTarget>,
 }
 
@@ -33,16 +40,34 @@ pub struct AssemblyLine {
     pub rel_address: u64,
     pub symbol: Option<String>,
     pub instruction: String,
+    pub source_file: Option<String>,
+    pub source_line: Option<u32>,
+}
+
+/// How x86 instructions are spelled in the assembly view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AsmSyntax {
+    /// `mov %rdi,%rax`, objdump's default.
+    #[default]
+    Att,
+    /// `mov rax,rdi`, as in the Intel and AMD manuals.
+    Intel,
 }
 
 pub trait Disassembler: Send + Sync {
     fn disassemble(&self, request: &DisassembleRequest) -> Result<Vec<AssemblyLine>>;
 }
 
-pub fn default_disassembler() -> Result<Box<dyn Disassembler>> {
+#[cfg_attr(
+    not(target_os = "linux"),
+    expect(unused_variables, reason = "the objdump backend is Linux-only")
+)]
+pub fn default_disassembler(syntax: AsmSyntax) -> Result<Box<dyn Disassembler>> {
     #[cfg(target_os = "linux")]
     {
-        Ok(Box::new(ObjdumpDisassembler::new(None)?))
+        Ok(Box::new(
+            ObjdumpDisassembler::new(None)?.with_syntax(syntax),
+        ))
     }
 
     #[cfg(not(target_os = "linux"))]
@@ -56,6 +81,7 @@ pub fn default_disassembler() -> Result<Box<dyn Disassembler>> {
 #[cfg(target_os = "linux")]
 struct ObjdumpDisassembler {
     program: PathBuf,
+    syntax: AsmSyntax,
 }
 
 #[cfg(target_os = "linux")]
@@ -68,18 +94,35 @@ impl ObjdumpDisassembler {
                 program.display()
             ));
         }
-        Ok(ObjdumpDisassembler { program })
+        Ok(ObjdumpDisassembler {
+            program,
+            syntax: AsmSyntax::default(),
+        })
+    }
+
+    /// Spells x86 instructions in `syntax`. Other architectures have a
+    /// single syntax and ignore it.
+    fn with_syntax(mut self, syntax: AsmSyntax) -> Self {
+        self.syntax = syntax;
+        self
     }
 
     fn run_objdump(
         &self,
         request: &DisassembleRequest,
+        input: &ObjdumpInput,
         target: &DisassembleTarget,
         selected_symbol: Option<&str>,
         demangle: bool,
     ) -> Result<Vec<AssemblyLine>> {
         let mut command = Command::new(&self.program);
         command.arg("-d").arg("--no-show-raw-insn");
+        if self.syntax == AsmSyntax::Intel && cfg!(target_arch = "x86_64") {
+            command.arg("-M").arg("intel");
+        }
+        if input.line_numbers {
+            command.arg("--line-numbers");
+        }
         if demangle {
             command.arg("--demangle");
         }
@@ -90,16 +133,13 @@ impl ObjdumpDisassembler {
                 .arg(format!("--start-address={}", target.start_address))
                 .arg(format!("--stop-address={}", target.end_address));
         }
-        let output = command
-            .arg(&request.module_path)
-            .output()
-            .with_context(|| {
-                format!(
-                    "failed to run {} on {}",
-                    self.program.display(),
-                    request.module_path.display()
-                )
-            })?;
+        let output = command.arg(&input.path).output().with_context(|| {
+            format!(
+                "failed to run {} on {}",
+                self.program.display(),
+                request.module_path.display()
+            )
+        })?;
 
         if !output.status.success() {
             return Err(anyhow!(
@@ -124,28 +164,83 @@ impl ObjdumpDisassembler {
     fn disassemble_target(
         &self,
         request: &DisassembleRequest,
+        input: &ObjdumpInput,
         target: &DisassembleTarget,
     ) -> Result<Vec<AssemblyLine>> {
         let Some(raw_symbol) = target.raw_symbol.as_deref() else {
-            return self.run_objdump(request, target, None, true);
+            return self.run_objdump(request, input, target, None, true);
         };
 
         // With --demangle enabled, GNU objdump matches --disassemble against the demangled
         // spelling, not the raw object symbol. Try that spelling first to retain readable call
         // targets, then fall back to the raw spelling without demangling for toolchain-specific
         // formatting differences.
-        let lines = self.run_objdump(request, target, Some(&target.owner_symbol), true)?;
+        let lines = self.run_objdump(request, input, target, Some(&target.owner_symbol), true)?;
         if !lines.is_empty() {
             return Ok(lines);
         }
-        let lines = self.run_objdump(request, target, Some(raw_symbol), false)?;
+        let lines = self.run_objdump(request, input, target, Some(raw_symbol), false)?;
         if !lines.is_empty() {
             return Ok(lines);
         }
 
         // Versioned and aliased ELF symbols are not always accepted by --disassemble even when
         // they came directly from the object symbol table. Their known bounds are still precise.
-        self.run_objdump(request, target, None, true)
+        self.run_objdump(request, input, target, None, true)
+    }
+
+    /// objdump only follows `.gnu_debuglink` and the system build-id tree, so
+    /// a split debug file from elsewhere is linked into a private copy of the
+    /// module. Section addresses are unchanged by the rewrite.
+    fn stage_debug_file(module_path: &Path, debug_file: &Path) -> Result<ObjdumpInput> {
+        let objcopy = which::which("objcopy")
+            .map_err(|_| anyhow!("failed to locate 'objcopy' required to attach debug info"))?;
+        let file_name = module_path
+            .file_name()
+            .ok_or_else(|| anyhow!("module path has no file name"))?;
+        let staging_dir =
+            std::env::temp_dir().join(format!("mperf-debuglink-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&staging_dir)?;
+        let input = ObjdumpInput {
+            path: staging_dir.join(file_name),
+            line_numbers: true,
+            staging_dir: Some(staging_dir),
+        };
+        let link = input.path.with_extension("mperf.debug");
+        std::os::unix::fs::symlink(debug_file, &link)?;
+
+        let status = Command::new(objcopy)
+            .arg("--remove-section=.gnu_debuglink")
+            .arg(format!("--add-gnu-debuglink={}", link.display()))
+            .arg(module_path)
+            .arg(&input.path)
+            .output()
+            .context("failed to run objcopy")?
+            .status;
+        if !status.success() {
+            return Err(anyhow!(
+                "objcopy returned non-zero exit status for {}",
+                module_path.display()
+            ));
+        }
+        Ok(input)
+    }
+}
+
+/// The object objdump actually reads; removes its staging directory on drop.
+#[cfg(target_os = "linux")]
+struct ObjdumpInput {
+    path: PathBuf,
+    line_numbers: bool,
+    staging_dir: Option<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for ObjdumpInput {
+    fn drop(&mut self) {
+        if let Some(staging_dir) = &self.staging_dir {
+            let _ = std::fs::remove_dir_all(staging_dir);
+        }
     }
 }
 
@@ -155,6 +250,23 @@ impl Disassembler for ObjdumpDisassembler {
         if request.targets.is_empty() {
             return Ok(Vec::new());
         }
+        let staged = request.debug_file.as_deref().and_then(|debug_file| {
+            Self::stage_debug_file(&request.module_path, debug_file)
+                .inspect_err(|err| {
+                    tracing::warn!(
+                        "ignoring debug file {} for {}: {err}",
+                        debug_file.display(),
+                        request.module_path.display()
+                    )
+                })
+                .ok()
+        });
+        let input = staged.unwrap_or_else(|| ObjdumpInput {
+            path: request.module_path.clone(),
+            line_numbers: false,
+            staging_dir: None,
+        });
+        let input = &input;
         let worker_count = std::thread::available_parallelism()
             .map(usize::from)
             .unwrap_or(1)
@@ -169,7 +281,7 @@ impl Disassembler for ObjdumpDisassembler {
                     scope.spawn(move || {
                         let mut lines = Vec::new();
                         for target in targets {
-                            lines.extend(self.disassemble_target(request, target)?);
+                            lines.extend(self.disassemble_target(request, input, target)?);
                         }
                         Ok::<_, anyhow::Error>((chunk_index, lines))
                     })
@@ -193,6 +305,7 @@ impl Disassembler for ObjdumpDisassembler {
 fn parse_objdump(output: &str, _load_bias: i64, owner: Option<&str>) -> Result<Vec<AssemblyLine>> {
     let mut lines = Vec::new();
     let mut current_symbol: Option<String> = None;
+    let mut current_source: Option<(String, u32)> = None;
 
     for raw_line in output.lines() {
         let trimmed = raw_line.trim();
@@ -210,12 +323,20 @@ fn parse_objdump(output: &str, _load_bias: i64, owner: Option<&str>) -> Result<V
             }
         }
 
-        if let Some(pos) = trimmed.rfind(':') {
-            let (left, right) = trimmed.split_at(pos);
+        // `--line-numbers` emits `file:line`, optionally followed by
+        // ` (discriminator N)`, before the instructions it covers.
+        let location = trimmed
+            .split_once(" (discriminator ")
+            .map_or(trimmed, |(location, _)| location);
+        if let Some(pos) = location.rfind(':') {
+            let (left, right) = location.split_at(pos);
             let number_part = &right[1..];
             if !left.chars().all(|c| c.is_ascii_hexdigit())
                 && number_part.chars().all(|c| c.is_ascii_digit())
             {
+                if let Ok(line) = number_part.parse::<u32>() {
+                    current_source = Some((left.to_string(), line));
+                }
                 continue;
             }
         }
@@ -232,6 +353,8 @@ fn parse_objdump(output: &str, _load_bias: i64, owner: Option<&str>) -> Result<V
                         rel_address: rel_addr,
                         symbol: owner.map(str::to_owned).or_else(|| current_symbol.clone()),
                         instruction,
+                        source_file: current_source.as_ref().map(|(file, _)| file.clone()),
+                        source_line: current_source.as_ref().map(|(_, line)| *line),
                     });
                 }
                 continue;
@@ -254,7 +377,25 @@ mod tests {
     }
 
     #[test]
-    fn targeted_rust_symbol_produces_instructions() {
+    fn line_number_annotations_attach_to_following_instructions() {
+        let output = "
+0000000000001129 <f>:
+f():
+/tmp/t.c:1
+    1129:	lea    (%rdi,%rdi,2),%eax
+/tmp/t.c:2 (discriminator 1)
+    112c:	ret
+";
+        let lines = parse_objdump(output, 0, None).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].symbol.as_deref(), Some("f"));
+        assert_eq!(lines[0].source_file.as_deref(), Some("/tmp/t.c"));
+        assert_eq!(lines[0].source_line, Some(1));
+        assert_eq!(lines[1].source_line, Some(2));
+    }
+
+    /// A request for [`targeted_disassembly_fixture`] in the test binary.
+    fn fixture_request() -> DisassembleRequest {
         assert_eq!(targeted_disassembly_fixture(2), 37);
         let module_path = std::env::current_exe().unwrap();
         let bytes = std::fs::read(&module_path).unwrap();
@@ -272,16 +413,24 @@ mod tests {
         let raw_symbol = symbol.name().unwrap().to_string();
         let owner_symbol =
             addr2line::demangle_auto(Cow::Borrowed(raw_symbol.as_str()), None).into_owned();
-        let request = DisassembleRequest {
+        DisassembleRequest {
             module_path,
             load_bias: 0,
+            debug_file: None,
             targets: vec![DisassembleTarget {
                 raw_symbol: Some(raw_symbol),
-                owner_symbol: owner_symbol.clone(),
+                owner_symbol,
                 start_address: symbol.address(),
                 end_address: symbol.address().saturating_add(symbol.size()),
             }],
-        };
+        }
+    }
+
+    #[test]
+    fn targeted_rust_symbol_produces_instructions() {
+        let request = fixture_request();
+        let owner_symbol = &request.targets[0].owner_symbol;
+        let start_address = request.targets[0].start_address;
 
         let lines = ObjdumpDisassembler::new(None)
             .unwrap()
@@ -292,8 +441,22 @@ mod tests {
         assert!(lines
             .iter()
             .all(|line| line.symbol.as_deref() == Some(owner_symbol.as_str())));
-        assert!(lines
-            .iter()
-            .any(|line| line.rel_address == symbol.address()));
+        assert!(lines.iter().any(|line| line.rel_address == start_address));
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn intel_syntax_drops_register_sigils() {
+        let request = fixture_request();
+        let disassembler = ObjdumpDisassembler::new(None).unwrap();
+        let att = disassembler.disassemble(&request).unwrap();
+        assert!(att.iter().any(|line| line.instruction.contains('%')));
+
+        let intel = disassembler
+            .with_syntax(AsmSyntax::Intel)
+            .disassemble(&request)
+            .unwrap();
+        assert_eq!(intel.len(), att.len());
+        assert!(intel.iter().all(|line| !line.instruction.contains('%')));
     }
 }
@@ -1,10 +1,13 @@
 #![allow(dead_code)]
 
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::{collections::HashMap, path::Path, sync::Arc};
 
-use mperf_data::{Event, IString, ProcMapEntry};
+use mperf_data::{
+    Event, IString, ProcMapEntry, ProcessCommandLine, ProcessExec, ProcessFork, ThreadName,
+};
 use parking_lot::{RwLock, RwLockUpgradableReadGuard};
 use thread_local::ThreadLocal;
 use tokio::{
@@ -18,20 +21,43 @@ pub struct EventDispatcher {
     last_unique_id: ThreadLocal<RefCell<u64>>,
     events_tx: Sender<Event>,
     string_tx: Sender<(u128, String)>,
-    proc_map_tx: Sender<ProcMapEntry>,
+    proc_map_tx: Sender<ProcMapChange>,
+    thread_name_tx: Sender<ThreadName>,
+    task_tx: Sender<TaskChange>,
+    lost_events: AtomicU64,
+    kernel_lost_samples: AtomicU64,
 }
 
 pub struct DispatcherJoinHandle {
     events_worker: JoinHandle<()>,
     string_worker: JoinHandle<()>,
     proc_map_worker: JoinHandle<()>,
+    thread_name_worker: JoinHandle<()>,
+    task_worker: JoinHandle<()>,
+}
+
+/// Mappings and execs share a channel, so a mapping is assigned to the image
+/// that was current when perf reported it.
+#[derive(Debug)]
+enum ProcMapChange {
+    Map(ProcMapEntry),
+    Exec(ProcessExec),
+}
+
+#[derive(Debug)]
+enum TaskChange {
+    Fork { pid: u32, ppid: u32, time: u64 },
+    Exit { pid: u32, time: u64 },
+    CommandLine(ProcessCommandLine),
 }
 
 impl EventDispatcher {
     pub fn new(output_directory: &Path) -> (Arc<Self>, DispatcherJoinHandle) {
         let (events_tx, mut event_rx) = mpsc::channel::<Event>(8192);
         let (string_tx, mut string_rx) = mpsc::channel::<(u128, String)>(8192);
-        let (proc_map_tx, mut proc_map_rx) = mpsc::channel::<ProcMapEntry>(8192);
+        let (proc_map_tx, mut proc_map_rx) = mpsc::channel::<ProcMapChange>(8192);
+        let (thread_name_tx, mut thread_name_rx) = mpsc::channel::<ThreadName>(8192);
+        let (task_tx, mut task_rx) = mpsc::channel::<TaskChange>(8192);
 
         let events_out_dir = output_directory.to_owned();
         let events_worker = tokio::spawn(async move {
@@ -42,7 +68,7 @@ impl EventDispatcher {
             while let Some(event) = event_rx.recv().await {
                 let result = event.write_binary(&mut events_file);
                 if result.is_err() {
-                    eprintln!("Failed to write data for event id {}", event.unique_id);
+                    tracing::warn!("Failed to write data for event id {}", event.unique_id);
                 }
             }
         });
@@ -62,15 +88,107 @@ impl EventDispatcher {
 
         let proc_map_out_dir = output_directory.to_owned();
         let proc_map_worker = tokio::spawn(async move {
-            let mut proc_map_entries = HashSet::<ProcMapEntry>::new();
-            while let Some(entry) = proc_map_rx.recv().await {
-                proc_map_entries.insert(entry);
+            let mut proc_map_entries = ProcMapBuilder::default();
+            let mut generations = HashMap::<u32, u32>::new();
+            let mut execs = Vec::new();
+            while let Some(change) = proc_map_rx.recv().await {
+                match change {
+                    ProcMapChange::Map(entry) => proc_map_entries.insert(ProcMapEntry {
+                        exec_generation: generations.get(&entry.pid).copied().unwrap_or(0),
+                        ..entry
+                    }),
+                    ProcMapChange::Exec(exec) => {
+                        *generations.entry(exec.pid).or_default() += 1;
+                        execs.push(exec);
+                    }
+                }
             }
 
-            let proc_map = proc_map_entries.into_iter().collect::<Vec<_>>();
+            let mut build_ids = HashMap::<String, Option<String>>::new();
+            let proc_map = proc_map_entries
+                .finish()
+                .into_iter()
+                .map(|entry| {
+                    let build_id = build_ids
+                        .entry(entry.filename.clone())
+                        .or_insert_with(|| symbolize::build_id(Path::new(&entry.filename)))
+                        .clone();
+                    ProcMapEntry { build_id, ..entry }
+                })
+                .collect::<Vec<_>>();
             let mut map_file =
                 std::fs::File::create(proc_map_out_dir.join("proc_map.json")).expect("proc map");
             serde_json::to_writer(&mut map_file, &proc_map).expect("failed to write proc maps");
+            let mut execs_file =
+                std::fs::File::create(proc_map_out_dir.join("execs.json")).expect("execs");
+            serde_json::to_writer(&mut execs_file, &execs).expect("failed to write execs");
+        });
+
+        let thread_name_out_dir = output_directory.to_owned();
+        let thread_name_worker = tokio::spawn(async move {
+            // A thread may be renamed several times; the last name wins.
+            let mut names = HashMap::<(u32, u32), String>::new();
+            while let Some(entry) = thread_name_rx.recv().await {
+                names.insert((entry.pid, entry.tid), entry.name);
+            }
+
+            let mut thread_names = names
+                .into_iter()
+                .map(|((pid, tid), name)| ThreadName { pid, tid, name })
+                .collect::<Vec<_>>();
+            thread_names.sort_unstable_by_key(|entry| (entry.pid, entry.tid));
+            let mut names_file =
+                std::fs::File::create(thread_name_out_dir.join("thread_names.json"))
+                    .expect("thread names");
+            serde_json::to_writer(&mut names_file, &thread_names)
+                .expect("failed to write thread names");
+        });
+
+        let task_out_dir = output_directory.to_owned();
+        let task_worker = tokio::spawn(async move {
+            let mut processes = HashMap::<u32, ProcessFork>::new();
+            // An `exec` replaces the arguments; the last ones win.
+            let mut command_lines = HashMap::<u32, Vec<String>>::new();
+            while let Some(change) = task_rx.recv().await {
+                match change {
+                    TaskChange::Fork { pid, ppid, time } => {
+                        processes.insert(
+                            pid,
+                            ProcessFork {
+                                pid,
+                                ppid,
+                                fork_time: time,
+                                exit_time: None,
+                            },
+                        );
+                    }
+                    TaskChange::Exit { pid, time } => {
+                        if let Some(process) = processes.get_mut(&pid) {
+                            process.exit_time = Some(time);
+                        }
+                    }
+                    TaskChange::CommandLine(command_line) => {
+                        command_lines.insert(command_line.pid, command_line.args);
+                    }
+                }
+            }
+
+            let mut processes = processes.into_values().collect::<Vec<_>>();
+            processes.sort_unstable_by_key(|process| (process.fork_time, process.pid));
+            let mut processes_file =
+                std::fs::File::create(task_out_dir.join("processes.json")).expect("processes");
+            serde_json::to_writer(&mut processes_file, &processes)
+                .expect("failed to write processes");
+
+            let mut command_lines = command_lines
+                .into_iter()
+                .map(|(pid, args)| ProcessCommandLine { pid, args })
+                .collect::<Vec<_>>();
+            command_lines.sort_unstable_by_key(|command_line| command_line.pid);
+            let mut command_lines_file =
+                std::fs::File::create(task_out_dir.join("cmdlines.json")).expect("cmdlines");
+            serde_json::to_writer(&mut command_lines_file, &command_lines)
+                .expect("failed to write command lines");
         });
 
         (
@@ -81,11 +199,17 @@ impl EventDispatcher {
                 events_tx,
                 string_tx,
                 proc_map_tx,
+                thread_name_tx,
+                task_tx,
+                lost_events: AtomicU64::new(0),
+                kernel_lost_samples: AtomicU64::new(0),
             }),
             DispatcherJoinHandle {
                 events_worker,
                 string_worker,
                 proc_map_worker,
+                thread_name_worker,
+                task_worker,
             },
         )
     }
@@ -114,7 +238,7 @@ impl EventDispatcher {
         }
 
         if let Err(err) = self.string_tx.blocking_send((id, string.to_string())) {
-            eprintln!("Lost string {} -> {}: {}", id, string, err);
+            tracing::warn!("Lost string {} -> {}: {}", id, string, err);
         }
 
         id
@@ -139,7 +263,7 @@ impl EventDispatcher {
         }
 
         if let Err(err) = self.string_tx.send((id, string.to_string())).await {
-            eprintln!("Lost string {} -> {}: {}", id, string, err);
+            tracing::warn!("Lost string {} -> {}: {}", id, string, err);
         }
 
         id
@@ -147,10 +271,31 @@ impl EventDispatcher {
 
     pub fn publish_event_sync(&self, evt: Event) {
         if let Err(err) = self.events_tx.blocking_send(evt) {
-            eprintln!("lost event: {:?}", err);
+            self.add_lost_events(1);
+            tracing::warn!("lost event: {:?}", err);
         }
     }
 
+    /// Counts events dropped before reaching `events.bin`, including those a
+    /// collector dropped on its side of the shared-memory channel.
+    pub fn add_lost_events(&self, count: u64) {
+        self.lost_events.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn lost_events(&self) -> u64 {
+        self.lost_events.load(Ordering::Relaxed)
+    }
+
+    /// Counts samples the kernel dropped because a sampling ring buffer was
+    /// full. These never reached `mperf`, unlike [`Self::add_lost_events`].
+    pub fn add_kernel_lost_samples(&self, count: u64) {
+        self.kernel_lost_samples.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn kernel_lost_samples(&self) -> u64 {
+        self.kernel_lost_samples.load(Ordering::Relaxed)
+    }
+
     pub fn publish_proc_map_sync(&self, map: ProcMapEntry) {
         // This is called both from the sampling thread (a plain OS thread) and
         // inline from the async `record` flow, which runs on a tokio worker.
@@ -158,15 +303,151 @@ impl EventDispatcher {
         // thread from within a runtime"), so use the non-blocking `try_send`.
         // Proc-map entries are few (hundreds) relative to the channel capacity,
         // so this does not drop in practice; a full channel is logged, not fatal.
-        if let Err(err) = self.proc_map_tx.try_send(map) {
-            eprintln!("lost proc map entry: {err:?}");
+        if let Err(err) = self.proc_map_tx.try_send(ProcMapChange::Map(map)) {
+            tracing::warn!("lost proc map entry: {err:?}");
+        }
+    }
+
+    /// Records an `exec` of `pid`: later mappings belong to its new image.
+    pub fn publish_exec_sync(&self, pid: u32, time: u64) {
+        // Same calling contexts as `publish_proc_map_sync`.
+        let exec = ProcMapChange::Exec(ProcessExec { pid, time });
+        if let Err(err) = self.proc_map_tx.try_send(exec) {
+            tracing::warn!("lost process exec: {err:?}");
+        }
+        self.publish_command_line_sync(pid);
+    }
+
+    /// Records the arguments `pid` runs with now. Processes that have already
+    /// exited keep the arguments read before.
+    pub fn publish_command_line_sync(&self, pid: u32) {
+        let Some(args) = read_command_line(pid) else {
+            return;
+        };
+        let command_line = TaskChange::CommandLine(ProcessCommandLine { pid, args });
+        if let Err(err) = self.task_tx.try_send(command_line) {
+            tracing::warn!("lost process command line: {err:?}");
+        }
+    }
+
+    pub fn publish_thread_name_sync(&self, name: ThreadName) {
+        // Same calling contexts as `publish_proc_map_sync`.
+        if let Err(err) = self.thread_name_tx.try_send(name) {
+            tracing::warn!("lost thread name: {err:?}");
+        }
+    }
+
+    /// Records a process fork. Thread creation (`pid == ppid`) is ignored.
+    pub fn publish_fork_sync(&self, pid: u32, ppid: u32, time: u64) {
+        if pid == ppid {
+            return;
+        }
+        // Same calling contexts as `publish_proc_map_sync`.
+        if let Err(err) = self.task_tx.try_send(TaskChange::Fork { pid, ppid, time }) {
+            tracing::warn!("lost process fork: {err:?}");
+        }
+        self.publish_command_line_sync(pid);
+    }
+
+    /// Records a process exit. Exits of individual threads (`pid != tid`)
+    /// are ignored.
+    pub fn publish_exit_sync(&self, pid: u32, tid: u32, time: u64) {
+        if pid != tid {
+            return;
+        }
+        if let Err(err) = self.task_tx.try_send(TaskChange::Exit { pid, time }) {
+            tracing::warn!("lost process exit: {err:?}");
         }
     }
 
     pub async fn publish_event(&self, evt: Event) {
         if let Err(err) = self.events_tx.send(evt).await {
-            eprintln!("lost event: {:?}", err);
+            self.add_lost_events(1);
+            tracing::warn!("lost event: {:?}", err);
+        }
+    }
+}
+
+/// Collects the mappings of every process image, keyed by pid and
+/// [`ProcMapEntry::exec_generation`]. JITs and allocators map and unmap the
+/// same ranges over and over, so a mapping replaces whatever part of an
+/// earlier one of the same image it overlaps (the last mapping wins) instead
+/// of being appended.
+#[derive(Default)]
+pub(crate) struct ProcMapBuilder {
+    maps: HashMap<(u32, u32), BTreeMap<usize, ProcMapEntry>>,
+}
+
+impl ProcMapBuilder {
+    pub(crate) fn insert(&mut self, entry: ProcMapEntry) {
+        if entry.size == 0 {
+            return;
+        }
+        let maps = self
+            .maps
+            .entry((entry.pid, entry.exec_generation))
+            .or_default();
+        let start = entry.address;
+        let end = start.saturating_add(entry.size);
+
+        // Stored mappings never overlap, so their ends are sorted too.
+        let overlapping = maps
+            .range(..end)
+            .rev()
+            .take_while(|(_, old)| old.address + old.size > start)
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        for address in overlapping {
+            let old = maps.remove(&address).expect("overlapping mapping");
+            let old_end = old.address + old.size;
+            if old.address < start {
+                maps.insert(
+                    old.address,
+                    ProcMapEntry {
+                        size: start - old.address,
+                        ..old.clone()
+                    },
+                );
+            }
+            if old_end > end {
+                maps.insert(
+                    end,
+                    ProcMapEntry {
+                        address: end,
+                        size: old_end - end,
+                        offset: old.offset + (end - old.address),
+                        ..old
+                    },
+                );
+            }
+        }
+        maps.insert(start, entry);
+    }
+
+    /// Mappings sorted by process, image and address, with adjacent ranges of
+    /// the same file that are also contiguous in the file merged into one.
+    pub(crate) fn finish(self) -> Vec<ProcMapEntry> {
+        let mut pids = self.maps.into_iter().collect::<Vec<_>>();
+        pids.sort_unstable_by_key(|(image, _)| *image);
+
+        let mut entries = Vec::<ProcMapEntry>::new();
+        for (_, maps) in pids {
+            let first = entries.len();
+            for entry in maps.into_values() {
+                if let Some(last) = entries[first..].last_mut() {
+                    if last.filename == entry.filename
+                        && last.build_id == entry.build_id
+                        && last.address + last.size == entry.address
+                        && last.offset + last.size == entry.offset
+                    {
+                        last.size += entry.size;
+                        continue;
+                    }
+                }
+                entries.push(entry);
+            }
         }
+        entries
     }
 }
 
@@ -186,8 +467,117 @@ fn current_thread_id() -> u64 {
     }
 }
 
+/// The NUL-separated arguments of `/proc/<pid>/cmdline`. Kernel threads and
+/// zombies have none.
+#[cfg(target_os = "linux")]
+fn read_command_line(pid: u32) -> Option<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let args = parse_command_line(&cmdline);
+    (!args.is_empty()).then_some(args)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_command_line(_pid: u32) -> Option<Vec<String>> {
+    None
+}
+
+fn parse_command_line(cmdline: &[u8]) -> Vec<String> {
+    cmdline
+        .strip_suffix(b"\0")
+        .unwrap_or(cmdline)
+        .split(|&byte| byte == 0)
+        .filter(|_| !cmdline.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
 impl DispatcherJoinHandle {
     pub async fn join(self) {
-        let _ = tokio::join!(self.events_worker, self.string_worker, self.proc_map_worker);
+        let _ = tokio::join!(
+            self.events_worker,
+            self.string_worker,
+            self.proc_map_worker,
+            self.thread_name_worker,
+            self.task_worker
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(filename: &str, address: usize, size: usize, offset: usize) -> ProcMapEntry {
+        ProcMapEntry {
+            filename: filename.to_string(),
+            address,
+            size,
+            offset,
+            pid: 1,
+            build_id: None,
+            exec_generation: 0,
+        }
+    }
+
+    #[test]
+    fn repeated_mappings_are_deduplicated_and_coalesced() {
+        let mut builder = ProcMapBuilder::default();
+        builder.insert(mapping("/bin/app", 0x1000, 0x1000, 0));
+        builder.insert(mapping("/bin/app", 0x1000, 0x1000, 0));
+        builder.insert(mapping("/bin/app", 0x2000, 0x1000, 0x1000));
+        builder.insert(mapping("/lib/libc.so", 0x3000, 0x1000, 0));
+
+        assert_eq!(
+            builder.finish(),
+            [
+                mapping("/bin/app", 0x1000, 0x2000, 0),
+                mapping("/lib/libc.so", 0x3000, 0x1000, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn later_mappings_replace_overlapped_ranges() {
+        let mut builder = ProcMapBuilder::default();
+        builder.insert(mapping("/tmp/jit-1", 0x1000, 0x3000, 0));
+        builder.insert(mapping("/tmp/jit-2", 0x2000, 0x1000, 0));
+
+        assert_eq!(
+            builder.finish(),
+            [
+                mapping("/tmp/jit-1", 0x1000, 0x1000, 0),
+                mapping("/tmp/jit-2", 0x2000, 0x1000, 0),
+                mapping("/tmp/jit-1", 0x3000, 0x1000, 0x2000)
+            ]
+        );
+    }
+
+    #[test]
+    fn images_of_one_process_are_kept_apart() {
+        let exec = |entry: ProcMapEntry| ProcMapEntry {
+            exec_generation: 1,
+            ..entry
+        };
+        let mut builder = ProcMapBuilder::default();
+        builder.insert(exec(mapping("/usr/bin/app", 0x1000, 0x1000, 0)));
+        builder.insert(mapping("/bin/sh", 0x1000, 0x2000, 0));
+
+        assert_eq!(
+            builder.finish(),
+            [
+                mapping("/bin/sh", 0x1000, 0x2000, 0),
+                exec(mapping("/usr/bin/app", 0x1000, 0x1000, 0))
+            ]
+        );
+    }
+
+    #[test]
+    fn command_lines_are_split_at_nul() {
+        assert_eq!(
+            parse_command_line(b"python3\0-m\0http.server\0"),
+            ["python3", "-m", "http.server"]
+        );
+        assert_eq!(parse_command_line(b"sh\0-c\0\0"), ["sh", "-c", ""]);
+        assert!(parse_command_line(b"").is_empty());
     }
 }
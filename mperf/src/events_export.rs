@@ -1,9 +1,71 @@
-use std::{fs::File, path::Path};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
+use anyhow::{Context, Result};
 use memmap2::{Advice, Mmap};
 use mperf_data::Event;
 
-pub fn do_events_export(path: &Path) {
+use crate::archive;
+
+/// What `events-export` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Every raw event of `events.bin` as JSON.
+    #[default]
+    Json,
+    /// Folded stacks, one `frame;frame;frame count` line per stack, as read
+    /// by `flamegraph.pl`, inferno and speedscope.
+    Folded,
+}
+
+/// The counter folded stacks are weighted by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FoldedMetric {
+    #[default]
+    Cycles,
+    Instructions,
+}
+
+impl FoldedMetric {
+    /// The file postprocessing writes the stacks of the metric to.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            FoldedMetric::Cycles => "flamegraph_cycles.folded",
+            FoldedMetric::Instructions => "flamegraph_instructions.folded",
+        }
+    }
+}
+
+/// Writes the export of `path` to `output`, or to stdout.
+pub async fn do_events_export(
+    path: &Path,
+    format: ExportFormat,
+    metric: FoldedMetric,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let mut writer: Box<dyn Write> = match &output {
+        Some(output) => Box::new(
+            File::create(output)
+                .with_context(|| format!("failed to create {}", output.display()))?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match format {
+        ExportFormat::Json => export_events(path, &mut writer)?,
+        ExportFormat::Folded => {
+            // Postprocesses recordings that have not been yet, like `show`.
+            let results = archive::open_results(path).await?;
+            export_folded(results.path(), metric, &mut writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn export_events(path: &Path, writer: &mut impl Write) -> Result<()> {
     let events_path = path.join("events.bin");
     let file = File::open(events_path).expect("failed to open events.bin");
 
@@ -22,5 +84,40 @@ pub fn do_events_export(path: &Path) {
         events.push(evt);
     }
 
-    println!("{}", serde_json::to_string_pretty(&events).unwrap());
+    writeln!(writer, "{}", serde_json::to_string_pretty(&events)?)?;
+    Ok(())
+}
+
+fn export_folded(path: &Path, metric: FoldedMetric, writer: &mut impl Write) -> Result<()> {
+    let folded_path = path.join(metric.file_name());
+    let mut folded = File::open(&folded_path).with_context(|| {
+        format!(
+            "{} has no folded stacks; was it recorded with call stacks?",
+            path.display()
+        )
+    })?;
+    std::io::copy(&mut folded, writer)
+        .with_context(|| format!("failed to copy {}", folded_path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_export_copies_the_stacks_of_the_metric() {
+        let dir = std::env::temp_dir().join(format!("mperf-export-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("flamegraph_cycles.folded"), "main;work 30\n").unwrap();
+        std::fs::write(dir.join("flamegraph_instructions.folded"), "main;work 70\n").unwrap();
+
+        let mut output = Vec::new();
+        export_folded(&dir, FoldedMetric::Instructions, &mut output).unwrap();
+        assert_eq!(output, b"main;work 70\n");
+
+        std::fs::remove_file(dir.join("flamegraph_cycles.folded")).unwrap();
+        assert!(export_folded(&dir, FoldedMetric::Cycles, &mut Vec::new()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,104 @@
+//! Exit codes of `mperf`, so scripts can tell failures apart without parsing
+//! the error message. clap exits with 2 on an invalid command line.
+
+use std::fmt;
+
+/// Successful run.
+pub const SUCCESS: u8 = 0;
+/// Any failure without a more specific code below.
+pub const FAILURE: u8 = 1;
+/// The recording finished but holds no samples.
+pub const NO_SAMPLES: u8 = 3;
+/// A requested counter, event or driver is not available on this machine.
+pub const UNSUPPORTED: u8 = 4;
+/// The OS refused access to the performance counters.
+pub const PERMISSION_DENIED: u8 = 5;
+/// The profiled command exited with a non-zero status. Results are written
+/// regardless.
+pub const TARGET_FAILED: u8 = 6;
+
+/// Outcomes that are not errors of `mperf` itself but still must not exit
+/// with success.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    NoSamples,
+    TargetFailed(i32),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::NoSamples => f.write_str("no samples were collected"),
+            Outcome::TargetFailed(code) => write!(f, "the profiled command exited with {code}"),
+        }
+    }
+}
+
+impl std::error::Error for Outcome {}
+
+/// Picks the exit code for `error` from the first cause that has one.
+pub fn for_error(error: &anyhow::Error) -> u8 {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(outcome) = cause.downcast_ref::<Outcome>() {
+                return Some(match outcome {
+                    Outcome::NoSamples => NO_SAMPLES,
+                    Outcome::TargetFailed(_) => TARGET_FAILED,
+                });
+            }
+            if let Some(error) = cause.downcast_ref::<pmu::Error>() {
+                return pmu_error_code(error);
+            }
+            cause
+                .downcast_ref::<std::io::Error>()
+                .filter(|error| error.kind() == std::io::ErrorKind::PermissionDenied)
+                .map(|_| PERMISSION_DENIED)
+        })
+        .unwrap_or(FAILURE)
+}
+
+fn pmu_error_code(error: &pmu::Error) -> Option<u8> {
+    match error {
+        pmu::Error::PermissionDenied => Some(PERMISSION_DENIED),
+        pmu::Error::PerfEventOpen { errno, .. } if *errno == libc::EACCES => {
+            Some(PERMISSION_DENIED)
+        }
+        pmu::Error::PerfEventOpen { errno, .. } if *errno == libc::EPERM => Some(PERMISSION_DENIED),
+        pmu::Error::UnsupportedCounter { .. } | pmu::Error::UnsupportedDriver { .. } => {
+            Some(UNSUPPORTED)
+        }
+        error if error.is_event_unsupported() => Some(UNSUPPORTED),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn causes_map_to_their_exit_code() {
+        let wrapped = Err::<(), _>(Outcome::TargetFailed(2))
+            .context("recording finished")
+            .unwrap_err();
+        assert_eq!(for_error(&wrapped), TARGET_FAILED);
+        assert_eq!(for_error(&Outcome::NoSamples.into()), NO_SAMPLES);
+        assert_eq!(
+            for_error(
+                &pmu::Error::UnsupportedCounter {
+                    counter: "cycles".to_string(),
+                    family: "unknown".to_string(),
+                }
+                .into()
+            ),
+            UNSUPPORTED
+        );
+        assert_eq!(
+            for_error(&pmu::Error::PermissionDenied.into()),
+            PERMISSION_DENIED
+        );
+        assert_eq!(for_error(&anyhow::anyhow!("anything else")), FAILURE);
+    }
+}
@@ -0,0 +1,107 @@
+//! `mperf flamegraph`: renders the folded stacks of a recording to a
+//! standalone image, for dashboards and CI artifacts that cannot embed the
+//! interactive SVG.
+
+use std::{io::BufRead, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use resvg::{tiny_skia, usvg};
+
+use crate::{archive, events_export::FoldedMetric};
+
+/// What `mperf flamegraph` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImageFormat {
+    /// Inferno's interactive SVG.
+    #[default]
+    Svg,
+    /// The SVG rasterized with `resvg`.
+    Png,
+}
+
+/// Renders the `metric` flamegraph of `path` to `output` as `format`,
+/// `width` pixels wide.
+pub async fn do_flamegraph(
+    path: &Path,
+    metric: FoldedMetric,
+    format: ImageFormat,
+    width: u32,
+    output: &Path,
+) -> Result<()> {
+    // Postprocesses recordings that have not been yet, like `show`.
+    let results = archive::open_results(path).await?;
+    let folded_path = results.path().join(metric.file_name());
+    let lines = read_folded(&folded_path).with_context(|| {
+        format!(
+            "{} has no folded stacks; was it recorded with call stacks?",
+            path.display()
+        )
+    })?;
+    if lines.is_empty() {
+        bail!("{} has no {metric:?} samples", path.display());
+    }
+
+    let svg = render_svg(&lines, width)?;
+    match format {
+        ImageFormat::Svg => std::fs::write(output, svg)
+            .with_context(|| format!("failed to write {}", output.display())),
+        ImageFormat::Png => rasterize(&svg)?
+            .save_png(output)
+            .with_context(|| format!("failed to write {}", output.display())),
+    }?;
+
+    tracing::info!("wrote the flamegraph to {}", output.display());
+    Ok(())
+}
+
+fn read_folded(path: &Path) -> Result<Vec<String>> {
+    let file = std::fs::File::open(path)?;
+    std::io::BufReader::new(file)
+        .lines()
+        .map(|line| line.map_err(anyhow::Error::from))
+        .collect()
+}
+
+fn render_svg(lines: &[String], width: u32) -> Result<Vec<u8>> {
+    let mut svg = Vec::new();
+    let mut options = inferno::flamegraph::Options::default();
+    options.image_width = Some(width as usize);
+    inferno::flamegraph::from_lines(&mut options, lines.iter().map(|s| s.as_str()), &mut svg)
+        .context("failed to render the flamegraph")?;
+    Ok(svg)
+}
+
+/// Rasterizes `svg` at its own size, with the system fonts for the frame
+/// names.
+fn rasterize(svg: &[u8]) -> Result<tiny_skia::Pixmap> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(svg, &options).context("failed to parse the flamegraph")?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| anyhow!("the flamegraph is empty"))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_is_rendered_at_the_requested_width() {
+        let lines = vec!["main;work 30".to_string(), "main;idle 10".to_string()];
+        let svg = String::from_utf8(render_svg(&lines, 800).unwrap()).unwrap();
+        assert!(svg.contains("width=\"800\""));
+        assert!(svg.contains("work"));
+    }
+
+    #[test]
+    fn png_is_rasterized_in_process() {
+        let lines = vec!["main;work 30".to_string(), "main;idle 10".to_string()];
+        let pixmap = rasterize(&render_svg(&lines, 640).unwrap()).unwrap();
+        assert_eq!(pixmap.width(), 640);
+        assert!(pixmap.height() > 0);
+        assert!(pixmap.encode_png().unwrap().starts_with(b"\x89PNG"));
+    }
+}
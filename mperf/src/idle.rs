@@ -0,0 +1,111 @@
+//! Idle functions: event loops and spin-waits whose samples are waiting
+//! rather than working, and would otherwise dominate the profile of an
+//! I/O-bound or event-driven program.
+
+use anyhow::{Context, Result};
+use regex::RegexSet;
+
+/// Functions treated as idle unless `--idle-function` replaces them: the
+/// glibc and kernel entry points of yielding, sleeping, polling and futex
+/// waits. Patterns are anchored so that e.g. `Future::poll` stays work.
+pub const DEFAULT_IDLE_FUNCTIONS: &[&str] = &[
+    r"^_*sched_yield$",
+    r"futex",
+    r"^_*lll_lock_wait",
+    r"^(__GI_)?_*(e|p)?poll(_wait)?$",
+    r"^(__GI_)?_*p?select$",
+    r"^_*(clock_)?nanosleep",
+    r"^_*pthread_cond_(timed|clock)?wait",
+];
+
+/// Root frame idle stacks are moved under, and the hotspots row idle
+/// functions are summed into.
+pub const IDLE_FRAME: &str = "[idle]";
+
+/// What happens to samples in idle functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IdleMode {
+    /// Show them like any other function.
+    Keep,
+    /// Sum them into an `[idle]` hotspot and flamegraph root.
+    #[default]
+    Bucket,
+    /// Leave them out of the hotspots view and flamegraphs.
+    Exclude,
+}
+
+/// Which functions are idle and what to do with their samples.
+#[derive(Debug, Clone)]
+pub struct IdleFilter {
+    pub mode: IdleMode,
+    functions: RegexSet,
+}
+
+impl IdleFilter {
+    /// `functions` are regular expressions matched against function names;
+    /// when empty, [`DEFAULT_IDLE_FUNCTIONS`] are used.
+    pub fn new(mode: IdleMode, functions: &[String]) -> Result<Self> {
+        let functions = if functions.is_empty() {
+            RegexSet::new(DEFAULT_IDLE_FUNCTIONS)
+        } else {
+            RegexSet::new(functions)
+        }
+        .context("invalid --idle-function pattern")?;
+        Ok(IdleFilter { mode, functions })
+    }
+
+    pub fn is_idle(&self, func_name: &str) -> bool {
+        self.functions.is_match(func_name)
+    }
+
+    /// Applies the mode to a folded stack whose leaf is idle: it moves under
+    /// [`IDLE_FRAME`] or becomes empty, which flamegraphs skip.
+    pub fn fold(&self, stack: String) -> String {
+        let leaf = stack.rsplit(';').next().unwrap_or_default();
+        if self.mode == IdleMode::Keep || stack.is_empty() || !self.is_idle(leaf) {
+            return stack;
+        }
+        match self.mode {
+            IdleMode::Exclude => String::new(),
+            _ => format!("{IDLE_FRAME};{stack}"),
+        }
+    }
+}
+
+impl Default for IdleFilter {
+    fn default() -> Self {
+        IdleFilter::new(IdleMode::default(), &[]).expect("default idle functions are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_leaves_are_bucketed_or_excluded() {
+        let bucket = IdleFilter::default();
+        for idle in ["__sched_yield", "__GI___poll", "epoll_wait", "futex_wait"] {
+            assert!(bucket.is_idle(idle), "{idle}");
+        }
+        for work in ["<Conn as Future>::poll", "parse_select", "main"] {
+            assert!(!bucket.is_idle(work), "{work}");
+        }
+        assert_eq!(
+            bucket.fold("main;run;epoll_wait".to_string()),
+            "[idle];main;run;epoll_wait"
+        );
+        assert_eq!(
+            bucket.fold("main;epoll_wait;work".to_string()),
+            "main;epoll_wait;work"
+        );
+
+        let exclude = IdleFilter::new(IdleMode::Exclude, &["^spin$".to_string()]).unwrap();
+        assert_eq!(exclude.fold("main;spin".to_string()), "");
+        assert_eq!(
+            exclude.fold("main;epoll_wait".to_string()),
+            "main;epoll_wait"
+        );
+        assert!(IdleFilter::new(IdleMode::Keep, &["(".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! `mperf list-scenarios`: what each scenario records and what it computes
+//! from it, for picking one before recording.
+
+use std::io::Write;
+
+use anyhow::Result;
+use mperf_data::Scenario;
+use serde::Serialize;
+
+use crate::counter_selection::get_pmu_counters;
+
+#[derive(Debug, Serialize)]
+pub struct ScenarioDescription {
+    /// Value of `record --scenario`.
+    pub name: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// Events recorded by default, as listed by `mperf list`.
+    pub events: Vec<String>,
+    pub metrics: Vec<MetricDescription>,
+    /// Why the scenario cannot be recorded on this host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unavailable: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricDescription {
+    pub name: String,
+    pub description: String,
+}
+
+impl MetricDescription {
+    fn new(name: &str, description: &str) -> Self {
+        MetricDescription {
+            name: name.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+pub fn do_list_scenarios(json: bool) -> Result<()> {
+    let scenarios = describe_scenarios();
+    let mut stdout = std::io::stdout().lock();
+    if json {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&scenarios)?)?;
+    } else {
+        write!(stdout, "{}", format_scenarios(&scenarios))?;
+    }
+    Ok(())
+}
+
+/// The built-in scenarios, with the events and metrics the host CPU family
+/// defines for TMA.
+fn describe_scenarios() -> Vec<ScenarioDescription> {
+    let snapshot_metrics = vec![
+        MetricDescription::new("ipc", "Instructions retired per cycle"),
+        MetricDescription::new("branch_miss_rate", "Share of branches mispredicted"),
+        MetricDescription::new("branch_mpki", "Branch misses per thousand instructions"),
+        MetricDescription::new(
+            "cache_miss_rate",
+            "Share of last-level cache references that missed",
+        ),
+        MetricDescription::new(
+            "cache_mpki",
+            "Last-level cache misses per thousand instructions",
+        ),
+    ];
+    let mut roofline_metrics = vec![];
+    for kind in [
+        "scalar_int",
+        "scalar_float",
+        "scalar_double",
+        "vector_int",
+        "vector_float",
+        "vector_double",
+    ] {
+        let ops = kind.replace('_', " ");
+        let ops = ops[..1].to_uppercase() + &ops[1..];
+        roofline_metrics.push(MetricDescription::new(
+            &format!("{kind}_ops"),
+            &format!("{ops} operations per second of each loop"),
+        ));
+        roofline_metrics.push(MetricDescription::new(
+            &format!("{kind}_ai"),
+            &format!("{ops} operations per byte loaded or stored (arithmetic intensity)"),
+        ));
+    }
+    let tma = pmu::host_tma_scenario();
+
+    vec![
+        ScenarioDescription {
+            name: "snapshot",
+            title: Scenario::Snapshot.name(),
+            description: "Samples where cycles go, with call stacks, and ranks functions \
+                          and modules by cycles, IPC, branch and cache misses.",
+            events: default_events(Scenario::Snapshot),
+            metrics: snapshot_metrics,
+            unavailable: None,
+        },
+        ScenarioDescription {
+            name: "roofline",
+            title: Scenario::Roofline.name(),
+            description: "Runs the program twice: a sampling pass and an instrumented pass \
+                          that counts the operations and bytes of every loop, to place loops \
+                          on a roofline. Needs a program built with the mperf instrumentation.",
+            events: default_events(Scenario::Roofline),
+            metrics: roofline_metrics,
+            unavailable: None,
+        },
+        ScenarioDescription {
+            name: "tma",
+            title: Scenario::TMA.name(),
+            description: "Top-down microarchitecture analysis: splits the pipeline slots of \
+                          every function into retiring, bad speculation, frontend and backend \
+                          bound, using the CPU family's methodology.",
+            events: tma
+                .as_ref()
+                .map(|tma| tma.events.clone())
+                .unwrap_or_default(),
+            metrics: tma
+                .as_ref()
+                .map(|tma| {
+                    tma.metrics
+                        .iter()
+                        .map(|metric| MetricDescription::new(&metric.name, &metric.desc))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            unavailable: tma
+                .is_none()
+                .then(|| "TMA is not supported on this CPU".to_string()),
+        },
+    ]
+}
+
+fn default_events(scenario: Scenario) -> Vec<String> {
+    get_pmu_counters(scenario)
+        .unwrap_or_default()
+        .iter()
+        .map(|counter| counter.name().to_string())
+        .collect()
+}
+
+fn format_scenarios(scenarios: &[ScenarioDescription]) -> String {
+    let mut text = String::new();
+    for (index, scenario) in scenarios.iter().enumerate() {
+        if index > 0 {
+            text.push('\n');
+        }
+        text += &format!("{} ({})\n", scenario.name, scenario.title);
+        text += &format!("  {}\n", scenario.description);
+        if let Some(reason) = &scenario.unavailable {
+            text += &format!("  Unavailable: {reason}\n");
+        }
+        if !scenario.events.is_empty() {
+            text += &format!("  Events: {}\n", scenario.events.join(", "));
+        }
+        if !scenario.metrics.is_empty() {
+            text += "  Metrics:\n";
+            let width = scenario
+                .metrics
+                .iter()
+                .map(|metric| metric.name.len())
+                .max()
+                .unwrap_or_default();
+            for metric in &scenario.metrics {
+                text += &format!("    {:width$}  {}\n", metric.name, metric.description);
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_scenario_is_described() {
+        let scenarios = describe_scenarios();
+        assert_eq!(
+            scenarios.iter().map(|s| s.name).collect::<Vec<_>>(),
+            ["snapshot", "roofline", "tma"]
+        );
+        assert!(scenarios[0].events.contains(&"cycles".to_string()));
+
+        let text = format_scenarios(&scenarios[..1]);
+        assert!(text.starts_with("snapshot (Snapshot)\n"));
+        assert!(text.contains("\n    ipc               Instructions retired per cycle\n"));
+    }
+}
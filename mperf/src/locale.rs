@@ -0,0 +1,83 @@
+//! Locale of the numbers `mperf` prints: the thousands separator of counts
+//! and the decimal point of metrics.
+
+use std::sync::OnceLock;
+
+use num_format::Locale;
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Environment variables that name the numeric locale, most specific first,
+/// as `setlocale` reads them.
+const LOCALE_VARIABLES: [&str; 3] = ["LC_ALL", "LC_NUMERIC", "LANG"];
+
+/// Sets the locale of every number printed from now on: `locale` when given,
+/// otherwise the one named by the environment, falling back to `en`.
+pub fn init(locale: Option<Locale>) {
+    let locale = locale.unwrap_or_else(|| {
+        let names = LOCALE_VARIABLES
+            .iter()
+            .filter_map(|variable| std::env::var(variable).ok());
+        from_environment(names)
+    });
+    let _ = LOCALE.set(locale);
+}
+
+/// The locale set by [`init`]; `en` before it is called, e.g. in tests.
+pub fn current() -> Locale {
+    LOCALE.get().copied().unwrap_or(Locale::en)
+}
+
+/// The locale of the first non-empty name, which takes precedence even when
+/// it is not a known locale, like in `setlocale`.
+fn from_environment(names: impl IntoIterator<Item = String>) -> Locale {
+    names
+        .into_iter()
+        .find(|name| !name.is_empty())
+        .and_then(|name| parse_posix(&name))
+        .unwrap_or(Locale::en)
+}
+
+/// Reads a POSIX locale name such as `de_AT.UTF-8@euro`, falling back from
+/// the territory to the language when only the latter is known.
+fn parse_posix(name: &str) -> Option<Locale> {
+    let name = name.split(['.', '@']).next().unwrap_or_default();
+    if matches!(name, "C" | "POSIX") {
+        return Some(Locale::en);
+    }
+    let name = name.replace('_', "-");
+    let language = name.split('-').next().unwrap_or_default();
+    Locale::from_name(&name)
+        .or_else(|_| Locale::from_name(language))
+        .ok()
+}
+
+/// Parses `--locale`, e.g. `de`, `en-IN` or `fr_FR.UTF-8`.
+pub fn parse_name(name: &str) -> Result<Locale, String> {
+    parse_posix(name).ok_or_else(|| format!("unknown locale '{name}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_are_read_like_setlocale() {
+        assert_eq!(parse_name("de_AT.UTF-8@euro"), Ok(Locale::de_AT));
+        assert_eq!(parse_name("de_DE.UTF-8"), Ok(Locale::de));
+        assert_eq!(parse_name("en-IN"), Ok(Locale::en_IN));
+        assert_eq!(parse_name("POSIX"), Ok(Locale::en));
+        assert!(parse_name("klingon").is_err());
+
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(from_environment(names(&["", "fr_FR.UTF-8"])), Locale::fr);
+        assert_eq!(from_environment(names(&["C", "fr_FR.UTF-8"])), Locale::en);
+        assert_eq!(from_environment(names(&["klingon", "fr"])), Locale::en);
+        assert_eq!(from_environment(names(&[])), Locale::en);
+    }
+}
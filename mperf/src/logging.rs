@@ -0,0 +1,93 @@
+use std::fmt;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::{format, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+    EnvFilter,
+};
+
+/// Environment variable the collector reads its log level from.
+const COLLECTOR_LOG_ENV: &str = "MPERF_COLLECTOR_LOG";
+
+/// Maps `-q`/`-v` flags to a log level. Progress messages are logged at info,
+/// so `-q` keeps only errors and each `-v` adds one level of detail.
+pub fn level_filter(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::ERROR;
+    }
+    match verbose {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Prints progress messages as they are, warnings and errors with their
+/// level, and debug detail with the module it came from.
+struct Format;
+
+impl<S, N> FormatEvent<S, N> for Format
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        match *metadata.level() {
+            Level::INFO => {}
+            Level::WARN => write!(writer, "warning: ")?,
+            Level::ERROR => write!(writer, "error: ")?,
+            level => write!(
+                writer,
+                "{}: [{}] ",
+                level.as_str().to_lowercase(),
+                metadata.target()
+            )?,
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Installs the stderr subscriber. `RUST_LOG` still overrides the
+/// flag-derived level for per-module filtering.
+pub fn init(verbose: u8, quiet: bool) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(level_filter(verbose, quiet).into())
+        .from_env_lossy();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .event_format(Format)
+        .init();
+}
+
+/// Environment entry that passes the current level to the collector in a
+/// launched program, so `-q` and `-v` apply to its messages too.
+pub fn collector_env() -> (String, String) {
+    (
+        COLLECTOR_LOG_ENV.to_string(),
+        LevelFilter::current().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::level_filter;
+    use tracing_subscriber::filter::LevelFilter;
+
+    #[test]
+    fn quiet_wins_over_verbose() {
+        assert_eq!(level_filter(0, false), LevelFilter::INFO);
+        assert_eq!(level_filter(1, false), LevelFilter::DEBUG);
+        assert_eq!(level_filter(3, false), LevelFilter::TRACE);
+        assert_eq!(level_filter(2, true), LevelFilter::ERROR);
+    }
+}
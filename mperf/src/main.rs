@@ -1,10 +1,25 @@
+mod annotate;
+mod archive;
 mod counter_selection;
+mod cpufreq;
+mod derived;
 mod disassembly;
 mod event_dispatcher;
 mod events_export;
+mod exit_code;
+mod flamegraph;
+mod idle;
+mod list_scenarios;
+mod locale;
+mod logging;
+mod merge;
 mod postprocess;
 mod processing;
 mod record;
+mod scenario_selection;
+mod schema;
+mod selftest;
+mod sqlite_int;
 mod stat;
 mod tui;
 #[cfg(all(
@@ -15,20 +30,38 @@ mod unwind;
 mod utils;
 
 use std::{
-    path::{Path, PathBuf},
-    str::FromStr,
+    path::{Component, Path, PathBuf},
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 
-use events_export::do_events_export;
-use mperf_data::Scenario;
-use record::do_record;
+use annotate::do_annotate;
+use derived::Aggregation;
+use disassembly::AsmSyntax;
+use events_export::{do_events_export, ExportFormat, FoldedMetric};
+use flamegraph::{do_flamegraph, ImageFormat};
+use idle::{IdleFilter, IdleMode};
+use mperf_data::{SampleClock, Scenario};
+use pmu_data::{OrderSpec, SortDirection};
+use postprocess::{do_postprocess, ViewOptions};
+use record::{do_record, RecordOptions};
+use scenario_selection::ScenarioChoice;
 use stat::do_stat;
 
 #[derive(Parser)]
 struct Cli {
+    /// Increase log detail (-v for debug, -vv for trace).
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only report errors; hides progress messages and bars.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Locale of thousands separators and decimal points, e.g. `de` or
+    /// `en-IN`. Defaults to LC_ALL, LC_NUMERIC or LANG, then `en`.
+    #[arg(long, global = true, value_parser = locale::parse_name)]
+    locale: Option<num_format::Locale>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -36,10 +69,18 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     List,
+    /// Describe the scenarios `record` can capture: what they measure, the
+    /// events they record and the metrics they compute.
+    ListScenarios {
+        /// Print the descriptions as JSON.
+        #[arg(long)]
+        json: bool,
+    },
     Stat {
         #[arg(short, long)]
         pid: Option<u32>,
-        /// Comma-separated event names (use `mperf list` to discover them).
+        /// Comma-separated event names (use `mperf list` to discover them),
+        /// or raw encodings such as `r412e`.
         #[arg(short = 'e', long = "event", value_delimiter = ',')]
         events: Vec<String>,
         /// Render the host Top-down methodology instead of the flat stat table.
@@ -48,73 +89,705 @@ enum Commands {
         /// Maximum Top-down tree level to display (default: 1).
         #[arg(short = 'l', long, default_value_t = 1)]
         level: u8,
+        /// Also count processes the target forks, like perf. With
+        /// `--follow-forks=false` only the target and its threads are counted.
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+        follow_forks: bool,
+        /// Also show each counter's raw count and enabled/running times, to
+        /// see how much multiplexing scaled it.
+        #[arg(long)]
+        detailed: bool,
         #[arg(last = true)]
         command: Vec<String>,
     },
     Record {
+        /// What to record. `auto` counts the target for a couple of seconds
+        /// first and picks TMA for stall-bound workloads, roofline for
+        /// floating-point heavy ones and snapshot otherwise.
         #[arg(short, long)]
-        scenario: Scenario,
-        #[arg(short, long)]
-        output_directory: String,
+        scenario: ScenarioChoice,
+        #[arg(short, long, required_unless_present = "in_memory")]
+        output_directory: Option<String>,
+        /// Keep the database in memory and show it right away, instead of
+        /// writing a result directory. The raw files go to a temporary
+        /// directory that is removed on exit.
+        #[arg(long, conflicts_with_all = ["output_directory", "print_summary"])]
+        in_memory: bool,
+        /// Attach to a running process. Combined with a command (snapshot
+        /// only), both are profiled until the command exits.
         #[arg(short, long)]
         pid: Option<u32>,
+        /// Extra directory with split debug info, laid out like `/usr/lib/debug`
+        /// (`.build-id/ab/cdef….debug`). May be repeated.
+        #[arg(long = "debug-dir")]
+        debug_dirs: Vec<PathBuf>,
+        /// Syntax of x86 instructions in the assembly view.
+        #[arg(long, value_enum, default_value_t = AsmSyntax::Att)]
+        asm_syntax: AsmSyntax,
+        /// Whether samples in idle functions (yields, polls, futex waits)
+        /// are kept, summed into an `[idle]` bucket, or excluded from the
+        /// hotspots view and flamegraphs.
+        #[arg(long, value_enum, default_value_t = IdleMode::Bucket)]
+        idle: IdleMode,
+        /// Regular expression of function names treated as idle, replacing
+        /// the defaults. May be repeated.
+        #[arg(long = "idle-function", value_name = "REGEX")]
+        idle_functions: Vec<String>,
+        /// Data pages per sampling ring buffer (a power of two). Raise it if
+        /// records are lost at high sample rates.
+        #[arg(long, default_value_t = pmu::DEFAULT_MMAP_PAGES, value_parser = parse_mmap_pages)]
+        mmap_pages: usize,
+        /// Print a JSON summary (sample count, top functions, lost events) to
+        /// stdout when recording finishes.
+        #[arg(long = "print-summary", alias = "json")]
+        print_summary: bool,
+        /// Sample retired loads slower than the load-latency threshold and
+        /// record each load's latency (snapshot only, Intel PEBS).
+        #[arg(long)]
+        mem_latency: bool,
+        /// Also sample page faults with their call stacks and write
+        /// `flamegraph_pagefaults.folded` (snapshot only).
+        #[arg(long)]
+        page_faults: bool,
+        /// Also sample L1 data cache reads and misses and L1 instruction
+        /// cache misses (snapshot only, Linux).
+        #[arg(long)]
+        l1_cache: bool,
+        /// Only sample on these CPUs, e.g. `0-3,6`. Execution on other CPUs
+        /// is not recorded (Linux only).
+        // Spelled out so clap parses the whole list as one value.
+        #[arg(long = "sample-cpu", value_parser = parse_cpu_list)]
+        sample_cpus: Option<std::vec::Vec<u32>>,
+        /// Comma-separated events replacing the scenario's default counters
+        /// (snapshot and roofline). Cycles are always sampled.
+        #[arg(short = 'e', long = "events", value_delimiter = ',')]
+        events: Vec<String>,
+        /// Interrupt the target only on this event, e.g. `llc_misses`, so
+        /// samples land where it occurs; the other counters are read with it
+        /// (snapshot only, Linux).
+        #[arg(long, value_name = "EVENT", conflicts_with = "mem_latency")]
+        leader: Option<String>,
+        /// Also sample processes the target forks, like perf. With
+        /// `--follow-forks=false` only the target and its threads are sampled.
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+        follow_forks: bool,
+        /// Sample only the exact target thread: the main thread of the
+        /// command, or the thread `--pid` names, which may be a thread ID.
+        /// Threads and processes it creates are not sampled (Linux only).
+        #[arg(long, conflicts_with = "count_totals")]
+        no_children: bool,
+        /// Count total cycles and instructions next to sampling, so the
+        /// hotspots table can show estimated absolute counts (snapshot only).
+        #[arg(long)]
+        count_totals: bool,
+        /// Sample without call stacks. Profiles are flat (leaf functions
+        /// only), but sampling is cheaper and `events.bin` much smaller.
+        #[arg(long)]
+        no_callstack: bool,
+        /// Clock that stamps the samples. `monotonic-raw` is the clock of the
+        /// collector's roofline events, so both can be placed on one
+        /// timeline (Linux only).
+        #[arg(long, value_enum, default_value_t = SampleClock::Perf)]
+        clock: SampleClock,
+        /// Record a command that exits within 100 ms again at a higher
+        /// sample frequency instead of only warning that it was too short.
+        #[arg(long)]
+        auto_freq: bool,
         #[arg(last = true)]
         command: Vec<String>,
     },
     Show {
+        /// A result directory, or a `.tar.zst` archive of one.
         result_directory: String,
+        /// Compare the hotspots with another recording, e.g. before a change.
+        #[arg(long = "compare-to", value_name = "DIR")]
+        compare_to: Option<PathBuf>,
+        /// Initial ordering of the tables as `<column>[:asc|desc]`,
+        /// descending by default, e.g. `cache_miss_rate`. Tables without the
+        /// column keep their own order.
+        #[arg(long, value_name = "COLUMN", value_parser = parse_sort)]
+        sort: Option<OrderSpec>,
+        /// Also navigate with h/j/k/l, gg and G, and search with /.
+        #[arg(long)]
+        tui_vim_keys: bool,
+        /// Rows of the flamegraph "Top" table rendered at once [default: 1000].
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        flamegraph_table_limit: Option<u64>,
     },
+    /// Export the raw events, or the folded stacks for external flamegraph
+    /// tools such as `flamegraph.pl` or speedscope.
     EventsExport {
         result_directory: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Counter the folded stacks are weighted by.
+        #[arg(long, value_enum, default_value_t = FoldedMetric::Cycles)]
+        metric: FoldedMetric,
+        /// Write to a file instead of stdout.
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Render a flamegraph of a recording to an image file, e.g. for CI
+    /// artifacts.
+    Flamegraph {
+        result_directory: String,
+        /// Counter the flamegraph is weighted by.
+        #[arg(long, value_enum, default_value_t = FoldedMetric::Cycles)]
+        metric: FoldedMetric,
+        /// Image format; `png` rasterizes the SVG.
+        #[arg(long, value_enum, default_value_t = ImageFormat::Svg)]
+        format: ImageFormat,
+        /// Image width in pixels.
+        #[arg(long, default_value_t = 1200, value_parser = clap::value_parser!(u32).range(1..))]
+        width: u32,
+        #[arg(long, short, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Combine recordings of the same scenario, e.g. shards of a test suite,
+    /// into one result directory for `show`.
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        result_directories: Vec<PathBuf>,
+        /// Directory for the merged results.
+        #[arg(long, short, value_name = "DIR")]
+        output: PathBuf,
+    },
+    /// Postprocess a recording again, e.g. after changing view definitions.
+    Postprocess {
+        result_directory: String,
+        /// Keep the raw tables and only rebuild the views and derived tables.
+        #[arg(long)]
+        views_only: bool,
+        /// Replace the existing `perf.db` contents.
+        #[arg(long)]
+        force: bool,
+        /// Complete a `perf.db` whose postprocessing failed, from the stage
+        /// that failed.
+        #[arg(long, conflicts_with_all = ["views_only", "force"])]
+        resume: bool,
+        /// Extra directory with split debug info, searched before the ones
+        /// given to `record`. May be repeated.
+        #[arg(long = "debug-dir")]
+        debug_dirs: Vec<PathBuf>,
+        /// Syntax of x86 instructions in the assembly view. Not applied with
+        /// `--views-only`, which keeps the stored disassembly.
+        #[arg(long, value_enum, default_value_t = AsmSyntax::Att)]
+        asm_syntax: AsmSyntax,
+        /// Whether samples in idle functions (yields, polls, futex waits)
+        /// are kept, summed into an `[idle]` bucket, or excluded from the
+        /// hotspots view and flamegraphs. `--views-only`
+        /// only updates the hotspots view.
+        #[arg(long, value_enum, default_value_t = IdleMode::Bucket)]
+        idle: IdleMode,
+        /// Regular expression of function names treated as idle, replacing
+        /// the defaults. May be repeated.
+        #[arg(long = "idle-function", value_name = "REGEX")]
+        idle_functions: Vec<String>,
+        /// Hide functions with fewer samples from the hotspots view.
+        #[arg(long, default_value_t = 1)]
+        min_samples: u64,
+        /// How counters scaled for multiplexing are summed in the hotspots
+        /// view. `weighted` gives rows whose counter barely ran less weight.
+        #[arg(long, value_enum, default_value_t = Aggregation::Descale)]
+        aggregation: Aggregation,
+        /// Hide loops that ran for less in total from the roofline view,
+        /// e.g. `50us` or `2ms`.
+        #[arg(long, value_parser = parse_duration)]
+        min_duration: Option<Duration>,
+    },
+    /// Measure the overhead of sampling by running a built-in fixed-work loop
+    /// with and without mperf sampling itself.
+    Selftest {
+        /// Iterations of the work loop per run.
+        #[arg(long, default_value_t = 200_000_000)]
+        iterations: u64,
+        /// Runs of each configuration; their medians are compared.
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        repeat: u64,
+        /// Sampling frequency in hertz, 1000 like `record`.
+        #[arg(long, default_value_t = 1000)]
+        freq: u64,
+        /// Fail when the wall-time overhead exceeds this percentage, e.g. in CI.
+        #[arg(long, value_name = "PERCENT")]
+        max_overhead: Option<f64>,
+    },
+    /// Write the annotated disassembly of the hottest functions to files.
+    Annotate {
+        result_directory: String,
+        /// Number of functions to annotate, hottest first.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Directory for the annotation files.
+        #[arg(short, long, default_value = "annotations")]
+        output: PathBuf,
     },
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
-async fn main() -> Result<()> {
+async fn main() -> std::process::ExitCode {
     let args = Cli::parse();
+    logging::init(args.verbose, args.quiet);
+    locale::init(args.locale);
 
-    match args.command {
+    match run(args.command).await {
+        Ok(()) => exit_code::SUCCESS.into(),
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            exit_code::for_error(&error).into()
+        }
+    }
+}
+
+async fn run(command: Commands) -> Result<()> {
+    match command {
         Commands::Stat {
             pid,
             events,
             topdown,
             level,
+            follow_forks,
+            detailed,
             command,
-        } => return do_stat(pid, command, events, topdown.then_some(level)),
+        } => {
+            return do_stat(
+                pid,
+                command,
+                events,
+                topdown.then_some(level),
+                follow_forks,
+                detailed,
+            )
+        }
         Commands::List => {
             let events = pmu::list_supported_counters(pmu::DriverKind::Default);
             for event in events {
                 println!("{} - {}", event.name(), event.description());
             }
         }
+        Commands::ListScenarios { json } => list_scenarios::do_list_scenarios(json)?,
         Commands::Record {
             scenario,
             output_directory,
             pid,
+            debug_dirs,
+            asm_syntax,
+            idle,
+            idle_functions,
+            mmap_pages,
+            print_summary,
+            mem_latency,
+            page_faults,
+            l1_cache,
+            sample_cpus,
+            events,
+            leader,
+            follow_forks,
+            no_children,
+            count_totals,
+            no_callstack,
+            in_memory,
+            clock,
+            auto_freq,
             command,
         } => {
-            if std::fs::exists(&output_directory)? {
-                return Err(Into::<anyhow::Error>::into(std::io::Error::new(
-                    std::io::ErrorKind::AlreadyExists,
-                    format!("'{output_directory}' already exists"),
-                ))
-                .context("profiling results must be put in different directories"));
-            }
-            std::fs::create_dir_all(&output_directory)?;
-
-            let output_directory = PathBuf::from_str(&output_directory)?;
+            let temporary = if in_memory {
+                Some(archive::Results::temporary("mperf-record")?)
+            } else {
+                None
+            };
+            let output_directory = match &temporary {
+                Some(temporary) => temporary.path().to_path_buf(),
+                None => {
+                    let output_directory = normalize_output_directory(
+                        output_directory
+                            .as_deref()
+                            .expect("clap requires --output-directory without --in-memory"),
+                    )?;
+                    if std::fs::exists(&output_directory)? {
+                        return Err(Into::<anyhow::Error>::into(std::io::Error::new(
+                            std::io::ErrorKind::AlreadyExists,
+                            format!("'{}' already exists", output_directory.display()),
+                        ))
+                        .context("profiling results must be put in different directories"));
+                    }
+                    std::fs::create_dir_all(&output_directory)?;
+                    output_directory
+                }
+            };
 
-            return do_record(scenario, &output_directory, pid, command).await;
+            let options = RecordOptions {
+                pid,
+                debug_dirs,
+                asm_syntax,
+                idle: IdleFilter::new(idle, &idle_functions)?,
+                mmap_pages,
+                print_summary,
+                mem_latency,
+                page_faults,
+                l1_cache,
+                sample_cpus: sample_cpus.unwrap_or_default(),
+                follow_forks,
+                no_children,
+                events,
+                leader,
+                count_totals,
+                no_callstack,
+                in_memory,
+                clock,
+                auto_freq,
+            };
+            let scenario = match scenario.fixed() {
+                Some(scenario) => scenario,
+                // These options only exist for snapshots.
+                None if mem_latency
+                    || page_faults
+                    || l1_cache
+                    || count_totals
+                    || options.leader.is_some() =>
+                {
+                    Scenario::Snapshot
+                }
+                None => scenario_selection::detect_scenario(&command, pid)
+                    .context("failed to pick a scenario automatically")?,
+            };
+            let connection = do_record(scenario, &output_directory, &options, command).await?;
+            if in_memory {
+                return tui::tui_main(&output_directory, None, None, false, None, Some(connection))
+                    .await;
+            }
         }
-        Commands::Show { result_directory } => {
+        Commands::Show {
+            result_directory,
+            compare_to,
+            sort,
+            tui_vim_keys,
+            flamegraph_table_limit,
+        } => {
+            let results = archive::open_results(Path::new(&result_directory)).await?;
+            let baseline = match &compare_to {
+                Some(path) => Some(archive::open_results(path).await?),
+                None => None,
+            };
+            return tui::tui_main(
+                results.path(),
+                baseline.as_ref().map(archive::Results::path),
+                sort,
+                tui_vim_keys,
+                flamegraph_table_limit.map(|limit| limit as usize),
+                None,
+            )
+            .await;
+        }
+        Commands::EventsExport {
+            result_directory,
+            format,
+            metric,
+            output,
+        } => {
             let path = Path::new(&result_directory);
-            return tui::tui_main(path).await;
+            return do_events_export(path, format, metric, output).await;
         }
-        Commands::EventsExport { result_directory } => {
+        Commands::Flamegraph {
+            result_directory,
+            metric,
+            format,
+            width,
+            output,
+        } => {
             let path = Path::new(&result_directory);
-            do_events_export(path);
+            return do_flamegraph(path, metric, format, width, &output).await;
+        }
+        Commands::Merge {
+            result_directories,
+            output,
+        } => {
+            return merge::do_merge(&result_directories, &output).await;
+        }
+        Commands::Postprocess {
+            result_directory,
+            views_only,
+            force,
+            resume,
+            debug_dirs,
+            asm_syntax,
+            idle,
+            idle_functions,
+            min_samples,
+            aggregation,
+            min_duration,
+        } => {
+            let views = ViewOptions {
+                min_samples,
+                aggregation,
+                min_loop_duration: min_duration.unwrap_or_default(),
+                idle: IdleFilter::new(idle, &idle_functions)?,
+            };
+            return do_postprocess(
+                Path::new(&result_directory),
+                views_only,
+                force,
+                resume,
+                &debug_dirs,
+                asm_syntax,
+                &views,
+            )
+            .await;
+        }
+        Commands::Selftest {
+            iterations,
+            repeat,
+            freq,
+            max_overhead,
+        } => {
+            return selftest::do_selftest(iterations, repeat as usize, freq, max_overhead);
+        }
+        Commands::Annotate {
+            result_directory,
+            top,
+            output,
+        } => {
+            return do_annotate(Path::new(&result_directory), top, &output);
         }
     }
 
     Ok(())
 }
+
+fn parse_mmap_pages(value: &str) -> Result<usize, String> {
+    let pages = value
+        .parse::<usize>()
+        .map_err(|error| format!("'{value}' is not a page count: {error}"))?;
+    if !pages.is_power_of_two() {
+        return Err(format!("{pages} is not a power of two"));
+    }
+    Ok(pages)
+}
+
+/// Parses a duration such as `500ns`, `50us`, `2ms` or `1.5s`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("'{value}' is not a duration"))?;
+    let seconds = match unit {
+        "ns" => number / 1e9,
+        "us" => number / 1e6,
+        "ms" => number / 1e3,
+        "s" => number,
+        _ => return Err(format!("'{value}' needs a unit: ns, us, ms or s")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|error| format!("'{value}': {error}"))
+}
+
+/// Parses `<column>[:asc|desc]`; the order is descending unless given.
+fn parse_sort(value: &str) -> Result<OrderSpec, String> {
+    let (column, direction) = match value.rsplit_once(':') {
+        Some((column, "asc")) => (column, SortDirection::Asc),
+        Some((column, "desc")) => (column, SortDirection::Desc),
+        Some((_, direction)) => {
+            return Err(format!(
+                "unknown sort direction '{direction}'; use asc or desc"
+            ))
+        }
+        None => (value, SortDirection::Desc),
+    };
+    // The column ends up in SQL, so only plain identifiers are accepted.
+    if column.is_empty()
+        || !column
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(format!("'{column}' is not a column name"));
+    }
+    Ok(OrderSpec {
+        column: column.to_string(),
+        direction,
+    })
+}
+
+/// Parses a CPU list such as `0-3,6` into sorted, unique CPU numbers.
+fn parse_cpu_list(value: &str) -> Result<Vec<u32>, String> {
+    let mut cpus = Vec::new();
+    for range in value.split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<u32>()
+                .map_err(|error| format!("'{range}' is not a CPU or CPU range: {error}"))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("CPU range '{range}' is reversed"));
+        }
+        cpus.extend(start..=end);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Turns a user-supplied results path into an absolute one: expands a leading
+/// `~`, resolves it against the current directory and canonicalizes the
+/// deepest existing ancestor, so symlinks are resolved before `..` is applied
+/// to them, like the kernel does. The missing rest is appended as written,
+/// with `.` dropped and `..` folded: it holds no symlinks yet.
+fn normalize_output_directory(raw: &str) -> Result<PathBuf> {
+    let expanded = expand_home(raw, std::env::var_os("HOME").map(PathBuf::from))?;
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        std::env::current_dir()
+            .context("failed to read the current directory")?
+            .join(expanded)
+    };
+
+    let components = absolute.components().collect::<Vec<_>>();
+    let mut existing = components.len();
+    let mut normalized = loop {
+        let ancestor = components[..existing].iter().collect::<PathBuf>();
+        match ancestor.canonicalize() {
+            Ok(canonical) => break canonical,
+            Err(_) if existing > 1 => existing -= 1,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to resolve '{}'", ancestor.display()))
+            }
+        }
+    };
+    for component in &components[existing..] {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
+
+fn expand_home(raw: &str, home: Option<PathBuf>) -> Result<PathBuf> {
+    let rest = match raw.strip_prefix('~') {
+        Some("") => "",
+        Some(rest) if rest.starts_with('/') => &rest[1..],
+        _ => return Ok(PathBuf::from(raw)),
+    };
+    let home = home.context("cannot expand '~': HOME is not set")?;
+    Ok(home.join(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        expand_home, normalize_output_directory, parse_cpu_list, parse_duration, parse_mmap_pages,
+        parse_sort, Cli, Commands, OrderSpec, SortDirection,
+    };
+    use clap::Parser;
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    #[test]
+    fn expands_leading_tilde_only() {
+        let home = Some(PathBuf::from("/home/user"));
+        assert_eq!(
+            expand_home("~/profiles/run1", home.clone()).unwrap(),
+            Path::new("/home/user/profiles/run1")
+        );
+        assert_eq!(
+            expand_home("~", home.clone()).unwrap(),
+            Path::new("/home/user")
+        );
+        assert_eq!(
+            expand_home("~other/run", home.clone()).unwrap(),
+            Path::new("~other/run")
+        );
+        assert_eq!(expand_home("run/~", home).unwrap(), Path::new("run/~"));
+        assert!(expand_home("~/run", None).is_err());
+    }
+
+    #[test]
+    fn normalizes_missing_tail_against_existing_parent() {
+        let root = std::env::temp_dir().join(format!("mperf-outdir-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let raw = root.join("a/./b/../run1");
+        let normalized = normalize_output_directory(raw.to_str().unwrap()).unwrap();
+        assert_eq!(normalized, root.canonicalize().unwrap().join("a/run1"));
+        assert!(normalized.is_absolute());
+
+        // `..` leaves the directory a symlink points to, not the symlink.
+        std::fs::create_dir_all(root.join("real/nested")).unwrap();
+        std::os::unix::fs::symlink(root.join("real/nested"), root.join("link")).unwrap();
+        let raw = root.join("link/../run2");
+        let normalized = normalize_output_directory(raw.to_str().unwrap()).unwrap();
+        assert_eq!(normalized, root.canonicalize().unwrap().join("real/run2"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn in_memory_replaces_the_output_directory() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(["mperf", "record", "-s", "snapshot"].iter().chain(args))
+        };
+        let Commands::Record {
+            output_directory,
+            in_memory,
+            ..
+        } = parse(&["--in-memory", "--", "ls"]).unwrap().command
+        else {
+            panic!("not a record command");
+        };
+        assert_eq!((output_directory, in_memory), (None, true));
+        assert!(parse(&["--", "ls"]).is_err());
+        assert!(parse(&["--in-memory", "-o", "run", "--", "ls"]).is_err());
+    }
+
+    #[test]
+    fn durations_need_a_unit() {
+        assert_eq!(parse_duration("50us"), Ok(Duration::from_micros(50)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0ns"), Ok(Duration::ZERO));
+        assert!(parse_duration("50").is_err());
+        assert!(parse_duration("ms").is_err());
+    }
+
+    #[test]
+    fn sort_defaults_to_descending() {
+        let sort = |column: &str, direction| OrderSpec {
+            column: column.to_string(),
+            direction,
+        };
+        assert_eq!(
+            parse_sort("cache_miss_rate"),
+            Ok(sort("cache_miss_rate", SortDirection::Desc))
+        );
+        assert_eq!(parse_sort("ipc:asc"), Ok(sort("ipc", SortDirection::Asc)));
+        assert_eq!(
+            parse_sort("total:desc"),
+            Ok(sort("total", SortDirection::Desc))
+        );
+        assert!(parse_sort("ipc:up").is_err());
+        assert!(parse_sort("ipc; DROP TABLE x").is_err());
+        assert!(parse_sort(":asc").is_err());
+    }
+
+    #[test]
+    fn mmap_pages_must_be_a_power_of_two() {
+        assert_eq!(parse_mmap_pages("1024"), Ok(1024));
+        assert!(parse_mmap_pages("0").is_err());
+        assert!(parse_mmap_pages("384").is_err());
+        assert!(parse_mmap_pages("many").is_err());
+    }
+
+    #[test]
+    fn cpu_lists_expand_ranges() {
+        assert_eq!(parse_cpu_list("4,0-2,2"), Ok(vec![0, 1, 2, 4]));
+        assert_eq!(parse_cpu_list(""), Ok(vec![]));
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("cpu0").is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! `mperf merge`: combines the results of several recordings of the same
+//! scenario, e.g. shards of a test suite or runs on different inputs, into
+//! one result directory that `show` opens like any other.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use mperf_data::{CountedTotals, RecordInfo, ScenarioInfo};
+
+use crate::{
+    archive,
+    postprocess::{
+        populate_assembly_samples, quote_identifier, rebuild_views, write_folded_stacks,
+        MappingCoverage, ViewOptions,
+    },
+    schema,
+};
+
+/// Trailing columns of `roofline_ops` and `roofline_loop_runs`, after the
+/// string ids of the file and function names.
+const ROOFLINE_OPS_COLUMNS: &str = "line, bytes_load, bytes_store, scalar_int_ops, \
+     scalar_float_ops, scalar_double_ops, vector_int_ops, vector_float_ops, vector_double_ops";
+const ROOFLINE_LOOP_RUNS_COLUMNS: &str = "line, loop_start_ts, loop_end_ts";
+
+/// What one input database holds, read before it is attached.
+struct Input {
+    db_path: PathBuf,
+    pmu_columns: Vec<String>,
+    roofline: bool,
+    assembly: bool,
+    coverage: Option<MappingCoverage>,
+}
+
+/// Merges the results at `inputs` into a new result directory `output`.
+pub async fn do_merge(inputs: &[PathBuf], output: &Path) -> Result<()> {
+    let db_path = output.join("perf.db");
+    if db_path.exists() {
+        bail!("{} already exists", db_path.display());
+    }
+
+    // Archives are extracted and raw recordings postprocessed, like `show`;
+    // the results stay open until the merge is done.
+    let mut results = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        results.push(archive::open_results(input).await?);
+    }
+    let infos = results
+        .iter()
+        .map(|results| read_info(results.path()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut info = merged_info(inputs, infos)?;
+    info.output_directory = Some(std::path::absolute(output)?);
+
+    std::fs::create_dir_all(output)
+        .with_context(|| format!("failed to create {}", output.display()))?;
+    let dirs = results
+        .iter()
+        .map(|results| results.path().to_path_buf())
+        .collect::<Vec<_>>();
+    let merged = async {
+        let connection = sqlite::open(&db_path)?;
+        merge_databases(&connection, &dirs)?;
+        rebuild_views(&connection, &info, &ViewOptions::default()).await
+    }
+    .await;
+    if let Err(error) = merged {
+        // A partly merged database would be opened by `show` as if complete.
+        let _ = std::fs::remove_file(&db_path);
+        return Err(error);
+    }
+
+    let info_file = std::fs::File::create(output.join("info.json"))?;
+    serde_json::to_writer(info_file, &info)?;
+    merge_folded_stacks(&dirs, output).await?;
+
+    tracing::info!(
+        "merged {} recordings into {}",
+        inputs.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn read_info(dir: &Path) -> Result<RecordInfo> {
+    let data = std::fs::read_to_string(dir.join("info.json"))
+        .with_context(|| format!("{} has no info.json", dir.display()))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// The `info.json` of the merged results: the first input's, with the totals
+/// counted by `--count-totals` summed when every input counted them.
+fn merged_info(inputs: &[PathBuf], infos: Vec<RecordInfo>) -> Result<RecordInfo> {
+    let mut infos = infos.into_iter().zip(inputs);
+    let Some((mut merged, first)) = infos.next() else {
+        bail!("nothing to merge");
+    };
+    let mut totals = counted_totals(&merged);
+    for (info, input) in infos {
+        if info.scenario != merged.scenario {
+            bail!(
+                "{} recorded the {:?} scenario, but {} recorded {:?}; only recordings of the same scenario can be merged",
+                first.display(),
+                merged.scenario,
+                input.display(),
+                info.scenario
+            );
+        }
+        totals = totals.zip(counted_totals(&info)).map(|(sum, totals)| {
+            let add = |a: Option<u64>, b: Option<u64>| a.zip(b).map(|(a, b)| a.saturating_add(b));
+            CountedTotals {
+                cycles: add(sum.cycles, totals.cycles),
+                instructions: add(sum.instructions, totals.instructions),
+            }
+        });
+    }
+    if let ScenarioInfo::Snapshot(snapshot) = &mut merged.scenario_info {
+        snapshot.counted_totals = totals;
+    }
+    // How one launched command ended says nothing about the others.
+    merged.target = None;
+    Ok(merged)
+}
+
+fn counted_totals(info: &RecordInfo) -> Option<CountedTotals> {
+    match &info.scenario_info {
+        ScenarioInfo::Snapshot(snapshot) => snapshot.counted_totals,
+        _ => None,
+    }
+}
+
+/// Copies the raw tables of the `perf.db` in each of `dirs` into
+/// `connection`. `pmu_counters` gets the columns of every input, NULL where
+/// an input did not record the event. String ids are renumbered, since ids
+/// of separate recordings can collide.
+fn merge_databases(connection: &sqlite::Connection, dirs: &[PathBuf]) -> Result<()> {
+    let inputs = dirs
+        .iter()
+        .map(|dir| read_input(&dir.join("perf.db")))
+        .collect::<Result<Vec<_>>>()?;
+
+    schema::create_common_tables(connection)?;
+    connection.execute(schema::pmu_counters(""))?;
+    let existing = schema::table_columns(connection, "pmu_counters")?;
+    let columns = inputs
+        .iter()
+        .flat_map(|input| input.pmu_columns.iter())
+        .filter(|column| !existing.contains(*column))
+        .collect::<BTreeSet<_>>();
+    for column in columns {
+        connection.execute(format!(
+            "ALTER TABLE pmu_counters ADD COLUMN {} INTEGER;",
+            quote_identifier(column)
+        ))?;
+    }
+    if inputs.iter().any(|input| input.roofline) {
+        connection.execute(schema::ROOFLINE_TABLES)?;
+    }
+    let assembly = inputs.iter().any(|input| input.assembly);
+    if assembly {
+        connection.execute(schema::ASSEMBLY_LINES)?;
+        connection.execute(schema::ASSEMBLY_MODULE_METADATA)?;
+    }
+
+    for input in &inputs {
+        let mut attach = connection.prepare("ATTACH DATABASE ? AS input;")?;
+        attach.bind((1, input.db_path.to_string_lossy().as_ref()))?;
+        attach.next()?;
+        drop(attach);
+        let appended = append_input(connection, input)
+            .with_context(|| format!("failed to merge {}", input.db_path.display()));
+        connection.execute("DETACH DATABASE input;")?;
+        appended?;
+    }
+
+    if assembly {
+        populate_assembly_samples(connection)?;
+    }
+    // Databases written before coverage was recorded leave it unknown.
+    let coverage = inputs
+        .iter()
+        .try_fold(MappingCoverage::default(), |sum, input| {
+            input.coverage.map(|coverage| MappingCoverage {
+                samples: sum.samples.saturating_add(coverage.samples),
+                unmapped: sum.unmapped.saturating_add(coverage.unmapped),
+            })
+        });
+    if let Some(coverage) = coverage {
+        coverage.persist(connection)?;
+    }
+    Ok(())
+}
+
+fn read_input(db_path: &Path) -> Result<Input> {
+    // Upgrades older databases, so every input has the current tables.
+    let connection = schema::open(db_path)?;
+    if !schema::table_exists(&connection, "pmu_counters")? {
+        bail!("{} has no samples", db_path.display());
+    }
+    let mut pmu_columns = schema::table_columns(&connection, "pmu_counters")?
+        .into_iter()
+        .collect::<Vec<_>>();
+    pmu_columns.sort();
+    Ok(Input {
+        db_path: db_path.to_path_buf(),
+        pmu_columns,
+        roofline: schema::table_exists(&connection, "roofline_ops")?,
+        assembly: schema::table_exists(&connection, "assembly_lines")?,
+        coverage: MappingCoverage::load(&connection),
+    })
+}
+
+/// Appends the database attached as `input` in one transaction.
+fn append_input(connection: &sqlite::Connection, input: &Input) -> Result<()> {
+    let pmu_columns = input
+        .pmu_columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // Addresses are only unique within one recording; the first recording
+    // to map an address names it.
+    let mut conflicts = connection.prepare(
+        "SELECT COUNT(*) AS conflicts FROM input.proc_map i
+         INNER JOIN main.proc_map m ON m.ip = i.ip
+         WHERE m.func_name IS NOT i.func_name OR m.module_path IS NOT i.module_path;",
+    )?;
+    conflicts.next()?;
+    let conflicts = conflicts.read::<i64, _>("conflicts")?;
+    if conflicts > 0 {
+        tracing::warn!(
+            "{conflicts} addresses of {} resolve to other functions in the recordings merged before it; their samples are attributed to the earlier functions",
+            input.db_path.display()
+        );
+    }
+
+    // Process ids are only unique within one recording too.
+    let collisions = colliding_pids(connection)?;
+    if collisions > 0 {
+        tracing::warn!(
+            "{collisions} process ids of {} were also used by the recordings merged before it; their thread names and command lines are those of the earlier processes",
+            input.db_path.display()
+        );
+    }
+
+    let mut statements = format!(
+        "BEGIN IMMEDIATE TRANSACTION;
+         INSERT INTO pmu_counters ({pmu_columns}) SELECT {pmu_columns} FROM input.pmu_counters;
+         INSERT INTO proc_map (ip, func_name, file_name, line, module_path)
+            SELECT ip, func_name, file_name, line, module_path FROM input.proc_map
+            WHERE ip NOT IN (SELECT ip FROM main.proc_map);
+         INSERT INTO strings (id, string)
+            SELECT (SELECT COALESCE(MAX(id), 0) FROM main.strings)
+                       + ROW_NUMBER() OVER (ORDER BY string),
+                   string
+            FROM (SELECT DISTINCT string FROM input.strings
+                  WHERE string NOT IN (SELECT string FROM main.strings));
+         INSERT OR IGNORE INTO thread_names (pid, tid, name)
+            SELECT pid, tid, name FROM input.thread_names;
+         INSERT OR IGNORE INTO processes (pid, ppid, fork_time, exit_time)
+            SELECT pid, ppid, fork_time, exit_time FROM input.processes;
+         INSERT OR IGNORE INTO process_cmdlines (pid, cmdline)
+            SELECT pid, cmdline FROM input.process_cmdlines;"
+    );
+    if input.roofline {
+        for (table, columns) in [
+            ("roofline_ops", ROOFLINE_OPS_COLUMNS),
+            ("roofline_loop_runs", ROOFLINE_LOOP_RUNS_COLUMNS),
+        ] {
+            statements.push_str(&format!(
+                "INSERT INTO {table} (
+                    unique_id, process_id, thread_id, file_name, function_name, {columns}
+                 )
+                 SELECT unique_id, process_id, thread_id, {}, {}, {columns}
+                 FROM input.{table} r;",
+                merged_string_id("r.file_name"),
+                merged_string_id("r.function_name"),
+            ));
+        }
+    }
+    if input.assembly {
+        statements.push_str(
+            "INSERT OR IGNORE INTO assembly_lines (
+                module_path, symbol, rel_address, runtime_address, instruction,
+                source_file, source_line, instruction_class
+             )
+             SELECT module_path, symbol, rel_address, runtime_address, instruction,
+                    source_file, source_line, instruction_class
+             FROM input.assembly_lines;
+             INSERT OR IGNORE INTO assembly_module_metadata (
+                module_path, load_bias, recorded_build_id, current_build_id
+             )
+             SELECT module_path, load_bias, recorded_build_id, current_build_id
+             FROM input.assembly_module_metadata;",
+        );
+    }
+    statements.push_str("COMMIT;");
+
+    connection.execute(statements).inspect_err(|_| {
+        let _ = connection.execute("ROLLBACK;");
+    })?;
+    Ok(())
+}
+
+/// Counts the process ids that both the attached input and the merged
+/// recordings have thread names, lifetimes or command lines for.
+fn colliding_pids(connection: &sqlite::Connection) -> Result<i64> {
+    let mut statement = connection.prepare(
+        "SELECT COUNT(*) AS collisions FROM (
+            SELECT pid FROM input.processes
+            UNION SELECT pid FROM input.process_cmdlines
+            UNION SELECT pid FROM input.thread_names
+         )
+         WHERE pid IN (
+            SELECT pid FROM main.processes
+            UNION SELECT pid FROM main.process_cmdlines
+            UNION SELECT pid FROM main.thread_names
+         );",
+    )?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>("collisions")?)
+}
+
+/// The merged id of the string the attached input stores as `column`; 0,
+/// which no string has, when the input lost the string.
+fn merged_string_id(column: &str) -> String {
+    format!(
+        "COALESCE((SELECT m.id FROM main.strings m
+                   INNER JOIN input.strings i ON i.string = m.string
+                   WHERE i.id = {column} LIMIT 1), 0)"
+    )
+}
+
+/// Sums the counts of equal stacks in the `*.folded` files of `dirs` and
+/// writes them to `output`, where the TUI and `mperf flamegraph` read them.
+async fn merge_folded_stacks(dirs: &[PathBuf], output: &Path) -> Result<()> {
+    let mut merged = HashMap::<String, HashMap<String, u64>>::new();
+    for dir in dirs {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "folded")
+            {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let stacks = merged.entry(stem.to_string()).or_default();
+            for line in std::io::BufReader::new(std::fs::File::open(&path)?).lines() {
+                let line = line?;
+                let Some((stack, count)) = line.rsplit_once(' ') else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<u64>() else {
+                    continue;
+                };
+                let sum = stacks.entry(stack.to_string()).or_default();
+                *sum = sum.saturating_add(count);
+            }
+        }
+    }
+    for (stem, stacks) in merged {
+        write_folded_stacks(output, &stem, stacks).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(dir: &Path, event: &str, strings: &[(f64, &str)]) {
+        std::fs::create_dir_all(dir).unwrap();
+        let connection = sqlite::open(dir.join("perf.db")).unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        connection
+            .execute(schema::pmu_counters(&format!(", \"{event}\" INTEGER")))
+            .unwrap();
+        connection.execute(schema::ROOFLINE_TABLES).unwrap();
+        connection
+            .execute(format!(
+                "INSERT INTO pmu_counters (
+                    process_id, thread_id, time_enabled, time_running, confidence,
+                    timestamp, ip, \"{event}\"
+                 ) VALUES (1, 1, 1, 1, 1.0, 1, 4096, 7);"
+            ))
+            .unwrap();
+        for (id, string) in strings {
+            let mut statement = connection
+                .prepare("INSERT INTO strings (id, string) VALUES (?, ?);")
+                .unwrap();
+            statement.bind((1, *id)).unwrap();
+            statement.bind((2, *string)).unwrap();
+            statement.next().unwrap();
+        }
+        // The loop names the first string as its file and the last as its
+        // function.
+        let mut statement = connection
+            .prepare("INSERT INTO roofline_loop_runs VALUES (NULL, 1, 1, ?, ?, 3, 0, 100);")
+            .unwrap();
+        statement.bind((1, strings[0].0)).unwrap();
+        statement.bind((2, strings[strings.len() - 1].0)).unwrap();
+        statement.next().unwrap();
+    }
+
+    #[test]
+    fn colliding_process_ids_are_counted() {
+        let root = std::env::temp_dir().join(format!("mperf-merge-{}", uuid::Uuid::now_v7()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        recording(&first, "pmu_cycles", &[(1.0, "a.c")]);
+        recording(&second, "pmu_cycles", &[(1.0, "a.c")]);
+        for (dir, pids) in [
+            (&first, "(10, 'make'), (11, 'cc')"),
+            (&second, "(11, 'ld')"),
+        ] {
+            sqlite::open(dir.join("perf.db"))
+                .unwrap()
+                .execute(format!(
+                    "INSERT INTO process_cmdlines (pid, cmdline) VALUES {pids};"
+                ))
+                .unwrap();
+        }
+
+        let connection = sqlite::open(":memory:").unwrap();
+        merge_databases(&connection, std::slice::from_ref(&first)).unwrap();
+        let mut attach = connection.prepare("ATTACH DATABASE ? AS input;").unwrap();
+        attach
+            .bind((1, second.join("perf.db").to_string_lossy().as_ref()))
+            .unwrap();
+        attach.next().unwrap();
+        drop(attach);
+        assert_eq!(colliding_pids(&connection).unwrap(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn colliding_string_ids_are_renumbered() {
+        let root = std::env::temp_dir().join(format!("mperf-merge-{}", uuid::Uuid::now_v7()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        recording(&first, "pmu_cycles", &[(1.0, "a.c"), (2.0, "kernel")]);
+        recording(
+            &second,
+            "pmu_instructions",
+            &[(2.0, "b.c"), (5.0, "kernel"), (1.0, "solve")],
+        );
+
+        let connection = sqlite::open(":memory:").unwrap();
+        merge_databases(&connection, &[first, second]).unwrap();
+
+        let mut statement = connection
+            .prepare(
+                "SELECT f.string AS file, g.string AS function
+                 FROM roofline_loop_runs r
+                 INNER JOIN strings f ON f.id = r.file_name
+                 INNER JOIN strings g ON g.id = r.function_name
+                 ORDER BY file;",
+            )
+            .unwrap();
+        let mut loops = Vec::new();
+        while let sqlite::State::Row = statement.next().unwrap() {
+            loops.push((
+                statement.read::<String, _>("file").unwrap(),
+                statement.read::<String, _>("function").unwrap(),
+            ));
+        }
+        assert_eq!(
+            loops,
+            [
+                ("a.c".to_string(), "kernel".to_string()),
+                ("b.c".to_string(), "solve".to_string()),
+            ]
+        );
+
+        let mut statement = connection
+            .prepare(
+                "SELECT COUNT(*) AS strings, COUNT(DISTINCT id) AS ids,
+                        (SELECT COUNT(pmu_cycles) FROM pmu_counters) AS cycles,
+                        (SELECT COUNT(pmu_instructions) FROM pmu_counters) AS instructions
+                 FROM strings;",
+            )
+            .unwrap();
+        statement.next().unwrap();
+        assert_eq!(statement.read::<i64, _>("strings").unwrap(), 4);
+        assert_eq!(statement.read::<i64, _>("ids").unwrap(), 4);
+        assert_eq!(statement.read::<i64, _>("cycles").unwrap(), 1);
+        assert_eq!(statement.read::<i64, _>("instructions").unwrap(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,14 +1,16 @@
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
-    path::Path,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 use anyhow::Result;
 use kdam::BarExt;
 use memmap2::{Advice, Mmap};
 use mperf_data::{
-    CallFrame, Event, EventType, IString, ProcMapEntry, RecordInfo, Scenario, ScenarioInfo,
+    CallFrame, CountedTotals, Event, EventType, IString, ProcMapEntry, ProcessCommandLine,
+    ProcessExec, ProcessFork, RecordInfo, Scenario, ScenarioInfo, ThreadName,
 };
 use object::{Object, ObjectSymbol, SymbolKind};
 use smallvec::SmallVec;
@@ -17,14 +19,155 @@ use tokio::{
     io::AsyncWriteExt,
 };
 
-use crate::disassembly::{default_disassembler, DisassembleRequest, DisassembleTarget};
-use crate::utils;
+use crate::disassembly::{
+    classify_instruction, default_disassembler, AsmSyntax, DisassembleRequest, DisassembleTarget,
+};
+use crate::{
+    derived::{Aggregation, DERIVED_METRICS},
+    idle::{IdleFilter, IdleMode, IDLE_FRAME},
+    schema,
+    sqlite_int::{sqlite_i64_from_u64, sqlite_u64_from_i64},
+    utils,
+};
 
 /// A core cluster resolved for post-processing: `(family_id, display name,
 /// inclusive CPU ranges)`.
 type ClusterRanges = (String, String, Vec<(u32, u32)>);
 
-pub async fn perform_postprocessing(res_dir: &Path, pb: kdam::Bar) -> Result<()> {
+/// Filters and options of the views built over the raw tables.
+#[derive(Debug, Clone)]
+pub struct ViewOptions {
+    /// Functions with fewer samples are left out of the hotspots view.
+    pub min_samples: u64,
+    /// How the hotspots view sums counters scaled for multiplexing.
+    pub aggregation: Aggregation,
+    /// Loops that ran for less in total are left out of the roofline view.
+    pub min_loop_duration: Duration,
+    /// Idle functions and whether the hotspots view and flamegraphs keep,
+    /// bucket or drop them.
+    pub idle: IdleFilter,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        ViewOptions {
+            min_samples: 1,
+            aggregation: Aggregation::default(),
+            min_loop_duration: Duration::ZERO,
+            idle: IdleFilter::default(),
+        }
+    }
+}
+
+/// Stages of [`perform_postprocessing`], in order. Each one commits its
+/// tables and is then recorded in `postprocess_state`, so a run that failed
+/// can resume after the last stage that completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Strings, thread names, processes and command lines.
+    Metadata,
+    /// `pmu_counters`, `proc_map`, the roofline tables and the flamegraphs.
+    Counters,
+    /// The sampled instructions and their disassembly.
+    Disassembly,
+    /// The views and derived tables.
+    Views,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage::Metadata,
+    Stage::Counters,
+    Stage::Disassembly,
+    Stage::Views,
+];
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Metadata => "metadata",
+            Stage::Counters => "counters",
+            Stage::Disassembly => "disassembly",
+            Stage::Views => "views",
+        }
+    }
+
+    /// Removes what a failed run of the stage may have committed, so it can
+    /// run again from scratch.
+    fn clear(self, connection: &sqlite::Connection) -> Result<()> {
+        let statements = match self {
+            Stage::Metadata => {
+                "DROP TABLE IF EXISTS proc_map;
+                 DROP TABLE IF EXISTS strings;
+                 DROP TABLE IF EXISTS thread_names;
+                 DROP TABLE IF EXISTS processes;
+                 DROP TABLE IF EXISTS process_cmdlines;
+                 DROP TABLE IF EXISTS sample_coverage;"
+            }
+            Stage::Counters => {
+                "DELETE FROM proc_map;
+                 DELETE FROM sample_coverage;
+                 DROP TABLE IF EXISTS pmu_counters;
+                 DROP TABLE IF EXISTS roofline_ops;
+                 DROP TABLE IF EXISTS roofline_loop_runs;"
+            }
+            Stage::Disassembly => {
+                "DROP VIEW IF EXISTS instruction_mix;
+                 DROP VIEW IF EXISTS assembly_stats;
+                 DROP TABLE IF EXISTS assembly_lines;
+                 DROP TABLE IF EXISTS assembly_module_metadata;
+                 DROP TABLE IF EXISTS assembly_samples;"
+            }
+            // Rebuilding the views drops them first.
+            Stage::Views => "",
+        };
+        connection.execute(statements)?;
+        Ok(())
+    }
+}
+
+/// Stages recorded as completed in `connection`.
+fn completed_stages(connection: &sqlite::Connection) -> Result<Vec<Stage>> {
+    connection.execute(schema::POSTPROCESS_STATE)?;
+    let mut statement = connection.prepare("SELECT stage FROM postprocess_state;")?;
+    let mut completed = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        let name = statement.read::<String, _>("stage")?;
+        completed.extend(STAGES.into_iter().filter(|stage| stage.name() == name));
+    }
+    Ok(completed)
+}
+
+fn mark_completed(connection: &sqlite::Connection, stage: Stage) -> Result<()> {
+    let mut statement =
+        connection.prepare("INSERT OR REPLACE INTO postprocess_state (stage) VALUES (?);")?;
+    statement.bind((1, stage.name()))?;
+    statement.next()?;
+    Ok(())
+}
+
+/// Whether the `perf.db` at `db_path` was left behind by postprocessing that
+/// failed before its last stage, and can be resumed.
+pub fn is_incomplete(db_path: &Path) -> Result<bool> {
+    let connection = sqlite::open(db_path)?;
+    if !schema::table_exists(&connection, "postprocess_state")? {
+        return Ok(false);
+    }
+    Ok(completed_stages(&connection)?.len() < STAGES.len())
+}
+
+/// Builds the database of a raw recording in `connection`, usually
+/// `perf.db` in `res_dir`. `debug_dirs` are searched for split debug
+/// information before the default locations; sampled functions are
+/// disassembled in `asm_syntax`. Stages completed by an earlier run on the
+/// same database are skipped.
+pub async fn perform_postprocessing(
+    connection: &sqlite::Connection,
+    res_dir: &Path,
+    pb: kdam::Bar,
+    debug_dirs: &[PathBuf],
+    asm_syntax: AsmSyntax,
+    views: &ViewOptions,
+) -> Result<()> {
     let mut pb = pb;
 
     let data = fs::read_to_string(res_dir.join("info.json"))
@@ -32,43 +175,179 @@ pub async fn perform_postprocessing(res_dir: &Path, pb: kdam::Bar) -> Result<()>
         .expect("failed to read info.json");
     let info: RecordInfo = serde_json::from_str(&data).expect("failed to parse info.json");
 
-    let connection = sqlite::open(res_dir.join("perf.db"))?;
-    connection.execute(
-        "
-            CREATE TABLE proc_map (
-                ip INTEGER,
-                func_name TEXT,
-                file_name TEXT,
-                line INTEGER,
-                module_path TEXT
+    let completed = completed_stages(connection)?;
+    for stage in STAGES {
+        if completed.contains(&stage) {
+            tracing::debug!("skipping the {} stage completed earlier", stage.name());
+            continue;
+        }
+        stage.clear(connection)?;
+        match stage {
+            Stage::Metadata => {
+                schema::create_common_tables(connection)?;
+                process_strings(connection, res_dir).await?;
+                process_thread_names(connection, res_dir)?;
+                process_forks(connection, res_dir)?;
+                process_command_lines(connection, res_dir)?;
+            }
+            Stage::Counters => {
+                process_pmu_counters(
+                    connection,
+                    &info.scenario_info,
+                    res_dir,
+                    debug_dirs,
+                    &views.idle,
+                    &mut pb,
+                )
+                .await?
+            }
+            Stage::Disassembly => {
+                process_disassembly(connection, res_dir, debug_dirs, asm_syntax, &mut pb).await?
+            }
+            Stage::Views => rebuild_views(connection, &info, views).await?,
+        }
+        mark_completed(connection, stage)?;
+    }
+    Ok(())
+}
+
+/// Drops and recreates every view and derived table.
+pub(crate) async fn rebuild_views(
+    connection: &sqlite::Connection,
+    info: &RecordInfo,
+    views: &ViewOptions,
+) -> Result<()> {
+    drop_views(connection)?;
+    if schema::table_exists(connection, "assembly_samples")? {
+        create_assembly_stats_view(connection)?;
+        create_instruction_mix_view(connection)?;
+    }
+    create_views(connection, info, views).await
+}
+
+/// Re-runs postprocessing of a finished recording. With `views_only` the raw
+/// tables in `perf.db` are kept and only the views and derived tables are
+/// rebuilt, without re-reading `events.bin`. With `resume` a `perf.db` left
+/// behind by a failed run is completed from the stage that failed.
+pub async fn do_postprocess(
+    res_dir: &Path,
+    views_only: bool,
+    force: bool,
+    resume: bool,
+    debug_dirs: &[PathBuf],
+    asm_syntax: AsmSyntax,
+    views: &ViewOptions,
+) -> Result<()> {
+    let db_path = res_dir.join("perf.db");
+    if resume {
+        if !db_path.exists() {
+            anyhow::bail!(
+                "{} does not exist; there is nothing to resume",
+                db_path.display()
             );
-            CREATE TABLE strings (id BINARY(128) NOT NULL, string TEXT NOT NULL);
-        ",
-    )?;
+        }
+        if !is_incomplete(&db_path)? {
+            tracing::info!("{} is already postprocessed", db_path.display());
+            return Ok(());
+        }
+        tracing::info!("resuming postprocessing of {}", db_path.display());
+    } else if views_only && !db_path.exists() {
+        anyhow::bail!(
+            "{} does not exist; run postprocess without --views-only first",
+            db_path.display()
+        );
+    }
+    if db_path.exists() && !force && !resume {
+        anyhow::bail!(
+            "{} already exists; pass --force to rebuild it",
+            db_path.display()
+        );
+    }
+
+    let data = fs::read_to_string(res_dir.join("info.json")).await?;
+    let info: RecordInfo = serde_json::from_str(&data)?;
+    if views_only {
+        let connection = schema::open(&db_path)?;
+        rebuild_views(&connection, &info, views).await?;
+        tracing::info!("rebuilt the views of {}", db_path.display());
+        return Ok(());
+    }
 
-    process_strings(&connection, res_dir).await?;
+    if !resume {
+        std::fs::remove_file(&db_path).or_else(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(error),
+        })?;
+    }
+    kdam::term::init(false);
+    kdam::term::hide_cursor()?;
+    let pb = kdam::tqdm!(
+        total = 100,
+        disable = !tracing::enabled!(tracing::Level::INFO)
+    );
+    // Directories given now are searched before the ones given to `record`.
+    let mut debug_dirs = debug_dirs.to_vec();
+    for dir in info.debug_dirs {
+        if !debug_dirs.contains(&dir) {
+            debug_dirs.push(dir);
+        }
+    }
+    let connection = sqlite::open(&db_path)?;
+    perform_postprocessing(&connection, res_dir, pb, &debug_dirs, asm_syntax, views).await?;
+    kdam::term::show_cursor()?;
+    Ok(())
+}
 
+/// Creates the views and derived tables computed from the raw tables.
+async fn create_views(
+    connection: &sqlite::Connection,
+    info: &RecordInfo,
+    views: &ViewOptions,
+) -> Result<()> {
     match info.scenario {
         Scenario::Snapshot => {
-            process_pmu_counters(&connection, &info.scenario_info, res_dir, &mut pb).await?;
-            process_disassembly(&connection, res_dir, &mut pb).await?;
-            create_hotspots_view(&connection).await?;
+            let totals = match &info.scenario_info {
+                ScenarioInfo::Snapshot(snapshot) => snapshot.counted_totals.as_ref(),
+                _ => None,
+            };
+            create_hotspots_view(connection, views, totals).await?;
+            create_module_hotspots_view(connection)?;
         }
         Scenario::Roofline => {
-            process_pmu_counters(&connection, &info.scenario_info, res_dir, &mut pb).await?;
-            process_disassembly(&connection, res_dir, &mut pb).await?;
-            create_hotspots_view(&connection).await?;
-            create_roofline_view(&connection).await?;
-        }
-        Scenario::TMA => {
-            process_pmu_counters(&connection, &info.scenario_info, res_dir, &mut pb).await?;
-            process_disassembly(&connection, res_dir, &mut pb).await?;
-            create_tma_view(&connection, &info.scenario_info).await?;
+            create_hotspots_view(connection, views, None).await?;
+            create_module_hotspots_view(connection)?;
+            create_roofline_view(connection, views.min_loop_duration).await?;
         }
+        Scenario::TMA => create_tma_view(connection, &info.scenario_info).await?,
     }
 
-    persist_derived_metrics(&connection)?;
+    create_thread_stats_view(connection)?;
+    create_memory_latency_view(connection)?;
+    persist_derived_metrics(connection)?;
+
+    Ok(())
+}
 
+/// Drops every view and the tables [`create_views`] derives from the raw
+/// data, so they can be created again.
+fn drop_views(connection: &sqlite::Connection) -> Result<()> {
+    let mut statement =
+        connection.prepare("SELECT name FROM sqlite_master WHERE type = 'view';")?;
+    let mut views = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        views.push(statement.read::<String, _>("name")?);
+    }
+    drop(statement);
+    for view in views {
+        connection.execute(format!("DROP VIEW {};", quote_identifier(&view)))?;
+    }
+    connection.execute(
+        "DROP TABLE IF EXISTS derived_metrics;
+         DROP TABLE IF EXISTS view_columns;
+         DROP TABLE IF EXISTS hotspot_estimates;
+         DROP TABLE IF EXISTS tma_intervals;
+         DROP TABLE IF EXISTS tma_summary;",
+    )?;
     Ok(())
 }
 
@@ -82,14 +361,7 @@ fn persist_metric_definitions(
 ) -> Result<()> {
     use sqlite::State;
 
-    connection.execute(
-        "CREATE TABLE IF NOT EXISTS derived_metrics (
-            name TEXT PRIMARY KEY,
-            value REAL NOT NULL,
-            unit TEXT,
-            expression TEXT NOT NULL
-        );",
-    )?;
+    connection.execute(schema::DERIVED_METRICS)?;
 
     for metric in metrics {
         let Ok(event_names) = metric.expression.event_names() else {
@@ -184,6 +456,267 @@ async fn process_strings(connection: &sqlite::Connection, res_dir: &Path) -> Res
     Ok(())
 }
 
+fn process_thread_names(connection: &sqlite::Connection, res_dir: &Path) -> Result<()> {
+    // Results recorded before PERF_RECORD_COMM support have no names.
+    let Ok(names_file) = std::fs::File::open(res_dir.join("thread_names.json")) else {
+        return Ok(());
+    };
+    let names: Vec<ThreadName> = serde_json::from_reader(names_file)?;
+
+    connection.execute("BEGIN IMMEDIATE TRANSACTION;")?;
+    let result = (|| -> Result<()> {
+        let mut statement = connection
+            .prepare("INSERT OR REPLACE INTO thread_names (pid, tid, name) VALUES (?, ?, ?);")?;
+        for entry in names {
+            statement.reset()?;
+            statement.bind((1, entry.pid as i64))?;
+            statement.bind((2, entry.tid as i64))?;
+            statement.bind((3, entry.name.as_str()))?;
+            statement.next()?;
+        }
+        Ok(())
+    })();
+    finish_transaction(connection, result)?;
+
+    Ok(())
+}
+
+fn read_thread_names(res_dir: &Path) -> Result<HashMap<(u32, u32), String>> {
+    let Ok(names_file) = std::fs::File::open(res_dir.join("thread_names.json")) else {
+        return Ok(HashMap::new());
+    };
+    let names: Vec<ThreadName> = serde_json::from_reader(names_file)?;
+    Ok(names
+        .into_iter()
+        .map(|entry| ((entry.pid, entry.tid), entry.name))
+        .collect())
+}
+
+/// Root frame of a thread in the per-thread flamegraph. `;` would split the
+/// frame, so thread names cannot keep it.
+fn thread_frame(name: Option<&String>, tid: u32) -> String {
+    match name {
+        Some(name) => format!("{} ({tid})", name.replace(';', ":")),
+        None => format!("[thread {tid}]"),
+    }
+}
+
+fn read_forks(res_dir: &Path) -> Result<Vec<ProcessFork>> {
+    // Recordings made before fork tracking have no process list.
+    let Ok(processes_file) = std::fs::File::open(res_dir.join("processes.json")) else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_reader(processes_file)?)
+}
+
+fn read_execs(res_dir: &Path) -> Result<Vec<ProcessExec>> {
+    // Recordings made before exec tracking have one image per process.
+    let Ok(execs_file) = std::fs::File::open(res_dir.join("execs.json")) else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_reader(execs_file)?)
+}
+
+/// Linux pids stay below `PID_MAX_LIMIT` (2^22), so the exec generation is
+/// kept in the bits above the pid.
+const IMAGE_GENERATION_SHIFT: u32 = 22;
+
+/// Key of the mappings of `pid` after `generation` execs. The first image is
+/// keyed by the pid itself.
+fn image_key(pid: u32, generation: u32) -> u32 {
+    pid | (generation.min(u32::MAX >> IMAGE_GENERATION_SHIFT) << IMAGE_GENERATION_SHIFT)
+}
+
+/// The image of a process a sample ran in, for processes that `exec` while
+/// recorded, e.g. a shell wrapper that `exec`s the real program.
+#[derive(Default)]
+struct ExecImages {
+    /// Sorted `exec` times of each process.
+    execs: HashMap<u32, Vec<u64>>,
+    /// Keys of the images with mappings.
+    mapped: HashSet<u32>,
+}
+
+impl ExecImages {
+    fn new(execs: &[ProcessExec], proc_map: &[ProcMapEntry]) -> Self {
+        let mut images = ExecImages::default();
+        for exec in execs {
+            images.execs.entry(exec.pid).or_default().push(exec.time);
+        }
+        for times in images.execs.values_mut() {
+            times.sort_unstable();
+        }
+        images.mapped = proc_map
+            .iter()
+            .map(|entry| image_key(entry.pid, entry.exec_generation))
+            .collect();
+        images
+    }
+
+    /// Number of execs `pid` made up to `time`.
+    fn generation(&self, pid: u32, time: u64) -> u32 {
+        self.execs.get(&pid).map_or(0, |times| {
+            times.partition_point(|exec| *exec <= time) as u32
+        })
+    }
+
+    /// Key of the image `pid` ran at `time`. Falls back to an earlier image
+    /// when the mappings of the current one were lost.
+    fn image(&self, pid: u32, time: u64) -> u32 {
+        let mut generation = self.generation(pid, time);
+        while generation > 0 && !self.mapped.contains(&image_key(pid, generation)) {
+            generation -= 1;
+        }
+        image_key(pid, generation)
+    }
+}
+
+fn process_forks(connection: &sqlite::Connection, res_dir: &Path) -> Result<()> {
+    let forks = read_forks(res_dir)?;
+
+    connection.execute("BEGIN IMMEDIATE TRANSACTION;")?;
+    let result = (|| -> Result<()> {
+        let mut statement = connection.prepare(
+            "INSERT OR REPLACE INTO processes (pid, ppid, fork_time, exit_time) VALUES (?, ?, ?, ?);",
+        )?;
+        for fork in forks {
+            statement.reset()?;
+            statement.bind((1, fork.pid as i64))?;
+            statement.bind((2, fork.ppid as i64))?;
+            statement.bind((3, sqlite_i64_from_u64(fork.fork_time)))?;
+            statement.bind((4, fork.exit_time.map(sqlite_i64_from_u64)))?;
+            statement.next()?;
+        }
+        Ok(())
+    })();
+    finish_transaction(connection, result)
+}
+
+fn process_command_lines(connection: &sqlite::Connection, res_dir: &Path) -> Result<()> {
+    // Recordings made before command lines were captured have none.
+    let Ok(command_lines_file) = std::fs::File::open(res_dir.join("cmdlines.json")) else {
+        return Ok(());
+    };
+    let command_lines: Vec<ProcessCommandLine> = serde_json::from_reader(command_lines_file)?;
+
+    connection.execute("BEGIN IMMEDIATE TRANSACTION;")?;
+    let result = (|| -> Result<()> {
+        let mut statement = connection
+            .prepare("INSERT OR REPLACE INTO process_cmdlines (pid, cmdline) VALUES (?, ?);")?;
+        for command_line in command_lines {
+            statement.reset()?;
+            statement.bind((1, command_line.pid as i64))?;
+            statement.bind((2, command_line.args.join(" ").as_str()))?;
+            statement.next()?;
+        }
+        Ok(())
+    })();
+    finish_transaction(connection, result)
+}
+
+/// A forked child starts with a copy of its parent's address space, but perf
+/// only reports the mappings the child creates itself. Give every child the
+/// parent mappings it has not replaced, in fork order so grandchildren see
+/// what their parent inherited. The parent's image is the one it ran when it
+/// forked; the child's first image is the copy.
+fn inherit_proc_maps(
+    mut proc_map: Vec<ProcMapEntry>,
+    forks: &[ProcessFork],
+    execs: &[ProcessExec],
+) -> Vec<ProcMapEntry> {
+    let images = ExecImages::new(execs, &[]);
+    let mut forks = forks.iter().collect::<Vec<_>>();
+    forks.sort_unstable_by_key(|fork| fork.fork_time);
+
+    for fork in forks {
+        let parent_generation = images.generation(fork.ppid, fork.fork_time);
+        let own = proc_map
+            .iter()
+            .filter(|entry| entry.pid == fork.pid && entry.exec_generation == 0)
+            .map(|entry| entry.address..entry.address + entry.size)
+            .collect::<Vec<_>>();
+        let inherited = proc_map
+            .iter()
+            .filter(|entry| entry.pid == fork.ppid && entry.exec_generation == parent_generation)
+            .filter(|entry| {
+                let end = entry.address + entry.size;
+                !own.iter()
+                    .any(|range| range.start < end && entry.address < range.end)
+            })
+            .map(|entry| ProcMapEntry {
+                pid: fork.pid,
+                exec_generation: 0,
+                ..entry.clone()
+            })
+            .collect::<Vec<_>>();
+        proc_map.extend(inherited);
+    }
+
+    proc_map
+}
+
+/// Per-thread sample breakdown with the recorded thread names.
+pub(crate) fn create_thread_stats_view(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(
+        "CREATE VIEW thread_stats AS
+         SELECT pmu_counters.process_id AS pid,
+                pmu_counters.thread_id AS tid,
+                thread_names.name AS name,
+                COUNT(*) AS samples,
+                SUM(pmu_counters.pmu_cycles) AS cycles,
+                SUM(pmu_counters.pmu_cycles) * 1.0 /
+                    NULLIF((SELECT SUM(pmu_cycles) FROM pmu_counters), 0) AS total
+         FROM pmu_counters
+         LEFT JOIN thread_names
+           ON thread_names.pid = pmu_counters.process_id
+          AND thread_names.tid = pmu_counters.thread_id
+         GROUP BY pmu_counters.process_id, pmu_counters.thread_id;",
+    )?;
+    Ok(())
+}
+
+/// Samples grouped by the module they hit, such as the binary or `libc`.
+/// `module` is the file name of `module_path`.
+pub(crate) fn create_module_hotspots_view(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(
+        "CREATE VIEW module_hotspots AS
+         SELECT COALESCE(proc_map.module_path, '[unknown]') AS module_path,
+                COALESCE(NULLIF(REPLACE(proc_map.module_path,
+                    RTRIM(proc_map.module_path, REPLACE(proc_map.module_path, '/', '')), ''), ''),
+                    proc_map.module_path, '[unknown]') AS module,
+                SUM(pmu_counters.pmu_cycles) * 1.0 /
+                    NULLIF((SELECT SUM(pmu_cycles) FROM pmu_counters), 0) AS total,
+                COUNT(*) AS samples,
+                SUM(pmu_counters.pmu_cycles) AS cycles,
+                SUM(pmu_counters.pmu_instructions) AS instructions,
+                SUM(pmu_counters.pmu_instructions) * 1.0 /
+                    NULLIF(SUM(pmu_counters.pmu_cycles), 0) AS ipc
+         FROM pmu_counters
+         INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
+         GROUP BY proc_map.module_path;",
+    )?;
+    Ok(())
+}
+
+/// Functions ranked by the latency of their sampled loads. Empty unless the
+/// recording used `--mem-latency`.
+pub(crate) fn create_memory_latency_view(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(
+        "CREATE VIEW IF NOT EXISTS memory_latency AS
+         SELECT proc_map.func_name AS func_name,
+                proc_map.module_path AS module_path,
+                COUNT(*) AS loads,
+                AVG(pmu_counters.latency) AS avg_latency,
+                MAX(pmu_counters.latency) AS max_latency,
+                SUM(pmu_counters.latency) AS total_latency
+         FROM pmu_counters
+         INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
+         WHERE pmu_counters.latency IS NOT NULL
+         GROUP BY proc_map.func_name;",
+    )?;
+    Ok(())
+}
+
 fn finish_transaction(connection: &sqlite::Connection, result: Result<()>) -> Result<()> {
     match result {
         Ok(()) => {
@@ -213,7 +746,10 @@ struct CounterLead {
     time_enabled: u64,
     time_running: u64,
     timestamp: u64,
+    /// `u32::MAX` when the sample did not report its CPU.
+    cpu: u32,
     callstack: SmallVec<[CallFrame; 32]>,
+    weight: u64,
 }
 
 #[derive(Clone)]
@@ -340,10 +876,22 @@ impl RooflineData {
     }
 }
 
+/// `pmu_counters` columns the snapshot and roofline views depend on.
+const VIEW_COLUMNS: [&str; 6] = [
+    "pmu_cycles",
+    "pmu_instructions",
+    "pmu_branch_instructions",
+    "pmu_branch_misses",
+    "pmu_llc_misses",
+    "pmu_llc_references",
+];
+
 async fn process_pmu_counters(
     connection: &sqlite::Connection,
     info: &ScenarioInfo,
     res_dir: &Path,
+    debug_dirs: &[PathBuf],
+    idle: &IdleFilter,
     pb: &mut kdam::Bar,
 ) -> Result<()> {
     let events = match info {
@@ -372,28 +920,22 @@ async fn process_pmu_counters(
         .map(|column| format!("{} INTEGER{}", quote_identifier(column), default_value))
         .collect::<Vec<_>>()
         .join(", ");
-    let event_schema = if str_events.is_empty() {
+    let mut event_schema = if str_events.is_empty() {
         String::new()
     } else {
         format!(", {str_events}")
     };
+    // The hotspots and assembly views read these columns. Recordings made with
+    // `--events` may lack some; those stay NULL rather than breaking the views.
+    if !matches!(info, ScenarioInfo::TMA(_)) {
+        for column in VIEW_COLUMNS {
+            if !event_columns.iter().any(|event| event == column) {
+                event_schema.push_str(&format!(", {column} INTEGER"));
+            }
+        }
+    }
 
-    connection.execute(format!(
-        "
-            CREATE TABLE pmu_counters (
-                unique_id BINARY(128),
-                process_id INTEGER NOT NULL,
-                thread_id INTEGER NOT NULL,
-                time_enabled INTEGER NOT NULL,
-                time_running INTEGER NOT NULL,
-                confidence REAL NOT NULL,
-                timestamp INTEGER NOT NULL,
-                ip INTEGER NOT NULL,
-                call_stack TEXT{}
-            );
-        ",
-        event_schema
-    ))?;
+    connection.execute(schema::pmu_counters(&event_schema))?;
 
     let mut roofline = RooflineData::new(info);
     if roofline.is_some() {
@@ -420,8 +962,32 @@ async fn process_pmu_counters(
 
     let proc_map_file = std::fs::File::open(res_dir.join("proc_map.json"))?;
     let proc_map: Vec<ProcMapEntry> = serde_json::from_reader(proc_map_file)?;
+    let execs = read_execs(res_dir)?;
+    let proc_map = inherit_proc_maps(proc_map, &read_forks(res_dir)?, &execs);
+    let images = ExecImages::new(&execs, &proc_map);
+    // JITs name their perf map after the pid, so every image of a process
+    // shares it. Recordings made before perf maps were saved with them fall
+    // back to the ones still in /tmp.
+    let perf_maps = proc_map
+        .iter()
+        .filter_map(|entry| {
+            let name = utils::perf_map_file_name(entry.pid);
+            let path = [res_dir.join(&name), Path::new("/tmp").join(&name)]
+                .into_iter()
+                .find(|path| path.exists())?;
+            Some((image_key(entry.pid, entry.exec_generation), path))
+        })
+        .collect::<HashMap<_, _>>();
+    // The symbolizer and the unwinder look mappings up by image, not pid.
+    let proc_map = proc_map
+        .into_iter()
+        .map(|entry| ProcMapEntry {
+            pid: image_key(entry.pid, entry.exec_generation),
+            ..entry
+        })
+        .collect::<Vec<_>>();
 
-    let resolved_pm = utils::resolve_proc_maps(&proc_map);
+    let resolved_pm = utils::resolve_proc_maps(&proc_map, debug_dirs, &perf_maps);
     #[cfg(all(
         target_os = "linux",
         any(target_arch = "x86_64", target_arch = "aarch64")
@@ -442,6 +1008,10 @@ async fn process_pmu_counters(
 
     let mut known_ips = HashSet::<u64>::new();
     let mut resolved_ips = HashMap::<(u32, u64), ResolvedIp>::new();
+    let mut coverage = MappingCoverage::default();
+    // Correlation id of the last sample dropped for lack of mappings, so a
+    // sample is counted once rather than once per counter.
+    let mut last_dropped = None;
 
     // Core-cluster topology, used to attribute samples per core on
     // heterogeneous (big.LITTLE) systems. Empty on homogeneous hosts.
@@ -456,6 +1026,19 @@ async fn process_pmu_counters(
 
     let mut flamegraph_cycles = HashMap::<String, u64>::new();
     let mut flamegraph_instructions = HashMap::<String, u64>::new();
+    let mut flamegraph_page_faults = HashMap::<String, u64>::new();
+    // Cycles stacks under a root frame per thread.
+    let mut flamegraph_threads = HashMap::<String, u64>::new();
+    // Cycles stacks with the CPU each sample ran on as the leaf frame.
+    let mut flamegraph_cpus = HashMap::<String, u64>::new();
+    // Cycles and instructions counted since the previous sample of each
+    // stack, not sample counts, so their ratio is the IPC of a frame.
+    let mut counted_cycles = HashMap::<String, u64>::new();
+    let mut counted_instructions = HashMap::<String, u64>::new();
+    let thread_names = read_thread_names(res_dir)?;
+    // (thread, timestamp, folded stack) of every cycles sample, for the
+    // time-weighted flamegraph.
+    let mut timed_stacks = Vec::<(u32, u64, String)>::new();
     // family_id -> (display name, folded stack -> value)
     let mut per_core_cycles = HashMap::<String, (String, HashMap<String, u64>)>::new();
     let mut per_core_instructions = HashMap::<String, (String, HashMap<String, u64>)>::new();
@@ -470,13 +1053,13 @@ async fn process_pmu_counters(
     } else {
         format!(", {insert_columns}")
     };
-    let placeholders = std::iter::repeat_n("?", 9 + event_columns.len())
+    let placeholders = std::iter::repeat_n("?", 11 + event_columns.len())
         .collect::<Vec<_>>()
         .join(", ");
     let mut counter_stmt = connection.prepare(format!(
         "INSERT INTO pmu_counters (
             unique_id, process_id, thread_id, time_enabled, time_running,
-            confidence, timestamp, ip, call_stack{insert_columns}
+            confidence, timestamp, ip, call_stack, latency, cpu{insert_columns}
          ) VALUES ({placeholders});"
     ))?;
 
@@ -507,20 +1090,48 @@ async fn process_pmu_counters(
                 continue;
             }
 
-            if !evt.ty.is_pmu() && !evt.ty.is_os() {
+            let is_page_fault = evt.ty == EventType::OsPageFaultSample;
+            if !evt.ty.is_pmu() && !evt.ty.is_os() && !is_page_fault {
                 continue;
             }
 
+            let image = images.image(evt.process_id, evt.timestamp);
             #[cfg(all(
                 target_os = "linux",
                 any(target_arch = "x86_64", target_arch = "aarch64")
             ))]
-            post_hoc_unwinder.unwind_event(&mut evt);
+            post_hoc_unwinder.unwind_event(&mut evt, image);
+
+            if !resolved_pm.has_process(image) {
+                if !is_page_fault && last_dropped != Some(evt.correlation_id) {
+                    last_dropped = Some(evt.correlation_id);
+                    coverage.samples += 1;
+                    coverage.unmapped += 1;
+                }
+                continue;
+            }
 
-            if !resolved_pm.has_process(evt.process_id) {
+            // Page-fault samples come from a driver of their own and only
+            // feed the page-fault flamegraph, never the counter groups.
+            if is_page_fault {
+                let stack = idle.fold(resolve_folded_stack(
+                    &resolved_pm,
+                    &mut resolved_ips,
+                    image,
+                    &evt.callstack,
+                ));
+                if !stack.is_empty() {
+                    if let Some(weight) = flamegraph_sample_weight(evt.value) {
+                        *flamegraph_page_faults.entry(stack).or_default() += weight;
+                    }
+                }
                 continue;
             }
 
+            // A sampling driver emits one event per counter of a hardware
+            // sample, back to back and with the sample's id as correlation
+            // id; they fill one `pmu_counters` row. Events of other drivers
+            // may land in between and were skipped above.
             let is_new_group = lead_event
                 .as_ref()
                 .is_none_or(|lead| evt.correlation_id != lead.correlation_id);
@@ -536,12 +1147,20 @@ async fn process_pmu_counters(
                     counters.clear();
                 }
 
-                folded_stack = resolve_folded_stack(
+                folded_stack = idle.fold(resolve_folded_stack(
                     &resolved_pm,
                     &mut resolved_ips,
-                    evt.process_id,
+                    image,
                     &evt.callstack,
-                );
+                ));
+
+                coverage.samples += 1;
+                if let Some(CallFrame::IP(ip)) = evt.callstack.first() {
+                    let resolved = resolve_ip(&resolved_pm, &mut resolved_ips, image, *ip);
+                    if resolved.module_path.is_none() && resolved.function == "[unknown]" {
+                        coverage.unmapped += 1;
+                    }
+                }
 
                 for frame in &evt.callstack {
                     let CallFrame::IP(ip) = frame else {
@@ -550,9 +1169,9 @@ async fn process_pmu_counters(
                     if !known_ips.insert(*ip) {
                         continue;
                     }
-                    let resolved = resolve_ip(&resolved_pm, &mut resolved_ips, evt.process_id, *ip);
+                    let resolved = resolve_ip(&resolved_pm, &mut resolved_ips, image, *ip);
                     proc_map_stmt.reset()?;
-                    proc_map_stmt.bind((1, *ip as i64))?;
+                    proc_map_stmt.bind((1, sqlite_i64_from_u64(*ip)))?;
                     proc_map_stmt.bind((2, resolved.function.as_str()))?;
                     proc_map_stmt.bind((3, resolved.file.as_str()))?;
                     proc_map_stmt.bind((4, resolved.line as i64))?;
@@ -568,7 +1187,9 @@ async fn process_pmu_counters(
                     time_enabled: evt.time_enabled,
                     time_running: evt.time_running,
                     timestamp: evt.timestamp,
+                    cpu: evt.cpu,
                     callstack: evt.callstack.clone(),
+                    weight: evt.weight,
                 });
             }
 
@@ -580,6 +1201,21 @@ async fn process_pmu_counters(
             if evt.ty == EventType::PmuCycles && !folded_stack.is_empty() {
                 if let Some(weight) = flamegraph_sample_weight(evt.value) {
                     *flamegraph_cycles.entry(folded_stack.clone()).or_default() += weight;
+                    let counted = counted_cycles.entry(folded_stack.clone()).or_default();
+                    *counted = counted.saturating_add(evt.value);
+                    let thread = thread_frame(
+                        thread_names.get(&(evt.process_id, evt.thread_id)),
+                        evt.thread_id,
+                    );
+                    *flamegraph_threads
+                        .entry(format!("{thread};{folded_stack}"))
+                        .or_default() += weight;
+                    if evt.cpu != u32::MAX {
+                        *flamegraph_cpus
+                            .entry(format!("{folded_stack};[cpu {}]", evt.cpu))
+                            .or_default() += weight;
+                    }
+                    timed_stacks.push((evt.thread_id, evt.timestamp, folded_stack.clone()));
                     if let Some((family_id, name)) = cluster_of(&clusters, evt.cpu) {
                         *per_core_cycles
                             .entry(family_id.to_owned())
@@ -594,6 +1230,10 @@ async fn process_pmu_counters(
                     *flamegraph_instructions
                         .entry(folded_stack.clone())
                         .or_default() += weight;
+                    let counted = counted_instructions
+                        .entry(folded_stack.clone())
+                        .or_default();
+                    *counted = counted.saturating_add(evt.value);
                     if let Some((family_id, name)) = cluster_of(&clusters, evt.cpu) {
                         *per_core_instructions
                             .entry(family_id.to_owned())
@@ -629,8 +1269,52 @@ async fn process_pmu_counters(
     drop(proc_map_stmt);
     finish_transaction(connection, result)?;
 
+    coverage.persist(connection)?;
+    if let Some(warning) = coverage.warning() {
+        tracing::warn!("{warning}");
+    }
+
+    // Reversed stacks put leaves at the root, so a callee such as `malloc`
+    // merges into one frame whose children are its callers.
+    write_flamegraph(
+        res_dir,
+        "flamegraph_cycles_reversed",
+        reverse_folded_stacks(&flamegraph_cycles),
+    )
+    .await?;
+    write_flamegraph(
+        res_dir,
+        "flamegraph_instructions_reversed",
+        reverse_folded_stacks(&flamegraph_instructions),
+    )
+    .await?;
     write_flamegraph(res_dir, "flamegraph_cycles", flamegraph_cycles).await?;
+    write_flamegraph(res_dir, "flamegraph_cycles_threads", flamegraph_threads).await?;
+    // Platforms that do not report the CPU of a sample have none.
+    if !flamegraph_cpus.is_empty() {
+        write_flamegraph(res_dir, "flamegraph_cycles_cpus", flamegraph_cpus).await?;
+    }
     write_flamegraph(res_dir, "flamegraph_instructions", flamegraph_instructions).await?;
+    let flamegraph_walltime = time_weighted_stacks(timed_stacks);
+    if !flamegraph_walltime.is_empty() {
+        write_time_flamegraph(
+            res_dir,
+            "flamegraph_walltime_reversed",
+            reverse_folded_stacks(&flamegraph_walltime),
+        )
+        .await?;
+        write_time_flamegraph(res_dir, "flamegraph_walltime", flamegraph_walltime).await?;
+    }
+    // Only recordings made with `--page-faults` have these samples.
+    if !flamegraph_page_faults.is_empty() {
+        write_flamegraph(res_dir, "flamegraph_pagefaults", flamegraph_page_faults).await?;
+    }
+    // Without both counters in the sampling groups there is no IPC to show.
+    // Only the TUI reads these, so they get no SVG.
+    if !counted_cycles.is_empty() && !counted_instructions.is_empty() {
+        write_folded_stacks(res_dir, "flamegraph_ipc_cycles", counted_cycles).await?;
+        write_folded_stacks(res_dir, "flamegraph_ipc_instructions", counted_instructions).await?;
+    }
 
     // Per-core flamegraphs on heterogeneous systems, e.g.
     // `flamegraph_cycles_cortex_a720.folded`.
@@ -649,7 +1333,59 @@ async fn process_pmu_counters(
     Ok(())
 }
 
-fn quote_identifier(identifier: &str) -> String {
+/// Share of unmapped samples above which the profile is called out as
+/// unreliable.
+const UNMAPPED_WARNING_SHARE: f64 = 0.1;
+
+/// Samples whose instruction pointer fell outside every mapping recorded for
+/// their process. They show up as `[unknown]`, or not at all when the process
+/// has no mappings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MappingCoverage {
+    pub(crate) samples: u64,
+    pub(crate) unmapped: u64,
+}
+
+impl MappingCoverage {
+    pub(crate) fn persist(&self, connection: &sqlite::Connection) -> Result<()> {
+        let mut statement =
+            connection.prepare("INSERT INTO sample_coverage (samples, unmapped) VALUES (?, ?);")?;
+        statement.bind((1, sqlite_i64_from_u64(self.samples)))?;
+        statement.bind((2, sqlite_i64_from_u64(self.unmapped)))?;
+        statement.next()?;
+        Ok(())
+    }
+
+    /// Reads what postprocessing recorded. `None` for databases written
+    /// before it was.
+    pub(crate) fn load(connection: &sqlite::Connection) -> Option<Self> {
+        let mut statement = connection
+            .prepare("SELECT samples, unmapped FROM sample_coverage;")
+            .ok()?;
+        match statement.next().ok()? {
+            sqlite::State::Row => Some(MappingCoverage {
+                samples: sqlite_u64_from_i64(statement.read::<i64, _>("samples").ok()?),
+                unmapped: sqlite_u64_from_i64(statement.read::<i64, _>("unmapped").ok()?),
+            }),
+            sqlite::State::Done => None,
+        }
+    }
+
+    /// Explains why the profile is unreliable when too many samples are
+    /// unmapped.
+    pub(crate) fn warning(&self) -> Option<String> {
+        let share = self.unmapped as f64 / self.samples.max(1) as f64;
+        (share > UNMAPPED_WARNING_SHARE).then(|| {
+            format!(
+                "{:.0}% of samples fell outside every known mapping ([unknown]); likely JIT \
+                 code without a perf map, code mapped after the maps were read, or a late attach",
+                share * 100.0
+            )
+        })
+    }
+}
+
+pub(crate) fn quote_identifier(identifier: &str) -> String {
     format!("\"{}\"", identifier.replace('"', "\"\""))
 }
 
@@ -662,6 +1398,12 @@ fn resolve_ip<'a>(
     cache.entry((pid, ip)).or_insert_with(|| {
         let frames = resolver.resolve(pid, ip);
         let functions = if frames.is_empty() {
+            tracing::debug!(
+                "no symbol for pid {pid} ip {ip:#x} (module {})",
+                resolver
+                    .module_path(pid, ip)
+                    .map_or_else(|| "unmapped".into(), |path| path.to_string_lossy())
+            );
             vec!["[unknown]".to_owned()]
         } else {
             frames.iter().map(|frame| frame.function.clone()).collect()
@@ -709,22 +1451,7 @@ fn resolve_folded_stack(
 }
 
 fn create_roofline_tables(connection: &sqlite::Connection) -> Result<()> {
-    connection.execute(
-        "
-        CREATE TABLE roofline_ops(
-            unique_id BINARY(128), process_id INTEGER NOT NULL, thread_id INTEGER NOT NULL,
-            file_name BINARY(128) NOT NULL, function_name BINARY(128) NOT NULL,
-            line INTEGER NOT NULL, bytes_load INTEGER NOT NULL, bytes_store INTEGER NOT NULL,
-            scalar_int_ops INTEGER NOT NULL, scalar_float_ops INTEGER NOT NULL,
-            scalar_double_ops INTEGER NOT NULL, vector_int_ops INTEGER NOT NULL,
-            vector_float_ops INTEGER NOT NULL, vector_double_ops INTEGER NOT NULL
-        );
-        CREATE TABLE roofline_loop_runs(
-            unique_id BINARY(128), process_id INTEGER NOT NULL, thread_id INTEGER NOT NULL,
-            file_name BINARY(128) NOT NULL, function_name BINARY(128) NOT NULL,
-            line INTEGER NOT NULL, loop_start_ts INTEGER NOT NULL, loop_end_ts INTEGER NOT NULL
-        );",
-    )?;
+    connection.execute(schema::ROOFLINE_TABLES)?;
     Ok(())
 }
 
@@ -743,8 +1470,8 @@ fn persist_roofline_data(connection: &sqlite::Connection, data: RooflineData) ->
         run_stmt.bind((4, run.file_name as f64))?;
         run_stmt.bind((5, run.func_name as f64))?;
         run_stmt.bind((6, run.line as i64))?;
-        run_stmt.bind((7, run.start as i64))?;
-        run_stmt.bind((8, end as i64))?;
+        run_stmt.bind((7, sqlite_i64_from_u64(run.start)))?;
+        run_stmt.bind((8, sqlite_i64_from_u64(end)))?;
         run_stmt.next()?;
     }
 
@@ -776,7 +1503,7 @@ fn persist_roofline_data(connection: &sqlite::Connection, data: RooflineData) ->
         .into_iter()
         .enumerate()
         {
-            ops_stmt.bind((7 + index, value as i64))?;
+            ops_stmt.bind((7 + index, sqlite_i64_from_u64(value)))?;
         }
         ops_stmt.next()?;
     }
@@ -823,22 +1550,30 @@ fn insert_counter_group(
     statement.bind((1, lead_event.unique_id as f64))?;
     statement.bind((2, lead_event.process_id as i64))?;
     statement.bind((3, lead_event.thread_id as i64))?;
-    statement.bind((4, lead_event.time_enabled as i64))?;
-    statement.bind((5, lead_event.time_running as i64))?;
+    statement.bind((4, sqlite_i64_from_u64(lead_event.time_enabled)))?;
+    statement.bind((5, sqlite_i64_from_u64(lead_event.time_running)))?;
     statement.bind((6, confidence))?;
-    statement.bind((7, lead_event.timestamp as i64))?;
+    statement.bind((7, sqlite_i64_from_u64(lead_event.timestamp)))?;
     statement.bind((
         8,
-        lead_event.callstack.first().map(|f| f.as_ip()).unwrap_or(0) as i64,
+        sqlite_i64_from_u64(lead_event.callstack.first().map(|f| f.as_ip()).unwrap_or(0)),
     ))?;
     statement.bind((9, call_stack.as_str()))?;
+    statement.bind((
+        10,
+        (lead_event.weight > 0).then(|| sqlite_i64_from_u64(lead_event.weight)),
+    ))?;
+    statement.bind((
+        11,
+        (lead_event.cpu != u32::MAX).then_some(lead_event.cpu as i64),
+    ))?;
     for (offset, column) in event_columns.iter().enumerate() {
         let value = counters
             .get(column)
             .copied()
-            .map(|value| value as i64)
+            .map(sqlite_i64_from_u64)
             .or_else(|| (!missing_is_null).then_some(0));
-        statement.bind((10 + offset, value))?;
+        statement.bind((12 + offset, value))?;
     }
     statement.next()?;
     Ok(())
@@ -851,8 +1586,7 @@ fn create_tma_intervals_and_summary(
     connection: &sqlite::Connection,
     info: &mperf_data::TMAInfo,
 ) -> Result<()> {
-    connection.execute("CREATE TABLE tma_intervals (start_ns INTEGER NOT NULL, metric TEXT NOT NULL, value REAL);
-                        CREATE TABLE tma_summary (metric TEXT PRIMARY KEY, value REAL, verdict TEXT);")?;
+    connection.execute(schema::TMA_TABLES)?;
     for metric in &info.metrics {
         let expression = pmu_data::arith_parser::try_parse_expr(&metric.formula)
             .map_err(|error| anyhow::anyhow!("invalid TMA formula '{}': {error}", metric.name))?;
@@ -902,11 +1636,44 @@ fn counter_group_has_profile_data(
     !lead_event.callstack.is_empty() && counters.values().any(|value| *value != 0)
 }
 
+/// Results of `scenario_info` recorded on a homogeneous test host, for tests
+/// that need an `info.json` or a `RecordInfo`.
+#[cfg(test)]
+pub(crate) fn test_record_info(scenario_info: ScenarioInfo) -> RecordInfo {
+    RecordInfo {
+        format_version: mperf_data::CURRENT_FORMAT_VERSION,
+        scenario: match scenario_info {
+            ScenarioInfo::Snapshot(_) => Scenario::Snapshot,
+            ScenarioInfo::Roofline(_) => Scenario::Roofline,
+            ScenarioInfo::TMA(_) => Scenario::TMA,
+        },
+        command: None,
+        cpu_model: "test".to_string(),
+        cpu_vendor: "test".to_string(),
+        cores: Vec::new(),
+        output_directory: None,
+        mmap_pages: None,
+        sample_cpus: Vec::new(),
+        debug_dirs: Vec::new(),
+        target: None,
+        frequency_policies: Vec::new(),
+        sample_clock: Default::default(),
+        scenario_info,
+    }
+}
+
 #[cfg(test)]
 mod counter_group_tests {
-    use super::{counter_group_has_profile_data, CounterLead};
-    use mperf_data::CallFrame;
+    use super::{
+        counter_group_has_profile_data, process_pmu_counters, test_record_info, CounterLead,
+        IdleFilter, MappingCoverage,
+    };
+    use crate::{schema, sqlite_int::sqlite_u64_from_i64};
+    use mperf_data::{
+        CallFrame, Event, EventType, ProcMapEntry, ProcessExec, ScenarioInfo, SnapshotInfo,
+    };
     use smallvec::SmallVec;
+    use sqlite::State;
     use std::collections::HashMap;
 
     fn event(callstack: SmallVec<[CallFrame; 32]>) -> CounterLead {
@@ -918,7 +1685,9 @@ mod counter_group_tests {
             time_enabled: 1,
             time_running: 1,
             timestamp: 1,
+            cpu: 0,
             callstack,
+            weight: 0,
         }
     }
 
@@ -942,11 +1711,230 @@ mod counter_group_tests {
             &counters
         ));
     }
+
+    /// A counter event of the hardware sample `correlation_id`.
+    fn sample(ty: EventType, correlation_id: u128, value: u64) -> Event {
+        Event {
+            unique_id: uuid::Uuid::now_v7().as_u128(),
+            correlation_id,
+            parent_id: 0,
+            ty,
+            thread_id: 1,
+            process_id: 1,
+            cpu: 0,
+            time_enabled: 10,
+            time_running: 10,
+            value,
+            timestamp: correlation_id as u64,
+            name: 0,
+            callstack: SmallVec::from_slice(&[CallFrame::IP(0x1010)]),
+            user_regs: None,
+            user_stack: Vec::new(),
+            weight: 0,
+        }
+    }
+
+    fn mapping(filename: &str, exec_generation: u32) -> ProcMapEntry {
+        ProcMapEntry {
+            filename: filename.to_string(),
+            address: 0x1000,
+            size: 0x1000,
+            offset: 0,
+            pid: 1,
+            build_id: None,
+            exec_generation,
+        }
+    }
+
+    /// Runs `process_pmu_counters` over a snapshot recording of `events`.
+    async fn process(
+        events: &[Event],
+        mappings: &[ProcMapEntry],
+        execs: &[ProcessExec],
+    ) -> sqlite::Connection {
+        let dir = std::env::temp_dir().join(format!("mperf-groups-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+        let info = ScenarioInfo::Snapshot(SnapshotInfo {
+            pid: 1,
+            attached_pid: None,
+            memory_latency: false,
+            counters: vec![
+                (EventType::PmuCycles, String::new()),
+                (EventType::PmuInstructions, String::new()),
+                (EventType::PmuBranchMisses, String::new()),
+            ],
+            sampling_leader: None,
+            counted_totals: None,
+        });
+        std::fs::write(
+            dir.join("info.json"),
+            serde_json::to_string(&test_record_info(info.clone())).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.join("strings.json"), "[]").unwrap();
+        std::fs::write(
+            dir.join("proc_map.json"),
+            serde_json::to_string(mappings).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("execs.json"),
+            serde_json::to_string(execs).unwrap(),
+        )
+        .unwrap();
+        let mut binary = Vec::new();
+        for event in events {
+            event.write_binary(&mut binary).unwrap();
+        }
+        std::fs::write(dir.join("events.bin"), binary).unwrap();
+
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        let mut pb = kdam::tqdm!(disable = true);
+        process_pmu_counters(
+            &connection,
+            &info,
+            &dir,
+            &[],
+            &IdleFilter::default(),
+            &mut pb,
+        )
+        .await
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        connection
+    }
+
+    #[tokio::test]
+    async fn counters_of_one_sample_fill_one_row() {
+        // The driver emits one event per counter of a hardware sample, all
+        // with the sample's id as correlation id. A page-fault sample of
+        // another driver may land between them.
+        let events = [
+            sample(EventType::PmuCycles, 1, 100),
+            sample(EventType::PmuInstructions, 1, 200),
+            sample(EventType::OsPageFaultSample, 9, 1),
+            sample(EventType::PmuBranchMisses, 1, 3),
+            sample(EventType::PmuCycles, 2, 50),
+            sample(EventType::PmuInstructions, 2, 25),
+            sample(EventType::PmuBranchMisses, 2, 0),
+        ];
+        let connection = process(&events, &[mapping("/nonexistent/app", 0)], &[]).await;
+
+        let mut statement = connection
+            .prepare(
+                "SELECT pmu_cycles, pmu_instructions, pmu_branch_misses
+                 FROM pmu_counters ORDER BY timestamp",
+            )
+            .unwrap();
+        let mut rows = Vec::new();
+        while let State::Row = statement.next().unwrap() {
+            rows.push((
+                statement.read::<i64, _>("pmu_cycles").unwrap(),
+                statement.read::<i64, _>("pmu_instructions").unwrap(),
+                statement.read::<i64, _>("pmu_branch_misses").unwrap(),
+            ));
+        }
+        assert_eq!(rows, [(100, 200, 3), (50, 25, 0)]);
+    }
+
+    #[tokio::test]
+    async fn values_above_i64_max_are_stored_losslessly() {
+        const KERNEL_IP: u64 = 0xffff_ffff_8100_0000;
+        let with_kernel_ip = |mut event: Event| {
+            event.callstack = SmallVec::from_slice(&[CallFrame::IP(KERNEL_IP)]);
+            event.timestamp = u64::MAX - 1;
+            event
+        };
+        let events = [
+            with_kernel_ip(sample(EventType::PmuCycles, 1, u64::MAX)),
+            with_kernel_ip(sample(EventType::PmuInstructions, 1, 1 << 63)),
+            with_kernel_ip(sample(EventType::PmuBranchMisses, 1, 1)),
+        ];
+        let connection = process(&events, &[mapping("/nonexistent/app", 0)], &[]).await;
+
+        // The kernel address still joins its proc_map entry.
+        let mut statement = connection
+            .prepare(
+                "SELECT pmu_counters.ip, timestamp, pmu_cycles, pmu_instructions
+                 FROM pmu_counters JOIN proc_map ON proc_map.ip = pmu_counters.ip",
+            )
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        let read = |column| sqlite_u64_from_i64(statement.read::<i64, _>(column).unwrap());
+        assert_eq!(read("ip"), KERNEL_IP);
+        assert_eq!(read("timestamp"), u64::MAX - 1);
+        assert_eq!(read("pmu_cycles"), u64::MAX);
+        assert_eq!(read("pmu_instructions"), 1 << 63);
+    }
+
+    #[tokio::test]
+    async fn samples_resolve_in_the_image_they_ran_in() {
+        // A wrapper `exec`s the real program at time 5, which maps its code
+        // over the wrapper's.
+        let at = |correlation_id, ip| Event {
+            callstack: SmallVec::from_slice(&[CallFrame::IP(ip)]),
+            ..sample(EventType::PmuCycles, correlation_id, 1)
+        };
+        let connection = process(
+            &[at(1, 0x1010), at(9, 0x1020)],
+            &[mapping("/bin/sh", 0), mapping("/usr/bin/app", 1)],
+            &[ProcessExec { pid: 1, time: 5 }],
+        )
+        .await;
+
+        let mut statement = connection
+            .prepare("SELECT ip, module_path FROM proc_map ORDER BY ip")
+            .unwrap();
+        let mut modules = Vec::new();
+        while let State::Row = statement.next().unwrap() {
+            modules.push((
+                statement.read::<i64, _>("ip").unwrap(),
+                statement.read::<String, _>("module_path").unwrap(),
+            ));
+        }
+        assert_eq!(
+            modules,
+            [
+                (0x1010, "/bin/sh".to_string()),
+                (0x1020, "/usr/bin/app".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn samples_outside_every_mapping_are_counted() {
+        let at = |correlation_id, ip| Event {
+            callstack: SmallVec::from_slice(&[CallFrame::IP(ip)]),
+            ..sample(EventType::PmuCycles, correlation_id, 1)
+        };
+        // Process 2 has no mappings at all, as after a late attach.
+        let other_process = Event {
+            process_id: 2,
+            ..at(4, 0x1010)
+        };
+        let connection = process(
+            &[at(1, 0x1010), at(2, 0x1020), at(3, 0x9000), other_process],
+            &[mapping("/nonexistent/app", 0)],
+            &[],
+        )
+        .await;
+
+        let coverage = MappingCoverage::load(&connection).unwrap();
+        assert_eq!((coverage.samples, coverage.unmapped), (4, 2));
+        assert!(coverage.warning().unwrap().starts_with("50% of samples"));
+
+        let mostly_mapped = MappingCoverage {
+            samples: 100,
+            unmapped: 5,
+        };
+        assert_eq!(mostly_mapped.warning(), None);
+    }
 }
 
 /// Parse a sysfs cpumask list such as `"0,5-11"` into inclusive `(start, end)`
 /// ranges.
-fn parse_cpumask(mask: &str) -> Vec<(u32, u32)> {
+pub(crate) fn parse_cpumask(mask: &str) -> Vec<(u32, u32)> {
     mask.trim()
         .split(',')
         .filter_map(|part| {
@@ -976,19 +1964,54 @@ fn cluster_of(clusters: &[ClusterRanges], cpu: u32) -> Option<(&str, &str)> {
         .map(|(family_id, name, _)| (family_id.as_str(), name.as_str()))
 }
 
+/// Reverse the frame order of every folded stack.
+fn reverse_folded_stacks(map: &HashMap<String, u64>) -> HashMap<String, u64> {
+    map.iter()
+        .map(|(stack, value)| (stack.rsplit(';').collect::<Vec<_>>().join(";"), *value))
+        .collect()
+}
+
+/// Weighs each sampled stack by the time until the next sample of the same
+/// thread, in nanoseconds. A thread that blocks stops producing cycle
+/// samples, so the time it spends off-CPU goes to the stack sampled right
+/// before it blocked. The last sample of each thread has no successor and
+/// weighs nothing.
+fn time_weighted_stacks(mut samples: Vec<(u32, u64, String)>) -> HashMap<String, u64> {
+    samples.sort_unstable_by_key(|(thread, timestamp, _)| (*thread, *timestamp));
+    let mut map = HashMap::<String, u64>::new();
+    for pair in samples.windows(2) {
+        let [(thread, timestamp, stack), (next_thread, next_timestamp, _)] = pair else {
+            continue;
+        };
+        if thread == next_thread && next_timestamp > timestamp {
+            *map.entry(stack.clone()).or_default() += next_timestamp - timestamp;
+        }
+    }
+    map
+}
+
 /// Write a folded stack collapse map to `<stem>.folded` and, when the map is
 /// non-empty, render it to `<stem>.svg`.
 async fn write_flamegraph(res_dir: &Path, stem: &str, map: HashMap<String, u64>) -> Result<()> {
-    let lines = map
-        .into_iter()
-        .map(|(key, value)| format!("{} {}", key, value))
-        .collect::<Vec<_>>();
+    write_counted_flamegraph(res_dir, stem, map, "samples").await
+}
 
-    let mut folded = File::create(res_dir.join(format!("{stem}.folded"))).await?;
-    for line in &lines {
-        folded.write_all(line.as_bytes()).await?;
-        folded.write_all(b"\n").await?;
-    }
+/// [`write_flamegraph`] for maps weighted in nanoseconds.
+async fn write_time_flamegraph(
+    res_dir: &Path,
+    stem: &str,
+    map: HashMap<String, u64>,
+) -> Result<()> {
+    write_counted_flamegraph(res_dir, stem, map, "ns").await
+}
+
+async fn write_counted_flamegraph(
+    res_dir: &Path,
+    stem: &str,
+    map: HashMap<String, u64>,
+    count_name: &str,
+) -> Result<()> {
+    let lines = write_folded_stacks(res_dir, stem, map).await?;
 
     // Some counters can legitimately have no positive samples (in particular
     // for short-lived processes or unavailable hardware events). Inferno treats
@@ -998,19 +2021,108 @@ async fn write_flamegraph(res_dir: &Path, stem: &str, map: HashMap<String, u64>)
         return Ok(());
     }
 
-    let mut options = inferno::flamegraph::Options::default();
-    options.reverse_stack_order = false;
-    let svg = std::fs::File::create(res_dir.join(format!("{stem}.svg")))?;
-    inferno::flamegraph::from_lines(&mut options, lines.iter().map(|s| s.as_str()), &svg)?;
+    // The SVG is a convenience for browsers; the TUI reads the folded file.
+    // Failing to render it must not lose the database written so far.
+    let svg_path = res_dir.join(format!("{stem}.svg"));
+    let rendered = std::fs::File::create(&svg_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|svg| {
+            let mut options = inferno::flamegraph::Options::default();
+            options.reverse_stack_order = false;
+            options.count_name = count_name.to_string();
+            inferno::flamegraph::from_lines(&mut options, lines.iter().map(|s| s.as_str()), svg)
+                .map_err(anyhow::Error::from)
+        });
+    if let Err(err) = rendered {
+        tracing::warn!("failed to render {}: {err:#}", svg_path.display());
+        let _ = std::fs::remove_file(&svg_path);
+    }
 
     Ok(())
 }
 
+/// Writes `{stem}.folded` and returns its lines.
+pub(crate) async fn write_folded_stacks(
+    res_dir: &Path,
+    stem: &str,
+    map: HashMap<String, u64>,
+) -> Result<Vec<String>> {
+    let mut sanitized = HashMap::<String, u64>::with_capacity(map.len());
+    for (stack, value) in map {
+        let merged = sanitized.entry(sanitize_folded_stack(&stack)).or_default();
+        *merged = merged.saturating_add(value);
+    }
+    let lines = sanitized
+        .into_iter()
+        .map(|(key, value)| format!("{} {}", key, value))
+        .collect::<Vec<_>>();
+
+    let mut folded = File::create(res_dir.join(format!("{stem}.folded"))).await?;
+    for line in &lines {
+        folded.write_all(line.as_bytes()).await?;
+        folded.write_all(b"\n").await?;
+    }
+    // tokio finishes writes in the background; readers of the file must not
+    // see it cut short.
+    folded.flush().await?;
+    Ok(lines)
+}
+
+/// Makes a folded stack safe for folded-stack parsers: a line break would
+/// split the line, and empty frames are named `[unknown]` like unresolved
+/// addresses elsewhere.
+fn sanitize_folded_stack(stack: &str) -> String {
+    stack
+        .split(';')
+        .map(|frame| {
+            let frame = frame.trim();
+            if frame.is_empty() {
+                Cow::Borrowed("[unknown]")
+            } else if frame.contains(['\n', '\r']) {
+                Cow::Owned(frame.replace(['\n', '\r'], " "))
+            } else {
+                Cow::Borrowed(frame)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
 #[cfg(test)]
 mod flamegraph_output_tests {
-    use super::{flamegraph_sample_weight, write_flamegraph};
+    use super::{
+        flamegraph_sample_weight, reverse_folded_stacks, sanitize_folded_stack, thread_frame,
+        time_weighted_stacks, write_flamegraph,
+    };
     use std::collections::HashMap;
 
+    #[test]
+    fn thread_frames_name_the_thread() {
+        assert_eq!(
+            thread_frame(Some(&"worker;1".to_owned()), 42),
+            "worker:1 (42)"
+        );
+        assert_eq!(thread_frame(None, 42), "[thread 42]");
+    }
+
+    #[test]
+    fn reversed_stacks_merge_by_leaf() {
+        let map = HashMap::from([
+            ("main;parse;malloc".to_owned(), 3),
+            ("main;render;malloc".to_owned(), 2),
+            ("main;render".to_owned(), 1),
+        ]);
+        let reversed = reverse_folded_stacks(&map);
+        assert_eq!(
+            reversed,
+            HashMap::from([
+                ("malloc;parse;main".to_owned(), 3),
+                ("malloc;render;main".to_owned(), 2),
+                ("render;main".to_owned(), 1),
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn empty_flamegraph_does_not_fail_postprocessing() {
         let dir =
@@ -1026,6 +2138,44 @@ mod flamegraph_output_tests {
         std::fs::remove_dir_all(dir).unwrap();
     }
 
+    #[tokio::test]
+    async fn malformed_frames_are_sanitized_before_rendering() {
+        assert_eq!(sanitize_folded_stack("main;;a\nb"), "main;[unknown];a b");
+
+        let dir = std::env::temp_dir().join(format!(
+            "mperf-sanitized-flamegraph-{}",
+            uuid::Uuid::now_v7()
+        ));
+        std::fs::create_dir(&dir).unwrap();
+        let map = HashMap::from([("main;".to_owned(), 2), ("main;[unknown]".to_owned(), 3)]);
+
+        write_flamegraph(&dir, "sanitized", map).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("sanitized.folded")).unwrap(),
+            "main;[unknown] 5\n"
+        );
+        assert!(dir.join("sanitized.svg").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stacks_are_weighted_by_time_to_the_next_sample() {
+        let samples = vec![
+            (1, 300, "main;sleep".to_owned()),
+            (1, 100, "main;work".to_owned()),
+            (2, 150, "worker".to_owned()),
+            (1, 1_300, "main;work".to_owned()),
+        ];
+        assert_eq!(
+            time_weighted_stacks(samples),
+            HashMap::from([
+                ("main;work".to_owned(), 200),
+                ("main;sleep".to_owned(), 1_000)
+            ])
+        );
+    }
+
     #[test]
     fn cumulative_gap_does_not_dominate_flamegraph_weight() {
         assert_eq!(flamegraph_sample_weight(60_000_000_000), Some(1));
@@ -1036,7 +2186,7 @@ mod flamegraph_output_tests {
 
 #[cfg(test)]
 mod replay_benchmark {
-    use super::perform_postprocessing;
+    use super::{perform_postprocessing, AsmSyntax, ViewOptions};
     use std::time::Instant;
 
     #[tokio::test]
@@ -1063,9 +2213,17 @@ mod replay_benchmark {
             }
 
             let started = Instant::now();
-            perform_postprocessing(&destination, kdam::Bar::new(100))
-                .await
-                .unwrap();
+            let connection = sqlite::open(destination.join("perf.db")).unwrap();
+            perform_postprocessing(
+                &connection,
+                &destination,
+                kdam::Bar::new(100),
+                &[],
+                AsmSyntax::default(),
+                &ViewOptions::default(),
+            )
+            .await
+            .unwrap();
             let elapsed = started.elapsed();
             let database_bytes = std::fs::metadata(destination.join("perf.db"))
                 .unwrap()
@@ -1094,38 +2252,29 @@ mod replay_benchmark {
 async fn process_disassembly(
     connection: &sqlite::Connection,
     res_dir: &Path,
+    debug_dirs: &[PathBuf],
+    syntax: AsmSyntax,
     pb: &mut kdam::Bar,
 ) -> Result<()> {
     use sqlite::State;
 
     populate_assembly_samples(connection)?;
 
-    connection.execute(
-        "
-        CREATE TABLE IF NOT EXISTS assembly_lines (
-            module_path TEXT NOT NULL,
-            symbol TEXT,
-            rel_address INTEGER NOT NULL,
-            runtime_address INTEGER NOT NULL,
-            instruction TEXT NOT NULL,
-            source_file TEXT,
-            source_line INTEGER,
-            PRIMARY KEY (module_path, runtime_address)
-        );
-        ",
-    )?;
-    connection.execute(
-        "CREATE TABLE IF NOT EXISTS assembly_module_metadata (
-            module_path TEXT PRIMARY KEY,
-            load_bias INTEGER NOT NULL
-        );",
-    )?;
+    connection.execute(schema::ASSEMBLY_LINES)?;
+    create_instruction_mix_view(connection)?;
+    connection.execute(schema::ASSEMBLY_MODULE_METADATA)?;
 
     let proc_map_file = std::fs::File::open(res_dir.join("proc_map.json"))?;
     let proc_map: Vec<ProcMapEntry> = serde_json::from_reader(proc_map_file)?;
 
     let mut module_bias = HashMap::<String, i64>::new();
+    let mut recorded_build_ids = HashMap::<String, String>::new();
     for entry in proc_map {
+        if let Some(build_id) = entry.build_id.clone() {
+            recorded_build_ids
+                .entry(entry.filename.clone())
+                .or_insert(build_id);
+        }
         let load_bias = entry.address as i64 - entry.offset as i64;
         module_bias
             .entry(entry.filename.clone())
@@ -1137,10 +2286,10 @@ async fn process_disassembly(
             .or_insert(load_bias);
     }
 
-    let disassembler = match default_disassembler() {
+    let disassembler = match default_disassembler(syntax) {
         Ok(disassembler) => disassembler,
         Err(err) => {
-            eprintln!("skipping assembly extraction: {err}");
+            tracing::warn!("skipping assembly extraction: {err}");
             return Ok(());
         }
     };
@@ -1153,7 +2302,7 @@ async fn process_disassembly(
         sampled_addresses
             .entry(module_stmt.read::<String, _>(0)?)
             .or_default()
-            .push(module_stmt.read::<i64, _>(1)? as u64);
+            .push(sqlite_u64_from_i64(module_stmt.read::<i64, _>(1)?));
     }
     let mut modules = sampled_addresses.into_iter().collect::<Vec<_>>();
     modules.sort_unstable_by(|left, right| left.0.cmp(&right.0));
@@ -1166,15 +2315,19 @@ async fn process_disassembly(
     pb.write("Extracting assembly")?;
 
     let mut insert_stmt = connection.prepare(
-        "INSERT OR IGNORE INTO assembly_lines (module_path, symbol, rel_address, runtime_address, instruction, source_file, source_line)
-         VALUES (?, ?, ?, ?, ?, ?, ?);",
+        "INSERT OR IGNORE INTO assembly_lines (module_path, symbol, rel_address, runtime_address, instruction, source_file, source_line, instruction_class)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
     )?;
     let mut metadata_stmt = connection.prepare(
-        "INSERT INTO assembly_module_metadata (module_path, load_bias)
-         VALUES (?, ?)
-         ON CONFLICT(module_path) DO UPDATE SET load_bias = excluded.load_bias;",
+        "INSERT INTO assembly_module_metadata (module_path, load_bias, recorded_build_id, current_build_id)
+         VALUES (?, ?, ?, ?)
+         ON CONFLICT(module_path) DO UPDATE SET
+            load_bias = excluded.load_bias,
+            recorded_build_id = excluded.recorded_build_id,
+            current_build_id = excluded.current_build_id;",
     )?;
 
+    let debug_cache = symbolize::BuildIdCache::default();
     connection.execute("BEGIN IMMEDIATE TRANSACTION;")?;
     let result = (|| -> Result<()> {
         for (idx, (module_path, addresses)) in modules.iter().enumerate() {
@@ -1185,9 +2338,20 @@ async fn process_disassembly(
             }
 
             let load_bias = module_bias.get(module_path).copied().unwrap_or(0);
+            let recorded_build_id = recorded_build_ids.get(module_path).map(String::as_str);
+            let current_build_id = symbolize::build_id(module_file);
+            if build_id_changed(recorded_build_id, current_build_id.as_deref()) {
+                tracing::warn!(
+                    "{module_path} changed since recording (build ID {} now {}); its disassembly may not match the samples",
+                    recorded_build_id.unwrap_or("none"),
+                    current_build_id.as_deref().unwrap_or("none"),
+                );
+            }
             metadata_stmt.reset()?;
             metadata_stmt.bind((1, module_path.as_str()))?;
             metadata_stmt.bind((2, load_bias))?;
+            metadata_stmt.bind((3, recorded_build_id))?;
+            metadata_stmt.bind((4, current_build_id.as_deref()))?;
             metadata_stmt.next()?;
 
             let (targets, address_base) =
@@ -1195,12 +2359,17 @@ async fn process_disassembly(
             let request = DisassembleRequest {
                 module_path: module_file.to_path_buf(),
                 load_bias,
+                debug_file: symbolize::find_separate_debug_file(
+                    module_file,
+                    &debug_cache,
+                    debug_dirs,
+                ),
                 targets,
             };
             let lines = match disassembler.disassemble(&request) {
                 Ok(lines) => lines,
                 Err(err) => {
-                    eprintln!("failed to disassemble {}: {err}", module_path);
+                    tracing::warn!("failed to disassemble {}: {err}", module_path);
                     continue;
                 }
             };
@@ -1213,13 +2382,14 @@ async fn process_disassembly(
                 insert_stmt.reset()?;
                 insert_stmt.bind((1, module_path.as_str()))?;
                 insert_stmt.bind((2, line.symbol.as_deref()))?;
-                insert_stmt.bind((3, rel_address as i64))?;
-                insert_stmt.bind((4, runtime_address as i64))?;
+                insert_stmt.bind((3, sqlite_i64_from_u64(rel_address)))?;
+                insert_stmt.bind((4, sqlite_i64_from_u64(runtime_address)))?;
                 insert_stmt.bind((5, line.instruction.as_str()))?;
-                // Source annotations are intentionally omitted from the eager path;
-                // loading full DWARF line tables dominates targeted disassembly.
-                insert_stmt.bind((6, ()))?;
-                insert_stmt.bind((7, ()))?;
+                // Only split debug files are asked for source annotations; loading
+                // full DWARF line tables of every module dominates targeted disassembly.
+                insert_stmt.bind((6, line.source_file.as_deref()))?;
+                insert_stmt.bind((7, line.source_line.map(i64::from)))?;
+                insert_stmt.bind((8, classify_instruction(&line.instruction).as_str()))?;
                 insert_stmt.next()?;
             }
         }
@@ -1238,24 +2408,14 @@ async fn process_disassembly(
     Ok(())
 }
 
-fn populate_assembly_samples(connection: &sqlite::Connection) -> Result<()> {
-    connection.execute(
-        "
-        CREATE TABLE IF NOT EXISTS assembly_samples (
-            module_path TEXT NOT NULL,
-            func_name TEXT NOT NULL,
-            address INTEGER NOT NULL,
-            samples INTEGER NOT NULL,
-            cycles INTEGER NOT NULL,
-            instructions INTEGER NOT NULL,
-            branch_misses INTEGER NOT NULL,
-            branch_instructions INTEGER NOT NULL,
-            llc_misses INTEGER NOT NULL,
-            llc_references INTEGER NOT NULL,
-            PRIMARY KEY (module_path, func_name, address)
-        );
-        ",
-    )?;
+/// A module changed on disk when it had a build ID at capture time and no
+/// longer carries the same one. Recordings without build IDs are not judged.
+pub(crate) fn build_id_changed(recorded: Option<&str>, current: Option<&str>) -> bool {
+    recorded.is_some_and(|recorded| current != Some(recorded))
+}
+
+pub(crate) fn populate_assembly_samples(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(schema::ASSEMBLY_SAMPLES)?;
 
     let available_columns = connection
         .prepare("PRAGMA table_info(pmu_counters);")?
@@ -1318,10 +2478,34 @@ fn create_assembly_stats_view(connection: &sqlite::Connection) -> Result<()> {
     Ok(())
 }
 
+/// Sample-weighted scalar/vector mix per function. Samples are attributed to
+/// the instruction at their exact address; unmatched samples count towards
+/// `samples` only, so the class columns may not add up to the total.
+pub(crate) fn create_instruction_mix_view(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(
+        "DROP VIEW IF EXISTS instruction_mix;
+         CREATE VIEW instruction_mix AS
+         SELECT s.func_name AS func_name,
+                SUM(s.samples) AS samples,
+                SUM(CASE WHEN l.instruction_class = 'scalar_int' THEN s.samples ELSE 0 END) AS scalar_int_samples,
+                SUM(CASE WHEN l.instruction_class = 'scalar_float' THEN s.samples ELSE 0 END) AS scalar_float_samples,
+                SUM(CASE WHEN l.instruction_class = 'vector_int' THEN s.samples ELSE 0 END) AS vector_int_samples,
+                SUM(CASE WHEN l.instruction_class = 'vector_float' THEN s.samples ELSE 0 END) AS vector_float_samples,
+                SUM(CASE WHEN l.instruction_class IN ('vector_int', 'vector_float') THEN s.samples ELSE 0 END) * 1.0 /
+                    NULLIF(SUM(CASE WHEN l.instruction_class IS NOT NULL THEN s.samples ELSE 0 END), 0) AS vectorization_ratio
+         FROM assembly_samples s
+         LEFT JOIN assembly_lines l
+           ON l.module_path = s.module_path AND l.runtime_address = s.address
+         GROUP BY s.func_name;",
+    )?;
+    Ok(())
+}
+
 #[derive(Clone)]
 struct ObjectTextSymbol {
     start: u64,
-    end: u64,
+    /// `None` for a sizeless symbol with nothing after it.
+    end: Option<u64>,
     raw_name: String,
     display_name: String,
 }
@@ -1359,46 +2543,76 @@ fn sampled_disassembly_targets(
         let next_start = symbols
             .iter()
             .skip(index + 1)
-            .find_map(|candidate| (candidate.0 > *start).then_some(candidate.0))
-            .unwrap_or(u64::MAX);
+            .find_map(|candidate| (candidate.0 > *start).then_some(candidate.0));
         let end = if *size > 0 {
-            start.saturating_add(*size)
+            Some(start.saturating_add(*size))
         } else {
             next_start
         };
         text_symbols.push(ObjectTextSymbol {
             start: start.saturating_sub(address_base),
-            end: end.saturating_sub(address_base),
+            end: end.map(|end| end.saturating_sub(address_base)),
             raw_name: raw_name.clone(),
             display_name: addr2line::demangle_auto(Cow::Borrowed(raw_name), None).into_owned(),
         });
     }
 
-    let mut selected = HashMap::<(u64, String), ObjectTextSymbol>::new();
+    let relative_addresses = runtime_addresses
+        .iter()
+        .filter_map(|address| remove_load_bias(*address, load_bias));
+    let targets = targets_for_samples(&text_symbols, relative_addresses, address_base);
+    Ok((targets, address_base))
+}
+
+/// Bytes disassembled around sampled addresses whose extent is not known
+/// from the symbol table.
+const SAMPLED_WINDOW: u64 = 256;
+
+/// Picks what to disassemble for the sampled `relative_addresses`: whole
+/// symbols when their bounds are known, otherwise only the sampled IP range.
+/// Nothing ever asks objdump for the rest of the module.
+fn targets_for_samples(
+    text_symbols: &[ObjectTextSymbol],
+    relative_addresses: impl Iterator<Item = u64>,
+    address_base: u64,
+) -> Vec<DisassembleTarget> {
+    let mut selected = HashMap::<(u64, String), (ObjectTextSymbol, u64)>::new();
     let mut fallback = Vec::<(u64, u64)>::new();
-    for runtime_address in runtime_addresses.iter().copied() {
-        let Some(relative) = remove_load_bias(runtime_address, load_bias) else {
-            continue;
-        };
+    for relative in relative_addresses {
         let insertion = text_symbols.partition_point(|symbol| symbol.start <= relative);
         let symbol = text_symbols[..insertion]
             .iter()
             .rev()
-            .find(|symbol| relative < symbol.end);
+            .find(|symbol| symbol.end.is_none_or(|end| relative < end));
         if let Some(symbol) = symbol {
-            selected.insert((symbol.start, symbol.raw_name.clone()), symbol.clone());
+            selected
+                .entry((symbol.start, symbol.raw_name.clone()))
+                .and_modify(|(_, last_sampled)| *last_sampled = (*last_sampled).max(relative))
+                .or_insert_with(|| (symbol.clone(), relative));
         } else {
-            fallback.push((relative.saturating_sub(256), relative.saturating_add(257)));
+            fallback.push((
+                relative.saturating_sub(SAMPLED_WINDOW),
+                relative.saturating_add(SAMPLED_WINDOW + 1),
+            ));
         }
     }
 
     let mut targets = selected
         .into_values()
-        .map(|symbol| DisassembleTarget {
-            raw_symbol: Some(symbol.raw_name),
-            owner_symbol: symbol.display_name,
-            start_address: symbol.start.saturating_add(address_base),
-            end_address: symbol.end.saturating_add(address_base),
+        .map(|(symbol, last_sampled)| {
+            // The last symbol of a module may carry no size. Passing it by
+            // name would make objdump run to the end of the section, so only
+            // the range up to the last sampled instruction is requested.
+            let (raw_symbol, end) = match symbol.end {
+                Some(end) => (Some(symbol.raw_name), end),
+                None => (None, last_sampled.saturating_add(SAMPLED_WINDOW + 1)),
+            };
+            DisassembleTarget {
+                raw_symbol,
+                owner_symbol: symbol.display_name,
+                start_address: symbol.start.saturating_add(address_base),
+                end_address: end.saturating_add(address_base),
+            }
         })
         .collect::<Vec<_>>();
     fallback.sort_unstable();
@@ -1426,7 +2640,7 @@ fn sampled_disassembly_targets(
                     .then_with(|| left.owner_symbol.cmp(&right.owner_symbol))
             })
     });
-    Ok((targets, address_base))
+    targets
 }
 
 fn apply_load_bias(relative: u64, load_bias: i64) -> Option<u64> {
@@ -1447,8 +2661,16 @@ fn remove_load_bias(runtime: u64, load_bias: i64) -> Option<u64> {
 
 #[cfg(test)]
 mod optimized_postprocessing_tests {
-    use super::{populate_assembly_samples, sampled_disassembly_targets, RooflineData};
-    use mperf_data::{CallFrame, Event, EventType, Location, RooflineInfo, ScenarioInfo};
+    use super::{
+        build_id_changed, create_instruction_mix_view, create_memory_latency_view,
+        create_module_hotspots_view, create_thread_stats_view, inherit_proc_maps,
+        populate_assembly_samples, sampled_disassembly_targets, targets_for_samples,
+        ObjectTextSymbol, RooflineData,
+    };
+    use mperf_data::{
+        CallFrame, Event, EventType, Location, ProcMapEntry, ProcessFork, RooflineInfo,
+        ScenarioInfo,
+    };
     use object::{Object, ObjectSymbol, SymbolKind};
     use sqlite::State;
 
@@ -1480,6 +2702,185 @@ mod optimized_postprocessing_tests {
         assert_eq!(statement.next().unwrap(), State::Done);
     }
 
+    #[test]
+    fn instruction_mix_weights_classes_by_samples() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE assembly_samples (
+                    module_path TEXT, func_name TEXT, address INTEGER, samples INTEGER
+                 );
+                 CREATE TABLE assembly_lines (
+                    module_path TEXT, runtime_address INTEGER, instruction_class TEXT
+                 );
+                 INSERT INTO assembly_samples VALUES
+                    ('/tmp/hot', 'kernel', 16, 6), ('/tmp/hot', 'kernel', 20, 2),
+                    ('/tmp/hot', 'kernel', 24, 2), ('/tmp/hot', 'cold', 64, 1);
+                 INSERT INTO assembly_lines VALUES
+                    ('/tmp/hot', 16, 'vector_float'), ('/tmp/hot', 20, 'scalar_int');",
+            )
+            .unwrap();
+
+        create_instruction_mix_view(&connection).unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM instruction_mix ORDER BY func_name")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("func_name").unwrap(), "cold");
+        assert_eq!(
+            statement
+                .read::<Option<f64>, _>("vectorization_ratio")
+                .unwrap(),
+            None
+        );
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<i64, _>("samples").unwrap(), 10);
+        assert_eq!(statement.read::<i64, _>("vector_float_samples").unwrap(), 6);
+        assert_eq!(statement.read::<i64, _>("scalar_int_samples").unwrap(), 2);
+        assert_eq!(
+            statement.read::<f64, _>("vectorization_ratio").unwrap(),
+            0.75
+        );
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[test]
+    fn thread_stats_attach_names_and_cycle_share() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (
+                    process_id INTEGER, thread_id INTEGER, pmu_cycles INTEGER
+                 );
+                 CREATE TABLE thread_names (pid INTEGER, tid INTEGER, name TEXT);
+                 INSERT INTO pmu_counters VALUES (7, 7, 10), (7, 8, 20), (7, 8, 10);
+                 INSERT INTO thread_names VALUES (7, 8, 'worker');",
+            )
+            .unwrap();
+
+        create_thread_stats_view(&connection).unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM thread_stats ORDER BY tid")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<Option<String>, _>("name").unwrap(), None);
+        assert_eq!(statement.read::<i64, _>("samples").unwrap(), 1);
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("name").unwrap(), "worker");
+        assert_eq!(statement.read::<i64, _>("samples").unwrap(), 2);
+        assert_eq!(statement.read::<f64, _>("total").unwrap(), 0.75);
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[test]
+    fn memory_latency_ranks_functions_by_weighted_samples() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER, latency INTEGER);
+                 CREATE TABLE proc_map (ip INTEGER, func_name TEXT, module_path TEXT);
+                 INSERT INTO pmu_counters VALUES (1, 40), (1, 200), (2, NULL), (3, 60);
+                 INSERT INTO proc_map VALUES (1, 'chase', 'a'), (2, 'idle', 'a'), (3, 'scan', 'a');",
+            )
+            .unwrap();
+
+        create_memory_latency_view(&connection).unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM memory_latency ORDER BY avg_latency DESC")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("func_name").unwrap(), "chase");
+        assert_eq!(statement.read::<i64, _>("loads").unwrap(), 2);
+        assert_eq!(statement.read::<f64, _>("avg_latency").unwrap(), 120.0);
+        assert_eq!(statement.read::<i64, _>("max_latency").unwrap(), 200);
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("func_name").unwrap(), "scan");
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[test]
+    fn module_hotspots_group_samples_by_module() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER, pmu_cycles INTEGER, pmu_instructions INTEGER);
+                 CREATE TABLE proc_map (ip INTEGER, func_name TEXT, module_path TEXT);
+                 INSERT INTO pmu_counters VALUES (1, 100, 200), (2, 300, 300), (3, 400, 200);
+                 INSERT INTO proc_map VALUES (1, 'main', '/usr/bin/app'), (2, 'memcpy', '/lib/libc.so.6'),
+                     (3, 'strlen', '/lib/libc.so.6');",
+            )
+            .unwrap();
+
+        create_module_hotspots_view(&connection).unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM module_hotspots ORDER BY total DESC")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("module").unwrap(), "libc.so.6");
+        assert_eq!(statement.read::<i64, _>("samples").unwrap(), 2);
+        assert_eq!(statement.read::<i64, _>("cycles").unwrap(), 700);
+        assert_eq!(statement.read::<f64, _>("total").unwrap(), 0.875);
+        assert_eq!(statement.read::<f64, _>("ipc").unwrap(), 500.0 / 700.0);
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("module").unwrap(), "app");
+        assert_eq!(
+            statement.read::<String, _>("module_path").unwrap(),
+            "/usr/bin/app"
+        );
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[test]
+    fn forked_children_inherit_parent_mappings_they_did_not_replace() {
+        let entry = |pid, address, filename: &str| ProcMapEntry {
+            filename: filename.to_owned(),
+            address,
+            size: 0x1000,
+            offset: 0,
+            pid,
+            build_id: None,
+            exec_generation: 0,
+        };
+        let fork = |pid, ppid, fork_time| ProcessFork {
+            pid,
+            ppid,
+            fork_time,
+            exit_time: None,
+        };
+        let proc_map = vec![
+            entry(1, 0x1000, "/bin/parent"),
+            entry(1, 0x4000, "/lib/libc.so"),
+            entry(2, 0x1000, "/bin/child"),
+        ];
+
+        // 3 is forked by 2 after 2 is forked by 1, but listed first.
+        let mut proc_map = inherit_proc_maps(proc_map, &[fork(3, 2, 20), fork(2, 1, 10)], &[]);
+        proc_map.sort_by_key(|entry| (entry.pid, entry.address));
+        let files = proc_map
+            .iter()
+            .map(|entry| (entry.pid, entry.filename.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                (1, "/bin/parent"),
+                (1, "/lib/libc.so"),
+                (2, "/bin/child"),
+                (2, "/lib/libc.so"),
+                (3, "/bin/child"),
+                (3, "/lib/libc.so"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuilt_modules_are_detected_by_build_id() {
+        assert!(!build_id_changed(Some("abcd"), Some("abcd")));
+        assert!(build_id_changed(Some("abcd"), Some("ef01")));
+        assert!(build_id_changed(Some("abcd"), None));
+        assert!(!build_id_changed(None, Some("ef01")));
+    }
+
     #[test]
     fn sampled_symbol_selection_avoids_unrelated_object_code() {
         let executable = std::env::current_exe().unwrap();
@@ -1500,6 +2901,33 @@ mod optimized_postprocessing_tests {
         assert!(targets[0].end_address > sampled_address);
     }
 
+    #[test]
+    fn sizeless_trailing_symbol_is_bounded_by_its_samples() {
+        let symbol = |start, end: Option<u64>, name: &str| ObjectTextSymbol {
+            start,
+            end,
+            raw_name: name.to_string(),
+            display_name: name.to_string(),
+        };
+        let text_symbols = [symbol(0x100, Some(0x180), "f"), symbol(0x200, None, "g")];
+
+        let targets =
+            targets_for_samples(&text_symbols, [0x110, 0x140, 0x210, 0x300].into_iter(), 0);
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].raw_symbol.as_deref(), Some("f"));
+        assert_eq!(
+            (targets[0].start_address, targets[0].end_address),
+            (0x100, 0x180)
+        );
+        assert_eq!(targets[1].raw_symbol, None);
+        assert_eq!(targets[1].owner_symbol, "g");
+        assert_eq!(
+            (targets[1].start_address, targets[1].end_address),
+            (0x200, 0x401)
+        );
+    }
+
     #[test]
     fn roofline_events_are_collected_during_the_pmu_pass() {
         let info = ScenarioInfo::Roofline(RooflineInfo {
@@ -1549,33 +2977,212 @@ mod optimized_postprocessing_tests {
             callstack: smallvec::SmallVec::new(),
             user_regs: None,
             user_stack: Vec::new(),
+            weight: 0,
         }
     }
 }
 
-async fn create_hotspots_view(connection: &sqlite::Connection) -> Result<()> {
-    connection.execute("
+/// Records the sampled functions `idle` matches in `idle_functions`.
+fn create_idle_functions(connection: &sqlite::Connection, idle: &IdleFilter) -> Result<()> {
+    connection.execute("DROP TABLE IF EXISTS idle_functions;")?;
+    connection.execute(schema::IDLE_FUNCTIONS)?;
+    let mut statement = connection.prepare("SELECT DISTINCT func_name FROM proc_map;")?;
+    let mut functions = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        if let Some(func_name) = statement.read::<Option<String>, _>("func_name")? {
+            if idle.is_idle(&func_name) {
+                functions.push(func_name);
+            }
+        }
+    }
+    drop(statement);
+
+    let mut insert = connection.prepare("INSERT INTO idle_functions (func_name) VALUES (?);")?;
+    for func_name in functions {
+        insert.reset()?;
+        insert.bind((1, func_name.as_str()))?;
+        insert.next()?;
+    }
+    Ok(())
+}
+
+/// Functions with fewer than `views.min_samples` samples are left out, as
+/// are idle functions with [`IdleMode::Exclude`]; their cycles still count
+/// towards the `total` of the others. [`IdleMode::Bucket`] sums idle
+/// functions into one [`IDLE_FRAME`] row.
+async fn create_hotspots_view(
+    connection: &sqlite::Connection,
+    views: &ViewOptions,
+    totals: Option<&CountedTotals>,
+) -> Result<()> {
+    create_idle_functions(connection, &views.idle)?;
+    let is_idle = "proc_map.func_name IN (SELECT func_name FROM idle_functions)";
+    let (func_name, filter) = match views.idle.mode {
+        IdleMode::Keep => ("proc_map.func_name".to_string(), String::new()),
+        IdleMode::Bucket => (
+            format!("(CASE WHEN {is_idle} THEN '{IDLE_FRAME}' ELSE proc_map.func_name END)"),
+            String::new(),
+        ),
+        IdleMode::Exclude => (
+            "proc_map.func_name".to_string(),
+            format!("WHERE NOT {is_idle}"),
+        ),
+    };
+    let mut columns = [
+        ("func_name", func_name.as_str()),
+        (
+            "total",
+            "(SUM(pmu_counters.pmu_cycles) * 1.0 / (SELECT SUM(pmu_cycles) FROM pmu_counters))",
+        ),
+        ("samples", "COUNT(*)"),
+        ("cycles", "SUM(pmu_counters.pmu_cycles)"),
+        ("instructions", "SUM(pmu_counters.pmu_instructions)"),
+    ]
+    .map(|(name, expression)| (name.to_string(), expression.to_string()))
+    .to_vec();
+    columns.extend(DERIVED_METRICS.iter().map(|metric| {
+        (
+            metric.key.to_string(),
+            metric.sql_expression(views.aggregation),
+        )
+    }));
+    if let Some(totals) = totals {
+        create_hotspot_estimates(connection, totals)?;
+        for column in ["est_cycles", "est_instructions", "relative_error"] {
+            columns.push((
+                column.to_string(),
+                format!("(SELECT {column} FROM hotspot_estimates WHERE hotspot_estimates.func_name = {func_name})"),
+            ));
+        }
+    }
+    columns.push((
+        "vectorization_ratio".to_string(),
+        format!("(SELECT vectorization_ratio FROM instruction_mix WHERE instruction_mix.func_name = {func_name})"),
+    ));
+    let select = select_list(&columns);
+    let min_samples = views.min_samples;
+    connection
+        .execute(format!(
+            "
     CREATE VIEW hotspots
     AS
     SELECT
-        proc_map.func_name as func_name,
-        (SUM(pmu_counters.pmu_cycles) * 1.0 / (SELECT SUM(pmu_cycles) FROM pmu_counters)) AS total,
-        SUM(pmu_counters.pmu_cycles) AS cycles,
-        SUM(pmu_counters.pmu_instructions) AS instructions,
-        (SUM(pmu_counters.pmu_instructions) * 1.0 / SUM(pmu_counters.pmu_cycles)) AS ipc,
-        (SUM(pmu_counters.pmu_branch_misses * 1.0 / pmu_counters.confidence) * 1.0 / SUM(pmu_counters.pmu_branch_instructions * 1.0 / pmu_counters.confidence)) AS branch_miss_rate,
-        (SUM(pmu_counters.pmu_branch_misses * 1.0 / pmu_counters.confidence) * 1.0 / SUM(pmu_counters.pmu_instructions) * 1000) AS branch_mpki,
-        (SUM(pmu_counters.pmu_llc_misses * 1.0 / pmu_counters.confidence) * 1.0 / (SUM(pmu_counters.pmu_llc_misses * 1.0 / pmu_counters.confidence) + SUM(pmu_counters.pmu_llc_references * 1.0 / pmu_counters.confidence))) AS cache_miss_rate,
-        (SUM(pmu_counters.pmu_llc_misses * 1.0 / pmu_counters.confidence) * 1.0 / SUM(pmu_counters.pmu_instructions) * 1000) AS cache_mpki
+        {select}
     FROM pmu_counters
     INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
-    GROUP BY proc_map.func_name;
-    ").expect("failed to create a view");
+    {filter}
+    GROUP BY {func_name}
+    HAVING COUNT(*) >= {min_samples};
+    "
+        ))
+        .expect("failed to create a view");
+    record_view_columns(connection, "hotspots", &columns)
+}
+
+fn select_list(columns: &[(String, String)]) -> String {
+    columns
+        .iter()
+        .map(|(name, expression)| format!("{expression} AS {name}"))
+        .collect::<Vec<_>>()
+        .join(",\n        ")
+}
+
+/// Records the expression of each column of `view`, so `show` can explain
+/// how a value was computed.
+fn record_view_columns(
+    connection: &sqlite::Connection,
+    view: &str,
+    columns: &[(String, String)],
+) -> Result<()> {
+    connection.execute(schema::VIEW_COLUMNS)?;
+    let mut insert = connection.prepare(
+        "INSERT OR REPLACE INTO view_columns (view, name, expression) VALUES (?, ?, ?);",
+    )?;
+    for (name, expression) in columns {
+        insert.reset()?;
+        insert.bind((1, view))?;
+        insert.bind((2, name.as_str()))?;
+        insert.bind((3, expression.as_str()))?;
+        insert.next()?;
+    }
     Ok(())
 }
 
-async fn create_roofline_view(connection: &sqlite::Connection) -> Result<()> {
-    connection.execute("
+/// Fills `hotspot_estimates` by scaling each function's share of the sampled
+/// cycles and instructions by the counted totals.
+fn create_hotspot_estimates(connection: &sqlite::Connection, totals: &CountedTotals) -> Result<()> {
+    connection.execute(schema::HOTSPOT_ESTIMATES)?;
+
+    let mut statement = connection.prepare(
+        "SELECT COUNT(*) AS samples, SUM(pmu_cycles) AS cycles,
+                SUM(pmu_instructions) AS instructions
+         FROM pmu_counters;",
+    )?;
+    statement.next()?;
+    let total_samples = statement.read::<i64, _>("samples")? as u64;
+    let sampled_cycles = statement.read::<Option<i64>, _>("cycles")?;
+    let sampled_instructions = statement.read::<Option<i64>, _>("instructions")?;
+    drop(statement);
+
+    let mut statement = connection.prepare(
+        "SELECT proc_map.func_name AS func_name, COUNT(*) AS samples,
+                SUM(pmu_counters.pmu_cycles) AS cycles,
+                SUM(pmu_counters.pmu_instructions) AS instructions
+         FROM pmu_counters
+         INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
+         GROUP BY proc_map.func_name;",
+    )?;
+    let mut rows = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        rows.push((
+            statement.read::<Option<String>, _>("func_name")?,
+            statement.read::<i64, _>("samples")? as u64,
+            statement.read::<Option<i64>, _>("cycles")?,
+            statement.read::<Option<i64>, _>("instructions")?,
+        ));
+    }
+    drop(statement);
+
+    let estimate = |sampled: Option<i64>, sampled_total: Option<i64>, total: Option<u64>| {
+        let (sampled, sampled_total, total) = (sampled?, sampled_total?, total?);
+        (sampled_total > 0).then(|| sampled as f64 / sampled_total as f64 * total as f64)
+    };
+    let mut insert = connection.prepare(
+        "INSERT INTO hotspot_estimates (func_name, est_cycles, est_instructions, relative_error)
+         VALUES (?, ?, ?, ?);",
+    )?;
+    for (func_name, samples, cycles, instructions) in rows {
+        insert.reset()?;
+        insert.bind((1, func_name.as_deref()))?;
+        insert.bind((2, estimate(cycles, sampled_cycles, totals.cycles)))?;
+        insert.bind((
+            3,
+            estimate(instructions, sampled_instructions, totals.instructions),
+        ))?;
+        insert.bind((4, relative_error(samples, total_samples)))?;
+        insert.next()?;
+    }
+    Ok(())
+}
+
+/// Half-width of the 95% confidence interval of a function's share of the
+/// samples, relative to the share. The share is a binomial proportion `p` of
+/// `total_samples`, so its relative standard error is `sqrt((1 - p) / samples)`.
+fn relative_error(samples: u64, total_samples: u64) -> Option<f64> {
+    if samples == 0 || total_samples == 0 {
+        return None;
+    }
+    let share = samples as f64 / total_samples as f64;
+    Some(1.96 * ((1.0 - share) / samples as f64).sqrt())
+}
+
+/// Loops that ran for less than `min_duration` in total are left out.
+async fn create_roofline_view(
+    connection: &sqlite::Connection,
+    min_duration: Duration,
+) -> Result<()> {
+    let min_duration = min_duration.as_nanos();
+    connection.execute(format!("
 CREATE VIEW roofline AS
 WITH
 ops AS (
@@ -1604,6 +3211,7 @@ runs AS (
     SUM(loop_end_ts - loop_start_ts) AS total_duration
   FROM roofline_loop_runs
   GROUP BY process_id, file_name, function_name, line
+  HAVING total_duration >= {min_duration}
 )
 SELECT
   s_file.string AS file_name,
@@ -1635,13 +3243,14 @@ LEFT JOIN ops
   AND runs.line = ops.line
 LEFT JOIN strings s_file ON runs.file_name = s_file.id
 LEFT JOIN strings s_func ON runs.function_name = s_func.id;
-    ").expect("failed to create a view");
+    ")).expect("failed to create a view");
     Ok(())
 }
 
 #[cfg(test)]
 mod metric_tests {
     use super::*;
+    use mperf_data::SnapshotInfo;
     use pmu::MetricExpression;
     use sqlite::State;
 
@@ -1678,6 +3287,352 @@ mod metric_tests {
             "instructions / cycles"
         );
     }
+
+    #[tokio::test]
+    async fn never_scheduled_groups_do_not_produce_infinite_rates() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        connection
+            .execute(schema::pmu_counters(
+                ", pmu_cycles INTEGER, pmu_instructions INTEGER, \
+                 pmu_branch_instructions INTEGER, pmu_branch_misses INTEGER, \
+                 pmu_llc_misses INTEGER, pmu_llc_references INTEGER",
+            ))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO proc_map (ip, func_name, file_name, line) VALUES (16, 'work', 'a.c', 1);
+                 INSERT INTO pmu_counters (process_id, thread_id, time_enabled, time_running,
+                     confidence, timestamp, ip, pmu_cycles, pmu_instructions,
+                     pmu_branch_instructions, pmu_branch_misses)
+                 VALUES (1, 1, 100, 50, 0.5, 1, 16, 100, 200, 10, 1),
+                        (1, 1, 0, 0, 0.0, 2, 16, 100, 200, 10, 1);",
+            )
+            .unwrap();
+        create_hotspots_view(&connection, &ViewOptions::default(), None)
+            .await
+            .unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT branch_miss_rate, branch_mpki FROM hotspots")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        // The zero-confidence row counts as raw: (2 + 1) / (20 + 10).
+        assert_eq!(statement.read::<f64, _>("branch_miss_rate").unwrap(), 0.1);
+        assert_eq!(statement.read::<f64, _>("branch_mpki").unwrap(), 7.5);
+        drop(statement);
+
+        let mut statement = connection
+            .prepare("SELECT expression FROM view_columns WHERE view = 'hotspots' AND name = 'ipc'")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(
+            statement.read::<String, _>("expression").unwrap(),
+            "(SUM(pmu_counters.pmu_instructions) * 1.0 / SUM(pmu_counters.pmu_cycles))"
+        );
+    }
+
+    #[tokio::test]
+    async fn weighted_aggregation_discounts_low_confidence_rows() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        connection
+            .execute(schema::pmu_counters(
+                ", pmu_cycles INTEGER, pmu_instructions INTEGER, \
+                 pmu_branch_instructions INTEGER, pmu_branch_misses INTEGER, \
+                 pmu_llc_misses INTEGER, pmu_llc_references INTEGER",
+            ))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO proc_map (ip, func_name, file_name, line) VALUES (16, 'work', 'a.c', 1);
+                 INSERT INTO pmu_counters (process_id, thread_id, time_enabled, time_running,
+                     confidence, timestamp, ip, pmu_cycles, pmu_instructions,
+                     pmu_branch_instructions, pmu_branch_misses)
+                 VALUES (1, 1, 100, 25, 0.25, 1, 16, 100, 200, 10, 1),
+                        (1, 1, 100, 100, 1.0, 2, 16, 100, 200, 10, 3);",
+            )
+            .unwrap();
+        let branch_miss_rate = |connection: &sqlite::Connection| {
+            let mut statement = connection
+                .prepare("SELECT branch_miss_rate FROM hotspots")
+                .unwrap();
+            assert_eq!(statement.next().unwrap(), State::Row);
+            statement.read::<f64, _>("branch_miss_rate").unwrap()
+        };
+
+        create_hotspots_view(&connection, &ViewOptions::default(), None)
+            .await
+            .unwrap();
+        // (4 + 3) / (40 + 10): the quarter-confidence row dominates.
+        assert_eq!(branch_miss_rate(&connection), 0.14);
+
+        connection.execute("DROP VIEW hotspots").unwrap();
+        let views = ViewOptions {
+            aggregation: Aggregation::Weighted,
+            ..Default::default()
+        };
+        create_hotspots_view(&connection, &views, None)
+            .await
+            .unwrap();
+        // (1 + 3) / (10 + 10), descaled alike by the mean confidence.
+        assert_eq!(branch_miss_rate(&connection), 0.2);
+    }
+
+    #[tokio::test]
+    async fn hotspots_estimate_absolute_counts_from_counted_totals() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        let mut event_schema = String::new();
+        for column in VIEW_COLUMNS {
+            event_schema.push_str(&format!(", {column} INTEGER"));
+        }
+        connection
+            .execute(schema::pmu_counters(&event_schema))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO proc_map (ip, func_name) VALUES (16, 'hot'), (32, 'cold');
+                 INSERT INTO pmu_counters (process_id, thread_id, time_enabled, time_running,
+                     confidence, timestamp, ip, pmu_cycles, pmu_instructions)
+                 VALUES (1, 1, 1, 1, 1.0, 1, 16, 100, 50), (1, 1, 1, 1, 1.0, 2, 16, 200, 150),
+                        (1, 1, 1, 1, 1.0, 3, 32, 100, 200);",
+            )
+            .unwrap();
+        let totals = CountedTotals {
+            cycles: Some(8_000),
+            instructions: None,
+        };
+        create_hotspots_view(&connection, &ViewOptions::default(), Some(&totals))
+            .await
+            .unwrap();
+
+        let mut statement = connection
+            .prepare(
+                "SELECT est_cycles, est_instructions, relative_error FROM hotspots
+                 WHERE func_name = 'hot'",
+            )
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<f64, _>("est_cycles").unwrap(), 6_000.0);
+        assert_eq!(
+            statement
+                .read::<Option<f64>, _>("est_instructions")
+                .unwrap(),
+            None
+        );
+        // Two of three samples: 1.96 * sqrt((1 - 2/3) / 2).
+        let error = statement.read::<f64, _>("relative_error").unwrap();
+        assert!((error - 0.8002).abs() < 1e-4, "{error}");
+    }
+
+    #[tokio::test]
+    async fn roofline_skips_loops_shorter_than_min_duration() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        connection.execute(schema::ROOFLINE_TABLES).unwrap();
+        connection
+            .execute(
+                "INSERT INTO strings VALUES (1, 'a.c'), (2, 'tiny'), (3, 'long');
+                 INSERT INTO roofline_loop_runs (process_id, thread_id, file_name,
+                     function_name, line, loop_start_ts, loop_end_ts)
+                 VALUES (1, 1, 1, 2, 10, 0, 400), (1, 1, 1, 2, 10, 1000, 1400),
+                        (1, 1, 1, 3, 20, 0, 5000);",
+            )
+            .unwrap();
+
+        create_roofline_view(&connection, Duration::from_micros(1))
+            .await
+            .unwrap();
+        let mut statement = connection
+            .prepare("SELECT function_name FROM roofline")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(
+            statement.read::<String, _>("function_name").unwrap(),
+            "long"
+        );
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[tokio::test]
+    async fn hotspots_skip_functions_below_min_samples() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        let mut event_schema = String::new();
+        for column in VIEW_COLUMNS {
+            event_schema.push_str(&format!(", {column} INTEGER"));
+        }
+        connection
+            .execute(schema::pmu_counters(&event_schema))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO proc_map (ip, func_name) VALUES (16, 'hot'), (32, 'cold');
+                 INSERT INTO pmu_counters (process_id, thread_id, time_enabled, time_running,
+                     confidence, timestamp, ip, pmu_cycles)
+                 VALUES (1, 1, 1, 1, 1.0, 1, 16, 100), (1, 1, 1, 1, 1.0, 2, 16, 200),
+                        (1, 1, 1, 1, 1.0, 3, 32, 100);",
+            )
+            .unwrap();
+        let views = ViewOptions {
+            min_samples: 2,
+            ..Default::default()
+        };
+        create_hotspots_view(&connection, &views, None)
+            .await
+            .unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT func_name, samples, total FROM hotspots")
+            .unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<String, _>("func_name").unwrap(), "hot");
+        assert_eq!(statement.read::<i64, _>("samples").unwrap(), 2);
+        assert_eq!(statement.read::<f64, _>("total").unwrap(), 0.75);
+        assert_eq!(statement.next().unwrap(), State::Done);
+    }
+
+    #[tokio::test]
+    async fn idle_functions_are_bucketed_or_left_out_of_hotspots() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        let mut event_schema = String::new();
+        for column in VIEW_COLUMNS {
+            event_schema.push_str(&format!(", {column} INTEGER"));
+        }
+        connection
+            .execute(schema::pmu_counters(&event_schema))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO proc_map (ip, func_name)
+                     VALUES (16, 'work'), (32, 'epoll_wait'), (48, '__sched_yield');
+                 INSERT INTO pmu_counters (process_id, thread_id, time_enabled, time_running,
+                     confidence, timestamp, ip, pmu_cycles)
+                 VALUES (1, 1, 1, 1, 1.0, 1, 16, 100), (1, 1, 1, 1, 1.0, 2, 32, 200),
+                        (1, 1, 1, 1, 1.0, 3, 48, 100);",
+            )
+            .unwrap();
+        let rows = |connection: &sqlite::Connection| {
+            let mut statement = connection
+                .prepare("SELECT func_name, cycles FROM hotspots ORDER BY func_name")
+                .unwrap();
+            let mut rows = Vec::new();
+            while let State::Row = statement.next().unwrap() {
+                rows.push((
+                    statement.read::<String, _>("func_name").unwrap(),
+                    statement.read::<i64, _>("cycles").unwrap(),
+                ));
+            }
+            rows
+        };
+
+        create_hotspots_view(&connection, &ViewOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            rows(&connection),
+            [("[idle]".to_string(), 300), ("work".to_string(), 100)]
+        );
+
+        connection.execute("DROP VIEW hotspots").unwrap();
+        let views = ViewOptions {
+            idle: IdleFilter::new(IdleMode::Exclude, &[]).unwrap(),
+            ..Default::default()
+        };
+        create_hotspots_view(&connection, &views, None)
+            .await
+            .unwrap();
+        assert_eq!(rows(&connection), [("work".to_string(), 100)]);
+    }
+
+    #[test]
+    fn failed_stages_are_cleared_before_they_resume() {
+        let db_path =
+            std::env::temp_dir().join(format!("mperf-resume-{}.db", uuid::Uuid::now_v7()));
+        let connection = sqlite::open(&db_path).unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        connection
+            .execute(schema::pmu_counters(", pmu_cycles INTEGER"))
+            .unwrap();
+        connection
+            .execute("INSERT INTO proc_map (ip, func_name) VALUES (16, 'work');")
+            .unwrap();
+        assert!(completed_stages(&connection).unwrap().is_empty());
+        mark_completed(&connection, Stage::Metadata).unwrap();
+        assert_eq!(completed_stages(&connection).unwrap(), [Stage::Metadata]);
+        assert!(is_incomplete(&db_path).unwrap());
+
+        // The counters stage failed after committing some rows.
+        Stage::Counters.clear(&connection).unwrap();
+        assert!(!schema::table_exists(&connection, "pmu_counters").unwrap());
+        let mut statement = connection.prepare("SELECT COUNT(*) FROM proc_map").unwrap();
+        assert_eq!(statement.next().unwrap(), State::Row);
+        assert_eq!(statement.read::<i64, _>(0).unwrap(), 0);
+        drop(statement);
+        assert!(schema::table_exists(&connection, "strings").unwrap());
+
+        for stage in [Stage::Counters, Stage::Disassembly, Stage::Views] {
+            mark_completed(&connection, stage).unwrap();
+        }
+        assert!(!is_incomplete(&db_path).unwrap());
+        drop(connection);
+        std::fs::remove_file(&db_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn views_can_be_rebuilt_in_place() {
+        let connection = sqlite::open(":memory:").unwrap();
+        schema::create_common_tables(&connection).unwrap();
+        let mut event_schema = String::new();
+        for column in VIEW_COLUMNS {
+            event_schema.push_str(&format!(", {column} INTEGER"));
+        }
+        connection
+            .execute(schema::pmu_counters(&event_schema))
+            .unwrap();
+        connection.execute(schema::ASSEMBLY_LINES).unwrap();
+        connection.execute(schema::ASSEMBLY_SAMPLES).unwrap();
+        create_instruction_mix_view(&connection).unwrap();
+        let info = test_record_info(ScenarioInfo::Snapshot(SnapshotInfo {
+            pid: 1,
+            attached_pid: None,
+            memory_latency: false,
+            counters: Vec::new(),
+            sampling_leader: None,
+            counted_totals: None,
+        }));
+
+        create_views(&connection, &info, &ViewOptions::default())
+            .await
+            .unwrap();
+        drop_views(&connection).unwrap();
+        assert!(!schema::table_exists(&connection, "hotspots").unwrap());
+        assert!(!schema::table_exists(&connection, "instruction_mix").unwrap());
+
+        create_instruction_mix_view(&connection).unwrap();
+        create_views(&connection, &info, &ViewOptions::default())
+            .await
+            .unwrap();
+        assert!(schema::table_exists(&connection, "hotspots").unwrap());
+        assert!(schema::table_exists(&connection, "thread_stats").unwrap());
+    }
 }
 
 async fn create_tma_view(connection: &sqlite::Connection, info: &ScenarioInfo) -> Result<()> {
@@ -1714,27 +3669,38 @@ async fn create_tma_view(connection: &sqlite::Connection, info: &ScenarioInfo) -
                 &expression,
                 marker.as_deref(),
             );
-            Ok::<String, anyhow::Error>(format!("{} AS {}", sql, metric.name.replace('.', "_")))
+            Ok::<(String, String), anyhow::Error>((metric.name.replace('.', "_"), sql))
         })
-        .collect::<Result<Vec<_>>>()?
-        .join(",\n");
+        .collect::<Result<Vec<_>>>()?;
+    let columns = [
+        ("func_name", "proc_map.func_name"),
+        ("num_samples", "COUNT(pmu_counters.pmu_cycles)"),
+        (
+            "total",
+            "SUM(pmu_counters.pmu_cycles) * 1.0 / NULLIF((SELECT SUM(pmu_cycles) FROM pmu_counters), 0)",
+        ),
+        ("cycles", "SUM(pmu_counters.pmu_cycles)"),
+        ("instructions", "SUM(pmu_counters.pmu_instructions)"),
+        (
+            "ipc",
+            "SUM(pmu_counters.pmu_instructions) * 1.0 / NULLIF(SUM(pmu_counters.pmu_cycles), 0)",
+        ),
+    ]
+    .map(|(name, expression)| (name.to_string(), expression.to_string()))
+    .into_iter()
+    .chain(columns)
+    .collect::<Vec<_>>();
 
     connection.execute(format!(
         "CREATE VIEW tma AS
          SELECT
-             proc_map.func_name AS func_name,
-             COUNT(pmu_counters.pmu_cycles) AS num_samples,
-             SUM(pmu_counters.pmu_cycles) * 1.0 /
-                 NULLIF((SELECT SUM(pmu_cycles) FROM pmu_counters), 0) AS total,
-             SUM(pmu_counters.pmu_cycles) AS cycles,
-             SUM(pmu_counters.pmu_instructions) AS instructions,
-             SUM(pmu_counters.pmu_instructions) * 1.0 /
-                 NULLIF(SUM(pmu_counters.pmu_cycles), 0) AS ipc,
-             {columns}
+             {}
          FROM pmu_counters
          INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
-         GROUP BY proc_map.func_name;"
+         GROUP BY proc_map.func_name;",
+        select_list(&columns)
     ))?;
+    record_view_columns(connection, "tma", &columns)?;
     create_tma_intervals_and_summary(connection, info)?;
     Ok(())
 }
@@ -1766,14 +3732,13 @@ fn build_tma_sql_expr(
                         event_type,
                         EventType::PmuCycles | EventType::PmuInstructions
                     ) {
-                        format!("SUM(pmu_counters.{column})")
+                        format!("pmu_counters.{column}")
                     } else {
-                        format!("SUM(pmu_counters.{column} / pmu_counters.confidence)")
+                        format!("pmu_counters.{column} / {}", schema::DESCALE_DIVISOR)
                     };
-                    marker.map_or(value.clone(), |marker| {
+                    marker.map_or(format!("SUM({value})"), |marker| {
                         format!(
-                            "SUM(CASE WHEN pmu_counters.{marker} IS NOT NULL THEN ({}) END)",
-                            value.trim_start_matches("SUM(").trim_end_matches(')')
+                            "SUM(CASE WHEN pmu_counters.{marker} IS NOT NULL THEN ({value}) END)"
                         )
                     })
                 })
@@ -1,48 +1,206 @@
 use anyhow::{Context, Result};
 use mperf_data::{
-    CallFrame, Event, IPCMessage, ProcMapEntry, RecordInfo, RooflineInfo, ScenarioInfo,
+    CallFrame, CountedTotals, Event, EventType, IPCMessage, ProcMapEntry, RecordInfo, RooflineInfo,
+    ScenarioInfo, TargetStatus, ThreadName,
 };
 use std::{
     collections::HashMap,
     fs::File,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use pmu::{Counter, Process, Record};
+use pmu::{
+    Counter, CountingDriver, Process, ReaderStatus, Record, SamplingDriver, TaskScope, UnwindMode,
+};
 
 const SIZE_16MB: usize = 16 * 1024 * 1024;
 
+/// A reader that has not finished a pass over its buffers for this long is
+/// reported as stalled.
+const READER_STALL_WARNING: Duration = Duration::from_secs(5);
+const TARGET_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Sample frequency in hertz unless a short command is recorded again.
+const DEFAULT_SAMPLE_FREQ: u64 = 1000;
+/// Launched commands that exit sooner leave too few samples at the default
+/// frequency to rank their functions.
+const SHORT_RUN: Duration = Duration::from_millis(100);
+/// Samples a short command is recorded again to collect.
+const SHORT_RUN_SAMPLES: f64 = 1000.0;
+/// Highest sample frequency where the kernel does not publish its limit.
+const FALLBACK_MAX_SAMPLE_RATE: u64 = 10_000;
+
 use crate::{
-    counter_selection::{get_pmu_counters, get_tma_counter_groups},
+    counter_selection::{
+        add_l1_cache_counters, get_tma_counter_groups, leader_counter, record_counters,
+    },
+    disassembly::AsmSyntax,
     event_dispatcher::EventDispatcher,
-    postprocess::perform_postprocessing,
-    utils::counter_to_event_ty,
+    exit_code::Outcome,
+    idle::IdleFilter,
+    logging,
+    postprocess::{perform_postprocessing, ViewOptions},
+    utils::{counter_to_event_ty, custom_counter_name},
     Scenario,
 };
 
 #[cfg(target_os = "macos")]
 const VM_PROT_EXECUTE: i32 = 0x4;
 
+/// Command-line settings shared by every recording scenario.
+pub struct RecordOptions {
+    /// Process to attach to (snapshot only).
+    pub pid: Option<u32>,
+    /// Extra split debug info roots for postprocessing.
+    pub debug_dirs: Vec<PathBuf>,
+    /// Syntax sampled functions are disassembled in.
+    pub asm_syntax: AsmSyntax,
+    /// Idle functions bucketed or dropped by postprocessing.
+    pub idle: IdleFilter,
+    /// Data pages per sampling ring buffer.
+    pub mmap_pages: usize,
+    /// Print a JSON summary to stdout once postprocessing is done.
+    pub print_summary: bool,
+    /// Sample slow loads with their latency (snapshot only).
+    pub mem_latency: bool,
+    /// Sample page faults with their call stacks (snapshot only).
+    pub page_faults: bool,
+    /// Also sample the level 1 cache counters (snapshot only).
+    pub l1_cache: bool,
+    /// CPUs sampling is restricted to; empty samples on every CPU.
+    pub sample_cpus: Vec<u32>,
+    /// Also sample processes the target forks.
+    pub follow_forks: bool,
+    /// Sample only the target thread, without the tasks it creates.
+    pub no_children: bool,
+    /// Events replacing the scenario's default counters, when not empty.
+    pub events: Vec<String>,
+    /// The only event that interrupts the target (snapshot only).
+    pub leader: Option<String>,
+    /// Count total cycles and instructions next to sampling (snapshot only).
+    pub count_totals: bool,
+    /// Sample only the interrupted instruction, without call stacks.
+    pub no_callstack: bool,
+    /// Build the database in memory instead of writing `perf.db`.
+    pub in_memory: bool,
+    /// Clock the samples are stamped with.
+    pub clock: mperf_data::SampleClock,
+    /// Record a launched command that exits quickly again at a higher
+    /// sample frequency (snapshot only).
+    pub auto_freq: bool,
+}
+
+impl RecordOptions {
+    fn unwind_mode(&self) -> UnwindMode {
+        if self.no_callstack {
+            UnwindMode::None
+        } else {
+            UnwindMode::default()
+        }
+    }
+
+    fn sample_clock(&self) -> pmu::SampleClock {
+        match self.clock {
+            mperf_data::SampleClock::Perf => pmu::SampleClock::Perf,
+            mperf_data::SampleClock::Monotonic => pmu::SampleClock::Monotonic,
+            mperf_data::SampleClock::MonotonicRaw => pmu::SampleClock::MonotonicRaw,
+        }
+    }
+}
+
+/// Records into `output_directory` and returns the postprocessed database.
 pub async fn do_record(
     scenario: Scenario,
     output_directory: &Path,
-    pid: Option<u32>,
+    options: &RecordOptions,
     command: Vec<String>,
-) -> Result<()> {
-    println!("Record profile with {scenario:?} scenario");
+) -> Result<sqlite::Connection> {
+    tracing::info!("Record profile with {scenario:?} scenario");
+    if options.mem_latency && scenario != Scenario::Snapshot {
+        anyhow::bail!("--mem-latency is only supported by the snapshot scenario");
+    }
+    if options.page_faults && scenario != Scenario::Snapshot {
+        anyhow::bail!("--page-faults is only supported by the snapshot scenario");
+    }
+    if options.l1_cache && scenario != Scenario::Snapshot {
+        anyhow::bail!("--l1-cache is only supported by the snapshot scenario");
+    }
+    if options.count_totals && scenario != Scenario::Snapshot {
+        anyhow::bail!("--count-totals is only supported by the snapshot scenario");
+    }
 
-    let (dispatcher, join_handle) = EventDispatcher::new(output_directory);
+    let frequency_policies = crate::cpufreq::host_frequency_policies();
+    if let Some(warning) = crate::cpufreq::governor_warning(&frequency_policies) {
+        tracing::warn!("{warning}");
+    }
 
-    let info = match scenario {
-        Scenario::Snapshot => snapshot(dispatcher.clone(), pid, &command)?,
-        Scenario::Roofline => roofline(dispatcher.clone(), &command).await?,
-        Scenario::TMA => topdown(dispatcher.clone(), &command)?,
-    };
+    let mut sample_freq = DEFAULT_SAMPLE_FREQ;
+    let (info, target, lost_events, kernel_lost_samples) = loop {
+        let (dispatcher, join_handle) = EventDispatcher::new(output_directory);
 
-    drop(dispatcher);
+        let started = Instant::now();
+        let (info, target) = match scenario {
+            Scenario::Snapshot => snapshot(dispatcher.clone(), options, &command, sample_freq)?,
+            Scenario::Roofline => roofline(dispatcher.clone(), options, &command).await?,
+            Scenario::TMA => topdown(dispatcher.clone(), options, &command)?,
+        };
+        let runtime = started.elapsed();
+
+        let lost_events = dispatcher.lost_events();
+        let kernel_lost_samples = dispatcher.kernel_lost_samples();
+        drop(dispatcher);
+        if kernel_lost_samples > 0 {
+            tracing::warn!(
+                "kernel dropped {kernel_lost_samples} samples because the ring buffer was full; \
+                 raise --mmap-pages or lower the sample rate"
+            );
+        }
+        if lost_events > 0 {
+            tracing::warn!("mperf dropped {lost_events} events before writing them to disk");
+        }
 
-    join_handle.join().await;
+        join_handle.join().await;
+        save_perf_maps(output_directory);
+
+        // Only a launched command that ran to completion can be run again;
+        // an attached process is gone.
+        let short = scenario == Scenario::Snapshot
+            && options.pid.is_none()
+            && runtime < SHORT_RUN
+            && target.as_ref().is_some_and(|target| target.exit_code == 0);
+        if !short {
+            break (info, target, lost_events, kernel_lost_samples);
+        }
+        let raised = raised_sample_freq(runtime, sample_freq, max_sample_rate());
+        match raised.filter(|_| sample_freq == DEFAULT_SAMPLE_FREQ) {
+            Some(raised) if options.auto_freq => {
+                tracing::warn!(
+                    "the command ran for {} ms, too short for reliable sampling at {sample_freq} Hz; \
+                     recording it again at {raised} Hz",
+                    runtime.as_millis()
+                );
+                sample_freq = raised;
+            }
+            Some(raised) => {
+                tracing::warn!(
+                    "the command ran for {} ms, too short for reliable sampling at {sample_freq} Hz; \
+                     profile a longer run, or pass --auto-freq to record it again at {raised} Hz",
+                    runtime.as_millis()
+                );
+                break (info, target, lost_events, kernel_lost_samples);
+            }
+            None => {
+                tracing::warn!(
+                    "the command ran for {} ms, too short for reliable sampling at {sample_freq} Hz; \
+                     profile a longer run, e.g. with more iterations",
+                    runtime.as_millis()
+                );
+                break (info, target, lost_events, kernel_lost_samples);
+            }
+        }
+    };
 
     let json_command = if !command.is_empty() {
         Some(command.clone())
@@ -68,6 +226,17 @@ pub async fn do_record(
         cpu_model,
         cpu_vendor,
         cores,
+        output_directory: Some(output_directory.to_path_buf()),
+        mmap_pages: Some(options.mmap_pages),
+        sample_cpus: options.sample_cpus.clone(),
+        debug_dirs: options
+            .debug_dirs
+            .iter()
+            .map(std::path::absolute)
+            .collect::<std::io::Result<_>>()?,
+        target,
+        frequency_policies,
+        sample_clock: options.clock,
         scenario_info: info,
     };
 
@@ -76,120 +245,507 @@ pub async fn do_record(
         serde_json::to_writer(&mut info_file, &ri)?;
     }
 
-    println!("Postprocessing...");
+    tracing::info!("Postprocessing...");
     kdam::term::init(false);
     kdam::term::hide_cursor()?;
 
-    let pb = kdam::tqdm!(total = 100);
-    perform_postprocessing(output_directory, pb).await?;
+    // The bar writes to the terminal directly; only the log level decides
+    // whether it is shown.
+    let pb = kdam::tqdm!(
+        total = 100,
+        disable = !tracing::enabled!(tracing::Level::INFO)
+    );
+    let connection = if options.in_memory {
+        sqlite::open(":memory:")?
+    } else {
+        sqlite::open(output_directory.join("perf.db"))?
+    };
+    perform_postprocessing(
+        &connection,
+        output_directory,
+        pb,
+        &options.debug_dirs,
+        options.asm_syntax,
+        &ViewOptions {
+            idle: options.idle.clone(),
+            ..Default::default()
+        },
+    )
+    .await?;
 
     kdam::term::show_cursor()?;
 
-    Ok(())
+    if options.print_summary {
+        let summary = record_summary(
+            &connection,
+            output_directory,
+            scenario,
+            lost_events,
+            kernel_lost_samples,
+        )?;
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+
+    // The results stay on disk either way; the exit code tells scripts
+    // whether they are worth looking at.
+    if let Some(code) = target
+        .map(|target| target.exit_code)
+        .filter(|code| *code != 0)
+    {
+        return Err(Outcome::TargetFailed(code).into());
+    }
+    if sample_count(&connection)? == 0 {
+        return Err(Outcome::NoSamples.into());
+    }
+
+    Ok(connection)
+}
+
+/// Copies the perf maps JITs wrote to `/tmp` for the recorded processes into
+/// `output_directory`, so their code is symbolized even after `/tmp` is
+/// cleaned or the results are moved to another machine.
+fn save_perf_maps(output_directory: &Path) {
+    let Ok(proc_map) = std::fs::read_to_string(output_directory.join("proc_map.json")) else {
+        return;
+    };
+    let Ok(proc_map) = serde_json::from_str::<Vec<ProcMapEntry>>(&proc_map) else {
+        return;
+    };
+    let pids = proc_map
+        .iter()
+        .map(|entry| entry.pid)
+        .collect::<std::collections::BTreeSet<_>>();
+    for pid in pids {
+        let name = crate::utils::perf_map_file_name(pid);
+        let perf_map = Path::new("/tmp").join(&name);
+        if !perf_map.exists() {
+            continue;
+        }
+        match std::fs::copy(&perf_map, output_directory.join(&name)) {
+            Ok(_) => tracing::debug!("saved the JIT symbols of pid {pid}"),
+            Err(err) => tracing::warn!("failed to save {}: {err}", perf_map.display()),
+        }
+    }
+}
+
+/// The frequency that collects [`SHORT_RUN_SAMPLES`] over `runtime`, capped
+/// at `max_rate`. `None` when that is no higher than `current`.
+fn raised_sample_freq(runtime: Duration, current: u64, max_rate: u64) -> Option<u64> {
+    let wanted = (SHORT_RUN_SAMPLES / runtime.as_secs_f64().max(1e-3)).ceil() as u64;
+    let raised = wanted.min(max_rate);
+    (raised > current).then_some(raised)
+}
+
+/// The kernel's limit on sample frequencies, which it lowers on its own when
+/// sampling interrupts take too long.
+fn max_sample_rate() -> u64 {
+    std::fs::read_to_string("/proc/sys/kernel/perf_event_max_sample_rate")
+        .ok()
+        .and_then(|rate| rate.trim().parse().ok())
+        .unwrap_or(FALLBACK_MAX_SAMPLE_RATE)
 }
 
+fn sample_count(connection: &sqlite::Connection) -> Result<i64> {
+    let mut statement = connection.prepare("SELECT COUNT(*) AS samples FROM pmu_counters;")?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>("samples")?)
+}
+
+/// Each scenario also returns how the command it launched ended.
 fn snapshot(
     dispatcher: Arc<EventDispatcher>,
-    pid: Option<u32>,
+    options: &RecordOptions,
     command: &[String],
-) -> Result<ScenarioInfo> {
+    sample_freq: u64,
+) -> Result<(ScenarioInfo, Option<TargetStatus>)> {
+    let pid = options.pid;
     if pid.is_none() && command.is_empty() {
         anyhow::bail!("record snapshot requires a command or --pid");
     }
 
-    let process = if pid.is_none() {
-        Some(Process::new(command, &[])?)
-    } else {
+    // With both a pid and a command (e.g. a server and its benchmark client),
+    // each target gets its own driver and both stream into one events.bin.
+    let process = if command.is_empty() {
         None
+    } else {
+        Some(Process::new(command, &[])?)
     };
 
-    let counters = get_pmu_counters(Scenario::Snapshot);
+    let mut counters = record_counters(Scenario::Snapshot, &options.events)?;
+    if options.l1_cache {
+        add_l1_cache_counters(&mut counters);
+    }
+    let leader = options.leader.as_deref().map(leader_counter).transpose()?;
+    if let Some(leader) = leader.as_ref().filter(|leader| !counters.contains(leader)) {
+        counters.insert(0, leader.clone());
+    }
 
-    let mut builder = pmu::SamplingDriverBuilder::new().counters(&counters);
+    let sampler = || {
+        let builder = pmu::SamplingDriverBuilder::new()
+            .counters(&counters)
+            .sample_freq(sample_freq)
+            .mmap_pages(options.mmap_pages)
+            .cpus(&options.sample_cpus)
+            .follow_forks(options.follow_forks)
+            .clock(options.sample_clock())
+            .thread_only(options.no_children)
+            .unwind_mode(options.unwind_mode());
+        if options.mem_latency {
+            builder.memory_latency()
+        } else if let Some(leader) = &leader {
+            builder.leader(leader.clone())
+        } else {
+            builder
+        }
+    };
+    let mut drivers = Vec::new();
     if let Some(process) = &process {
-        builder = builder.process(process);
-    } else if let Some(pid) = pid {
-        builder = builder.pid(pid as i32);
+        drivers.push(sampler().process(process).build()?);
     }
-    let mut driver = builder.build()?;
-    let recorded_pid = pid.unwrap_or_else(|| process.as_ref().unwrap().pid() as u32) as i32;
+    if let Some(pid) = pid {
+        drivers.push(
+            sampler()
+                .pid(pid as i32)
+                .build()
+                .with_context(|| format!("failed to attach to pid {pid}"))?,
+        );
+    }
+    // Page faults are a software event, so they lead a group of their own
+    // that samples each fault's call stack.
+    let fault_drivers_start = drivers.len();
+    if options.page_faults {
+        let fault_sampler = || {
+            pmu::SamplingDriverBuilder::new()
+                .counters(&[Counter::PageFaults])
+                .mmap_pages(options.mmap_pages)
+                .cpus(&options.sample_cpus)
+                .follow_forks(options.follow_forks)
+                .clock(options.sample_clock())
+                .thread_only(options.no_children)
+                .unwind_mode(options.unwind_mode())
+        };
+        if let Some(process) = &process {
+            drivers.push(fault_sampler().process(process).build()?);
+        }
+        if let Some(pid) = pid {
+            drivers.push(
+                fault_sampler()
+                    .pid(pid as i32)
+                    .build()
+                    .with_context(|| format!("failed to sample page faults of pid {pid}"))?,
+            );
+        }
+    }
+    for driver in &drivers[..fault_drivers_start] {
+        warn_on_narrow_scope(driver.as_ref(), options);
+    }
+    let launched_pid = process.as_ref().map(|process| process.pid());
+    let attached_pid = pid.map(|pid| pid as i32);
     // On macOS Process::new returns an already-exec'd, suspended child, so its
     // dyld mappings are available before the first instruction is profiled.
     // Attached processes are already live on every platform.
-    if cfg!(target_os = "macos") || pid.is_some() {
-        publish_process_maps(dispatcher.clone(), recorded_pid);
+    if let Some(launched_pid) = launched_pid.filter(|_| cfg!(target_os = "macos")) {
+        publish_process_maps(dispatcher.clone(), launched_pid);
+    }
+    if let Some(attached_pid) = attached_pid {
+        publish_process_maps(dispatcher.clone(), thread_group_id(attached_pid));
     }
 
-    let sample_dispatcher = dispatcher.clone();
-    driver.start(Arc::new(move |record| {
-        match record {
-            Record::Sample(sample) => {
-                let unique_id = uuid::Uuid::now_v7().as_u128();
-                let callstack = sample.callstack.into_iter().map(CallFrame::IP).collect();
-                let name = if let Counter::Custom(name) = &sample.counter {
-                    sample_dispatcher.string_id(name)
-                } else {
-                    0
-                };
-                let event = Event {
-                    unique_id,
-                    correlation_id: sample.event_id,
-                    parent_id: 0,
-                    ty: counter_to_event_ty(&sample.counter),
-                    thread_id: sample.tid,
-                    process_id: sample.pid,
-                    cpu: sample.cpu,
-                    time_enabled: sample.time_enabled,
-                    time_running: sample.time_running,
-                    value: sample.value,
-                    timestamp: sample.time,
-                    name,
-                    callstack,
-                    user_regs: sample.user_regs.map(|regs| mperf_data::UserRegs {
-                        abi: regs.abi,
-                        mask: regs.mask,
-                        values: regs.values,
-                    }),
-                    user_stack: sample.user_stack,
-                };
-
-                sample_dispatcher.publish_event_sync(event);
-            }
-            Record::ProcAddr(addr) => {
-                let entry = ProcMapEntry {
-                    filename: addr.filename,
-                    address: addr.addr as usize,
-                    size: addr.len as usize,
-                    offset: addr.pgoff as usize,
-                    pid: addr.pid,
-                };
-
-                sample_dispatcher.publish_proc_map_sync(entry);
-            }
+    // The counting group runs next to the sampling one and gives the totals
+    // that sample shares are scaled by.
+    let mut total_counters = Vec::new();
+    if options.count_totals {
+        let counter = || {
+            pmu::CountingDriverBuilder::new()
+                .counters(&[Counter::Cycles, Counter::Instructions])
+                .follow_forks(options.follow_forks)
         };
-    }))?;
+        if let Some(process) = &process {
+            total_counters.push(
+                counter()
+                    .process(Some(process))
+                    .build()
+                    .context("failed to count totals")?,
+            );
+        }
+        if let Some(pid) = pid {
+            total_counters.push(
+                counter()
+                    .pid(Some(pid as i32))
+                    .build()
+                    .with_context(|| format!("failed to count totals of pid {pid}"))?,
+            );
+        }
+        for driver in &mut total_counters {
+            driver.reset()?;
+            driver.start()?;
+        }
+    }
+
+    let sample_dispatcher = dispatcher.clone();
+    let callback =
+        Arc::new(move |record| publish_snapshot_record(&sample_dispatcher, record, false));
+    let fault_dispatcher = dispatcher.clone();
+    let fault_callback =
+        Arc::new(move |record| publish_snapshot_record(&fault_dispatcher, record, true));
+    for (index, driver) in drivers.iter_mut().enumerate() {
+        if index < fault_drivers_start {
+            driver.start(callback.clone())?;
+        } else {
+            driver.start(fault_callback.clone())?;
+        }
+    }
+    // The launched command bounds the recording; an attached process alone is
+    // followed until it exits.
     if let Some(process) = &process {
         process.cont();
         std::thread::sleep(std::time::Duration::from_millis(20));
-        publish_process_maps(dispatcher.clone(), recorded_pid);
-        process.wait()?;
-    } else if let Some(pid) = pid {
-        while unsafe { libc::kill(pid as i32, 0) } == 0 {
-            std::thread::sleep(std::time::Duration::from_millis(100));
-        }
+        publish_process_maps(dispatcher.clone(), process.pid());
     }
-    driver.stop()?;
+    wait_for_targets(process.as_ref(), pid, &mut drivers)?;
+    for driver in &mut drivers {
+        driver.stop()?;
+    }
+    let counted_totals = if options.count_totals {
+        Some(read_totals(&mut total_counters)?)
+    } else {
+        None
+    };
 
-    Ok(ScenarioInfo::Snapshot(mperf_data::SnapshotInfo {
-        pid: recorded_pid,
+    let info = ScenarioInfo::Snapshot(mperf_data::SnapshotInfo {
+        pid: launched_pid.or(attached_pid).unwrap_or_default(),
+        attached_pid: attached_pid.filter(|_| launched_pid.is_some()),
+        memory_latency: options.mem_latency,
         counters: counters
             .iter()
             .map(|counter| (counter_to_event_ty(counter), counter.name().to_string()))
             .collect(),
+        sampling_leader: leader.map(|leader| leader.name().to_string()),
+        counted_totals,
+    });
+    Ok((info, process.as_ref().and_then(target_status)))
+}
+
+/// Exit status and resource usage of a launched command that has exited.
+/// Reaps the command, so call it once its counters are stopped.
+fn target_status(process: &Process) -> Option<TargetStatus> {
+    let exit_code = process.exit_code()?;
+    let usage = process.resource_usage();
+    Some(TargetStatus {
+        exit_code,
+        max_rss_bytes: usage.map(|usage| usage.max_rss_bytes),
+        user_time_us: usage.map(|usage| usage.user_time.as_micros() as u64),
+        system_time_us: usage.map(|usage| usage.system_time.as_micros() as u64),
+    })
+}
+
+/// Stops the counting groups and sums their totals over all targets.
+fn read_totals(drivers: &mut [Box<dyn CountingDriver>]) -> Result<CountedTotals> {
+    let mut totals = CountedTotals::default();
+    for driver in drivers {
+        driver.stop()?;
+        let result = driver.counters()?;
+        let add = |total: &mut Option<u64>, counter| {
+            if let Some(value) = result.get(counter) {
+                *total = Some(total.unwrap_or_default() + value.value);
+            }
+        };
+        add(&mut totals.cycles, Counter::Cycles);
+        add(&mut totals.instructions, Counter::Instructions);
+    }
+    Ok(totals)
+}
+
+/// Forwards one snapshot record to the dispatcher. Samples of the page-fault
+/// driver are tagged so postprocessing keeps them out of the counter groups.
+fn publish_snapshot_record(
+    sample_dispatcher: &EventDispatcher,
+    record: Record,
+    page_fault_samples: bool,
+) {
+    match record {
+        Record::Sample(sample) => {
+            let unique_id = uuid::Uuid::now_v7().as_u128();
+            let callstack = sample.callstack.into_iter().map(CallFrame::IP).collect();
+            let name = custom_counter_name(&sample.counter)
+                .map_or(0, |name| sample_dispatcher.string_id(name));
+            let event = Event {
+                unique_id,
+                correlation_id: sample.event_id,
+                parent_id: 0,
+                ty: if page_fault_samples {
+                    EventType::OsPageFaultSample
+                } else {
+                    counter_to_event_ty(&sample.counter)
+                },
+                thread_id: sample.tid,
+                process_id: sample.pid,
+                cpu: sample.cpu,
+                time_enabled: sample.time_enabled,
+                time_running: sample.time_running,
+                value: sample.value,
+                timestamp: sample.time,
+                name,
+                callstack,
+                user_regs: sample.user_regs.map(|regs| mperf_data::UserRegs {
+                    abi: regs.abi,
+                    mask: regs.mask,
+                    values: regs.values,
+                }),
+                user_stack: sample.user_stack,
+                weight: sample.weight,
+            };
+
+            sample_dispatcher.publish_event_sync(event);
+        }
+        Record::ProcAddr(addr) => {
+            let entry = ProcMapEntry {
+                filename: addr.filename.to_string_lossy().into_owned(),
+                address: addr.addr as usize,
+                size: addr.len as usize,
+                offset: addr.pgoff as usize,
+                pid: addr.pid,
+                build_id: None,
+                exec_generation: 0,
+            };
+
+            sample_dispatcher.publish_proc_map_sync(entry);
+        }
+        Record::ThreadName(thread) => sample_dispatcher.publish_thread_name_sync(ThreadName {
+            pid: thread.pid,
+            tid: thread.tid,
+            name: thread.name,
+        }),
+        Record::Fork(task) => sample_dispatcher.publish_fork_sync(task.pid, task.ppid, task.time),
+        Record::Exit(task) => sample_dispatcher.publish_exit_sync(task.pid, task.tid, task.time),
+        Record::Exec(exec) => sample_dispatcher.publish_exec_sync(exec.pid, exec.time),
+        Record::Lost(count) => sample_dispatcher.add_kernel_lost_samples(count),
+    };
+}
+
+/// Number of hottest functions listed by `--print-summary`.
+const SUMMARY_TOP_FUNCTIONS: usize = 5;
+
+/// Compact machine-readable digest of a recording for CI pipelines. TMA
+/// results have no `hotspots` view, so their function list is empty.
+fn record_summary(
+    connection: &sqlite::Connection,
+    output_directory: &Path,
+    scenario: Scenario,
+    lost_events: u64,
+    kernel_lost_samples: u64,
+) -> Result<serde_json::Value> {
+    let samples = sample_count(connection)?;
+
+    let mut top_functions = Vec::new();
+    if let Ok(mut statement) = connection.prepare(format!(
+        "SELECT func_name, cycles, total FROM hotspots ORDER BY cycles DESC LIMIT {SUMMARY_TOP_FUNCTIONS};"
+    )) {
+        while let sqlite::State::Row = statement.next()? {
+            top_functions.push(serde_json::json!({
+                "function": statement.read::<Option<String>, _>("func_name")?,
+                "cycles": statement.read::<Option<i64>, _>("cycles")?,
+                "share": statement.read::<Option<f64>, _>("total")?,
+            }));
+        }
+    }
+
+    Ok(serde_json::json!({
+        "result_directory": output_directory,
+        "scenario": scenario,
+        "samples": samples,
+        "top_functions": top_functions,
+        "lost_events": lost_events,
+        "kernel_lost_samples": kernel_lost_samples,
     }))
 }
 
+/// Waits until the launched process exits, or the attached `pid` when
+/// nothing was launched, while watching the sampling readers. A reader that
+/// dies mid-run fails the recording with its panic instead of producing an
+/// empty capture.
+/// Tells the user when the kernel could not inherit the sampling events as far
+/// as `--follow-forks` asked for.
+fn warn_on_narrow_scope(driver: &dyn SamplingDriver, options: &RecordOptions) {
+    let requested = if options.no_children {
+        TaskScope::Thread
+    } else if options.follow_forks {
+        TaskScope::ProcessTree
+    } else {
+        TaskScope::Process
+    };
+    match driver.task_scope() {
+        TaskScope::Thread if requested > TaskScope::Thread && cfg!(target_os = "linux") => {
+            tracing::warn!(
+                "this kernel cannot inherit sampling groups (Linux 6.12 or newer is needed); \
+             only the target's main thread is sampled"
+            )
+        }
+        TaskScope::Process if requested == TaskScope::ProcessTree => {
+            tracing::warn!("child processes of the target are not sampled")
+        }
+        _ => {}
+    }
+}
+
+fn wait_for_targets(
+    process: Option<&Process>,
+    pid: Option<u32>,
+    drivers: &mut [Box<dyn SamplingDriver>],
+) -> Result<()> {
+    let mut stall_reported = false;
+    loop {
+        let exited = match (process, pid) {
+            (Some(process), _) => process.try_wait()?,
+            (None, Some(pid)) => unsafe { libc::kill(pid as i32, 0) != 0 },
+            (None, None) => true,
+        };
+        if exited {
+            return Ok(());
+        }
+
+        for driver in drivers.iter_mut() {
+            match driver.reader_status() {
+                ReaderStatus::Exited => {
+                    driver
+                        .stop()
+                        .context("sampling stopped before the workload finished")?;
+                    anyhow::bail!("sampling reader exited before the workload finished");
+                }
+                ReaderStatus::Running { since_heartbeat }
+                    if since_heartbeat >= READER_STALL_WARNING && !stall_reported =>
+                {
+                    tracing::warn!(
+                        "sampling reader has made no progress for {}s; samples are likely being lost",
+                        since_heartbeat.as_secs()
+                    );
+                    stall_reported = true;
+                }
+                _ => {}
+            }
+        }
+        std::thread::sleep(TARGET_POLL_INTERVAL);
+    }
+}
+
+/// The process `tid` belongs to, so `--pid` also accepts a thread ID. Samples
+/// carry the process ID, which the mappings must be published under.
+fn thread_group_id(tid: i32) -> i32 {
+    std::fs::read_to_string(format!("/proc/{tid}/status"))
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Tgid:"))
+                .and_then(|tgid| tgid.trim().parse().ok())
+        })
+        .unwrap_or(tid)
+}
+
 fn publish_process_maps(dispatcher: Arc<EventDispatcher>, pid: i32) {
+    // Forked processes report their arguments as perf sees them start; the
+    // target itself is only seen here.
+    dispatcher.publish_command_line_sync(pid as u32);
     #[cfg(target_os = "macos")]
     if let Ok(images) = proc_maps::mac_maps::get_dyld_info(pid as proc_maps::Pid) {
         if !images.is_empty() {
@@ -220,6 +776,8 @@ fn publish_process_maps(dispatcher: Arc<EventDispatcher>, pid: i32) {
                     // `runtime - address + offset` reconstructs that address.
                     offset: link_address as usize,
                     pid: pid as u32,
+                    build_id: None,
+                    exec_generation: 0,
                 };
                 dispatcher.publish_proc_map_sync(entry);
             }
@@ -252,6 +810,8 @@ fn publish_process_maps(dispatcher: Arc<EventDispatcher>, pid: i32) {
             #[cfg(not(target_os = "linux"))]
             offset: 0,
             pid: pid as u32,
+            build_id: None,
+            exec_generation: 0,
         };
         dispatcher.publish_proc_map_sync(entry);
     }
@@ -280,7 +840,11 @@ fn get_exe_dir() -> std::io::Result<PathBuf> {
     Ok(exe_path)
 }
 
-async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<ScenarioInfo> {
+async fn roofline(
+    dispatcher: Arc<EventDispatcher>,
+    options: &RecordOptions,
+    command: &[String],
+) -> Result<(ScenarioInfo, Option<TargetStatus>)> {
     let exe_path = get_exe_dir()?.to_str().unwrap().to_string();
 
     // FIXME make this platform independent
@@ -289,7 +853,7 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
         Err(_) => format!("{}:{}/../lib", exe_path, exe_path),
     };
 
-    println!(
+    tracing::info!(
         "Run 1: collecting performance data for '{}'",
         command.join(" ")
     );
@@ -301,17 +865,25 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
         command,
         &[
             ("MPERF_COLLECTOR_SHMEM_ID".to_string(), pipe_name.clone()),
+            logging::collector_env(),
             ("LD_LIBRARY_PATH".to_string(), ld_path.clone()),
             ("MPERF_COLLECTOR_ENABLED".to_string(), "1".to_string()),
         ],
     )?;
 
-    let counters = get_pmu_counters(Scenario::Roofline);
+    let counters = record_counters(Scenario::Roofline, &options.events)?;
 
     let mut driver = pmu::SamplingDriverBuilder::new()
         .counters(&counters)
         .process(&process)
+        .mmap_pages(options.mmap_pages)
+        .cpus(&options.sample_cpus)
+        .follow_forks(options.follow_forks)
+        .clock(options.sample_clock())
+        .thread_only(options.no_children)
+        .unwind_mode(options.unwind_mode())
         .build()?;
+    warn_on_narrow_scope(driver.as_ref(), options);
 
     let roofline_dispatcher = dispatcher.clone();
 
@@ -320,11 +892,8 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
             Record::Sample(sample) => {
                 let unique_id = uuid::Uuid::now_v7().as_u128();
                 let callstack = sample.callstack.into_iter().map(CallFrame::IP).collect();
-                let name = if let Counter::Custom(name) = &sample.counter {
-                    dispatcher.string_id(name)
-                } else {
-                    0
-                };
+                let name = custom_counter_name(&sample.counter)
+                    .map_or(0, |name| dispatcher.string_id(name));
                 let event = Event {
                     unique_id,
                     correlation_id: sample.event_id,
@@ -345,32 +914,45 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
                         values: regs.values,
                     }),
                     user_stack: sample.user_stack,
+                    weight: sample.weight,
                 };
 
                 dispatcher.publish_event_sync(event);
             }
             Record::ProcAddr(addr) => {
                 let entry = ProcMapEntry {
-                    filename: addr.filename,
+                    filename: addr.filename.to_string_lossy().into_owned(),
                     address: addr.addr as usize,
                     size: addr.len as usize,
                     offset: addr.pgoff as usize,
                     pid: addr.pid,
+                    build_id: None,
+                    exec_generation: 0,
                 };
 
                 dispatcher.publish_proc_map_sync(entry);
             }
+            Record::ThreadName(thread) => dispatcher.publish_thread_name_sync(ThreadName {
+                pid: thread.pid,
+                tid: thread.tid,
+                name: thread.name,
+            }),
+            Record::Fork(task) => dispatcher.publish_fork_sync(task.pid, task.ppid, task.time),
+            Record::Exit(task) => dispatcher.publish_exit_sync(task.pid, task.tid, task.time),
+            Record::Exec(exec) => dispatcher.publish_exec_sync(exec.pid, exec.time),
+            Record::Lost(count) => dispatcher.add_kernel_lost_samples(count),
         };
     }))?;
 
     process.cont();
-    process.wait()?;
+    wait_for_targets(Some(&process), None, std::slice::from_mut(&mut driver))?;
     driver.stop()?;
     task.await?;
 
     let perf_pid = process.pid();
+    let perf_status = target_status(&process);
 
-    println!(
+    tracing::info!(
         "Run 2: collecting loop statistics for '{}'",
         command.join(" ")
     );
@@ -382,6 +964,7 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
         command,
         &[
             ("MPERF_COLLECTOR_SHMEM_ID".to_string(), pipe_name.clone()),
+            logging::collector_env(),
             ("LD_LIBRARY_PATH".to_string(), ld_path),
             ("MPERF_COLLECTOR_ENABLED".to_string(), "1".to_string()),
             (
@@ -398,14 +981,18 @@ async fn roofline(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Resul
 
     let inst_pid = process.pid();
 
-    Ok(ScenarioInfo::Roofline(RooflineInfo {
+    let info = ScenarioInfo::Roofline(RooflineInfo {
         perf_pid,
         counters: counters
             .iter()
             .map(|counter| (counter_to_event_ty(counter), counter.name().to_string()))
             .collect(),
         inst_pid,
-    }))
+    });
+    let status = perf_status
+        .filter(|status| status.exit_code != 0)
+        .or(target_status(&process));
+    Ok((info, status))
 }
 
 fn create_shmem_pipe(
@@ -447,12 +1034,20 @@ fn create_shmem_pipe(
                 }
             }
         }
+        roofline_dispatcher.add_lost_events(rx.dropped_count() as u64);
     });
 
     Ok((pipe_name, task))
 }
 
-fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<ScenarioInfo> {
+fn topdown(
+    dispatcher: Arc<EventDispatcher>,
+    options: &RecordOptions,
+    command: &[String],
+) -> Result<(ScenarioInfo, Option<TargetStatus>)> {
+    if command.is_empty() {
+        anyhow::bail!("record tma requires a command");
+    }
     let scenario = pmu::host_tma_scenario().context("TMA is not supported on this CPU")?;
     let process = Process::new(command, &[])?;
     // Validate the formula groups, but do not turn each one into an independent
@@ -460,7 +1055,7 @@ fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<Scena
     // severely perturb the workload (especially while capturing DWARF stacks).
     // The original TMA collector sampled the deduplicated event set once.
     get_tma_counter_groups(&scenario)?;
-    let counters = get_pmu_counters(Scenario::TMA);
+    let counters = record_counters(Scenario::TMA, &options.events)?;
 
     // TMA uses the same sampling engine and attribution mode as Snapshot.
     // Only the counter set differs. The original TMA implementation worked
@@ -469,7 +1064,14 @@ fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<Scena
     let mut driver = pmu::SamplingDriverBuilder::new()
         .counters(&counters)
         .process(&process)
+        .mmap_pages(options.mmap_pages)
+        .cpus(&options.sample_cpus)
+        .follow_forks(options.follow_forks)
+        .clock(options.sample_clock())
+        .thread_only(options.no_children)
+        .unwind_mode(options.unwind_mode())
         .build()?;
+    warn_on_narrow_scope(driver.as_ref(), options);
     let recorded_pid = process.pid();
     if cfg!(target_os = "macos") {
         publish_process_maps(dispatcher.clone(), recorded_pid);
@@ -478,11 +1080,8 @@ fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<Scena
     let sample_dispatcher = dispatcher.clone();
     driver.start(Arc::new(move |record| match record {
         Record::Sample(sample) => {
-            let name = if let Counter::Custom(name) = &sample.counter {
-                sample_dispatcher.string_id(name)
-            } else {
-                0
-            };
+            let name = custom_counter_name(&sample.counter)
+                .map_or(0, |name| sample_dispatcher.string_id(name));
             sample_dispatcher.publish_event_sync(Event {
                 unique_id: uuid::Uuid::now_v7().as_u128(),
                 correlation_id: sample.event_id,
@@ -503,24 +1102,36 @@ fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<Scena
                     values: regs.values,
                 }),
                 user_stack: sample.user_stack,
+                weight: sample.weight,
             });
         }
         Record::ProcAddr(addr) => sample_dispatcher.publish_proc_map_sync(ProcMapEntry {
-            filename: addr.filename,
+            filename: addr.filename.to_string_lossy().into_owned(),
             address: addr.addr as usize,
             size: addr.len as usize,
             offset: addr.pgoff as usize,
             pid: addr.pid,
+            build_id: None,
+            exec_generation: 0,
         }),
+        Record::ThreadName(thread) => sample_dispatcher.publish_thread_name_sync(ThreadName {
+            pid: thread.pid,
+            tid: thread.tid,
+            name: thread.name,
+        }),
+        Record::Fork(task) => sample_dispatcher.publish_fork_sync(task.pid, task.ppid, task.time),
+        Record::Exit(task) => sample_dispatcher.publish_exit_sync(task.pid, task.tid, task.time),
+        Record::Exec(exec) => sample_dispatcher.publish_exec_sync(exec.pid, exec.time),
+        Record::Lost(count) => sample_dispatcher.add_kernel_lost_samples(count),
     }))?;
 
     process.cont();
     std::thread::sleep(std::time::Duration::from_millis(20));
     publish_process_maps(dispatcher, recorded_pid);
-    process.wait()?;
+    wait_for_targets(Some(&process), None, std::slice::from_mut(&mut driver))?;
     driver.stop()?;
 
-    Ok(ScenarioInfo::TMA(mperf_data::TMAInfo {
+    let info = ScenarioInfo::TMA(mperf_data::TMAInfo {
         pid: recorded_pid,
         counters: counters
             .iter()
@@ -531,19 +1142,22 @@ fn topdown(dispatcher: Arc<EventDispatcher>, command: &[String]) -> Result<Scena
         metrics: scenario.metrics,
         constants: scenario.constants,
         ui: scenario.ui,
-    }))
+    });
+    Ok((info, target_status(&process)))
 }
 
-#[cfg(all(test, target_os = "macos"))]
+#[cfg(test)]
 mod tests {
-    use super::{mach_o_text_address, macos_segment_is_executable, VM_PROT_EXECUTE};
+    use super::*;
 
+    #[cfg(target_os = "macos")]
     #[test]
     fn finds_link_time_text_address_in_current_mach_o() {
         let executable = std::env::current_exe().unwrap();
         assert!(mach_o_text_address(&executable).is_some());
     }
 
+    #[cfg(target_os = "macos")]
     #[test]
     fn rejects_non_executable_mach_o_segments() {
         assert!(!macos_segment_is_executable(0x1_0000_0000, 0));
@@ -551,4 +1165,56 @@ mod tests {
         assert!(!macos_segment_is_executable(0, VM_PROT_EXECUTE));
         assert!(macos_segment_is_executable(0x1000, VM_PROT_EXECUTE));
     }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn thread_ids_resolve_to_their_process() {
+        let pid = std::process::id() as i32;
+        let (tid, tgid) = std::thread::spawn(|| {
+            let tid = unsafe { libc::gettid() };
+            (tid, thread_group_id(tid))
+        })
+        .join()
+        .unwrap();
+        assert_ne!(tid, pid);
+        assert_eq!(tgid, pid);
+        assert_eq!(thread_group_id(pid), pid);
+    }
+
+    #[test]
+    fn short_runs_raise_the_frequency_up_to_the_kernel_limit() {
+        let raised = |ms| raised_sample_freq(Duration::from_millis(ms), 1000, 50_000);
+        assert_eq!(raised(50), Some(20_000));
+        assert_eq!(raised(5), Some(50_000));
+        assert_eq!(raised(0), Some(50_000));
+        assert_eq!(raised(1000), None);
+        assert_eq!(
+            raised_sample_freq(Duration::from_millis(50), 1000, 800),
+            None
+        );
+    }
+
+    #[test]
+    fn summary_lists_hottest_functions_first() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER);
+                 INSERT INTO pmu_counters VALUES (1), (2), (3);
+                 CREATE TABLE hotspots (func_name TEXT, cycles INTEGER, total REAL);
+                 INSERT INTO hotspots VALUES ('cold', 10, 0.1), ('hot', 90, 0.9);",
+            )
+            .unwrap();
+
+        let summary =
+            record_summary(&connection, Path::new("/tmp/run"), Scenario::Snapshot, 2, 5).unwrap();
+
+        assert_eq!(summary["result_directory"], "/tmp/run");
+        assert_eq!(summary["scenario"], "Snapshot");
+        assert_eq!(summary["samples"], 3);
+        assert_eq!(summary["lost_events"], 2);
+        assert_eq!(summary["kernel_lost_samples"], 5);
+        assert_eq!(summary["top_functions"][0]["function"], "hot");
+        assert_eq!(summary["top_functions"][1]["cycles"], 10);
+    }
 }
@@ -0,0 +1,263 @@
+//! `record --scenario auto`: a short counting pre-pass over the target that
+//! picks the scenario whose views fit the workload.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use mperf_data::Scenario;
+use pmu::{Counter, Process};
+
+/// How long the pre-pass lets the target run before it is killed.
+const PREPASS_DURATION: Duration = Duration::from_secs(2);
+/// Share of retired instructions that are floating-point operations above
+/// which the loops are worth a roofline.
+const FP_SHARE_THRESHOLD: f64 = 0.2;
+/// Share of cycles stalled in the frontend or backend above which the
+/// workload is stall-bound.
+const STALL_SHARE_THRESHOLD: f64 = 0.3;
+/// Without stall counters: IPC below this, or more last-level cache misses
+/// per thousand instructions than [`LLC_MPKI_THRESHOLD`], is stall-bound.
+const IPC_THRESHOLD: f64 = 1.0;
+const LLC_MPKI_THRESHOLD: f64 = 5.0;
+
+/// Retired floating-point operation events of the supported CPU families.
+/// The first one the host resolves is counted.
+const FP_EVENTS: &[&str] = &[
+    "FP_ARITH_INST_RETIRED.SCALAR",
+    "FP_ARITH_INST_RETIRED.VECTOR",
+    "VFP_SPEC",
+    "FP_DP_SPEC",
+    "FP_SP_SPEC",
+];
+
+/// The `--scenario` argument of `record`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ScenarioChoice {
+    /// Pick a scenario from a short counting pre-pass.
+    Auto,
+    Snapshot,
+    Roofline,
+    #[value(name = "tma")]
+    Tma,
+}
+
+impl ScenarioChoice {
+    /// The scenario asked for, or `None` for `auto`.
+    pub fn fixed(self) -> Option<Scenario> {
+        match self {
+            ScenarioChoice::Auto => None,
+            ScenarioChoice::Snapshot => Some(Scenario::Snapshot),
+            ScenarioChoice::Roofline => Some(Scenario::Roofline),
+            ScenarioChoice::Tma => Some(Scenario::TMA),
+        }
+    }
+}
+
+/// Counter totals of the pre-pass. Counters the PMU lacks are `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Characteristics {
+    pub cycles: u64,
+    pub instructions: u64,
+    pub stalled_frontend: Option<u64>,
+    pub stalled_backend: Option<u64>,
+    pub llc_misses: Option<u64>,
+    pub fp_ops: Option<u64>,
+}
+
+/// Picks a scenario with the reason for it. Roofline and TMA launch the
+/// command they record, so an attached process, without `has_command`, is
+/// always snapshotted.
+pub fn choose_scenario(characteristics: &Characteristics, has_command: bool) -> (Scenario, String) {
+    if !has_command {
+        return (
+            Scenario::Snapshot,
+            "only snapshot can attach to a running process".to_string(),
+        );
+    }
+    let Characteristics {
+        cycles,
+        instructions,
+        ..
+    } = *characteristics;
+    if cycles == 0 || instructions == 0 {
+        return (
+            Scenario::Snapshot,
+            "the pre-pass counted no cycles".to_string(),
+        );
+    }
+    let instructions_f = instructions as f64;
+
+    if let Some(fp_ops) = characteristics.fp_ops {
+        let share = fp_ops as f64 / instructions_f;
+        if share >= FP_SHARE_THRESHOLD {
+            return (
+                Scenario::Roofline,
+                format!(
+                    "{:.0}% of instructions are floating-point operations",
+                    share * 100.0
+                ),
+            );
+        }
+    }
+
+    let stalls = match (
+        characteristics.stalled_frontend,
+        characteristics.stalled_backend,
+    ) {
+        (None, None) => None,
+        (frontend, backend) => Some(frontend.unwrap_or(0) + backend.unwrap_or(0)),
+    };
+    if let Some(stalls) = stalls {
+        let share = stalls as f64 / cycles as f64;
+        if share >= STALL_SHARE_THRESHOLD {
+            return (
+                Scenario::TMA,
+                format!("{:.0}% of cycles are stalled", share * 100.0),
+            );
+        }
+    } else {
+        let ipc = instructions_f / cycles as f64;
+        if ipc < IPC_THRESHOLD {
+            return (Scenario::TMA, format!("IPC is {ipc:.2}"));
+        }
+        if let Some(llc_misses) = characteristics.llc_misses {
+            let mpki = llc_misses as f64 * 1000.0 / instructions_f;
+            if mpki > LLC_MPKI_THRESHOLD {
+                return (
+                    Scenario::TMA,
+                    format!("{mpki:.1} last-level cache misses per thousand instructions"),
+                );
+            }
+        }
+    }
+
+    (
+        Scenario::Snapshot,
+        "the workload is neither stall-bound nor floating-point heavy".to_string(),
+    )
+}
+
+/// Counts `command` (or `pid`) for up to [`PREPASS_DURATION`] and picks a
+/// scenario. A launched command that is still running is killed; the full
+/// recording starts it again.
+pub fn detect_scenario(command: &[String], pid: Option<u32>) -> Result<Scenario> {
+    let process = if command.is_empty() {
+        None
+    } else {
+        Some(Process::new(command, &[])?)
+    };
+
+    let mut counters = vec![
+        Counter::Cycles,
+        Counter::Instructions,
+        Counter::StalledCyclesFrontend,
+        Counter::StalledCyclesBackend,
+        Counter::LLCMisses,
+    ];
+    let fp_counter = FP_EVENTS.iter().find_map(|name| {
+        pmu::host_scenario_counters(&[name.to_string()])
+            .ok()
+            .and_then(|resolved| resolved.into_iter().next())
+    });
+    counters.extend(fp_counter.clone());
+
+    let mut driver = loop {
+        match pmu::CountingDriverBuilder::new()
+            .counters(&counters)
+            .process(process.as_ref())
+            .pid(pid.map(|pid| pid as i32))
+            .build()
+        {
+            Ok(driver) => break driver,
+            Err(error) if error.is_event_unsupported() => {
+                let unsupported = error.counter_name().unwrap_or_default();
+                // Cycles and instructions are what every decision needs.
+                match counters
+                    .iter()
+                    .skip(2)
+                    .position(|counter| counter.name() == unsupported)
+                {
+                    Some(index) => {
+                        counters.remove(index + 2);
+                    }
+                    None => return Err(error.into()),
+                }
+            }
+            Err(error) => return Err(error.into()),
+        }
+    };
+
+    driver.reset()?;
+    driver.start()?;
+    let started = Instant::now();
+    if let Some(process) = &process {
+        process.cont();
+        while !process.try_wait()? && started.elapsed() < PREPASS_DURATION {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if !process.try_wait()? {
+            unsafe { libc::kill(process.pid(), libc::SIGKILL) };
+            process.wait()?;
+        }
+    } else {
+        std::thread::sleep(PREPASS_DURATION);
+    }
+    driver.stop()?;
+
+    let result = driver.counters()?;
+    let value = |counter: &Counter| result.get(counter.clone()).map(|value| value.value);
+    let characteristics = Characteristics {
+        cycles: value(&Counter::Cycles).unwrap_or(0),
+        instructions: value(&Counter::Instructions).unwrap_or(0),
+        stalled_frontend: value(&Counter::StalledCyclesFrontend),
+        stalled_backend: value(&Counter::StalledCyclesBackend),
+        llc_misses: value(&Counter::LLCMisses),
+        fp_ops: fp_counter.as_ref().and_then(value),
+    };
+    tracing::debug!("scenario pre-pass: {characteristics:?}");
+
+    let (scenario, reason) = choose_scenario(&characteristics, !command.is_empty());
+    tracing::info!("picked the {scenario:?} scenario: {reason}");
+    Ok(scenario)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenario_follows_the_workload() {
+        let base = Characteristics {
+            cycles: 1_000,
+            instructions: 2_000,
+            ..Default::default()
+        };
+        let pick = |characteristics, has_command| choose_scenario(&characteristics, has_command).0;
+
+        assert_eq!(pick(base, true), Scenario::Snapshot);
+        let fp_heavy = Characteristics {
+            fp_ops: Some(800),
+            ..base
+        };
+        assert_eq!(pick(fp_heavy, true), Scenario::Roofline);
+        assert_eq!(pick(fp_heavy, false), Scenario::Snapshot);
+        let stalled = Characteristics {
+            stalled_backend: Some(400),
+            ..base
+        };
+        assert_eq!(pick(stalled, true), Scenario::TMA);
+        assert_eq!(pick(stalled, false), Scenario::Snapshot);
+        let low_ipc = Characteristics {
+            instructions: 500,
+            ..base
+        };
+        assert_eq!(pick(low_ipc, true), Scenario::TMA);
+        assert_eq!(pick(low_ipc, false), Scenario::Snapshot);
+        let missing = Characteristics {
+            llc_misses: Some(20),
+            ..base
+        };
+        assert_eq!(pick(missing, true), Scenario::TMA);
+    }
+}
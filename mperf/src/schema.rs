@@ -0,0 +1,487 @@
+//! Table layout of `perf.db` and upgrades for databases written by older
+//! releases.
+//!
+//! Bump [`SCHEMA_VERSION`] whenever a table or view consumed by `show` changes,
+//! and register a step in [`MIGRATIONS`] that upgrades the previous version in
+//! place. Databases too old to upgrade are refused with a message instead of
+//! failing later on a missing column.
+
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+use crate::postprocess::{
+    create_instruction_mix_view, create_memory_latency_view, create_module_hotspots_view,
+    create_thread_stats_view,
+};
+
+/// Schema written by this build of `mperf`.
+pub const SCHEMA_VERSION: i64 = 10;
+
+/// Databases without a `schema_version` table predate versioning and are
+/// treated as this version when their core tables look usable.
+const LEGACY_SCHEMA_VERSION: i64 = 1;
+
+type Migration = fn(&sqlite::Connection) -> Result<()>;
+
+/// Upgrade steps, keyed by the version they start from.
+const MIGRATIONS: &[(i64, Migration)] = &[
+    (LEGACY_SCHEMA_VERSION, migrate_v1_to_v2),
+    (2, migrate_v2_to_v3),
+    (3, migrate_v3_to_v4),
+    (4, migrate_v4_to_v5),
+    (5, migrate_v5_to_v6),
+    (6, migrate_v6_to_v7),
+    (7, migrate_v7_to_v8),
+    (8, migrate_v8_to_v9),
+    (9, migrate_v9_to_v10),
+];
+
+pub const SCHEMA_VERSION_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);";
+
+pub const PROC_MAP: &str = "CREATE TABLE proc_map (
+        ip INTEGER,
+        func_name TEXT,
+        file_name TEXT,
+        line INTEGER,
+        module_path TEXT
+    );";
+
+pub const STRINGS: &str = "CREATE TABLE strings (id BINARY(128) NOT NULL, string TEXT NOT NULL);";
+
+pub const THREAD_NAMES: &str = "CREATE TABLE IF NOT EXISTS thread_names (
+        pid INTEGER NOT NULL,
+        tid INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (pid, tid)
+    );";
+
+pub const PROCESSES: &str = "CREATE TABLE IF NOT EXISTS processes (
+        pid INTEGER PRIMARY KEY,
+        ppid INTEGER NOT NULL,
+        fork_time INTEGER NOT NULL,
+        exit_time INTEGER
+    );";
+
+/// The arguments of each profiled process, joined with spaces.
+pub const PROCESS_CMDLINES: &str = "CREATE TABLE IF NOT EXISTS process_cmdlines (
+        pid INTEGER PRIMARY KEY,
+        cmdline TEXT NOT NULL
+    );";
+
+/// How many samples were read, and how many of them hit an address outside
+/// every recorded mapping. One row.
+pub const SAMPLE_COVERAGE: &str = "CREATE TABLE IF NOT EXISTS sample_coverage (
+        samples INTEGER NOT NULL,
+        unmapped INTEGER NOT NULL
+    );";
+
+/// The SQL expression of each column of the metric views, as written when
+/// the view was created.
+pub const VIEW_COLUMNS: &str = "CREATE TABLE IF NOT EXISTS view_columns (
+        view TEXT NOT NULL,
+        name TEXT NOT NULL,
+        expression TEXT NOT NULL,
+        PRIMARY KEY (view, name)
+    );";
+
+pub const DERIVED_METRICS: &str = "CREATE TABLE IF NOT EXISTS derived_metrics (
+        name TEXT PRIMARY KEY,
+        value REAL NOT NULL,
+        unit TEXT,
+        expression TEXT NOT NULL
+    );";
+
+/// Absolute per-function counts estimated from sample shares and the totals
+/// counted with `--count-totals`. `relative_error` is the half-width of the
+/// 95% confidence interval, as a share of the estimate.
+pub const HOTSPOT_ESTIMATES: &str = "CREATE TABLE hotspot_estimates (
+        func_name TEXT PRIMARY KEY,
+        est_cycles REAL,
+        est_instructions REAL,
+        relative_error REAL
+    );";
+
+/// Postprocessing stages that completed, so a failed run can resume.
+pub const POSTPROCESS_STATE: &str =
+    "CREATE TABLE IF NOT EXISTS postprocess_state (stage TEXT PRIMARY KEY);";
+
+/// Sampled functions matched by the idle patterns of postprocessing.
+pub const IDLE_FUNCTIONS: &str = "CREATE TABLE idle_functions (func_name TEXT PRIMARY KEY);";
+
+pub const ROOFLINE_TABLES: &str = "
+    CREATE TABLE roofline_ops(
+        unique_id BINARY(128), process_id INTEGER NOT NULL, thread_id INTEGER NOT NULL,
+        file_name BINARY(128) NOT NULL, function_name BINARY(128) NOT NULL,
+        line INTEGER NOT NULL, bytes_load INTEGER NOT NULL, bytes_store INTEGER NOT NULL,
+        scalar_int_ops INTEGER NOT NULL, scalar_float_ops INTEGER NOT NULL,
+        scalar_double_ops INTEGER NOT NULL, vector_int_ops INTEGER NOT NULL,
+        vector_float_ops INTEGER NOT NULL, vector_double_ops INTEGER NOT NULL
+    );
+    CREATE TABLE roofline_loop_runs(
+        unique_id BINARY(128), process_id INTEGER NOT NULL, thread_id INTEGER NOT NULL,
+        file_name BINARY(128) NOT NULL, function_name BINARY(128) NOT NULL,
+        line INTEGER NOT NULL, loop_start_ts INTEGER NOT NULL, loop_end_ts INTEGER NOT NULL
+    );";
+
+pub const TMA_TABLES: &str = "
+    CREATE TABLE tma_intervals (start_ns INTEGER NOT NULL, metric TEXT NOT NULL, value REAL);
+    CREATE TABLE tma_summary (metric TEXT PRIMARY KEY, value REAL, verdict TEXT);";
+
+pub const ASSEMBLY_LINES: &str = "CREATE TABLE IF NOT EXISTS assembly_lines (
+        module_path TEXT NOT NULL,
+        symbol TEXT,
+        rel_address INTEGER NOT NULL,
+        runtime_address INTEGER NOT NULL,
+        instruction TEXT NOT NULL,
+        source_file TEXT,
+        source_line INTEGER,
+        instruction_class TEXT,
+        PRIMARY KEY (module_path, runtime_address)
+    );";
+
+pub const ASSEMBLY_MODULE_METADATA: &str = "CREATE TABLE IF NOT EXISTS assembly_module_metadata (
+        module_path TEXT PRIMARY KEY,
+        load_bias INTEGER NOT NULL,
+        recorded_build_id TEXT,
+        current_build_id TEXT
+    );";
+
+pub const ASSEMBLY_SAMPLES: &str = "CREATE TABLE IF NOT EXISTS assembly_samples (
+        module_path TEXT NOT NULL,
+        func_name TEXT NOT NULL,
+        address INTEGER NOT NULL,
+        samples INTEGER NOT NULL,
+        cycles INTEGER NOT NULL,
+        instructions INTEGER NOT NULL,
+        branch_misses INTEGER NOT NULL,
+        branch_instructions INTEGER NOT NULL,
+        llc_misses INTEGER NOT NULL,
+        llc_references INTEGER NOT NULL,
+        PRIMARY KEY (module_path, func_name, address)
+    );";
+
+/// Divisor that descales a multiplexed counter by its group's `confidence`
+/// (`time_running / time_enabled`). A group that never ran has a confidence
+/// of zero; its values are taken as raw rather than divided into infinity.
+pub const DESCALE_DIVISOR: &str =
+    "(CASE WHEN pmu_counters.confidence > 0 THEN pmu_counters.confidence ELSE 1.0 END)";
+
+/// `pmu_counters` has one column per recorded event; `event_columns` is the
+/// already quoted `, "name" INTEGER ...` suffix. `latency` is the sample
+/// weight in cycles, NULL unless memory latency was sampled. `cpu` is NULL
+/// when the sample did not report the CPU it ran on.
+pub fn pmu_counters(event_columns: &str) -> String {
+    format!(
+        "CREATE TABLE pmu_counters (
+            unique_id BINARY(128),
+            process_id INTEGER NOT NULL,
+            thread_id INTEGER NOT NULL,
+            time_enabled INTEGER NOT NULL,
+            time_running INTEGER NOT NULL,
+            confidence REAL NOT NULL,
+            timestamp INTEGER NOT NULL,
+            ip INTEGER NOT NULL,
+            call_stack TEXT,
+            latency INTEGER,
+            cpu INTEGER{event_columns}
+        );"
+    )
+}
+
+/// Creates the tables every scenario fills and stamps the current version.
+pub fn create_common_tables(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(PROC_MAP)?;
+    connection.execute(STRINGS)?;
+    connection.execute(THREAD_NAMES)?;
+    connection.execute(PROCESSES)?;
+    connection.execute(PROCESS_CMDLINES)?;
+    connection.execute(SAMPLE_COVERAGE)?;
+    write_version(connection, SCHEMA_VERSION)
+}
+
+fn write_version(connection: &sqlite::Connection, version: i64) -> Result<()> {
+    connection.execute(SCHEMA_VERSION_TABLE)?;
+    connection.execute(format!(
+        "DELETE FROM schema_version; INSERT INTO schema_version (version) VALUES ({version});"
+    ))?;
+    Ok(())
+}
+
+/// Opens `perf.db` for viewing, upgrading it in place when it was written by
+/// an older `mperf`.
+pub fn open(db_path: &Path) -> Result<sqlite::Connection> {
+    let connection =
+        sqlite::open(db_path).with_context(|| format!("failed to open {}", db_path.display()))?;
+    let version = stored_version(&connection)?;
+
+    if version > SCHEMA_VERSION {
+        bail!(
+            "{} uses schema version {version}, but this mperf supports up to version {SCHEMA_VERSION}; upgrade mperf to view these results",
+            db_path.display()
+        );
+    }
+    if version < SCHEMA_VERSION {
+        migrate(&connection, version).with_context(|| {
+            format!(
+                "failed to upgrade {} from schema version {version}",
+                db_path.display()
+            )
+        })?;
+    }
+    Ok(connection)
+}
+
+fn stored_version(connection: &sqlite::Connection) -> Result<i64> {
+    if table_exists(connection, "schema_version")? {
+        let mut statement =
+            connection.prepare("SELECT MAX(version) AS version FROM schema_version;")?;
+        if let sqlite::State::Row = statement.next()? {
+            if let Some(version) = statement.read::<Option<i64>, _>("version")? {
+                return Ok(version);
+            }
+        }
+    }
+
+    // Before versioning the schema only changed by adding tables and columns,
+    // except for proc_map gaining module_path, which cannot be backfilled.
+    let usable = table_exists(connection, "pmu_counters")?
+        && table_exists(connection, "strings")?
+        && table_columns(connection, "proc_map")?.contains("module_path");
+    if !usable {
+        bail!(
+            "perf.db is incomplete or was written by an mperf too old to upgrade; record the workload again to regenerate it"
+        );
+    }
+    Ok(LEGACY_SCHEMA_VERSION)
+}
+
+fn migrate(connection: &sqlite::Connection, from: i64) -> Result<()> {
+    connection.execute("BEGIN IMMEDIATE TRANSACTION;")?;
+    let result = (|| -> Result<()> {
+        let mut version = from;
+        while version < SCHEMA_VERSION {
+            let Some((_, step)) = MIGRATIONS.iter().find(|(start, _)| *start == version) else {
+                bail!(
+                    "no upgrade from schema version {version}; record the workload again to regenerate perf.db"
+                );
+            };
+            step(connection)?;
+            version += 1;
+        }
+        write_version(connection, version)
+    })();
+
+    match result {
+        Ok(()) => {
+            connection.execute("COMMIT;")?;
+            tracing::info!("upgraded perf.db from schema version {from} to {SCHEMA_VERSION}");
+            Ok(())
+        }
+        Err(error) => {
+            let _ = connection.execute("ROLLBACK;");
+            Err(error)
+        }
+    }
+}
+
+/// Adds thread names, source line and instruction class columns on
+/// assembly lines, and the views built on them.
+fn migrate_v1_to_v2(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(THREAD_NAMES)?;
+    if !table_exists(connection, "thread_stats")? {
+        create_thread_stats_view(connection)?;
+    }
+
+    if table_exists(connection, "assembly_lines")? {
+        let columns = table_columns(connection, "assembly_lines")?;
+        for (column, kind) in [
+            ("source_file", "TEXT"),
+            ("source_line", "INTEGER"),
+            ("instruction_class", "TEXT"),
+        ] {
+            if !columns.contains(column) {
+                connection.execute(format!(
+                    "ALTER TABLE assembly_lines ADD COLUMN {column} {kind};"
+                ))?;
+            }
+        }
+        if table_exists(connection, "assembly_samples")? {
+            create_instruction_mix_view(connection)?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the per-sample load latency and the memory latency view.
+fn migrate_v2_to_v3(connection: &sqlite::Connection) -> Result<()> {
+    if !table_columns(connection, "pmu_counters")?.contains("latency") {
+        connection.execute("ALTER TABLE pmu_counters ADD COLUMN latency INTEGER;")?;
+    }
+    create_memory_latency_view(connection)
+}
+
+/// Adds the processes forked during the recording.
+fn migrate_v3_to_v4(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(PROCESSES)?;
+    Ok(())
+}
+
+/// Adds the build IDs compared when disassembling each module.
+fn migrate_v4_to_v5(connection: &sqlite::Connection) -> Result<()> {
+    if !table_exists(connection, "assembly_module_metadata")? {
+        return Ok(());
+    }
+    let columns = table_columns(connection, "assembly_module_metadata")?;
+    for column in ["recorded_build_id", "current_build_id"] {
+        if !columns.contains(column) {
+            connection.execute(format!(
+                "ALTER TABLE assembly_module_metadata ADD COLUMN {column} TEXT;"
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the CPU each sample ran on; older samples keep it NULL.
+fn migrate_v5_to_v6(connection: &sqlite::Connection) -> Result<()> {
+    if !table_columns(connection, "pmu_counters")?.contains("cpu") {
+        connection.execute("ALTER TABLE pmu_counters ADD COLUMN cpu INTEGER;")?;
+    }
+    Ok(())
+}
+
+/// Adds the command lines of the profiled processes.
+fn migrate_v6_to_v7(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(PROCESS_CMDLINES)?;
+    Ok(())
+}
+
+/// Adds the expressions of the view columns. Views created by older releases
+/// keep none until postprocessed again.
+fn migrate_v7_to_v8(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(VIEW_COLUMNS)?;
+    Ok(())
+}
+
+/// Adds the share of samples outside every mapping. Older databases leave it
+/// empty, so the summary does not warn about them.
+fn migrate_v8_to_v9(connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(SAMPLE_COVERAGE)?;
+    Ok(())
+}
+
+/// Adds the per-module breakdown of the hotspots, for recordings that
+/// sampled cycles and instructions.
+fn migrate_v9_to_v10(connection: &sqlite::Connection) -> Result<()> {
+    if table_exists(connection, "module_hotspots")? || !table_exists(connection, "proc_map")? {
+        return Ok(());
+    }
+    let columns = table_columns(connection, "pmu_counters")?;
+    if columns.contains("pmu_cycles") && columns.contains("pmu_instructions") {
+        create_module_hotspots_view(connection)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn table_exists(connection: &sqlite::Connection, name: &str) -> Result<bool> {
+    let mut statement = connection
+        .prepare("SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?;")?;
+    statement.bind((1, name))?;
+    Ok(matches!(statement.next()?, sqlite::State::Row))
+}
+
+pub(crate) fn table_columns(
+    connection: &sqlite::Connection,
+    table: &str,
+) -> Result<HashSet<String>> {
+    let mut statement = connection.prepare(format!("PRAGMA table_info({table});"))?;
+    let mut columns = HashSet::new();
+    while let sqlite::State::Row = statement.next()? {
+        columns.insert(statement.read::<String, _>("name")?);
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mperf-schema-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn legacy_database(path: &Path) {
+        let connection = sqlite::open(path).unwrap();
+        connection
+            .execute(
+                "CREATE TABLE proc_map (
+                    ip INTEGER, func_name TEXT, file_name TEXT, line INTEGER, module_path TEXT
+                 );
+                 CREATE TABLE strings (id BINARY(128) NOT NULL, string TEXT NOT NULL);
+                 CREATE TABLE pmu_counters (
+                    process_id INTEGER, thread_id INTEGER, ip INTEGER, pmu_cycles INTEGER,
+                    pmu_instructions INTEGER
+                 );
+                 CREATE TABLE assembly_samples (
+                    module_path TEXT, func_name TEXT, address INTEGER, samples INTEGER
+                 );
+                 CREATE TABLE assembly_lines (
+                    module_path TEXT, symbol TEXT, rel_address INTEGER,
+                    runtime_address INTEGER, instruction TEXT
+                 );",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn upgrades_unversioned_database_in_place() {
+        let dir = scratch_dir();
+        let path = dir.join("perf.db");
+        legacy_database(&path);
+
+        let connection = open(&path).unwrap();
+        assert_eq!(stored_version(&connection).unwrap(), SCHEMA_VERSION);
+        assert!(table_columns(&connection, "assembly_lines")
+            .unwrap()
+            .contains("instruction_class"));
+        let pmu_columns = table_columns(&connection, "pmu_counters").unwrap();
+        assert!(pmu_columns.contains("latency"));
+        assert!(pmu_columns.contains("cpu"));
+        for name in [
+            "thread_names",
+            "thread_stats",
+            "instruction_mix",
+            "memory_latency",
+            "processes",
+            "process_cmdlines",
+            "view_columns",
+            "sample_coverage",
+            "module_hotspots",
+        ] {
+            assert!(table_exists(&connection, name).unwrap(), "{name}");
+        }
+        drop(connection);
+
+        // A second open finds the current version and changes nothing.
+        open(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_and_unusable_databases() {
+        let dir = scratch_dir();
+        let newer = dir.join("newer.db");
+        write_version(&sqlite::open(&newer).unwrap(), SCHEMA_VERSION + 1).unwrap();
+        let error = format!("{:#}", open(&newer).err().unwrap());
+        assert!(error.contains("upgrade mperf"), "{error}");
+
+        let empty = dir.join("empty.db");
+        let error = format!("{:#}", open(&empty).err().unwrap());
+        assert!(error.contains("record the workload again"), "{error}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
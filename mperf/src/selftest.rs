@@ -0,0 +1,197 @@
+//! `mperf selftest`: measures the overhead of sampling on a fixed amount of
+//! work, to pick a sample frequency and to guard against overhead
+//! regressions.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use comfy_table::{Cell, CellAlignment, Table};
+use num_format::ToFormattedString;
+use pmu::{Counter, CountingDriverBuilder, Record, SamplingDriverBuilder};
+
+use crate::locale;
+
+/// Counters and wall time of one run of the fixed work.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Run {
+    wall: Duration,
+    cycles: u64,
+    instructions: u64,
+}
+
+/// The median of each quantity over several runs, which keeps one run
+/// disturbed by the rest of the system from skewing the comparison.
+fn median(runs: &[Run]) -> Run {
+    let pick = |mut values: Vec<u64>| {
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    Run {
+        wall: Duration::from_nanos(pick(
+            runs.iter().map(|run| run.wall.as_nanos() as u64).collect(),
+        )),
+        cycles: pick(runs.iter().map(|run| run.cycles).collect()),
+        instructions: pick(runs.iter().map(|run| run.instructions).collect()),
+    }
+}
+
+/// Slowdown of `sampled` over `baseline`, in percent.
+fn overhead(baseline: f64, sampled: f64) -> f64 {
+    if baseline == 0.0 {
+        return 0.0;
+    }
+    (sampled / baseline - 1.0) * 100.0
+}
+
+/// Integer work with a loop-carried dependency, so it cannot be vectorized
+/// or folded away and retires the same instructions on every run.
+fn fixed_work(iterations: u64) -> u64 {
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    for i in 0..iterations {
+        state = (state.rotate_left(5) ^ i).wrapping_mul(0x2545_f491_4f6c_dd1d);
+    }
+    std::hint::black_box(state)
+}
+
+/// Counts the cycles and instructions of the calling thread over one run.
+fn measure(iterations: u64) -> Result<Run> {
+    let mut driver = CountingDriverBuilder::new()
+        .counters(&[Counter::Cycles, Counter::Instructions])
+        .build()
+        .context("selftest needs the cycles and instructions counters")?;
+    driver.reset()?;
+    driver.start()?;
+    let start = Instant::now();
+    fixed_work(iterations);
+    let wall = start.elapsed();
+    driver.stop()?;
+    let counters = driver.counters()?;
+    let value = |counter| counters.get(counter).map_or(0, |value| value.value);
+    Ok(Run {
+        wall,
+        cycles: value(Counter::Cycles),
+        instructions: value(Counter::Instructions),
+    })
+}
+
+/// Runs the work `repeat` times while `mperf` samples itself at `freq` Hz
+/// with call stacks, like `record`. Returns the runs and the samples taken.
+fn measure_sampled(iterations: u64, repeat: usize, freq: u64) -> Result<(Vec<Run>, u64)> {
+    let samples = Arc::new(AtomicU64::new(0));
+    let counted = samples.clone();
+    let mut driver = SamplingDriverBuilder::new()
+        .counters(&[Counter::Cycles])
+        .pid(std::process::id() as i32)
+        .follow_forks(false)
+        .sample_freq(freq)
+        .build()
+        .context("failed to start sampling mperf itself")?;
+    driver.start(Arc::new(move |record| {
+        if let Record::Sample(_) = record {
+            counted.fetch_add(1, Ordering::Relaxed);
+        }
+    }))?;
+    let runs = (0..repeat)
+        .map(|_| measure(iterations))
+        .collect::<Result<Vec<_>>>();
+    driver.stop()?;
+    Ok((runs?, samples.load(Ordering::Relaxed)))
+}
+
+pub fn do_selftest(
+    iterations: u64,
+    repeat: usize,
+    freq: u64,
+    max_overhead: Option<f64>,
+) -> Result<()> {
+    // A warm-up run brings the core out of its idle frequency before the
+    // baseline is taken.
+    measure(iterations)?;
+    let baseline = (0..repeat)
+        .map(|_| measure(iterations))
+        .collect::<Result<Vec<_>>>()?;
+    let (sampled, samples) = measure_sampled(iterations, repeat, freq)?;
+    let baseline = median(&baseline);
+    let sampled = median(&sampled);
+
+    println!("{}", format_report(&baseline, &sampled));
+    let wall_overhead = overhead(baseline.wall.as_secs_f64(), sampled.wall.as_secs_f64());
+    println!(
+        "Sampling at {freq} Hz took {} samples and slowed the work down by {wall_overhead:.2}%",
+        samples.to_formatted_string(&locale::current())
+    );
+
+    if let Some(max_overhead) = max_overhead {
+        if wall_overhead > max_overhead {
+            bail!(
+                "sampling overhead of {wall_overhead:.2}% exceeds --max-overhead {max_overhead}%"
+            );
+        }
+    }
+    Ok(())
+}
+
+fn format_report(baseline: &Run, sampled: &Run) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec!["", "Baseline", "Sampled", "Overhead"]);
+    let rows = [
+        (
+            "Wall time (ms)",
+            baseline.wall.as_millis() as u64,
+            sampled.wall.as_millis() as u64,
+            overhead(baseline.wall.as_secs_f64(), sampled.wall.as_secs_f64()),
+        ),
+        (
+            "Cycles",
+            baseline.cycles,
+            sampled.cycles,
+            overhead(baseline.cycles as f64, sampled.cycles as f64),
+        ),
+        (
+            "Instructions",
+            baseline.instructions,
+            sampled.instructions,
+            overhead(baseline.instructions as f64, sampled.instructions as f64),
+        ),
+    ];
+    for (name, baseline, sampled, overhead) in rows {
+        table.add_row(vec![
+            Cell::new(name),
+            Cell::new(baseline.to_formatted_string(&locale::current()))
+                .set_alignment(CellAlignment::Right),
+            Cell::new(sampled.to_formatted_string(&locale::current()))
+                .set_alignment(CellAlignment::Right),
+            Cell::new(format!("{overhead:+.2}%")).set_alignment(CellAlignment::Right),
+        ]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overhead_compares_the_median_runs() {
+        let run = |ms, cycles| Run {
+            wall: Duration::from_millis(ms),
+            cycles,
+            instructions: 1_000,
+        };
+        let baseline = median(&[run(100, 300), run(900, 100), run(110, 200)]);
+        assert_eq!(baseline, run(110, 200));
+        let sampled = median(&[run(121, 220)]);
+
+        assert!((overhead(200.0, 220.0) - 10.0).abs() < 1e-9);
+        assert_eq!(overhead(0.0, 5.0), 0.0);
+        let report = format_report(&baseline, &sampled).to_string();
+        assert!(report.contains("+10.00%"));
+        assert!(report.contains("+0.00%"));
+    }
+}
@@ -0,0 +1,57 @@
+//! Unsigned values of a recording in SQLite's signed 64-bit INTEGER.
+//!
+//! Addresses, timestamps and counter values are `u64`. Kernel addresses and
+//! wrapped counters do not fit an `i64`, so they are stored with their bits
+//! reinterpreted rather than converted: every value survives the round trip
+//! and equal values still join. SQL ordering and arithmetic see values above
+//! `i64::MAX` as negative, so views compare them for equality only.
+
+/// The INTEGER that stores `value`.
+pub fn sqlite_i64_from_u64(value: u64) -> i64 {
+    i64::from_ne_bytes(value.to_ne_bytes())
+}
+
+/// The value stored by [`sqlite_i64_from_u64`].
+pub fn sqlite_u64_from_i64(value: i64) -> u64 {
+    u64::from_ne_bytes(value.to_ne_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_above_i64_max_round_trip() {
+        for value in [
+            0,
+            1,
+            i64::MAX as u64,
+            1 << 63,
+            0xffff_ffff_8100_0000,
+            u64::MAX,
+        ] {
+            assert_eq!(sqlite_u64_from_i64(sqlite_i64_from_u64(value)), value);
+        }
+        assert_eq!(sqlite_i64_from_u64(u64::MAX), -1);
+
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute("CREATE TABLE addresses (address INTEGER);")
+            .unwrap();
+        let mut insert = connection
+            .prepare("INSERT INTO addresses VALUES (?);")
+            .unwrap();
+        insert
+            .bind((1, sqlite_i64_from_u64(0xffff_ffff_8100_0000)))
+            .unwrap();
+        insert.next().unwrap();
+        let mut select = connection
+            .prepare("SELECT address FROM addresses;")
+            .unwrap();
+        select.next().unwrap();
+        assert_eq!(
+            sqlite_u64_from_i64(select.read::<i64, _>(0).unwrap()),
+            0xffff_ffff_8100_0000
+        );
+    }
+}
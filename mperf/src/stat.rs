@@ -1,10 +1,13 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use comfy_table::{Cell, CellAlignment, Color, Table};
-use num_format::{Locale, ToFormattedString};
+use num_format::ToFormattedString;
 use pmu::{Counter, CounterValue, Metric, Process};
 
+use crate::{derived::DERIVED_METRICS, exit_code::Outcome, locale};
+
 /// PMU (hardware) counters, shown per-core on heterogeneous systems.
 fn pmu_counters() -> Vec<Counter> {
     vec![
@@ -34,6 +37,8 @@ pub fn do_stat(
     command: Vec<String>,
     event_names: Vec<String>,
     topdown_level: Option<u8>,
+    follow_forks: bool,
+    detailed: bool,
 ) -> Result<()> {
     if pid.is_none() && command.is_empty() {
         anyhow::bail!(
@@ -49,7 +54,7 @@ pub fn do_stat(
 
     let capabilities = pmu::capabilities();
     if !capabilities.hardware_counters {
-        eprintln!("notice: no hardware PMU detected (VM/container or permissions); hardware counters may be unavailable");
+        tracing::warn!("no hardware PMU detected (VM/container or permissions); hardware counters may be unavailable");
     }
 
     let supported = pmu::list_supported_counters(pmu::DriverKind::Default);
@@ -83,6 +88,7 @@ pub fn do_stat(
             .counters(&counters)
             .process(process.as_ref())
             .pid(pid.map(|pid| pid as i32))
+            .follow_forks(follow_forks)
             .build()
         {
             Ok(driver) => break driver,
@@ -94,7 +100,7 @@ pub fn do_stat(
                 else {
                     return Err(error.into());
                 };
-                eprintln!("notice: {unsupported} is not supported by this PMU; omitting it");
+                tracing::warn!("{unsupported} is not supported by this PMU; omitting it");
                 counters.remove(index);
                 if counters.is_empty() {
                     return Err(error.into());
@@ -106,6 +112,7 @@ pub fn do_stat(
 
     driver.reset()?;
     driver.start()?;
+    let started = Instant::now();
     if let Some(process) = &process {
         process.cont();
         process.wait()?;
@@ -115,6 +122,7 @@ pub fn do_stat(
         }
     }
     driver.stop()?;
+    let wall_time = started.elapsed();
 
     let result = driver.counters()?;
 
@@ -122,7 +130,7 @@ pub fn do_stat(
         let scenario =
             pmu::host_tma_scenario().expect("architectural TMA fallback is always available");
         render_topdown(&scenario, level, &result);
-        return Ok(());
+        return target_outcome(process.as_ref());
     }
 
     let selected_pmu: Vec<Counter> = counters
@@ -148,25 +156,37 @@ Performance counter stats for '{}':
 
     if cores.is_empty() {
         // Homogeneous system: a single table with everything, as before.
-        let table = render_table(&counters, &metrics, |c| result.get(c.clone()));
+        let table = render_table(&counters, &metrics, wall_time, detailed, |c| {
+            result.get(c.clone())
+        });
         println!("{table}");
     } else {
         // Heterogeneous system: one table per core cluster, then a faithful
         // total summed across all clusters.
         for core in &cores {
             let core_metrics = applicable_metrics(&metrics, &selected_pmu);
-            let table = render_table(&selected_pmu, &core_metrics, |c| {
+            let table = render_table(&selected_pmu, &core_metrics, wall_time, detailed, |c| {
                 result.get_for(&Some(core.clone()), c.clone())
             });
             println!("{} (cpus {})\n{table}\n", core.name, core.cpus);
         }
 
         let all = [selected_pmu, selected_software].concat();
-        let table = render_table(&all, &metrics, |c| result.get(c.clone()));
+        let table = render_table(&all, &metrics, wall_time, detailed, |c| {
+            result.get(c.clone())
+        });
         println!("Total \u{2014} all cores (faithful sum)\n{table}");
     }
 
-    Ok(())
+    target_outcome(process.as_ref())
+}
+
+/// Counts are printed either way; a failed command still fails `stat`.
+fn target_outcome(process: Option<&Process>) -> Result<()> {
+    match process.and_then(Process::exit_code) {
+        Some(code) if code != 0 => Err(Outcome::TargetFailed(code).into()),
+        _ => Ok(()),
+    }
 }
 
 fn render_topdown(scenario: &pmu_data::TmaScenario, level: u8, result: &pmu::CounterResult) {
@@ -287,33 +307,80 @@ fn eval_tma(
 }
 
 /// Render one counter table for a given scope. `get` returns the counter value
-/// within that scope (a single core, or the aggregate total).
+/// within that scope (a single core, or the aggregate total). `detailed` adds
+/// the raw count and enabled/running times behind the scaled value.
 fn render_table(
     counters: &[Counter],
     metrics: &[Metric],
+    wall_time: Duration,
+    detailed: bool,
     get: impl Fn(&Counter) -> Option<CounterValue>,
 ) -> Table {
     let cycles = get(&Counter::Cycles).map(|v| v.value);
     let instructions = get(&Counter::Instructions).map(|v| v.value);
 
     let mut table = Table::new();
-    table.set_header(vec!["Counter", "Value", "Info", "Scaling", "Description"]);
+    let mut header = vec!["Counter", "Value", "Info", "Scaling"];
+    if detailed {
+        header.extend(["Raw", "Enabled", "Running"]);
+    }
+    header.push("Description");
+    table.set_header(header);
 
     for cntr in counters {
         let Some(value) = get(cntr) else {
             continue;
         };
 
-        let info = info_cell(cntr, &value, cycles, instructions);
+        let info = info_cell(cntr, &value, cycles, instructions, wall_time);
+        let rendered = if cntr.is_time() {
+            format_nanoseconds(value.value)
+        } else {
+            value.value.to_formatted_string(&locale::current())
+        };
 
-        table.add_row(vec![
+        let mut row = vec![
             Cell::new(cntr.name()),
-            Cell::new(value.value.to_formatted_string(&Locale::en))
-                .set_alignment(CellAlignment::Right),
+            Cell::new(rendered).set_alignment(CellAlignment::Right),
             info,
             Cell::new(format!("{:.2}", value.scaling)).set_alignment(CellAlignment::Right),
-            Cell::new(cntr.description()),
-        ]);
+        ];
+        if detailed {
+            row.extend(detail_cells(&value));
+        }
+        row.push(Cell::new(cntr.description()));
+        table.add_row(row);
+    }
+
+    // The same formulas as the hotspots view, unless a host metric of the
+    // same name is about to be printed anyway.
+    for metric in DERIVED_METRICS.iter().filter(|derived| {
+        !metrics.iter().any(|metric| {
+            metric.name.eq_ignore_ascii_case(derived.label)
+                || metric.name.eq_ignore_ascii_case(derived.key)
+        })
+    }) {
+        let Some(value) = metric.evaluate(|input| {
+            let counter = input.counter();
+            counters
+                .contains(&counter)
+                .then(|| get(&counter))
+                .flatten()
+                .map(|value| value.value)
+        }) else {
+            continue;
+        };
+        let mut row = vec![
+            Cell::new(metric.label),
+            Cell::new(metric.format(value)).set_alignment(CellAlignment::Right),
+            Cell::new("derived"),
+            Cell::new("-"),
+        ];
+        if detailed {
+            row.extend([Cell::new("-"), Cell::new("-"), Cell::new("-")]);
+        }
+        row.push(Cell::new(metric.desc));
+        table.add_row(row);
     }
 
     let values: HashMap<String, f64> = counters
@@ -330,13 +397,17 @@ fn render_table(
             || format!("{value:.3}"),
             |unit| format!("{value:.3} {unit}"),
         );
-        table.add_row(vec![
+        let mut row = vec![
             Cell::new(&metric.name),
             Cell::new(rendered).set_alignment(CellAlignment::Right),
             Cell::new("derived"),
             Cell::new("-"),
-            Cell::new(&metric.desc),
-        ]);
+        ];
+        if detailed {
+            row.extend([Cell::new("-"), Cell::new("-"), Cell::new("-")]);
+        }
+        row.push(Cell::new(&metric.desc));
+        table.add_row(row);
     }
 
     table
@@ -354,6 +425,10 @@ fn requested_counters_and_metrics(
             push_counter(&mut counters, counter.clone());
             continue;
         }
+        if let Some(counter) = Counter::parse_raw(name) {
+            push_counter(&mut counters, counter);
+            continue;
+        }
         let Some(metric) = host_metrics
             .iter()
             .find(|metric| metric.name.eq_ignore_ascii_case(name))
@@ -407,15 +482,54 @@ fn push_counter(counters: &mut Vec<Counter>, counter: Counter) {
     }
 }
 
-/// Compute the derived "Info" cell (IPC, MPKI, stall %) for a counter, relative
-/// to the cycles/instructions of the same scope.
+/// Formats a nanosecond clock counter in milliseconds, or seconds once it
+/// reaches one second.
+fn format_nanoseconds(nanoseconds: u64) -> String {
+    let duration = Duration::from_nanos(nanoseconds);
+    if duration >= Duration::from_secs(1) {
+        format!("{:.3} s", duration.as_secs_f64())
+    } else {
+        format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Raw count and enabled/running times, showing how much a counter was
+/// multiplexed. Platforms without timings show "-" instead.
+fn detail_cells(value: &CounterValue) -> [Cell; 3] {
+    let time = |nanoseconds: u64| {
+        if value.time_enabled == 0 {
+            "-".to_owned()
+        } else {
+            format_nanoseconds(nanoseconds)
+        }
+    };
+    [
+        Cell::new(value.raw.to_formatted_string(&locale::current()))
+            .set_alignment(CellAlignment::Right),
+        Cell::new(time(value.time_enabled)).set_alignment(CellAlignment::Right),
+        Cell::new(time(value.time_running)).set_alignment(CellAlignment::Right),
+    ]
+}
+
+/// Compute the derived "Info" cell (IPC, MPKI, stall %, CPU utilization) for a
+/// counter, relative to the cycles/instructions of the same scope and the
+/// wall-clock duration of the measurement.
 fn info_cell(
     cntr: &Counter,
     value: &CounterValue,
     cycles: Option<u64>,
     instructions: Option<u64>,
+    wall_time: Duration,
 ) -> Cell {
     match cntr {
+        _ if cntr.is_time() => {
+            if wall_time.is_zero() {
+                return Cell::new("");
+            }
+            // Above 100% means the workload kept several CPUs busy.
+            let utilization = value.value as f64 / wall_time.as_nanos() as f64 * 100_f64;
+            Cell::new(format!("{utilization:.1}% CPU utilization"))
+        }
         Counter::Instructions | Counter::BranchInstructions => {
             let Some(cycles) = cycles.filter(|c| *c > 0) else {
                 return Cell::new("");
@@ -433,7 +547,12 @@ fn info_cell(
 
             cell
         }
-        Counter::BranchMisses | Counter::LLCMisses => {
+        Counter::BranchMisses
+        | Counter::LLCMisses
+        | Counter::L1DMisses
+        | Counter::L1IMisses
+        | Counter::DTLBMisses
+        | Counter::ITLBMisses => {
             let Some(instructions) = instructions.filter(|i| *i > 0) else {
                 return Cell::new("");
             };
@@ -492,4 +611,108 @@ mod metric_tests {
             vec![ipc()]
         );
     }
+
+    #[test]
+    fn cpu_clock_is_shown_in_time_units() {
+        assert_eq!(format_nanoseconds(1_500_000), "1.500 ms");
+        assert_eq!(format_nanoseconds(2_250_000_000), "2.250 s");
+    }
+
+    #[test]
+    fn cpu_clock_reports_utilization_of_wall_time() {
+        let value = CounterValue {
+            value: 1_500_000_000,
+            scaling: 1.0,
+            quality: pmu::MeasurementQuality::Exact,
+            raw: 1_500_000_000,
+            time_enabled: 1_500_000_000,
+            time_running: 1_500_000_000,
+        };
+        let cell = info_cell(
+            &Counter::CpuClock,
+            &value,
+            None,
+            None,
+            Duration::from_secs(1),
+        );
+        assert_eq!(cell.content(), "150.0% CPU utilization");
+    }
+
+    #[test]
+    fn detailed_mode_shows_raw_count_and_multiplexing_times() {
+        let value = CounterValue {
+            value: 2_000,
+            scaling: 2.0,
+            quality: pmu::MeasurementQuality::Scaled,
+            raw: 1_000,
+            time_enabled: 4_000_000,
+            time_running: 2_000_000,
+        };
+        let table = render_table(
+            &[Counter::Cycles],
+            &[],
+            Duration::from_secs(1),
+            true,
+            |_| Some(value.clone()),
+        )
+        .to_string();
+        assert!(table.contains("Running"));
+        assert!(table.contains("1,000"));
+        assert!(table.contains("4.000 ms"));
+        assert!(table.contains("2.000 ms"));
+
+        let plain = render_table(
+            &[Counter::Cycles],
+            &[],
+            Duration::from_secs(1),
+            false,
+            |_| Some(value.clone()),
+        )
+        .to_string();
+        assert!(!plain.contains("Running"));
+    }
+
+    #[test]
+    fn derived_metrics_follow_the_raw_counts() {
+        let table = render_table(
+            &[
+                Counter::Cycles,
+                Counter::Instructions,
+                Counter::BranchMisses,
+            ],
+            &[],
+            Duration::from_secs(1),
+            false,
+            |counter| {
+                let value = match counter {
+                    Counter::Cycles => 1_000,
+                    Counter::Instructions => 3_000,
+                    _ => 6,
+                };
+                Some(CounterValue {
+                    value,
+                    scaling: 1.0,
+                    quality: pmu::MeasurementQuality::Exact,
+                    raw: value,
+                    time_enabled: 0,
+                    time_running: 0,
+                })
+            },
+        )
+        .to_string();
+        assert!(table.contains("3.00 inst/cycle"));
+        assert!(table.contains("Branch MPKI"));
+        assert!(table.contains("2.00"));
+        assert!(!table.contains("Branch miss rate"));
+
+        let with_host_ipc = render_table(
+            &[Counter::Cycles, Counter::Instructions],
+            &[ipc()],
+            Duration::from_secs(1),
+            false,
+            |_| None,
+        )
+        .to_string();
+        assert!(!with_host_ipc.contains("inst/cycle"));
+    }
 }
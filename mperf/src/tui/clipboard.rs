@@ -0,0 +1,49 @@
+//! Copying text to the system clipboard through the terminal, with the OSC 52
+//! escape sequence. It works over SSH and needs no display server, but some
+//! terminals ignore it and tmux only forwards it with `set-clipboard on`.
+
+use std::io::{IsTerminal, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Whether a copy can reach the clipboard: stdout is a terminal that takes
+/// OSC 52, and tmux, if any, forwards it.
+pub fn is_available() -> bool {
+    if !std::io::stdout().is_terminal() {
+        return false;
+    }
+    // The Linux console and GNU screen drop the sequence.
+    let term = std::env::var("TERM").unwrap_or_default();
+    if term == "linux" || term == "dumb" || std::env::var_os("STY").is_some() {
+        return false;
+    }
+    if std::env::var_os("TMUX").is_some() {
+        return std::process::Command::new("tmux")
+            .args(["show-options", "-gv", "set-clipboard"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "on");
+    }
+    true
+}
+
+/// Asks the terminal to put `text` on the clipboard.
+pub fn copy(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(sequence(text).as_bytes())?;
+    stdout.flush()
+}
+
+fn sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_sent_base64_encoded() {
+        assert_eq!(sequence(""), "\x1b]52;c;\x07");
+        assert_eq!(sequence("| ★ |"), "\x1b]52;c;fCDimIUgfA==\x07");
+    }
+}
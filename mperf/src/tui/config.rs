@@ -3,7 +3,14 @@ use pmu_data::{MetricColumnSpec, MetricsTableSpec, OrderSpec, ScenarioUi, SortDi
 
 pub fn scenario_ui(record: &RecordInfo) -> ScenarioUi {
     match record.scenario {
-        Scenario::Snapshot => snapshot_ui(),
+        Scenario::Snapshot => match &record.scenario_info {
+            ScenarioInfo::Snapshot(snapshot) if snapshot.memory_latency => {
+                let mut ui = snapshot_ui();
+                ui.tabs.insert(2, memory_latency_tab());
+                ui
+            }
+            _ => snapshot_ui(),
+        },
         Scenario::Roofline => roofline_ui(),
         Scenario::TMA => match &record.scenario_info {
             ScenarioInfo::TMA(tma) => tma.ui.clone().unwrap_or_else(|| tma_fallback_ui(tma)),
@@ -53,6 +60,38 @@ fn snapshot_ui() -> ScenarioUi {
                         sticky: false,
                         optional: false,
                     },
+                    MetricColumnSpec {
+                        key: "vectorization_ratio".to_string(),
+                        label: Some("Vectorized".to_string()),
+                        format: pmu_data::ValueFormat::Percent1,
+                        width: Some(12),
+                        sticky: false,
+                        optional: true,
+                    },
+                    MetricColumnSpec {
+                        key: "est_cycles".to_string(),
+                        label: Some("Est. cycles".to_string()),
+                        format: pmu_data::ValueFormat::Integer,
+                        width: Some(18),
+                        sticky: false,
+                        optional: true,
+                    },
+                    MetricColumnSpec {
+                        key: "est_instructions".to_string(),
+                        label: Some("Est. instructions".to_string()),
+                        format: pmu_data::ValueFormat::Integer,
+                        width: Some(18),
+                        sticky: false,
+                        optional: true,
+                    },
+                    MetricColumnSpec {
+                        key: "relative_error".to_string(),
+                        label: Some("± (95%)".to_string()),
+                        format: pmu_data::ValueFormat::Percent1,
+                        width: Some(10),
+                        sticky: false,
+                        optional: true,
+                    },
                 ],
                 order_by: Some(OrderSpec {
                     column: "total".to_string(),
@@ -63,19 +102,146 @@ fn snapshot_ui() -> ScenarioUi {
                 function_column: Some("func_name".to_string()),
                 enable_assembly: true,
             }),
+            module_hotspots_tab(),
             TabSpec::Flamegraph,
         ],
     }
 }
 
+/// Time spent per module, before drilling into functions.
+fn module_hotspots_tab() -> TabSpec {
+    TabSpec::MetricsTable(MetricsTableSpec {
+        view: "module_hotspots".to_string(),
+        title: Some("Modules".to_string()),
+        include_default_columns: false,
+        columns: vec![
+            MetricColumnSpec {
+                key: "module".to_string(),
+                label: Some("Module".to_string()),
+                format: pmu_data::ValueFormat::Text,
+                width: Some(34),
+                sticky: true,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "total".to_string(),
+                label: Some("Total %".to_string()),
+                format: pmu_data::ValueFormat::Percent2,
+                width: Some(12),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "samples".to_string(),
+                label: Some("Samples".to_string()),
+                format: pmu_data::ValueFormat::Integer,
+                width: Some(12),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "cycles".to_string(),
+                label: Some("Cycles".to_string()),
+                format: pmu_data::ValueFormat::Integer,
+                width: Some(18),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "instructions".to_string(),
+                label: Some("Instructions".to_string()),
+                format: pmu_data::ValueFormat::Integer,
+                width: Some(18),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "ipc".to_string(),
+                label: Some("IPC".to_string()),
+                format: pmu_data::ValueFormat::Float2,
+                width: Some(10),
+                sticky: false,
+                optional: false,
+            },
+        ],
+        order_by: Some(OrderSpec {
+            column: "total".to_string(),
+            direction: SortDirection::Desc,
+        }),
+        limit: None,
+        sticky_columns: Some(1),
+        function_column: Some("module_path".to_string()),
+        enable_assembly: false,
+    })
+}
+
+/// Functions whose sampled loads waited longest, from `--mem-latency` runs.
+fn memory_latency_tab() -> TabSpec {
+    TabSpec::MetricsTable(MetricsTableSpec {
+        view: "memory_latency".to_string(),
+        title: Some("Memory latency".to_string()),
+        include_default_columns: false,
+        columns: vec![
+            MetricColumnSpec {
+                key: "func_name".to_string(),
+                label: Some("Function".to_string()),
+                format: pmu_data::ValueFormat::Text,
+                width: Some(34),
+                sticky: true,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "loads".to_string(),
+                label: Some("Loads".to_string()),
+                format: pmu_data::ValueFormat::Integer,
+                width: Some(12),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "avg_latency".to_string(),
+                label: Some("Avg latency".to_string()),
+                format: pmu_data::ValueFormat::Float1,
+                width: Some(14),
+                sticky: false,
+                optional: false,
+            },
+            MetricColumnSpec {
+                key: "max_latency".to_string(),
+                label: Some("Max latency".to_string()),
+                format: pmu_data::ValueFormat::Integer,
+                width: Some(14),
+                sticky: false,
+                optional: false,
+            },
+        ],
+        order_by: Some(OrderSpec {
+            column: "avg_latency".to_string(),
+            direction: SortDirection::Desc,
+        }),
+        limit: Some(50),
+        sticky_columns: Some(1),
+        function_column: Some("func_name".to_string()),
+        enable_assembly: false,
+    })
+}
+
 fn roofline_ui() -> ScenarioUi {
     ScenarioUi {
-        tabs: vec![TabSpec::Summary, TabSpec::Loops, TabSpec::Flamegraph],
+        tabs: vec![
+            TabSpec::Summary,
+            TabSpec::Loops,
+            module_hotspots_tab(),
+            TabSpec::Flamegraph,
+        ],
     }
 }
 
 fn tma_fallback_ui(tma: &mperf_data::TMAInfo) -> ScenarioUi {
     let mut ui = snapshot_ui();
+    ui.tabs.retain(
+        |tab| !matches!(tab, TabSpec::MetricsTable(table) if table.view == "module_hotspots"),
+    );
     for tab in &mut ui.tabs {
         if let TabSpec::MetricsTable(table) = tab {
             table.view = "tma".to_string();
@@ -99,11 +265,36 @@ fn tma_fallback_ui(tma: &mperf_data::TMAInfo) -> ScenarioUi {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::postprocess::test_record_info;
+    use mperf_data::SnapshotInfo;
 
     #[test]
     fn snapshot_ui_contains_expected_tabs() {
         let ui = snapshot_ui();
-        assert_eq!(ui.tabs.len(), 3);
+        assert_eq!(ui.tabs.len(), 4);
+        assert!(matches!(
+            &ui.tabs[2],
+            TabSpec::MetricsTable(table) if table.view == "module_hotspots"
+        ));
+    }
+
+    #[test]
+    fn memory_latency_runs_get_a_latency_tab() {
+        let record = test_record_info(ScenarioInfo::Snapshot(SnapshotInfo {
+            pid: 1,
+            attached_pid: None,
+            memory_latency: true,
+            counters: Vec::new(),
+            sampling_leader: None,
+            counted_totals: None,
+        }));
+
+        let ui = scenario_ui(&record);
+        assert_eq!(ui.tabs.len(), 5);
+        assert!(matches!(
+            &ui.tabs[2],
+            TabSpec::MetricsTable(table) if table.view == "memory_latency"
+        ));
     }
 
     #[test]
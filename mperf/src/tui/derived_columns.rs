@@ -0,0 +1,113 @@
+//! Columns computed from the other columns of a row when the table is
+//! loaded, for metrics the scenario views do not define. Registered columns
+//! are offered in the column chooser of every table whose view has their
+//! inputs.
+
+use std::sync::{Arc, RwLock};
+
+use pmu_data::{Value as MetricValue, ValueFormat};
+
+static REGISTRY: RwLock<Vec<Arc<dyn DerivedColumn>>> = RwLock::new(Vec::new());
+
+/// A column computed from the view columns of the same row.
+pub trait DerivedColumn: Send + Sync {
+    /// Key of the column; a view column of the same name takes precedence.
+    fn name(&self) -> &str;
+
+    /// Header shown in the table and the column chooser.
+    fn label(&self) -> &str {
+        self.name()
+    }
+
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Auto
+    }
+
+    /// View columns the value is computed from. The column is only offered
+    /// for views that have all of them.
+    fn inputs(&self) -> &[&str];
+
+    fn compute(&self, row: &DerivedRow) -> MetricValue;
+}
+
+/// The view columns of the row a derived column is computed for.
+pub struct DerivedRow<'a> {
+    row: &'a sqlite::Row,
+}
+
+impl<'a> DerivedRow<'a> {
+    pub(super) fn new(row: &'a sqlite::Row) -> Self {
+        DerivedRow { row }
+    }
+
+    pub fn value(&self, column: &str) -> MetricValue {
+        match self
+            .row
+            .iter()
+            .find(|(name, _)| *name == column)
+            .map(|(_, value)| value)
+        {
+            Some(sqlite::Value::Integer(value)) => MetricValue::Integer(*value),
+            Some(sqlite::Value::Float(value)) => MetricValue::Float(*value),
+            Some(sqlite::Value::String(value)) => MetricValue::Text(value.clone()),
+            _ => MetricValue::Null,
+        }
+    }
+
+    /// The column as a number, if it is one.
+    pub fn number(&self, column: &str) -> Option<f64> {
+        match self.value(column) {
+            MetricValue::Integer(value) => Some(value as f64),
+            MetricValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Adds `column` to the tables opened afterwards, replacing a registered
+/// column of the same name.
+pub fn register(column: Arc<dyn DerivedColumn>) {
+    let mut registry = REGISTRY.write().expect("derived column registry");
+    registry.retain(|registered| registered.name() != column.name());
+    registry.push(column);
+}
+
+/// Registers the columns that ship with mperf.
+pub fn register_builtin() {
+    register(Arc::new(CyclesPerInstruction));
+}
+
+pub(super) fn registered() -> Vec<Arc<dyn DerivedColumn>> {
+    REGISTRY.read().expect("derived column registry").clone()
+}
+
+/// Cycles per instruction, the inverse of IPC, which reads more naturally
+/// when looking for expensive instructions.
+pub struct CyclesPerInstruction;
+
+impl DerivedColumn for CyclesPerInstruction {
+    fn name(&self) -> &str {
+        "cpi"
+    }
+
+    fn label(&self) -> &str {
+        "CPI"
+    }
+
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Float2
+    }
+
+    fn inputs(&self) -> &[&str] {
+        &["cycles", "instructions"]
+    }
+
+    fn compute(&self, row: &DerivedRow) -> MetricValue {
+        match (row.number("cycles"), row.number("instructions")) {
+            (Some(cycles), Some(instructions)) if instructions > 0.0 => {
+                MetricValue::Float(cycles / instructions)
+            }
+            _ => MetricValue::Null,
+        }
+    }
+}
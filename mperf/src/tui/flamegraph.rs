@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::Duration;
 use std::{
@@ -23,6 +25,13 @@ use ratatui::widgets::block::Position;
 use ratatui::widgets::StatefulWidget;
 use ratatui::widgets::Widget;
 use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Wrap};
+use serde::{Deserialize, Serialize};
+
+use super::ui_state::UiStateFile;
+
+/// Rows of the "Top" table rendered at once; the rest are paged in as the
+/// selection moves past them.
+pub const DEFAULT_TABLE_LIMIT: usize = 1000;
 
 #[derive(Clone)]
 pub struct FlamegraphTab {
@@ -30,10 +39,68 @@ pub struct FlamegraphTab {
     app: Arc<Mutex<Option<flamelens::app::App>>>,
     cycles: Arc<RwLock<Option<FlameGraph>>>,
     instructions: Arc<RwLock<Option<FlameGraph>>>,
+    cycles_reversed: Arc<RwLock<Option<FlameGraph>>>,
+    instructions_reversed: Arc<RwLock<Option<FlameGraph>>>,
+    walltime: Arc<RwLock<Option<FlameGraph>>>,
+    walltime_reversed: Arc<RwLock<Option<FlameGraph>>>,
+    /// Cycles split by thread at the root.
+    cycles_threads: Arc<RwLock<Option<FlameGraph>>>,
+    /// Cycles with the CPU of every sample as the leaf frame.
+    cycles_cpus: Arc<RwLock<Option<FlameGraph>>>,
+    /// Cycles and instructions counted per stack, for IPC coloring.
+    ipc_cycles: Arc<RwLock<Option<FlameGraph>>>,
+    ipc_instructions: Arc<RwLock<Option<FlameGraph>>>,
     state: Arc<Mutex<FlamelensWidgetState>>,
     load_started: Arc<AtomicBool>,
+    /// Set while the folded stacks are being read.
+    is_running: Arc<AtomicBool>,
     load_error: Arc<RwLock<Option<String>>>,
-    show_instructions: bool,
+    /// Set when the recording produced no cycle samples at all.
+    empty_capture: Arc<AtomicBool>,
+    mode: FlamegraphMode,
+    reversed: bool,
+    per_thread: bool,
+    /// Whether the status bar shows the CPUs the selected stack ran on.
+    show_cpus: bool,
+    /// Whether frames are colored by their IPC rather than by name.
+    color_by_ipc: bool,
+    frame_names: FrameNameElision,
+    ui_state: Option<UiStateFile>,
+    table_limit: usize,
+}
+
+/// What the width of a frame stands for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum FlamegraphMode {
+    #[default]
+    Cycles,
+    Instructions,
+    /// Nanoseconds until the thread's next sample, including time off-CPU.
+    WallTime,
+}
+
+/// How frame names wider than their cell are shortened. The selected frame
+/// is always spelled out in full in the status bar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameNameElision {
+    /// Cut at the cell width.
+    Cut,
+    /// Replace template and parameter lists with `<…>` and `(…)` first.
+    Templates,
+    /// Additionally drop the namespace qualifiers before the base name.
+    #[default]
+    BaseName,
+}
+
+impl FrameNameElision {
+    fn next(self) -> Self {
+        match self {
+            FrameNameElision::Cut => FrameNameElision::Templates,
+            FrameNameElision::Templates => FrameNameElision::BaseName,
+            FrameNameElision::BaseName => FrameNameElision::Cut,
+        }
+    }
 }
 
 impl FlamegraphTab {
@@ -43,30 +110,204 @@ impl FlamegraphTab {
             app: Arc::new(Mutex::new(None)),
             cycles: Arc::new(RwLock::new(None)),
             instructions: Arc::new(RwLock::new(None)),
+            cycles_reversed: Arc::new(RwLock::new(None)),
+            instructions_reversed: Arc::new(RwLock::new(None)),
+            walltime: Arc::new(RwLock::new(None)),
+            walltime_reversed: Arc::new(RwLock::new(None)),
+            cycles_threads: Arc::new(RwLock::new(None)),
+            cycles_cpus: Arc::new(RwLock::new(None)),
+            ipc_cycles: Arc::new(RwLock::new(None)),
+            ipc_instructions: Arc::new(RwLock::new(None)),
             state: Arc::new(Mutex::new(FlamelensWidgetState::default())),
             load_started: Arc::new(AtomicBool::new(false)),
+            is_running: Arc::new(AtomicBool::new(false)),
             load_error: Arc::new(RwLock::new(None)),
-            show_instructions: false,
+            empty_capture: Arc::new(AtomicBool::new(false)),
+            mode: FlamegraphMode::default(),
+            reversed: false,
+            per_thread: false,
+            show_cpus: false,
+            color_by_ipc: false,
+            frame_names: FrameNameElision::default(),
+            ui_state: None,
+            table_limit: DEFAULT_TABLE_LIMIT,
         }
     }
 
+    pub fn with_table_limit(mut self, limit: usize) -> Self {
+        self.table_limit = limit.max(1);
+        self
+    }
+
+    /// Restores the frame name elision from `ui_state` and saves later changes
+    /// to it.
+    pub fn with_ui_state(mut self, ui_state: UiStateFile) -> Self {
+        self.frame_names = ui_state.get().frame_names;
+        self.ui_state = Some(ui_state);
+        self
+    }
+
+    /// Keys this tab handles, for the help overlay.
+    pub fn help_entries(&self) -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("m", "Switch between cycles, instructions and wall time"),
+            ("r", "Reverse the flamegraph"),
+            ("t", "Split cycles by thread"),
+            ("c", "Show the CPUs the selected stack ran on"),
+            ("i", "Color frames by IPC, red for stalls to green"),
+            ("n", "Cycle how long frame names are shortened"),
+            ("v", "Switch between the flamegraph and the Top table"),
+            ("↑/↓", "Select the caller/callee, or a row of the Top table"),
+            ("←/→", "Select the previous/next frame on the same level"),
+            ("<pgup>/<pgdn>", "Scroll a page"),
+            ("<home>/<end>", "Scroll to the top/bottom"),
+        ]
+    }
+
     pub fn handle_event(&mut self, code: KeyCode) {
-        if let KeyCode::Char('m') = code {
-            let mut app = self.app.lock();
-            if app.is_none() {
+        match code {
+            KeyCode::Char('m') => {
+                self.mode = match self.mode {
+                    FlamegraphMode::Cycles => FlamegraphMode::Instructions,
+                    // Recordings processed before time weighting lack it.
+                    FlamegraphMode::Instructions if self.walltime.read().is_some() => {
+                        FlamegraphMode::WallTime
+                    }
+                    FlamegraphMode::Instructions | FlamegraphMode::WallTime => {
+                        FlamegraphMode::Cycles
+                    }
+                }
+            }
+            KeyCode::Char('r') => self.reversed = !self.reversed,
+            // Recordings processed before per-thread stacks lack them.
+            KeyCode::Char('t') if self.cycles_threads.read().is_some() => {
+                self.per_thread = !self.per_thread
+            }
+            // Recordings processed before per-CPU stacks lack them.
+            KeyCode::Char('c') if self.cycles_cpus.read().is_some() => {
+                self.show_cpus = !self.show_cpus;
+                return;
+            }
+            // Recordings processed before counted stacks lack them.
+            KeyCode::Char('i') if self.ipc_cycles.read().is_some() => {
+                self.color_by_ipc = !self.color_by_ipc;
+                return;
+            }
+            KeyCode::Char('n') => {
+                self.frame_names = self.frame_names.next();
+                if let Some(ui_state) = &self.ui_state {
+                    ui_state.update(|ui_state| ui_state.frame_names = self.frame_names);
+                }
+                return;
+            }
+            KeyCode::Char('v') => {
+                if let Some(app) = self.app.lock().as_mut() {
+                    app.flamegraph_view.state.toggle_view_kind();
+                }
                 return;
             }
+            code => {
+                self.navigate(code);
+                return;
+            }
+        }
 
-            self.show_instructions = !self.show_instructions;
+        let mut app = self.app.lock();
+        if app.is_none() {
+            return;
+        }
 
-            if self.show_instructions {
-                if let Some(fg) = self.instructions.read().clone() {
-                    *app = Some(flamelens::app::App::with_flamegraph("Instructions", fg));
-                }
-            } else if let Some(fg) = self.cycles.read().clone() {
-                *app = Some(flamelens::app::App::with_flamegraph("Cycles", fg));
+        let (title, graph) = match (self.mode, self.reversed) {
+            (FlamegraphMode::Cycles, false) if self.per_thread => {
+                ("Cycles by thread", &self.cycles_threads)
+            }
+            (FlamegraphMode::Cycles, false) => ("Cycles", &self.cycles),
+            (FlamegraphMode::Instructions, false) => ("Instructions", &self.instructions),
+            (FlamegraphMode::WallTime, false) => ("Wall time (ns)", &self.walltime),
+            (FlamegraphMode::Cycles, true) => ("Cycles (reversed)", &self.cycles_reversed),
+            (FlamegraphMode::Instructions, true) => {
+                ("Instructions (reversed)", &self.instructions_reversed)
+            }
+            (FlamegraphMode::WallTime, true) => {
+                ("Wall time (ns, reversed)", &self.walltime_reversed)
             }
+        };
+        if let Some(fg) = graph.read().clone() {
+            *app = Some(flamelens::app::App::with_flamegraph(title, fg));
+        }
+    }
+
+    /// Highlights the frames containing `query` and selects the next one. The
+    /// Top table shows only the matching functions.
+    pub fn search(&self, query: &str) -> bool {
+        let mut app = self.app.lock();
+        let Some(app) = app.as_mut() else {
+            return false;
+        };
+        app.set_manual_search_pattern(query, false);
+        let found = app
+            .flamegraph()
+            .hit_ids()
+            .is_some_and(|hits| !hits.is_empty());
+        let view = &mut app.flamegraph_view;
+        if view.state.view_kind == ViewKind::Table {
+            view.state.table_state.reset();
+        } else {
+            view.to_next_search_result();
         }
+        found
+    }
+
+    /// Moves the selection of the shown view. The Top table pages in rows
+    /// past `table_limit` as the selection reaches them.
+    fn navigate(&self, code: KeyCode) {
+        let mut app = self.app.lock();
+        let Some(app) = app.as_mut() else {
+            return;
+        };
+        let view = &mut app.flamegraph_view;
+        if view.state.view_kind == ViewKind::FlameGraph {
+            match code {
+                KeyCode::Down => view.to_child_stack(),
+                KeyCode::Up => view.to_parent_stack(),
+                KeyCode::Left => view.to_previous_sibling(),
+                KeyCode::Right => view.to_next_sibling(),
+                KeyCode::PageDown => view.page_down(),
+                KeyCode::PageUp => view.page_up(),
+                KeyCode::Home => view.scroll_top(),
+                KeyCode::End => view.scroll_bottom(),
+                _ => {}
+            }
+            return;
+        }
+        let last_row = view.flamegraph.ordered_stacks.num_rows.saturating_sub(1);
+        match code {
+            KeyCode::Down => view.to_next_row(),
+            KeyCode::Up => view.to_previous_row(),
+            KeyCode::PageDown => view.scroll_next_rows(),
+            KeyCode::PageUp => view.scroll_previous_rows(),
+            KeyCode::Home => view.state.table_state.reset(),
+            KeyCode::End => {
+                view.state.table_state.selected = last_row;
+                view.state.table_state.offset = last_row;
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads the folded stacks again and shows the cycles flamegraph. Ignored
+    /// while a read is still in flight.
+    pub fn reload(&mut self) {
+        if self.is_running.load(Ordering::Acquire) {
+            return;
+        }
+        *self.load_error.write() = None;
+        self.empty_capture.store(false, Ordering::Release);
+        self.mode = FlamegraphMode::default();
+        self.reversed = false;
+        self.per_thread = false;
+        self.load_started.store(false, Ordering::Release);
+        self.run();
     }
 
     pub fn run(&self) {
@@ -77,6 +318,7 @@ impl FlamegraphTab {
         {
             return;
         }
+        self.is_running.store(true, Ordering::Release);
         let this = self.clone();
         tokio::spawn(this.fetch_data());
     }
@@ -86,14 +328,70 @@ impl FlamegraphTab {
             let cycles = read_flamegraph(&self.res_dir.join("flamegraph_cycles.folded")).await?;
             let instructions =
                 read_flamegraph(&self.res_dir.join("flamegraph_instructions.folded")).await?;
-            Ok::<_, String>((cycles, instructions))
+            // Recordings processed before reversed stacks existed lack these files.
+            let cycles_reversed =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_cycles_reversed.folded"))
+                    .await?;
+            let instructions_reversed = read_optional_flamegraph(
+                &self.res_dir.join("flamegraph_instructions_reversed.folded"),
+            )
+            .await?;
+            let walltime =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_walltime.folded")).await?;
+            let walltime_reversed =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_walltime_reversed.folded"))
+                    .await?;
+            let cycles_threads =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_cycles_threads.folded"))
+                    .await?;
+            let cycles_cpus =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_cycles_cpus.folded"))
+                    .await?;
+            let ipc_cycles =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_ipc_cycles.folded"))
+                    .await?;
+            let ipc_instructions =
+                read_optional_flamegraph(&self.res_dir.join("flamegraph_ipc_instructions.folded"))
+                    .await?;
+            Ok::<_, String>((
+                cycles,
+                instructions,
+                cycles_reversed,
+                instructions_reversed,
+                walltime,
+                walltime_reversed,
+                cycles_threads,
+                cycles_cpus,
+                ipc_cycles,
+                ipc_instructions,
+            ))
         }
         .await;
 
         match result {
-            Ok((cycles, instructions)) => {
+            Ok((None, ..)) => self.empty_capture.store(true, Ordering::Release),
+            Ok((
+                Some(cycles),
+                instructions,
+                cycles_reversed,
+                instructions_reversed,
+                walltime,
+                walltime_reversed,
+                cycles_threads,
+                cycles_cpus,
+                ipc_cycles,
+                ipc_instructions,
+            )) => {
                 *self.cycles.write() = Some(cycles.clone());
-                *self.instructions.write() = Some(instructions);
+                *self.instructions.write() = instructions;
+                *self.cycles_reversed.write() = cycles_reversed;
+                *self.instructions_reversed.write() = instructions_reversed;
+                *self.walltime.write() = walltime;
+                *self.walltime_reversed.write() = walltime_reversed;
+                *self.cycles_threads.write() = cycles_threads;
+                *self.cycles_cpus.write() = cycles_cpus;
+                *self.ipc_cycles.write() = ipc_cycles;
+                *self.ipc_instructions.write() = ipc_instructions;
                 *self.app.lock() = Some(flamelens::app::App::with_flamegraph("Cycles", cycles));
             }
             Err(error) => {
@@ -101,6 +399,8 @@ impl FlamegraphTab {
                     Some(format!("Could not load flamegraph data:\n\n{error}"));
             }
         }
+
+        self.is_running.store(false, Ordering::Release);
     }
 }
 
@@ -117,6 +417,15 @@ impl Widget for FlamegraphTab {
             return;
         }
 
+        if self.empty_capture.load(Ordering::Acquire) {
+            Paragraph::new(super::NO_SAMPLES_MESSAGE)
+                .block(Block::bordered().title("Flamegraph"))
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .render(area, buf);
+            return;
+        }
+
         let mut app = self.app.lock();
         let Some(app) = app.as_mut() else {
             return;
@@ -124,19 +433,249 @@ impl Widget for FlamegraphTab {
 
         let mut state = self.state.lock();
 
-        let flamelens_widget = FlamelensWidget::new(app);
+        let cycles_cpus = self.cycles_cpus.read();
+        // The stacks of the other flamegraphs do not match the per-CPU ones.
+        let cpus = cycles_cpus.as_ref().filter(|_| {
+            self.show_cpus
+                && self.mode == FlamegraphMode::Cycles
+                && !self.reversed
+                && !self.per_thread
+        });
+        let ipc_cycles = self.ipc_cycles.read();
+        let ipc_instructions = self.ipc_instructions.read();
+        // Reversed and per-thread stacks are named differently from the
+        // counted ones.
+        let ipc = ipc_cycles
+            .as_ref()
+            .zip(ipc_instructions.as_ref())
+            .filter(|_| self.color_by_ipc && !self.reversed && !self.per_thread);
+        let flamelens_widget = FlamelensWidget::new(app, self.frame_names, self.table_limit)
+            .with_cpus(cpus)
+            .with_ipc(ipc);
         StatefulWidget::render(flamelens_widget, area, buf, &mut *state);
+        // Paging moves by the rows that fit.
+        app.flamegraph_view.set_frame_height(state.frame_height);
+        app.flamegraph_view.set_frame_width(state.frame_width);
     }
 }
 
-async fn read_flamegraph(path: &Path) -> Result<FlameGraph, String> {
+/// Sums, per frame name, the total count of every stack with that name and
+/// the total count of the stack directly above it, so their ratio is the
+/// share of the callers' time spent in the frame and its callees.
+fn total_and_parent_counts(flamegraph: &FlameGraph) -> HashMap<&str, (u64, u64)> {
+    let mut counts = HashMap::<&str, (u64, u64)>::new();
+    for level in 0..flamegraph.get_num_levels() {
+        let Some(ids) = flamegraph.get_stacks_at_level(level) else {
+            continue;
+        };
+        for id in ids {
+            let Some(stack) = flamegraph.get_stack(id) else {
+                continue;
+            };
+            let Some(parent) = stack
+                .parent
+                .and_then(|parent| flamegraph.get_stack(&parent))
+            else {
+                continue;
+            };
+            let entry = counts
+                .entry(flamegraph.get_stack_short_name_from_info(stack))
+                .or_default();
+            entry.0 += stack.total_count;
+            entry.1 += parent.total_count;
+        }
+    }
+    counts
+}
+
+/// Shortens `name` to at most `width` characters, following `elision`. Each
+/// step is only taken while the name is still too wide, and whatever is left
+/// over is cut with a trailing `…`.
+fn elide_frame_name(name: &str, width: usize, elision: FrameNameElision) -> Cow<'_, str> {
+    if name.chars().count() <= width {
+        return Cow::Borrowed(name);
+    }
+    let mut name = Cow::Borrowed(name);
+    if elision != FrameNameElision::Cut {
+        name = Cow::Owned(elide_brackets(&name));
+    }
+    if elision == FrameNameElision::BaseName && name.chars().count() > width {
+        if let Some(base) = base_name(&name) {
+            name = Cow::Owned(format!("…{base}"));
+        }
+    }
+    if name.chars().count() <= width {
+        return name;
+    }
+    let mut cut = name
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    if width > 0 {
+        cut.push('…');
+    }
+    Cow::Owned(cut)
+}
+
+/// Collapses the contents of every outermost `<…>` and `(…)` group. The
+/// brackets of `operator<`, `operator()` and friends are kept as they are.
+fn elide_brackets(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        if depth == 0 && rest.starts_with("operator") {
+            let operator = rest["operator".len()..]
+                .find(|c: char| !"<>=!+-*/%&|^~[]()".contains(c) || c == ' ')
+                .map_or(rest.len(), |end| end + "operator".len());
+            let operator = match &rest[..operator] {
+                // `operator()(int)`: only the first pair names the operator.
+                op if op.starts_with("operator()") => "operator()",
+                op => op,
+            };
+            out.push_str(operator);
+            rest = &rest[operator.len()..];
+            continue;
+        }
+        match c {
+            '<' | '(' => {
+                if depth == 0 {
+                    out.push(c);
+                    out.push('…');
+                }
+                depth += 1;
+            }
+            '>' | ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    out.push(c);
+                }
+            }
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// The part of a qualified name after its last top-level `::`, or `None`
+/// for an unqualified name.
+fn base_name(name: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    let mut split = None;
+    for (index, c) in name.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 && name[index..].starts_with("::") => split = Some(index + 2),
+            _ => {}
+        }
+    }
+    split
+        .map(|split| &name[split..])
+        .filter(|base| !base.is_empty())
+}
+
+/// Instructions per cycle of the stack `full_name` and everything it calls,
+/// or of all stacks for `None`. `None` without cycles counted for it.
+fn stack_ipc(
+    cycles: &FlameGraph,
+    instructions: &FlameGraph,
+    full_name: Option<&str>,
+) -> Option<f64> {
+    let total = |graph: &FlameGraph| match full_name {
+        Some(full_name) => graph
+            .get_stack_id_by_full_name(full_name)
+            .and_then(|id| graph.get_stack(&id))
+            .map(|stack| stack.total_count),
+        None => Some(graph.root().total_count),
+    };
+    let cycles = total(cycles).filter(|cycles| *cycles > 0)?;
+    Some(total(instructions).unwrap_or(0) as f64 / cycles as f64)
+}
+
+/// Red for stall-bound frames at an IPC of [`IPC_LOW`] or less, through
+/// yellow, to green at [`IPC_HIGH`] or more.
+fn ipc_color(ipc: f64) -> (u8, u8, u8) {
+    let t = ((ipc - IPC_LOW) / (IPC_HIGH - IPC_LOW)).clamp(0.0, 1.0);
+    if t < 0.5 {
+        (220, (60.0 + 360.0 * t) as u8, 40)
+    } else {
+        ((220.0 - 320.0 * (t - 0.5)) as u8, 240, 40)
+    }
+}
+
+/// First row of the "Top" table page that holds the selection.
+/// Samples per CPU of the stack `full_name` and everything it calls, or of
+/// all stacks for `None`, most first. `cpus` has the CPU of every sample as
+/// the leaf frame.
+fn cpu_counts(cpus: &FlameGraph, full_name: Option<&str>) -> Vec<(u32, u64)> {
+    let stack = match full_name {
+        Some(full_name) => cpus.get_stack_id_by_full_name(full_name),
+        None => Some(cpus.root().id),
+    };
+    let mut counts = HashMap::<u32, u64>::new();
+    for id in stack
+        .map(|stack| cpus.get_descendants(&stack))
+        .unwrap_or_default()
+    {
+        let Some(stack) = cpus.get_stack(&id) else {
+            continue;
+        };
+        if let Some(cpu) = cpus
+            .get_stack_short_name_from_info(stack)
+            .strip_prefix("[cpu ")
+            .and_then(|cpu| cpu.strip_suffix(']'))
+            .and_then(|cpu| cpu.parse().ok())
+        {
+            *counts.entry(cpu).or_default() += stack.total_count;
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+/// The first CPUs of [`cpu_counts`] with their share of the samples.
+fn format_cpu_shares(counts: &[(u32, u64)]) -> String {
+    const SHOWN: usize = 3;
+    let total = counts.iter().map(|(_, count)| count).sum::<u64>();
+    if total == 0 {
+        return "no samples with a CPU".to_string();
+    }
+    let mut text = counts
+        .iter()
+        .take(SHOWN)
+        .map(|(cpu, count)| format!("cpu {cpu} {:.1}%", *count as f64 / total as f64 * 100.0))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if counts.len() > SHOWN {
+        text += &format!(", {} more", counts.len() - SHOWN);
+    }
+    text
+}
+
+fn table_page_start(selected: usize, limit: usize) -> usize {
+    selected / limit * limit
+}
+
+/// Returns `None` when the file holds no samples.
+async fn read_flamegraph(path: &Path) -> Result<Option<FlameGraph>, String> {
     let data = tokio::fs::read_to_string(path)
         .await
         .map_err(|error| format!("failed to read {}: {error}", path.display()))?;
     parse_flamegraph(data).map_err(|error| format!("invalid {}: {error}", path.display()))
 }
 
-fn parse_flamegraph(data: String) -> Result<FlameGraph, String> {
+async fn read_optional_flamegraph(path: &Path) -> Result<Option<FlameGraph>, String> {
+    if !tokio::fs::try_exists(path).await.unwrap_or(false) {
+        return Ok(None);
+    }
+    read_flamegraph(path).await
+}
+
+fn parse_flamegraph(data: String) -> Result<Option<FlameGraph>, String> {
     let mut sample_count = 0usize;
     for (index, line) in data.lines().enumerate() {
         let line = line.trim();
@@ -152,30 +691,255 @@ fn parse_flamegraph(data: String) -> Result<FlameGraph, String> {
         sample_count += 1;
     }
     if sample_count == 0 {
-        return Err("no valid folded stack samples found".to_string());
+        return Ok(None);
     }
-    Ok(FlameGraph::from_string(data, false))
+    Ok(Some(FlameGraph::from_string(data, false)))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_flamegraph;
+    use super::{
+        cpu_counts, elide_frame_name, format_cpu_shares, ipc_color, parse_flamegraph, stack_ipc,
+        table_page_start, total_and_parent_counts, FlamegraphTab, FrameNameElision,
+        DEFAULT_TABLE_LIMIT,
+    };
+    use crate::tui::{App, Tab};
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use flamelens::flame::FlameGraph;
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::Widget;
+    use std::path::PathBuf;
+
+    fn tab_with(folded: String) -> FlamegraphTab {
+        let tab = FlamegraphTab::new(PathBuf::new());
+        let flamegraph = FlameGraph::from_string(folded, false);
+        *tab.app.lock() = Some(flamelens::app::App::with_flamegraph("Cycles", flamegraph));
+        tab
+    }
+
+    fn rendered(tab: &FlamegraphTab) -> String {
+        let area = Rect::new(0, 0, 120, 30);
+        let mut buf = Buffer::empty(area);
+        tab.clone().render(area, &mut buf);
+        buf.content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    fn selected_row(tab: &FlamegraphTab) -> usize {
+        let app = tab.app.lock();
+        app.as_ref()
+            .unwrap()
+            .flamegraph_state()
+            .table_state
+            .selected
+    }
+
+    #[test]
+    fn top_table_pages_follow_the_selection() {
+        assert_eq!(table_page_start(0, 100), 0);
+        assert_eq!(table_page_start(99, 100), 0);
+        assert_eq!(table_page_start(100, 100), 100);
+        assert_eq!(table_page_start(250, 100), 200);
+    }
+
+    #[test]
+    fn top_table_keys_page_past_the_limit() {
+        let folded = (0..DEFAULT_TABLE_LIMIT + 500)
+            .map(|i| format!("main;f{i:04} {}\n", i + 1))
+            .collect();
+        let mut tab = tab_with(folded);
+        tab.handle_event(KeyCode::Char('v'));
+        assert!(!rendered(&tab).contains("main"));
+
+        // 1500 functions by their own count, then `main`, which has none.
+        tab.handle_event(KeyCode::End);
+        assert_eq!(selected_row(&tab), DEFAULT_TABLE_LIMIT + 500);
+        let page = rendered(&tab);
+        assert!(page.contains("main"));
+        assert!(!page.contains("more"));
+
+        tab.handle_event(KeyCode::Up);
+        assert_eq!(selected_row(&tab), DEFAULT_TABLE_LIMIT + 499);
+        tab.handle_event(KeyCode::PageUp);
+        assert!(selected_row(&tab) < DEFAULT_TABLE_LIMIT + 499);
+        tab.handle_event(KeyCode::Home);
+        assert_eq!(selected_row(&tab), 0);
+        tab.handle_event(KeyCode::PageDown);
+        assert!(selected_row(&tab) > 0);
+    }
+
+    #[test]
+    fn status_bar_spells_out_the_selected_frame() {
+        let name = "std::vector<std::pair<int, float>>::push_back(std::pair<int, float>&&)";
+        let mut tab = tab_with(format!("main;{name} 3\nmain;other 7\n"));
+        tab.handle_event(KeyCode::Down);
+        tab.handle_event(KeyCode::Down);
+        assert!(!rendered(&tab).contains(name));
+
+        // The frame is too narrow for the name; the status bar is not.
+        tab.handle_event(KeyCode::Left);
+        assert!(rendered(&tab).contains(name));
+        tab.handle_event(KeyCode::Right);
+        assert!(!rendered(&tab).contains(name));
+    }
+
+    #[test]
+    fn vim_keys_search_and_move_the_selection() {
+        let tab = tab_with("main;parse;lex 3\nmain;render 5\n".to_string());
+        let mut app = App {
+            vim_keys: Some(Default::default()),
+            ..Default::default()
+        };
+        app.tabs.tabs.write().push(Tab::Flamegraph(tab.clone()));
+        let mut type_keys = |keys: &str| {
+            for c in keys.chars() {
+                let code = if c == '\n' {
+                    KeyCode::Enter
+                } else {
+                    KeyCode::Char(c)
+                };
+                app.handle_event(&Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+            }
+        };
+
+        type_keys("/lex\n");
+        assert!(rendered(&tab).contains("lex [3 samples"));
+        type_keys("k");
+        assert!(rendered(&tab).contains("parse [3 samples"));
+        type_keys("gg/nope\n");
+        assert_eq!(app.notice.as_deref(), Some("Pattern not found: nope"));
+    }
 
     #[test]
-    fn rejects_empty_or_corrupt_folded_stacks() {
-        for data in [
-            "",
-            "# only a comment\n",
-            "main not-a-count\n",
-            "main 1\ncorrupt\n",
-        ] {
+    fn cpu_breakdown_follows_the_selection() {
+        let mut tab = tab_with("main;work 6\nmain;wait 4\n".to_string());
+        *tab.cycles_cpus.write() = Some(FlameGraph::from_string(
+            "main;work;[cpu 2] 6\nmain;wait;[cpu 1] 4\n".to_string(),
+            false,
+        ));
+        tab.handle_event(KeyCode::Char('c'));
+        assert!(rendered(&tab).contains("cpu 2 60.0%, cpu 1 40.0%"));
+
+        tab.handle_event(KeyCode::Down);
+        tab.handle_event(KeyCode::Down);
+        assert!(rendered(&tab).contains("cpu 2 100.0%"));
+        tab.handle_event(KeyCode::Right);
+        assert!(rendered(&tab).contains("cpu 1 100.0%"));
+        tab.handle_event(KeyCode::Up);
+        assert!(rendered(&tab).contains("cpu 2 60.0%, cpu 1 40.0%"));
+    }
+
+    #[test]
+    fn cpu_counts_include_the_callees() {
+        let cpus = FlameGraph::from_string(
+            "main;work;[cpu 2] 6\nmain;work;leaf;[cpu 1] 3\nmain;[cpu 1] 1\n".to_string(),
+            false,
+        );
+        assert_eq!(cpu_counts(&cpus, Some("main;work")), vec![(2, 6), (1, 3)]);
+        assert_eq!(cpu_counts(&cpus, None), vec![(2, 6), (1, 4)]);
+        assert!(cpu_counts(&cpus, Some("other")).is_empty());
+        assert_eq!(
+            format_cpu_shares(&cpu_counts(&cpus, Some("main;work"))),
+            "cpu 2 66.7%, cpu 1 33.3%"
+        );
+    }
+
+    #[test]
+    fn frames_are_colored_by_the_ipc_of_their_subtree() {
+        let cycles = parse_flamegraph("main;work 300\nmain;wait 100\n".to_string())
+            .unwrap()
+            .unwrap();
+        let instructions = parse_flamegraph("main;work 900\n".to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            stack_ipc(&cycles, &instructions, Some("main;work")),
+            Some(3.0)
+        );
+        assert_eq!(
+            stack_ipc(&cycles, &instructions, Some("main;wait")),
+            Some(0.0)
+        );
+        assert_eq!(stack_ipc(&cycles, &instructions, Some("main")), Some(2.25));
+        assert_eq!(stack_ipc(&cycles, &instructions, None), Some(2.25));
+        assert_eq!(stack_ipc(&cycles, &instructions, Some("other")), None);
+
+        assert_eq!(ipc_color(0.0), (220, 60, 40));
+        assert_eq!(ipc_color(3.0), (60, 240, 40));
+    }
+
+    #[test]
+    fn rejects_corrupt_folded_stacks() {
+        for data in ["main not-a-count\n", "main 1\ncorrupt\n"] {
             assert!(parse_flamegraph(data.to_string()).is_err());
         }
     }
 
+    #[test]
+    fn empty_folded_stacks_have_no_flamegraph() {
+        for data in ["", "# only a comment\n"] {
+            assert!(parse_flamegraph(data.to_string()).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn parent_share_is_relative_to_the_calling_frame() {
+        let flamegraph = FlameGraph::from_string(
+            "main;parse 2\nmain;parse;malloc 2\nmain;render;malloc 6\nmain;idle 2\n".to_string(),
+            false,
+        );
+        let counts = total_and_parent_counts(&flamegraph);
+        assert_eq!(counts["parse"], (4, 12));
+        // 2 of parse's 4 and all 6 of render's 6.
+        assert_eq!(counts["malloc"], (8, 10));
+    }
+
+    #[test]
+    fn top_table_shows_the_parent_share() {
+        let mut tab = tab_with("main;parse 1\nmain;parse;lex 3\nmain;idle 4\n".to_string());
+        tab.handle_event(KeyCode::Char('v'));
+        let table = rendered(&tab);
+        assert!(table.contains("% of Parent"));
+        // lex takes 3 of parse's 4, but only 3 of the 8 samples.
+        assert!(table.contains("75.00%"));
+    }
+
+    #[test]
+    fn long_frame_names_keep_their_base_name() {
+        let name =
+            "std::__1::vector<std::__1::pair<int, float>>::push_back(std::__1::pair<int, float>&&)";
+        assert_eq!(
+            elide_frame_name(name, 200, FrameNameElision::BaseName),
+            name
+        );
+        assert_eq!(
+            elide_frame_name(name, 40, FrameNameElision::Templates),
+            "std::__1::vector<…>::push_back(…)"
+        );
+        assert_eq!(
+            elide_frame_name(name, 20, FrameNameElision::BaseName),
+            "…push_back(…)"
+        );
+        assert_eq!(
+            elide_frame_name(name, 10, FrameNameElision::Cut),
+            "std::__1:…"
+        );
+        assert_eq!(
+            elide_frame_name(
+                "Lambda<long>::operator()(int, long) const",
+                30,
+                FrameNameElision::Templates
+            ),
+            "Lambda<…>::operator()(…) const"
+        );
+    }
+
     #[test]
     fn accepts_folded_stack_samples() {
-        assert!(parse_flamegraph("main;work 42\n".to_string()).is_ok());
+        assert!(parse_flamegraph("main;work 42\n".to_string())
+            .unwrap()
+            .is_some());
     }
 }
 
@@ -207,6 +971,10 @@ const SEARCH_PREFIX: &str = "";
 const COLOR_SELECTED_STACK: Color = Color::Rgb(250, 250, 250);
 const COLOR_MATCHED_BACKGROUND: Color = Color::Rgb(10, 35, 150);
 const COLOR_TABLE_SELECTED_ROW: Color = Color::Rgb(65, 65, 65);
+/// Frames without counted cycles when colored by IPC.
+const COLOR_NO_IPC: (u8, u8, u8) = (128, 128, 128);
+const IPC_LOW: f64 = 0.5;
+const IPC_HIGH: f64 = 2.5;
 
 #[derive(Debug, Clone, Default)]
 pub struct FlamelensWidgetState {
@@ -218,11 +986,38 @@ pub struct FlamelensWidgetState {
 
 pub struct FlamelensWidget<'a> {
     pub app: &'a flamelens::app::App,
+    pub frame_names: FrameNameElision,
+    /// Rows of the "Top" table rendered at once.
+    pub table_limit: usize,
+    /// Per-CPU stacks of the shown flamegraph, to annotate the selection with.
+    pub cpus: Option<&'a FlameGraph>,
+    /// Counted cycles and instructions stacks to color frames by IPC with.
+    pub ipc: Option<(&'a FlameGraph, &'a FlameGraph)>,
 }
 
 impl<'a> FlamelensWidget<'a> {
-    pub fn new(app: &'a flamelens::app::App) -> Self {
-        Self { app }
+    pub fn new(
+        app: &'a flamelens::app::App,
+        frame_names: FrameNameElision,
+        table_limit: usize,
+    ) -> Self {
+        Self {
+            app,
+            frame_names,
+            table_limit,
+            cpus: None,
+            ipc: None,
+        }
+    }
+
+    pub fn with_cpus(mut self, cpus: Option<&'a FlameGraph>) -> Self {
+        self.cpus = cpus;
+        self
+    }
+
+    pub fn with_ipc(mut self, ipc: Option<(&'a FlameGraph, &'a FlameGraph)>) -> Self {
+        self.ipc = ipc;
+        self
     }
 }
 
@@ -356,10 +1151,12 @@ impl<'a> FlamelensWidget<'a> {
     }
 
     fn render_table(&self, area: Rect, buf: &mut Buffer) {
-        let ordered_stacks_table = self.get_ordered_stacks_table();
+        let table_state = &self.app.flamegraph_state().table_state;
+        let start = table_page_start(table_state.selected, self.table_limit);
+        let ordered_stacks_table = self.get_ordered_stacks_table(start);
         let mut table_state = ratatui::widgets::TableState::default()
-            .with_selected(self.app.flamegraph_state().table_state.selected)
-            .with_offset(self.app.flamegraph_state().table_state.offset);
+            .with_selected(table_state.selected - start)
+            .with_offset(table_state.offset.saturating_sub(start));
         StatefulWidget::render(ordered_stacks_table, area, buf, &mut table_state);
     }
 
@@ -438,7 +1235,9 @@ impl<'a> FlamelensWidget<'a> {
         has_more_rows_to_render
     }
 
-    fn get_ordered_stacks_table(&self) -> Table<'_> {
+    /// The page of at most `table_limit` visible entries from `start`, with a
+    /// row counting the entries after it.
+    fn get_ordered_stacks_table(&self, start: usize) -> Table<'_> {
         let add_sorted_indicator = |label: &str, sort_column: SortColumn| {
             let suffix = if sort_column == self.app.flamegraph().ordered_stacks.sorted_column {
                 " [▼]"
@@ -450,6 +1249,7 @@ impl<'a> FlamelensWidget<'a> {
         let header = Row::new(vec![
             add_sorted_indicator("Total", SortColumn::Total),
             add_sorted_indicator("Own", SortColumn::Own),
+            "% of Parent".to_string(),
             "Name".to_string(),
         ])
         .style(
@@ -462,6 +1262,7 @@ impl<'a> FlamelensWidget<'a> {
         let total_count = self.app.flamegraph().total_count();
         let mut total_max_width: u16 = 0;
         let mut own_max_width: u16 = 0;
+        let parent_counts = total_and_parent_counts(self.app.flamegraph());
 
         fn format_count(count: u64, total_count: u64) -> String {
             format!(
@@ -471,11 +1272,18 @@ impl<'a> FlamelensWidget<'a> {
             )
         }
 
-        for entry in counts.iter().filter(|entry| entry.visible) {
+        let mut visible = counts.iter().filter(|entry| entry.visible).skip(start);
+        for entry in visible.by_ref().take(self.table_limit) {
             let total_formatted = Line::from(format_count(entry.count.total, total_count));
             let own_formatted = Line::from(format_count(entry.count.own, total_count));
             total_max_width = total_max_width.max(total_formatted.width() as u16);
             own_max_width = own_max_width.max(own_formatted.width() as u16);
+            let parent_formatted = match parent_counts.get(entry.name.as_str()) {
+                Some(&(total, parent)) if parent > 0 => {
+                    format!("{:.2}%", 100.0 * total as f64 / parent as f64)
+                }
+                _ => "-".to_string(),
+            };
             let name_formatted = if let Some(p) = &self.app.flamegraph_state().search_pattern {
                 if p.is_manual {
                     Line::from(self.get_highlighted_spans(
@@ -492,12 +1300,23 @@ impl<'a> FlamelensWidget<'a> {
             rows.push(Row::new(vec![
                 total_formatted,
                 own_formatted,
+                Line::from(parent_formatted),
                 name_formatted,
             ]));
         }
+        let more = visible.count();
+        if more > 0 {
+            rows.push(Row::new(vec![
+                Line::from(""),
+                Line::from(""),
+                Line::from(""),
+                Line::from(format!("… {more} more")).italic(),
+            ]));
+        }
         let widths = [
             Constraint::Max(total_max_width),
             Constraint::Max(own_max_width),
+            Constraint::Length(14),
             Constraint::Fill(1),
         ];
         Table::new(rows, widths)
@@ -537,21 +1356,29 @@ impl<'a> FlamelensWidget<'a> {
         re: &Option<&regex::Regex>,
     ) -> Line<'_> {
         let short_name = self.app.flamegraph().get_stack_short_name_from_info(stack);
+        let name = elide_frame_name(
+            short_name,
+            width.saturating_sub(1) as usize,
+            self.frame_names,
+        );
 
         // Empty space separator at the beginning
         let mut spans = vec![Span::styled(if width > 1 { " " } else { "." }, style)];
 
         // Stack name with highlighted search terms if needed
-        let short_name_spans = if let (true, &Some(re)) = (stack.hit, re) {
-            self.get_highlighted_spans(short_name, re, style)
+        let name_spans = if let (true, &Some(re)) = (stack.hit, re) {
+            self.get_highlighted_spans(&name, re, style)
+                .into_iter()
+                .map(|span| Span::styled(span.content.into_owned(), span.style))
+                .collect()
         } else {
-            vec![Span::styled(short_name, style)]
+            vec![Span::styled(name.to_string(), style)]
         };
-        spans.extend(short_name_spans);
+        spans.extend(name_spans);
 
         // Padding to fill the rest of the width
         let pad_length = width
-            .saturating_sub(short_name.len() as u16)
+            .saturating_sub(name.chars().count() as u16)
             .saturating_sub(1) as usize;
         spans.push(Span::styled(
             format!("{:width$}", "", width = pad_length),
@@ -577,7 +1404,10 @@ impl<'a> FlamelensWidget<'a> {
         let mut r;
         let mut g;
         let mut b;
-        if !stack.hit {
+        if let (false, Some((cycles, instructions))) = (stack.hit, self.ipc) {
+            let full_name = stack.parent.is_some().then_some(full_name);
+            (r, g, b) = stack_ipc(cycles, instructions, full_name).map_or(COLOR_NO_IPC, ipc_color);
+        } else if !stack.hit {
             r = 205 + (50.0 * v2) as u8;
             g = (230.0 * v1) as u8;
             b = (55.0 * v2) as u8;
@@ -741,6 +1571,23 @@ impl<'a> FlamelensWidget<'a> {
                 let status_text = format!("{:width$}", selected_text, width = width as usize,);
                 if self.is_flamegraph_view() {
                     lines.push(("Selected", Line::from(status_text)));
+                    if let Some(cpus) = self.cpus {
+                        let full_name = stack
+                            .parent
+                            .is_some()
+                            .then(|| self.app.flamegraph().get_stack_full_name_from_info(stack));
+                        let shares = format_cpu_shares(&cpu_counts(cpus, full_name));
+                        lines.push(("CPUs", Line::from(shares)));
+                    }
+                    if let Some((cycles, instructions)) = self.ipc {
+                        let full_name = stack
+                            .parent
+                            .is_some()
+                            .then(|| self.app.flamegraph().get_stack_full_name_from_info(stack));
+                        let ipc = stack_ipc(cycles, instructions, full_name)
+                            .map_or("no cycles counted".to_string(), |ipc| format!("{ipc:.2}"));
+                        lines.push(("IPC", Line::from(ipc)));
+                    }
                 }
                 if self.app.debug {
                     let elapsed_str = format!(
@@ -1,19 +1,58 @@
 use std::sync::Arc;
 
+use crossterm::event::KeyCode;
 use parking_lot::{Mutex, RwLock};
 use ratatui::{
     layout::Constraint,
-    style::{Style, Stylize},
-    widgets::{Block, Cell, Paragraph, Row, Table, Widget, Wrap},
+    style::{Color, Style, Stylize},
+    widgets::{Block, Cell, Paragraph, Row, StatefulWidget, Table, TableState, Widget, Wrap},
 };
 use sqlite::Connection;
 
+use super::metrics_table::{load_assembly, AssemblyRow};
+use crate::sqlite_int::sqlite_u64_from_i64;
+
 #[derive(Clone)]
 pub struct LoopsTab {
     hotspots: Arc<RwLock<Vec<Loop>>>,
     is_running: Arc<RwLock<bool>>,
     connection: Arc<Mutex<Connection>>,
     load_error: Arc<RwLock<Option<String>>>,
+    state: Arc<Mutex<LoopsState>>,
+}
+
+#[derive(Default)]
+struct LoopsState {
+    selected: Option<usize>,
+    /// Individual runs of the selected loop, once Enter opened them.
+    runs: Option<Result<LoopRuns, String>>,
+    /// Hot assembly of the selected loop, once `a` opened it.
+    assembly: Option<Result<LoopAssembly, String>>,
+}
+
+/// The sampled instructions of one loop, narrowed from its function's
+/// assembly to the addresses the debug info places on the loop line.
+struct LoopAssembly {
+    title: String,
+    rows: Vec<AssemblyRow>,
+    /// Index of the row with the most samples.
+    hottest: Option<usize>,
+    selected: usize,
+}
+
+struct LoopRuns {
+    title: String,
+    runs: Vec<LoopRun>,
+}
+
+/// One execution of a loop. Operation counts come from the instrumented
+/// process and are matched to the timed run by their order.
+#[derive(Debug, PartialEq)]
+struct LoopRun {
+    start: u64,
+    duration: u64,
+    flops: Option<u64>,
+    bytes: Option<u64>,
 }
 
 #[allow(dead_code)]
@@ -54,6 +93,30 @@ impl Widget for LoopsTab {
             return;
         }
 
+        let state = self.state.lock();
+        match &state.runs {
+            Some(Ok(runs)) => return render_runs(runs, area, buf),
+            Some(Err(error)) => {
+                Paragraph::new(error.as_str())
+                    .block(Block::bordered().title("Loop runs (Esc to go back)"))
+                    .wrap(Wrap { trim: true })
+                    .render(area, buf);
+                return;
+            }
+            None => {}
+        }
+        match &state.assembly {
+            Some(Ok(assembly)) => return render_assembly(assembly, area, buf),
+            Some(Err(error)) => {
+                Paragraph::new(error.as_str())
+                    .block(Block::bordered().title("Loop assembly (Esc to go back)"))
+                    .wrap(Wrap { trim: true })
+                    .render(area, buf);
+                return;
+            }
+            None => {}
+        }
+
         let header = [
             Cell::from("Function"),
             Cell::from("Location"),
@@ -103,12 +166,273 @@ impl Widget for LoopsTab {
 
         let t = Table::new(rows, widths)
             .header(header)
+            .row_highlight_style(Style::default().bg(Color::DarkGray))
+            .highlight_symbol("▶ ")
             .block(Block::bordered());
 
-        t.render(area, buf);
+        let mut table_state = TableState::default().with_selected(state.selected);
+        StatefulWidget::render(t, area, buf, &mut table_state);
+    }
+}
+
+fn render_runs(runs: &LoopRuns, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+    let mut durations = runs.runs.iter().map(|run| run.duration).collect::<Vec<_>>();
+    durations.sort_unstable();
+    let median = durations.get(durations.len() / 2).copied().unwrap_or(0);
+    let first_start = runs.runs.iter().map(|run| run.start).min().unwrap_or(0);
+
+    let header = [
+        "Run",
+        "Start",
+        "Duration",
+        "vs median",
+        "FP ops",
+        "Bytes",
+        "GFLOP/s",
+    ]
+    .into_iter()
+    .map(Cell::from)
+    .collect::<Row>()
+    .style(Style::new().bold());
+
+    let rows = runs.runs.iter().enumerate().map(|(index, run)| {
+        let ratio = if median > 0 {
+            run.duration as f64 / median as f64
+        } else {
+            1.0
+        };
+        let mut ratio_cell = Cell::from(format!("{ratio:.2}x"));
+        if ratio > 1.5 {
+            ratio_cell = ratio_cell.fg(Color::Red);
+        } else if ratio < 0.67 {
+            ratio_cell = ratio_cell.fg(Color::Yellow);
+        }
+        let optional =
+            |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+        let gflops = match run.flops {
+            Some(flops) if run.duration > 0 => format!("{:.2}", flops as f64 / run.duration as f64),
+            _ => "-".to_string(),
+        };
+        [
+            Cell::from((index + 1).to_string()),
+            Cell::from(format_duration(run.start - first_start)),
+            Cell::from(format_duration(run.duration)),
+            ratio_cell,
+            Cell::from(optional(run.flops)),
+            Cell::from(optional(run.bytes)),
+            Cell::from(gflops),
+        ]
+        .into_iter()
+        .collect::<Row>()
+    });
+
+    let widths = [
+        Constraint::Max(8),
+        Constraint::Max(14),
+        Constraint::Max(14),
+        Constraint::Max(10),
+        Constraint::Max(16),
+        Constraint::Max(16),
+        Constraint::Max(10),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::bordered().title(format!(
+            " {} ({} runs, Esc to go back) ",
+            runs.title,
+            runs.runs.len()
+        )));
+    Widget::render(table, area, buf);
+}
+
+fn render_assembly(
+    assembly: &LoopAssembly,
+    area: ratatui::prelude::Rect,
+    buf: &mut ratatui::prelude::Buffer,
+) {
+    let header = ["Address", "Samples", "Share", "Instruction"]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(Style::new().bold());
+
+    let rows = assembly.rows.iter().enumerate().map(|(index, row)| {
+        let cells = [
+            Cell::from(format!("{:#x}", row.address)),
+            Cell::from(row.samples.to_string()),
+            Cell::from(format!("{:.2}%", row.share)),
+            Cell::from(row.instruction.as_str()),
+        ]
+        .into_iter()
+        .collect::<Row>();
+        if Some(index) == assembly.hottest {
+            cells.fg(Color::Red).bold()
+        } else {
+            cells
+        }
+    });
+
+    let widths = [
+        Constraint::Max(18),
+        Constraint::Max(10),
+        Constraint::Max(10),
+        Constraint::Fill(1),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .row_highlight_style(Style::default().bg(Color::DarkGray))
+        .block(Block::bordered().title(format!(" {} (Esc to go back) ", assembly.title)));
+    let mut table_state = TableState::default().with_selected(Some(assembly.selected));
+    StatefulWidget::render(table, area, buf, &mut table_state);
+}
+
+fn format_duration(nanoseconds: u64) -> String {
+    match nanoseconds {
+        0..1_000 => format!("{nanoseconds} ns"),
+        1_000..1_000_000 => format!("{:.1} µs", nanoseconds as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.2} ms", nanoseconds as f64 / 1e6),
+        _ => format!("{:.3} s", nanoseconds as f64 / 1e9),
     }
 }
 
+/// Every run of the loop at `function`/`file`/`line`, in execution order.
+fn fetch_runs(
+    conn: &Connection,
+    function: &str,
+    file: &str,
+    line: u32,
+) -> Result<Vec<LoopRun>, String> {
+    let mut statement = conn
+        .prepare(
+            "WITH loop_runs AS (
+                SELECT r.loop_start_ts AS start_ts,
+                       r.loop_end_ts - r.loop_start_ts AS duration,
+                       ROW_NUMBER() OVER (ORDER BY r.rowid) AS run
+                FROM roofline_loop_runs r
+                JOIN strings s_func ON r.function_name = s_func.id
+                JOIN strings s_file ON r.file_name = s_file.id
+                WHERE s_func.string = ?1 AND s_file.string = ?2 AND r.line = ?3
+             ),
+             loop_ops AS (
+                SELECT o.scalar_float_ops + o.scalar_double_ops
+                         + o.vector_float_ops + o.vector_double_ops AS flops,
+                       o.bytes_load + o.bytes_store AS bytes,
+                       ROW_NUMBER() OVER (ORDER BY o.rowid) AS run
+                FROM roofline_ops o
+                JOIN strings s_func ON o.function_name = s_func.id
+                JOIN strings s_file ON o.file_name = s_file.id
+                WHERE s_func.string = ?1 AND s_file.string = ?2 AND o.line = ?3
+             )
+             SELECT loop_runs.start_ts, loop_runs.duration, loop_ops.flops, loop_ops.bytes
+             FROM loop_runs
+             LEFT JOIN loop_ops ON loop_ops.run = loop_runs.run
+             ORDER BY loop_runs.run;",
+        )
+        .map_err(|error| error.to_string())?;
+    statement
+        .bind((1, function))
+        .map_err(|error| error.to_string())?;
+    statement
+        .bind((2, file))
+        .map_err(|error| error.to_string())?;
+    statement
+        .bind((3, line as i64))
+        .map_err(|error| error.to_string())?;
+
+    statement
+        .into_iter()
+        .map(|row| {
+            let row = row.map_err(|error| error.to_string())?;
+            let read = |column| {
+                row.try_read::<Option<i64>, _>(column)
+                    .map(|value| value.map(sqlite_u64_from_i64))
+                    .map_err(|error| error.to_string())
+            };
+            Ok(LoopRun {
+                start: read("start_ts")?.unwrap_or_default(),
+                duration: read("duration")?.unwrap_or_default(),
+                flops: read("flops")?,
+                bytes: read("bytes")?,
+            })
+        })
+        .collect()
+}
+
+/// The assembly of the loop at `function`/`file`/`line`: the function's
+/// disassembly from the first to the last instruction the debug info puts on
+/// the loop line, which spans the loop header and its back edge. Falls back
+/// to the whole function when the line has no instructions.
+fn fetch_loop_assembly(
+    conn: &Connection,
+    function: &str,
+    file: &str,
+    line: u32,
+) -> Result<LoopAssembly, String> {
+    let view = load_assembly(conn, function)?;
+
+    let mut statement = conn
+        .prepare(
+            "SELECT runtime_address, source_file FROM assembly_lines
+             WHERE module_path = ? AND source_line = ?;",
+        )
+        .map_err(|error| error.to_string())?;
+    statement
+        .bind((1, view.module_path.as_str()))
+        .map_err(|error| error.to_string())?;
+    statement
+        .bind((2, line as i64))
+        .map_err(|error| error.to_string())?;
+    // The collector reports the file as the compiler saw it and debug info may
+    // hold a different spelling of the same path, so only file names are
+    // compared.
+    let file_name = std::path::Path::new(file).file_name();
+    let mut range: Option<(u64, u64)> = None;
+    for row in statement.into_iter() {
+        let row = row.map_err(|error| error.to_string())?;
+        let source_file = row
+            .try_read::<Option<&str>, _>("source_file")
+            .map_err(|error| error.to_string())?;
+        if source_file.is_some_and(|source| std::path::Path::new(source).file_name() != file_name) {
+            continue;
+        }
+        let address = sqlite_u64_from_i64(
+            row.try_read::<i64, _>("runtime_address")
+                .map_err(|error| error.to_string())?,
+        );
+        if !view.rows.iter().any(|row| row.address == address) {
+            continue;
+        }
+        range = Some(range.map_or((address, address), |(low, high)| {
+            (low.min(address), high.max(address))
+        }));
+    }
+
+    let mut rows = view.rows;
+    let title = match range {
+        Some((low, high)) => {
+            rows.retain(|row| (low..=high).contains(&row.address));
+            format!("{function} ({file}:{line}), {low:#x}-{high:#x}")
+        }
+        None => format!("{function} ({file}:{line}), no line info, whole function"),
+    };
+    let title = match view.build_id_warning {
+        Some(warning) => format!("{title} [{warning}]"),
+        None => title,
+    };
+    let hottest = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| row.samples > 0)
+        .max_by_key(|(_, row)| row.samples)
+        .map(|(index, _)| index);
+    Ok(LoopAssembly {
+        title,
+        rows,
+        hottest,
+        selected: hottest.unwrap_or(0),
+    })
+}
+
 impl LoopsTab {
     pub fn new(connection: Arc<Mutex<Connection>>) -> Self {
         LoopsTab {
@@ -116,7 +440,130 @@ impl LoopsTab {
             is_running: Arc::new(RwLock::new(false)),
             connection,
             load_error: Arc::new(RwLock::new(None)),
+            state: Arc::new(Mutex::new(LoopsState::default())),
+        }
+    }
+
+    /// Keys this tab handles in its current view, for the help overlay.
+    pub fn help_entries(&self) -> Vec<(&'static str, &'static str)> {
+        let state = self.state.lock();
+        if state.runs.is_some() {
+            return vec![("<esc>", "Back to the loop list")];
+        }
+        if state.assembly.is_some() {
+            return vec![
+                ("↑/↓/<home>/<end>", "Move through the instructions"),
+                ("<esc>", "Back to the loop list"),
+            ];
+        }
+        vec![
+            ("↑/↓/<home>/<end>", "Select a loop"),
+            ("<enter>", "Show the runs of the selected loop"),
+            ("a", "Show the hot assembly of the selected loop"),
+        ]
+    }
+
+    pub fn handle_event(&self, code: KeyCode) {
+        let loops = self.hotspots.read().len();
+        let mut state = self.state.lock();
+        if state.runs.is_some() {
+            if code == KeyCode::Esc {
+                state.runs = None;
+            }
+            return;
+        }
+        if let Some(assembly) = state.assembly.as_mut() {
+            match (code, assembly) {
+                (KeyCode::Esc, _) => state.assembly = None,
+                (KeyCode::Down, Ok(assembly)) => {
+                    assembly.selected =
+                        (assembly.selected + 1).min(assembly.rows.len().saturating_sub(1));
+                }
+                (KeyCode::Up, Ok(assembly)) => {
+                    assembly.selected = assembly.selected.saturating_sub(1);
+                }
+                (KeyCode::Home, Ok(assembly)) => assembly.selected = 0,
+                (KeyCode::End, Ok(assembly)) => {
+                    assembly.selected = assembly.rows.len().saturating_sub(1);
+                }
+                _ => {}
+            }
+            return;
+        }
+        if loops == 0 {
+            return;
+        }
+        match code {
+            KeyCode::Down => {
+                state.selected = Some(state.selected.map_or(0, |index| (index + 1).min(loops - 1)));
+            }
+            KeyCode::Up => {
+                state.selected = Some(state.selected.unwrap_or(0).saturating_sub(1));
+            }
+            KeyCode::Home => state.selected = Some(0),
+            KeyCode::End => state.selected = Some(loops - 1),
+            KeyCode::Enter => {
+                let Some(index) = state.selected else {
+                    return;
+                };
+                let hotspots = self.hotspots.read();
+                let loop_ = &hotspots[index];
+                let runs = fetch_runs(
+                    &self.connection.lock(),
+                    &loop_.function_name,
+                    &loop_.file_name,
+                    loop_.line,
+                );
+                state.runs = Some(runs.map(|runs| LoopRuns {
+                    title: format!(
+                        "{} ({}:{})",
+                        loop_.function_name, loop_.file_name, loop_.line
+                    ),
+                    runs,
+                }));
+            }
+            KeyCode::Char('a') => {
+                let Some(index) = state.selected else {
+                    return;
+                };
+                let hotspots = self.hotspots.read();
+                let loop_ = &hotspots[index];
+                state.assembly = Some(fetch_loop_assembly(
+                    &self.connection.lock(),
+                    &loop_.function_name,
+                    &loop_.file_name,
+                    loop_.line,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    /// Selects the next loop whose function or file contains `query`.
+    pub fn search(&self, query: &str) -> bool {
+        let hotspots = self.hotspots.read();
+        let mut state = self.state.lock();
+        let found = super::next_match(hotspots.len(), state.selected, |index| {
+            let loop_ = &hotspots[index];
+            loop_.function_name.contains(query) || loop_.file_name.contains(query)
+        });
+        if found.is_some() {
+            state.selected = found;
         }
+        found.is_some()
+    }
+
+    /// Drops the loaded loops and queries them again. Ignored while a query
+    /// is still in flight.
+    pub fn reload(&self) {
+        if *self.is_running.read() {
+            return;
+        }
+        *self.load_error.write() = None;
+        *self.state.lock() = LoopsState::default();
+        *self.is_running.write() = true;
+        let this = self.clone();
+        tokio::spawn(this.fetch_data());
     }
 
     pub fn run(&self) {
@@ -177,16 +624,124 @@ impl LoopsTab {
             });
         drop(conn);
 
-        let rows = match result {
-            Ok(rows) => rows,
+        match result {
+            Ok(rows) => *self.hotspots.write() = rows,
             Err(error) => {
                 *self.load_error.write() =
                     Some(format!("Could not load roofline data:\n\n{error}"));
-                return;
             }
-        };
+        }
+
+        *self.is_running.write() = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_are_listed_individually_with_matching_ops() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection.execute(crate::schema::STRINGS).unwrap();
+        connection.execute(crate::schema::ROOFLINE_TABLES).unwrap();
+        connection
+            .execute(
+                "INSERT INTO strings VALUES (1.0, 'kernel'), (2.0, 'a.c');
+                 INSERT INTO roofline_loop_runs VALUES
+                    (10.0, 1, 1, 2.0, 1.0, 7, 100, 200),
+                    (11.0, 1, 1, 2.0, 1.0, 7, 300, 800),
+                    (12.0, 1, 1, 2.0, 1.0, 9, 900, 950);
+                 INSERT INTO roofline_ops VALUES
+                    (20.0, 2, 2, 2.0, 1.0, 7, 64, 32, 0, 10, 0, 0, 0, 40),
+                    (21.0, 2, 2, 2.0, 1.0, 7, 64, 32, 0, 10, 0, 0, 0, 40);",
+            )
+            .unwrap();
+
+        let runs = fetch_runs(&connection, "kernel", "a.c", 7).unwrap();
+        assert_eq!(
+            runs,
+            [
+                LoopRun {
+                    start: 100,
+                    duration: 100,
+                    flops: Some(50),
+                    bytes: Some(96),
+                },
+                LoopRun {
+                    start: 300,
+                    duration: 500,
+                    flops: Some(50),
+                    bytes: Some(96),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_assembly_is_narrowed_to_the_loop_line() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER, pmu_cycles INTEGER);
+                 CREATE TABLE proc_map (ip INTEGER, module_path TEXT, func_name TEXT);
+                 CREATE TABLE assembly_address_stats (
+                    module_path TEXT, func_name TEXT, address INTEGER, samples INTEGER,
+                    cycles INTEGER, instructions INTEGER, branch_misses INTEGER,
+                    branch_instructions INTEGER, llc_misses INTEGER, llc_references INTEGER
+                 );
+                 CREATE TABLE assembly_lines (
+                    module_path TEXT, symbol TEXT, rel_address INTEGER,
+                    runtime_address INTEGER, instruction TEXT,
+                    source_file TEXT, source_line INTEGER
+                 );
+                 INSERT INTO pmu_counters VALUES (4104, 10);
+                 INSERT INTO proc_map VALUES (4104, '/tmp/test', 'kernel');
+                 INSERT INTO assembly_address_stats VALUES
+                    ('/tmp/test', 'kernel', 4100, 1, 10, 10, 0, 0, 0, 0),
+                    ('/tmp/test', 'kernel', 4104, 8, 80, 80, 0, 0, 0, 0),
+                    ('/tmp/test', 'kernel', 4112, 1, 10, 10, 0, 0, 0, 0);
+                 INSERT INTO assembly_lines VALUES
+                    ('/tmp/test', 'kernel', 4096, 4096, 'xor %eax,%eax', '/src/a.c', 6),
+                    ('/tmp/test', 'kernel', 4100, 4100, 'cmp %rsi,%rdi', '/src/a.c', 7),
+                    ('/tmp/test', 'kernel', 4104, 4104, 'addsd (%rdi),%xmm0', '/src/a.c', 8),
+                    ('/tmp/test', 'kernel', 4108, 4108, 'jne 1004', '/src/a.c', 7),
+                    ('/tmp/test', 'kernel', 4112, 4112, 'ret', '/src/a.c', 10);",
+            )
+            .unwrap();
+
+        let assembly = fetch_loop_assembly(&connection, "kernel", "a.c", 7).unwrap();
+        let addresses = assembly
+            .rows
+            .iter()
+            .map(|row| row.address)
+            .collect::<Vec<_>>();
+        assert_eq!(addresses, [4100, 4104, 4108]);
+        assert_eq!(assembly.hottest, Some(1));
+
+        let whole = fetch_loop_assembly(&connection, "kernel", "a.c", 42).unwrap();
+        assert_eq!(whole.rows.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn reload_is_ignored_while_a_query_runs() {
+        let tab = LoopsTab::new(Arc::new(Mutex::new(Connection::open(":memory:").unwrap())));
+        tab.state.lock().selected = Some(3);
+        *tab.is_running.write() = true;
+        tab.reload();
+        assert_eq!(tab.state.lock().selected, Some(3));
+
+        // A failed query still ends the run, so later reloads go through.
+        tab.clone().fetch_data().await;
+        assert!(!*tab.is_running.read());
+        assert!(tab.load_error.read().is_some());
+    }
 
-        let mut hotspots = self.hotspots.write();
-        *hotspots = rows;
+    #[test]
+    fn durations_use_readable_units() {
+        assert_eq!(format_duration(999), "999 ns");
+        assert_eq!(format_duration(1_500), "1.5 µs");
+        assert_eq!(format_duration(2_500_000), "2.50 ms");
+        assert_eq!(format_duration(3_000_000_000), "3.000 s");
     }
 }
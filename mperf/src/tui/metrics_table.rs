@@ -1,20 +1,32 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use crossterm::event::KeyCode;
-use num_format::{Locale, ToFormattedString};
+use num_format::ToFormattedString;
 use parking_lot::{Mutex, RwLock};
-use pmu_data::{MetricColumnSpec, MetricsTableSpec, SortDirection, ValueFormat};
+use pmu_data::{
+    MetricColumnSpec, MetricsTableSpec, SortDirection, Value as MetricValue, ValueFormat,
+};
 use ratatui::{
-    layout::{Alignment, Constraint, Layout, Rect},
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
     style::{Color, Style, Stylize},
     text::{Line, Text},
     widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Widget},
 };
 use sqlite::Connection;
 
+use super::{
+    clipboard,
+    derived_columns::{self, DerivedColumn, DerivedRow},
+    theme::Theme,
+    ui_state::UiStateFile,
+};
+use crate::locale;
+use crate::sqlite_int::{sqlite_i64_from_u64, sqlite_u64_from_i64};
+
 #[derive(Clone)]
 pub struct MetricsTableTab {
     rows: Arc<RwLock<Vec<MetricsRow>>>,
@@ -23,6 +35,9 @@ pub struct MetricsTableTab {
     state: Arc<Mutex<MetricsState>>,
     config: Arc<MetricsTableConfig>,
     layout: Arc<RwLock<Option<RuntimeLayout>>>,
+    ui_state: Option<UiStateFile>,
+    /// Directory Markdown copies of the table are also saved to.
+    export_dir: Option<PathBuf>,
 }
 
 #[derive(Clone)]
@@ -35,6 +50,11 @@ struct MetricsTableConfig {
     limit: Option<usize>,
     function_column: Option<String>,
     enable_assembly: bool,
+    theme: Theme,
+    /// Column compared against the attached `baseline` database, if any.
+    baseline_column: Option<String>,
+    /// Registered derived columns, offered when the view has their inputs.
+    derived_columns: Vec<Arc<dyn DerivedColumn>>,
 }
 
 #[derive(Clone)]
@@ -42,6 +62,9 @@ struct RuntimeLayout {
     columns: Vec<ColumnConfig>,
     sticky_columns: usize,
     function_column_index: Option<usize>,
+    /// Every column the column chooser offers, shown or not: the configured
+    /// ones first, then the remaining columns of the view.
+    choices: Vec<ColumnConfig>,
 }
 
 #[derive(Clone)]
@@ -53,6 +76,8 @@ struct ColumnConfig {
     sticky: bool,
     optional: bool,
     alignment: Alignment,
+    /// Computes the value instead of reading it from the view.
+    derived: Option<Arc<dyn DerivedColumn>>,
 }
 
 #[derive(Clone)]
@@ -80,33 +105,55 @@ struct MetricsState {
     assembly_request_id: u64,
     assembly_summary: Option<Vec<(String, String)>>,
     table_error: Option<String>,
+    /// Hide functions whose location could not be resolved to a source file.
+    source_only: bool,
+    /// Columns picked in the column chooser; `None` shows the configured ones.
+    visible_columns: Option<Vec<String>>,
+    /// Highlighted entry while the column chooser is open.
+    column_chooser: Option<usize>,
+    /// How the selected cell was computed, while its popup is open.
+    explanation: Option<CellExplanation>,
+    /// Bookmarked functions, shared with the other tables through the UI
+    /// state file.
+    bookmarks: BTreeSet<String>,
+    /// Highlighted entry while the bookmark list is open.
+    bookmark_list: Option<usize>,
+    /// Rows that fit the table when it was last drawn.
+    visible_rows: usize,
+    /// Width of the table when it was last drawn.
+    table_width: u16,
+    /// Shown under the table until the next key, e.g. after copying it.
+    notice: Option<String>,
 }
 
-#[derive(Clone)]
-struct MetricsRow {
-    values: Vec<MetricValue>,
+/// The expression behind a cell and the stored counters of its function.
+struct CellExplanation {
+    title: String,
+    value: String,
+    /// The column's SQL, or how a derived column is computed. `None` for
+    /// views created before the expressions were recorded.
+    expression: Option<String>,
+    /// Sums of the `pmu_counters` columns over the function's samples.
+    raw: Result<Vec<(String, String)>, String>,
 }
 
 #[derive(Clone)]
-enum MetricValue {
-    Text(String),
-    Integer(i64),
-    Float(f64),
-    Null,
+struct MetricsRow {
+    values: Vec<MetricValue>,
 }
 
 #[derive(Clone)]
-struct AssemblyRow {
-    address: u64,
-    instruction: String,
-    samples: u64,
-    share: f64,
-    cycles: u64,
-    instructions: u64,
-    branch_misses: u64,
-    branch_instructions: u64,
-    llc_misses: u64,
-    llc_references: u64,
+pub(crate) struct AssemblyRow {
+    pub(crate) address: u64,
+    pub(crate) instruction: String,
+    pub(crate) samples: u64,
+    pub(crate) share: f64,
+    pub(crate) cycles: u64,
+    pub(crate) instructions: u64,
+    pub(crate) branch_misses: u64,
+    pub(crate) branch_instructions: u64,
+    pub(crate) llc_misses: u64,
+    pub(crate) llc_references: u64,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -134,6 +181,20 @@ impl AssemblyStats {
     }
 }
 
+impl AssemblyRow {
+    fn stats(&self) -> AssemblyStats {
+        AssemblyStats {
+            samples: self.samples,
+            cycles: self.cycles,
+            instructions: self.instructions,
+            branch_misses: self.branch_misses,
+            branch_instructions: self.branch_instructions,
+            llc_misses: self.llc_misses,
+            llc_references: self.llc_references,
+        }
+    }
+}
+
 fn assembly_row(
     address: u64,
     instruction: String,
@@ -159,14 +220,111 @@ fn assembly_row(
 }
 
 #[derive(Clone)]
-struct AssemblyViewState {
-    func_name: String,
-    module_path: String,
-    symbol: String,
-    rows: Vec<AssemblyRow>,
+pub(crate) struct AssemblyViewState {
+    pub(crate) func_name: String,
+    pub(crate) module_path: String,
+    pub(crate) symbol: String,
+    /// Set when the module on disk no longer matches the recorded one.
+    pub(crate) build_id_warning: Option<String>,
+    pub(crate) rows: Vec<AssemblyRow>,
     selected: Option<usize>,
     offset: usize,
     max_samples: u64,
+    /// Row where an in-progress `v` selection started.
+    range_anchor: Option<usize>,
+    /// Last completed selection, as inclusive row indices.
+    range: Option<(usize, usize)>,
+    /// The instructions while `rows` holds their basic blocks.
+    instruction_rows: Option<Vec<AssemblyRow>>,
+}
+
+impl AssemblyViewState {
+    /// Starts a selection at the cursor, or completes the one in progress.
+    fn toggle_range_selection(&mut self) {
+        let cursor = self.selected.unwrap_or(0);
+        match self.range_anchor.take() {
+            Some(anchor) => self.range = Some((anchor.min(cursor), anchor.max(cursor))),
+            None => {
+                self.range_anchor = Some(cursor);
+                self.range = None;
+            }
+        }
+    }
+
+    /// Selection to highlight: the live one while extending, else the last
+    /// completed one.
+    fn selected_range(&self) -> Option<(usize, usize)> {
+        match self.range_anchor {
+            Some(anchor) => {
+                let cursor = self.selected.unwrap_or(0);
+                Some((anchor.min(cursor), anchor.max(cursor)))
+            }
+            None => self.range,
+        }
+    }
+
+    /// Switches between instructions and their basic blocks. Samples skid
+    /// past the instruction that caused them, but rarely out of its block.
+    fn toggle_basic_blocks(&mut self) {
+        self.rows = match self.instruction_rows.take() {
+            Some(instructions) => instructions,
+            None => {
+                let blocks = basic_blocks(&self.rows);
+                self.instruction_rows = Some(std::mem::take(&mut self.rows));
+                blocks
+            }
+        };
+        self.max_samples = self.rows.iter().map(|row| row.samples).max().unwrap_or(0);
+        self.selected = None;
+        self.offset = 0;
+        self.range_anchor = None;
+        self.range = None;
+    }
+
+    fn range_stats(&self) -> Option<(usize, usize, AssemblyStats)> {
+        let (start, end) = self.selected_range()?;
+        let rows = self
+            .rows
+            .get(start..=end.min(self.rows.len().checked_sub(1)?))?;
+        let mut stats = AssemblyStats::default();
+        for row in rows {
+            stats.merge(row.stats());
+        }
+        Some((start, end, stats))
+    }
+}
+
+/// Sums `rows` over runs of instructions that end at a jump, branch, call or
+/// return.
+fn basic_blocks(rows: &[AssemblyRow]) -> Vec<AssemblyRow> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for (index, row) in rows.iter().enumerate() {
+        let last = index + 1 == rows.len();
+        if !(last || crate::disassembly::ends_basic_block(&row.instruction)) {
+            continue;
+        }
+        let block = &rows[start..=index];
+        let mut stats = AssemblyStats::default();
+        for row in block {
+            stats.merge(row.stats());
+        }
+        let instruction = match block {
+            [single] => single.instruction.clone(),
+            [first, .., last] => format!(
+                "{} instructions: {} … {}",
+                block.len(),
+                first.instruction,
+                last.instruction
+            ),
+            [] => unreachable!("blocks hold at least one instruction"),
+        };
+        let mut block_row = assembly_row(block[0].address, instruction, stats, 0);
+        block_row.share = block.iter().map(|row| row.share).sum();
+        blocks.push(block_row);
+        start = index + 1;
+    }
+    blocks
 }
 
 impl Widget for MetricsTableTab {
@@ -184,6 +342,7 @@ impl Widget for MetricsTableTab {
         let [table_area] = horizontal.areas(table_area);
 
         let mut state = self.state.lock();
+        self.sync_bookmarks(&mut state);
 
         if let Some(message) = state.table_error.clone() {
             Paragraph::new(message)
@@ -203,7 +362,12 @@ impl Widget for MetricsTableTab {
 
         let total_rows = rows.len();
         if total_rows == 0 {
-            Paragraph::new("No data available")
+            let message = if state.source_only {
+                "No functions with source information (press s to show all)"
+            } else {
+                super::NO_SAMPLES_MESSAGE
+            };
+            Paragraph::new(message)
                 .alignment(Alignment::Center)
                 .render(table_area, buf);
             return;
@@ -218,7 +382,7 @@ impl Widget for MetricsTableTab {
             }
         }
 
-        let sticky_columns = layout.sticky_columns.max(1).min(layout.columns.len());
+        let sticky_columns = layout.sticky_columns.min(layout.columns.len());
         let metric_columns = layout.columns.len().saturating_sub(sticky_columns);
         let max_metric_offset = metric_columns.saturating_sub(1);
         if state.column_offset > max_metric_offset {
@@ -228,9 +392,16 @@ impl Widget for MetricsTableTab {
         let header = build_header(&layout, sticky_columns, state.column_offset);
         let widths = build_constraints(&layout, sticky_columns, state.column_offset);
 
-        let table_rows = rows
-            .iter()
-            .map(|row| build_row(row, &layout, sticky_columns, state.column_offset));
+        let table_rows = rows.iter().map(|row| {
+            build_row(
+                row,
+                &layout,
+                sticky_columns,
+                state.column_offset,
+                &self.config.theme,
+                &state.bookmarks,
+            )
+        });
 
         let mut table_state = TableState::default()
             .with_selected(state.selected)
@@ -240,7 +411,16 @@ impl Widget for MetricsTableTab {
             .header(header)
             .row_highlight_style(Style::default().bg(Color::DarkGray))
             .highlight_symbol("▶ ")
-            .block(Block::new().borders(Borders::TOP | Borders::BOTTOM));
+            .block({
+                let mut block = Block::new().borders(Borders::TOP | Borders::BOTTOM);
+                if state.source_only {
+                    block = block.title(" only functions with source ");
+                }
+                if let Some(notice) = &state.notice {
+                    block = block.title_bottom(format!(" {notice} "));
+                }
+                block
+            });
 
         let table_height = table_area.height as usize;
         let header_height = 2usize;
@@ -262,6 +442,8 @@ impl Widget for MetricsTableTab {
 
         state.selected = table_state.selected();
         state.offset = table_state.offset();
+        state.visible_rows = visible_rows;
+        state.table_width = table_area.width;
 
         if sticky_columns + state.column_offset < layout.columns.len() {
             let header_separator_y = table_area.y + 2;
@@ -271,6 +453,25 @@ impl Widget for MetricsTableTab {
                 Paragraph::new(line.as_str())
                     .style(Style::default())
                     .render(line_area, buf);
+
+                let (left, right) = hidden_columns(
+                    &layout,
+                    sticky_columns,
+                    state.column_offset,
+                    table_area.width,
+                );
+                let indicator = Style::default().fg(Color::Yellow).bold();
+                if left {
+                    Paragraph::new("◀ more")
+                        .style(indicator)
+                        .render(line_area, buf);
+                }
+                if right {
+                    Paragraph::new("more ▶")
+                        .style(indicator)
+                        .alignment(Alignment::Right)
+                        .render(line_area, buf);
+                }
             }
         }
 
@@ -280,6 +481,26 @@ impl Widget for MetricsTableTab {
         {
             render_assembly_overlay(table_area, buf, &mut state);
         }
+
+        if let Some(selected) = state.column_chooser {
+            render_column_chooser(table_area, buf, &layout, selected);
+        }
+
+        if let Some(explanation) = &state.explanation {
+            render_explanation(table_area, buf, explanation);
+        }
+
+        if let Some(selected) = state.bookmark_list {
+            let present = layout
+                .function_column_index
+                .map(|idx| {
+                    rows.iter()
+                        .filter_map(|row| row.values.get(idx)?.as_text())
+                        .collect::<HashSet<_>>()
+                })
+                .unwrap_or_default();
+            render_bookmark_list(table_area, buf, &state.bookmarks, &present, selected);
+        }
     }
 }
 
@@ -292,7 +513,69 @@ impl MetricsTableTab {
             state: Arc::new(Mutex::new(MetricsState::default())),
             config: Arc::new(MetricsTableConfig::from_spec(spec)),
             layout: Arc::new(RwLock::new(None)),
+            ui_state: None,
+            export_dir: None,
+        }
+    }
+
+    /// Saves Markdown copies of the table to `<view>.md` in `dir` instead,
+    /// when the terminal does not pass copies on to the clipboard.
+    pub fn with_export_dir(mut self, dir: &Path) -> Self {
+        self.export_dir = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Compares the column the table is sorted by with the same view in the
+    /// database attached as `baseline`, matching rows by function. Adds the
+    /// baseline value and the change right after that column.
+    pub fn with_baseline(mut self) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        let (Some(order), Some(_)) = (&config.order_by, &config.function_column) else {
+            return self;
+        };
+        let key = order.column.clone();
+        let position = config.columns.iter().position(|column| column.key == key);
+        let (label, format, width) = position
+            .map(|idx| &config.columns[idx])
+            .map(|column| (column.label.clone(), column.format.clone(), column.width))
+            .unwrap_or_else(|| (key.clone(), ValueFormat::Auto, None));
+        let insert_at = position.map_or(config.columns.len(), |idx| idx + 1);
+        config.columns.splice(
+            insert_at..insert_at,
+            [
+                ColumnConfig::default_column(
+                    &format!("baseline_{key}"),
+                    &format!("Baseline {label}"),
+                    format.clone(),
+                    width,
+                    false,
+                    true,
+                ),
+                ColumnConfig::default_column(
+                    &format!("delta_{key}"),
+                    &format!("Δ {label}"),
+                    format,
+                    width,
+                    false,
+                    true,
+                ),
+            ],
+        );
+        config.baseline_column = Some(key);
+        self
+    }
+
+    /// Restores the chosen columns and the bookmarks from `ui_state` and
+    /// saves later changes there.
+    pub fn with_ui_state(mut self, ui_state: UiStateFile) -> Self {
+        {
+            let saved = ui_state.get();
+            let mut state = self.state.lock();
+            state.visible_columns = saved.columns.get(&self.config.view).cloned();
+            state.bookmarks = saved.bookmarks;
         }
+        self.ui_state = Some(ui_state);
+        self
     }
 
     pub fn title(&self) -> &str {
@@ -311,17 +594,33 @@ impl MetricsTableTab {
         tokio::spawn(this.fetch_data());
     }
 
+    /// Queries the view again, e.g. after it was rebuilt.
+    pub fn reload(&self) {
+        if *self.is_running.read() {
+            return;
+        }
+        *self.is_running.write() = true;
+        let this = self.clone();
+        tokio::spawn(this.fetch_data());
+    }
+
     async fn fetch_data(self) {
         let result: Result<Vec<MetricsRow>, String> = (|| {
+            let (source_only, visible_columns) = {
+                let state = self.state.lock();
+                (state.source_only, state.visible_columns.clone())
+            };
             let conn = self.connection.lock();
-            let query = self.config.build_query();
+            let query = self.config.build_query(source_only);
             let stmt = conn.prepare(&query).map_err(|err| err.to_string())?;
 
             let column_names = (0..stmt.column_count())
                 .map(|idx| stmt.column_name(idx).unwrap_or("").to_string())
                 .collect::<HashSet<_>>();
 
-            let layout = self.config.build_runtime_layout(&column_names)?;
+            let layout = self
+                .config
+                .build_runtime_layout(&column_names, visible_columns.as_deref())?;
             *self.layout.write() = Some(layout.clone());
 
             let mut rows = Vec::new();
@@ -351,6 +650,64 @@ impl MetricsTableTab {
         *self.is_running.write() = false;
     }
 
+    /// Keys this tab handles in its current view, for the help overlay.
+    pub fn help_entries(&self) -> Vec<(&'static str, &'static str)> {
+        if self.state.lock().explanation.is_some() {
+            return vec![("<esc>", "Close the explanation")];
+        }
+        if self.state.lock().bookmark_list.is_some() {
+            return vec![
+                ("↑/↓", "Select a bookmark"),
+                ("<enter>", "Jump to the bookmarked function"),
+                ("d", "Remove the bookmark"),
+                ("<esc>", "Close the bookmarks"),
+            ];
+        }
+        if self.state.lock().column_chooser.is_some() {
+            return vec![
+                ("↑/↓", "Select a column"),
+                ("<space>", "Show or hide the column"),
+                ("<esc>", "Close the column chooser"),
+            ];
+        }
+        if self.state.lock().focus == MetricsFocus::Assembly {
+            return vec![
+                ("↑/↓", "Select an instruction"),
+                ("PgUp/PgDn", "Scroll by a page"),
+                ("Home/End", "Jump to the first/last instruction"),
+                ("v", "Select an assembly range"),
+                ("b", "Sum samples per basic block"),
+                ("<esc>", "Clear the range or close the assembly"),
+                ("<enter>", "Close the assembly"),
+            ];
+        }
+        let mut entries = vec![
+            ("↑/↓", "Select a row"),
+            ("PgUp/PgDn", "Scroll by a page"),
+            ("Home/End", "Jump to the first/last row"),
+            ("←/→", "Scroll the metric columns"),
+            ("c", "Choose the visible columns"),
+            ("e", "Explain the first visible metric"),
+            ("y", "Copy the visible rows as a Markdown table"),
+        ];
+        if self.config.filters_by_source() {
+            entries.push(("s", "Only functions with source"));
+        }
+        if self
+            .layout
+            .read()
+            .as_ref()
+            .is_some_and(|layout| layout.function_column_index.is_some())
+        {
+            entries.push(("b", "Bookmark the selected function"));
+            entries.push(("B", "List the bookmarks"));
+        }
+        if self.config.enable_assembly {
+            entries.push(("<enter>", "Open the selected function's assembly"));
+        }
+        entries
+    }
+
     pub fn handle_event(&mut self, code: KeyCode) {
         let metrics_len = self.rows.read().len();
         let layout_opt = self.layout.read().clone();
@@ -359,9 +716,97 @@ impl MetricsTableTab {
         };
 
         let mut state = self.state.lock();
+        self.sync_bookmarks(&mut state);
+        state.notice = None;
+
+        if state.explanation.is_some() {
+            if matches!(code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('e')) {
+                state.explanation = None;
+            }
+            return;
+        }
+
+        if let Some(selected) = state.bookmark_list {
+            let count = state.bookmarks.len();
+            match code {
+                KeyCode::Down => {
+                    state.bookmark_list = Some((selected + 1).min(count.saturating_sub(1)));
+                }
+                KeyCode::Up => state.bookmark_list = Some(selected.saturating_sub(1)),
+                KeyCode::Enter => {
+                    let Some(func_name) = state.bookmarks.iter().nth(selected).cloned() else {
+                        return;
+                    };
+                    if let Some(idx) = self.row_of_function(&layout, &func_name) {
+                        state.selected = Some(idx);
+                        state.bookmark_list = None;
+                    }
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    if let Some(func_name) = state.bookmarks.iter().nth(selected).cloned() {
+                        self.toggle_bookmark(&mut state, func_name);
+                    }
+                    let count = state.bookmarks.len();
+                    state.bookmark_list = Some(selected.min(count.saturating_sub(1)));
+                }
+                KeyCode::Esc | KeyCode::Char('B') => state.bookmark_list = None,
+                _ => {}
+            }
+            return;
+        }
+
+        if let Some(selected) = state.column_chooser {
+            match code {
+                KeyCode::Down => {
+                    state.column_chooser =
+                        Some((selected + 1).min(layout.choices.len().saturating_sub(1)));
+                }
+                KeyCode::Up => state.column_chooser = Some(selected.saturating_sub(1)),
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    let Some(column) = layout.choices.get(selected) else {
+                        return;
+                    };
+                    let mut visible = layout
+                        .columns
+                        .iter()
+                        .map(|column| column.key.clone())
+                        .filter(|key| self.config.function_column.as_ref() != Some(key))
+                        .collect::<Vec<_>>();
+                    match visible.iter().position(|key| key == &column.key) {
+                        // Keep at least one metric on screen.
+                        Some(_) if visible.len() == 1 => return,
+                        Some(idx) => {
+                            visible.remove(idx);
+                        }
+                        None => visible.push(column.key.clone()),
+                    }
+                    state.visible_columns = Some(visible.clone());
+                    drop(state);
+                    if let Some(ui_state) = &self.ui_state {
+                        ui_state.update(|ui_state| {
+                            ui_state.columns.insert(self.config.view.clone(), visible);
+                        });
+                    }
+                    self.reload();
+                }
+                KeyCode::Esc | KeyCode::Char('c') => state.column_chooser = None,
+                _ => {}
+            }
+            return;
+        }
 
         if state.focus == MetricsFocus::Assembly {
             match code {
+                KeyCode::Esc
+                    if state
+                        .assembly
+                        .as_ref()
+                        .is_some_and(|assembly| assembly.range_anchor.is_some()) =>
+                {
+                    if let Some(assembly) = state.assembly.as_mut() {
+                        assembly.range_anchor = None;
+                    }
+                }
                 KeyCode::Esc | KeyCode::Enter => {
                     state.assembly_request_id = state.assembly_request_id.wrapping_add(1);
                     state.focus = MetricsFocus::List;
@@ -417,6 +862,8 @@ impl MetricsTableTab {
                             KeyCode::End => {
                                 assembly.selected = Some(len - 1);
                             }
+                            KeyCode::Char('v') => assembly.toggle_range_selection(),
+                            KeyCode::Char('b') => assembly.toggle_basic_blocks(),
                             _ => {}
                         }
                     }
@@ -434,6 +881,7 @@ impl MetricsTableTab {
             .len()
             .saturating_sub(layout.sticky_columns)
             .saturating_sub(1);
+        #[allow(clippy::collapsible_match)] // Keep bounds checks inside the scrolling arms.
         match code {
             KeyCode::Down => {
                 let current = state.selected.unwrap_or(0);
@@ -475,6 +923,50 @@ impl MetricsTableTab {
             KeyCode::Left => {
                 state.column_offset = state.column_offset.saturating_sub(1);
             }
+            KeyCode::Char('c') if !layout.choices.is_empty() => {
+                state.column_chooser = Some(0);
+            }
+            KeyCode::Char('y') => {
+                let rows = self.rows.read();
+                let shown = rows
+                    .iter()
+                    .skip(state.offset)
+                    .take(state.visible_rows.max(1))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                drop(rows);
+                let columns = on_screen_columns(
+                    &layout,
+                    layout.sticky_columns.min(layout.columns.len()),
+                    state.column_offset,
+                    state.table_width,
+                );
+                state.notice = Some(self.copy_as_markdown(&layout, &columns, &shown));
+            }
+            KeyCode::Char('e') => {
+                let Some(idx) = state.selected else {
+                    return;
+                };
+                let explanation =
+                    self.explain_cell(&layout, idx, layout.sticky_columns + state.column_offset);
+                state.explanation = explanation;
+            }
+            KeyCode::Char('s') if self.config.filters_by_source() => {
+                state.source_only = !state.source_only;
+                drop(state);
+                self.reload();
+            }
+            KeyCode::Char('b') => {
+                let func_name = state
+                    .selected
+                    .and_then(|idx| self.function_of_row(&layout, idx));
+                if let Some(func_name) = func_name {
+                    self.toggle_bookmark(&mut state, func_name);
+                }
+            }
+            KeyCode::Char('B') if layout.function_column_index.is_some() => {
+                state.bookmark_list = Some(0);
+            }
             KeyCode::Enter => {
                 if !self.config.enable_assembly {
                     return;
@@ -510,221 +1002,138 @@ impl MetricsTableTab {
         }
     }
 
-    fn request_assembly(&self, func_name: String, request_id: u64) {
-        let this = self.clone();
-        tokio::spawn(this.fetch_assembly(func_name, request_id));
-    }
-
-    async fn fetch_assembly(self, func_name: String, request_id: u64) {
-        let result: Result<AssemblyViewState, String> = (|| {
-            use sqlite::State;
-
-            let conn = self.connection.lock();
-
-            let mut module_stmt = conn
-                .prepare(
-                    "SELECT proc_map.module_path AS module_path, SUM(pmu_counters.pmu_cycles) AS total_cycles
-                     FROM pmu_counters
-                     INNER JOIN proc_map ON proc_map.ip = pmu_counters.ip
-                     WHERE proc_map.func_name = ?
-                     GROUP BY proc_map.module_path
-                     ORDER BY total_cycles DESC
-                     LIMIT 1;",
-                )
-                .map_err(|err| err.to_string())?;
-            module_stmt
-                .bind((1, func_name.as_str()))
-                .map_err(|err| err.to_string())?;
-
-            let module_path = match module_stmt.next().map_err(|err| err.to_string())? {
-                State::Row => module_stmt
-                    .read::<String, _>(0)
-                    .map_err(|err| err.to_string())?,
-                State::Done => {
-                    return Err("Assembly data is not available for the selected row".to_string());
-                }
+    /// Copies `rows` as a Markdown table to the clipboard, or saves it to
+    /// the export directory when the terminal cannot take the copy, and
+    /// describes where it went.
+    fn copy_as_markdown(
+        &self,
+        layout: &RuntimeLayout,
+        columns: &[usize],
+        rows: &[MetricsRow],
+    ) -> String {
+        let markdown = markdown_table(layout, columns, rows);
+        if clipboard::is_available() {
+            return match clipboard::copy(&markdown) {
+                Ok(()) => format!("copied {} rows as Markdown", rows.len()),
+                Err(err) => format!("failed to copy the rows: {err}"),
             };
+        }
+        let Some(dir) = &self.export_dir else {
+            return "the terminal does not support copying to the clipboard".to_string();
+        };
+        let path = dir.join(format!("{}.md", self.config.view));
+        match std::fs::write(&path, &markdown) {
+            Ok(()) => format!(
+                "saved {} rows as Markdown to {}",
+                rows.len(),
+                path.display()
+            ),
+            Err(err) => format!("failed to save the rows: {err}"),
+        }
+    }
 
-            let mut stats_stmt = conn
-                .prepare(
-                    "SELECT address, samples, cycles, instructions, branch_misses, branch_instructions, llc_misses, llc_references
-                     FROM assembly_address_stats
-                     WHERE module_path = ? AND func_name = ?
-                     ORDER BY address;",
-                )
-                .map_err(|err| err.to_string())?;
-            stats_stmt
-                .bind((1, module_path.as_str()))
-                .map_err(|err| err.to_string())?;
-            stats_stmt
-                .bind((2, func_name.as_str()))
-                .map_err(|err| err.to_string())?;
-
-            let mut stats_map = HashMap::new();
-            let mut ordered_addresses = Vec::new();
-            let mut total_samples = 0u64;
-
-            while let State::Row = stats_stmt.next().map_err(|err| err.to_string())? {
-                let address = stats_stmt
-                    .read::<i64, _>("address")
-                    .map_err(|err| err.to_string())? as u64;
-                let samples = stats_stmt
-                    .read::<i64, _>("samples")
-                    .map_err(|err| err.to_string())? as u64;
-                let cycles = stats_stmt
-                    .read::<i64, _>("cycles")
-                    .map_err(|err| err.to_string())? as u64;
-                let instructions = stats_stmt
-                    .read::<i64, _>("instructions")
-                    .map_err(|err| err.to_string())? as u64;
-                let branch_misses = stats_stmt
-                    .read::<i64, _>("branch_misses")
-                    .map_err(|err| err.to_string())? as u64;
-                let branch_instructions = stats_stmt
-                    .read::<i64, _>("branch_instructions")
-                    .map_err(|err| err.to_string())?
-                    as u64;
-                let llc_misses = stats_stmt
-                    .read::<i64, _>("llc_misses")
-                    .map_err(|err| err.to_string())? as u64;
-                let llc_references = stats_stmt
-                    .read::<i64, _>("llc_references")
-                    .map_err(|err| err.to_string())? as u64;
-
-                stats_map.insert(
-                    address,
-                    AssemblyStats {
-                        samples,
-                        cycles,
-                        instructions,
-                        branch_misses,
-                        branch_instructions,
-                        llc_misses,
-                        llc_references,
-                    },
-                );
-                ordered_addresses.push(address);
+    /// Picks up bookmarks another table changed.
+    fn sync_bookmarks(&self, state: &mut MetricsState) {
+        if let Some(ui_state) = &self.ui_state {
+            state.bookmarks = ui_state.get().bookmarks;
+        }
+    }
 
-                total_samples = total_samples.saturating_add(samples);
+    /// Bookmarks `func_name`, or removes its bookmark, and saves the change.
+    fn toggle_bookmark(&self, state: &mut MetricsState, func_name: String) {
+        let toggle = |bookmarks: &mut BTreeSet<String>| {
+            if !bookmarks.remove(&func_name) {
+                bookmarks.insert(func_name.clone());
             }
-
-            if ordered_addresses.is_empty() {
-                return Err("No assembly information found for the selected function".to_string());
+        };
+        match &self.ui_state {
+            Some(ui_state) => {
+                ui_state.update(|ui_state| toggle(&mut ui_state.bookmarks));
+                state.bookmarks = ui_state.get().bookmarks;
             }
+            None => toggle(&mut state.bookmarks),
+        }
+    }
 
-            // PMU instruction pointers are not guaranteed to equal the first byte of an
-            // instruction. Attribute each one to the closest preceding persisted instruction,
-            // bounded by the maximum x86 instruction length so gaps between selected symbols do
-            // not absorb unrelated samples.
-            const MAX_INSTRUCTION_BYTES: u64 = 15;
-            let mut instruction_stmt = conn
-                .prepare(
-                    "SELECT runtime_address, symbol FROM assembly_lines
-                     WHERE module_path = ? AND runtime_address BETWEEN ? AND ?
-                       AND symbol IS NOT NULL
-                     ORDER BY runtime_address DESC LIMIT 1;",
-                )
-                .map_err(|err| err.to_string())?;
-            let mut attributed_stats = HashMap::<u64, AssemblyStats>::new();
-            let mut owner_symbols = Vec::new();
-            let mut unattributed = Vec::new();
-            for address in ordered_addresses {
-                instruction_stmt.reset().map_err(|err| err.to_string())?;
-                instruction_stmt
-                    .bind((1, module_path.as_str()))
-                    .map_err(|err| err.to_string())?;
-                instruction_stmt
-                    .bind((2, address.saturating_sub(MAX_INSTRUCTION_BYTES) as i64))
-                    .map_err(|err| err.to_string())?;
-                instruction_stmt
-                    .bind((3, address as i64))
-                    .map_err(|err| err.to_string())?;
-
-                let stats = stats_map[&address];
-                match instruction_stmt.next().map_err(|err| err.to_string())? {
-                    State::Row => {
-                        let instruction_address = instruction_stmt
-                            .read::<i64, _>("runtime_address")
-                            .map_err(|err| err.to_string())?
-                            as u64;
-                        let owner = instruction_stmt
-                            .read::<String, _>("symbol")
-                            .map_err(|err| err.to_string())?;
-                        attributed_stats
-                            .entry(instruction_address)
-                            .or_default()
-                            .merge(stats);
-                        owner_symbols.push(owner);
-                    }
-                    State::Done => unattributed.push((address, stats)),
-                }
-            }
-            owner_symbols.sort_unstable();
-            owner_symbols.dedup();
+    /// Selects the next row with a text value containing `query`.
+    pub fn search(&self, query: &str) -> bool {
+        let rows = self.rows.read();
+        let mut state = self.state.lock();
+        let found = super::next_match(rows.len(), state.selected, |row| {
+            rows[row]
+                .values
+                .iter()
+                .filter_map(MetricValue::as_text)
+                .any(|text| text.contains(query))
+        });
+        if found.is_some() {
+            state.selected = found;
+        }
+        found.is_some()
+    }
 
-            let mut lines_stmt = conn
-                .prepare(
-                    "SELECT runtime_address, instruction FROM assembly_lines
-                     WHERE module_path = ? AND symbol = ? ORDER BY runtime_address;",
-                )
-                .map_err(|err| err.to_string())?;
+    fn function_of_row(&self, layout: &RuntimeLayout, row_idx: usize) -> Option<String> {
+        let column_idx = layout.function_column_index?;
+        let rows = self.rows.read();
+        let value = rows.get(row_idx)?.values.get(column_idx)?;
+        value.as_text().map(str::to_string)
+    }
 
-            let mut rows = Vec::new();
-            for owner in &owner_symbols {
-                lines_stmt.reset().map_err(|err| err.to_string())?;
-                lines_stmt
-                    .bind((1, module_path.as_str()))
-                    .map_err(|err| err.to_string())?;
-                lines_stmt
-                    .bind((2, owner.as_str()))
-                    .map_err(|err| err.to_string())?;
-                while let State::Row = lines_stmt.next().map_err(|err| err.to_string())? {
-                    let address = lines_stmt
-                        .read::<i64, _>("runtime_address")
-                        .map_err(|err| err.to_string())? as u64;
-                    let instruction = lines_stmt
-                        .read::<String, _>("instruction")
-                        .map_err(|err| err.to_string())?;
-                    let stats = attributed_stats.get(&address).copied().unwrap_or_default();
-                    rows.push(assembly_row(address, instruction, stats, total_samples));
-                }
-            }
+    fn row_of_function(&self, layout: &RuntimeLayout, func_name: &str) -> Option<usize> {
+        let column_idx = layout.function_column_index?;
+        self.rows.read().iter().position(|row| {
+            row.values.get(column_idx).and_then(|value| value.as_text()) == Some(func_name)
+        })
+    }
 
-            // Old or partially postprocessed recordings may not contain the selected machine
-            // symbol. Keep their metrics visible and make the missing instruction explicit.
-            rows.extend(unattributed.into_iter().map(|(address, stats)| {
-                assembly_row(
-                    address,
-                    "<persisted instruction unavailable>".to_string(),
-                    stats,
-                    total_samples,
-                )
-            }));
-            rows.sort_unstable_by_key(|row| row.address);
-
-            if rows.is_empty() {
-                return Err(
-                    "Persisted assembly is not available for the selected function".to_string(),
-                );
-            }
+    /// Explains the value in column `column_idx` of row `row_idx`, or the last
+    /// column when scrolled past it.
+    fn explain_cell(
+        &self,
+        layout: &RuntimeLayout,
+        row_idx: usize,
+        column_idx: usize,
+    ) -> Option<CellExplanation> {
+        let column_idx = column_idx.min(layout.columns.len().checked_sub(1)?);
+        let column = &layout.columns[column_idx];
+        let rows = self.rows.read();
+        let row = rows.get(row_idx)?;
+        let value = row.values.get(column_idx).unwrap_or(&MetricValue::Null);
+        let func_name = layout
+            .function_column_index
+            .and_then(|idx| row.values.get(idx))
+            .and_then(|value| value.as_text());
+
+        let conn = self.connection.lock();
+        let expression = match &column.derived {
+            Some(derived) => Some(format!(
+                "Computed by mperf from {}",
+                derived.inputs().join(", ")
+            )),
+            None => view_column_expression(&conn, &self.config.view, &column.key),
+        };
+        let raw = match func_name.filter(|_| self.config.filters_by_source()) {
+            Some(func_name) => raw_counter_sums(&conn, func_name),
+            None => Err("Raw counters are only available for functions".to_string()),
+        };
+        Some(CellExplanation {
+            title: match func_name {
+                Some(func_name) => format!("{} of {func_name}", column.label),
+                None => column.label.clone(),
+            },
+            value: pmu_data::format_localized(value, &column.format, &locale::current()),
+            expression,
+            raw,
+        })
+    }
 
-            let max_samples = rows.iter().map(|row| row.samples).max().unwrap_or(0);
-            let symbol = match owner_symbols.as_slice() {
-                [] => "[instructions unavailable]".to_string(),
-                [owner] => owner.clone(),
-                owners => format!("{} machine symbols", owners.len()),
-            };
+    fn request_assembly(&self, func_name: String, request_id: u64) {
+        let this = self.clone();
+        tokio::spawn(this.fetch_assembly(func_name, request_id));
+    }
 
-            Ok(AssemblyViewState {
-                func_name: func_name.clone(),
-                module_path,
-                symbol,
-                rows,
-                selected: None,
-                offset: 0,
-                max_samples,
-            })
-        })();
+    async fn fetch_assembly(self, func_name: String, request_id: u64) {
+        let result = load_assembly(&self.connection.lock(), &func_name);
 
         let mut state = self.state.lock();
         if request_id != state.assembly_request_id {
@@ -775,13 +1184,47 @@ impl MetricsTableConfig {
                 .function_column
                 .or_else(|| Some("func_name".to_string())),
             enable_assembly: spec.enable_assembly,
+            theme: Theme::default(),
+            baseline_column: None,
+            derived_columns: derived_columns::registered(),
         }
     }
 
-    fn build_query(&self) -> String {
-        let mut query = format!("SELECT * FROM {}", self.view);
+    /// The source filter looks rows up in `proc_map` by function name, so
+    /// tables keyed by something else, like modules, do not offer it.
+    fn filters_by_source(&self) -> bool {
+        self.function_column.as_deref() == Some("func_name")
+    }
+
+    /// `source_only` keeps functions with at least one sample resolved to a
+    /// source file; the symbolizer records unresolved files as `unknown`.
+    fn build_query(&self, source_only: bool) -> String {
+        let view = &self.view;
+        let (mut query, prefix) = match (&self.baseline_column, &self.function_column) {
+            (Some(column), Some(function_column)) => (
+                format!(
+                    "SELECT current.*, baseline.{column} AS baseline_{column},
+                     current.{column} - baseline.{column} AS delta_{column}
+                     FROM {view} AS current LEFT JOIN baseline.{view} AS baseline
+                     ON baseline.{function_column} = current.{function_column}"
+                ),
+                "current.",
+            ),
+            _ => (format!("SELECT * FROM {view}"), ""),
+        };
+        if let Some(function_column) = self
+            .function_column
+            .as_ref()
+            .filter(|_| source_only && self.filters_by_source())
+        {
+            query.push_str(&format!(
+                " WHERE {prefix}{function_column} IN (SELECT func_name FROM proc_map
+                 WHERE file_name IS NOT NULL AND file_name != 'unknown')"
+            ));
+        }
         if let Some(order) = &self.order_by {
             query.push_str(" ORDER BY ");
+            query.push_str(prefix);
             query.push_str(&order.column);
             query.push(' ');
             query.push_str(match order.direction {
@@ -795,13 +1238,19 @@ impl MetricsTableConfig {
         query
     }
 
-    fn build_runtime_layout(&self, available: &HashSet<String>) -> Result<RuntimeLayout, String> {
-        let mut resolved = Vec::new();
+    /// `visible` lists the columns picked in the column chooser. The function
+    /// column is always shown, since the assembly view is opened through it.
+    fn build_runtime_layout(
+        &self,
+        available: &HashSet<String>,
+        visible: Option<&[String]>,
+    ) -> Result<RuntimeLayout, String> {
+        let mut configured = Vec::new();
         let mut missing = Vec::new();
 
         for column in &self.columns {
             if available.contains(&column.key) {
-                resolved.push(column.clone());
+                configured.push(column.clone());
             } else if !column.optional {
                 missing.push(column.label.clone());
             }
@@ -811,6 +1260,46 @@ impl MetricsTableConfig {
             return Err(format!("Missing required columns: {}", missing.join(", ")));
         }
 
+        let mut extra = available
+            .iter()
+            .filter(|key| !self.columns.iter().any(|column| &column.key == *key))
+            .collect::<Vec<_>>();
+        extra.sort();
+        let derived = self.derived_columns.iter().filter(|column| {
+            !available.contains(column.name())
+                && column
+                    .inputs()
+                    .iter()
+                    .all(|input| available.contains(*input))
+        });
+        let choices = configured
+            .iter()
+            .cloned()
+            .chain(extra.into_iter().map(|key| {
+                ColumnConfig::default_column(key, key, ValueFormat::Auto, None, false, true)
+            }))
+            .chain(derived.map(|column| ColumnConfig::derived(column.clone())))
+            .filter(|column| self.function_column.as_ref() != Some(&column.key))
+            .collect::<Vec<_>>();
+
+        let resolved = match visible {
+            Some(visible) => {
+                let mut resolved = configured
+                    .iter()
+                    .filter(|column| self.function_column.as_ref() == Some(&column.key))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                resolved.extend(
+                    choices
+                        .iter()
+                        .filter(|column| visible.contains(&column.key))
+                        .cloned(),
+                );
+                resolved
+            }
+            None => configured,
+        };
+
         if resolved.is_empty() {
             return Err("No columns available for this metrics table".to_string());
         }
@@ -835,6 +1324,7 @@ impl MetricsTableConfig {
             columns: resolved,
             sticky_columns,
             function_column_index,
+            choices,
         })
     }
 }
@@ -867,6 +1357,21 @@ impl ColumnConfig {
             sticky,
             optional,
             alignment,
+            derived: None,
+        }
+    }
+
+    fn derived(column: Arc<dyn DerivedColumn>) -> Self {
+        ColumnConfig {
+            derived: Some(column.clone()),
+            ..ColumnConfig::default_column(
+                column.name(),
+                column.label(),
+                column.format(),
+                None,
+                false,
+                true,
+            )
         }
     }
 
@@ -883,31 +1388,7 @@ impl ColumnConfig {
             sticky: spec.sticky,
             optional: spec.optional,
             alignment,
-        }
-    }
-}
-
-impl MetricValue {
-    fn as_text(&self) -> Option<&str> {
-        match self {
-            MetricValue::Text(s) => Some(s.as_str()),
-            _ => None,
-        }
-    }
-
-    fn as_integer(&self) -> Option<i64> {
-        match self {
-            MetricValue::Integer(v) => Some(*v),
-            MetricValue::Float(v) => Some(*v as i64),
-            _ => None,
-        }
-    }
-
-    fn as_float(&self) -> Option<f64> {
-        match self {
-            MetricValue::Float(v) => Some(*v),
-            MetricValue::Integer(v) => Some(*v as f64),
-            _ => None,
+            derived: None,
         }
     }
 }
@@ -961,6 +1442,9 @@ fn read_row(layout: &RuntimeLayout, row: &sqlite::Row) -> MetricsRow {
 }
 
 fn read_value(row: &sqlite::Row, column: &ColumnConfig) -> MetricValue {
+    if let Some(derived) = &column.derived {
+        return derived.compute(&DerivedRow::new(row));
+    }
     match column.format {
         ValueFormat::Text => row
             .try_read::<Option<&str>, _>(column.key.as_str())
@@ -974,6 +1458,16 @@ fn read_value(row: &sqlite::Row, column: &ColumnConfig) -> MetricValue {
             .flatten()
             .map(MetricValue::Integer)
             .unwrap_or(MetricValue::Null),
+        ValueFormat::Auto => match row
+            .iter()
+            .find(|(name, _)| *name == column.key)
+            .map(|(_, value)| value)
+        {
+            Some(sqlite::Value::Integer(value)) => MetricValue::Integer(*value),
+            Some(sqlite::Value::Float(value)) => MetricValue::Float(*value),
+            Some(sqlite::Value::String(value)) => MetricValue::Text(value.clone()),
+            _ => MetricValue::Null,
+        },
         _ => row
             .try_read::<Option<f64>, _>(column.key.as_str())
             .ok()
@@ -1019,41 +1513,155 @@ fn build_constraints(
     constraints
 }
 
-fn column_constraint(column: &ColumnConfig) -> Constraint {
-    match column.width {
-        Some(width) => Constraint::Length(width),
-        None => match column.alignment {
-            Alignment::Left => Constraint::Max(30),
-            Alignment::Right | Alignment::Center => Constraint::Max(18),
-        },
-    }
+/// Whether scrolled-past columns sit off-screen to the left, and whether the
+/// remaining columns need more than `width` at their preferred widths, so
+/// scrolling right reveals more of them.
+fn hidden_columns(
+    layout: &RuntimeLayout,
+    sticky_len: usize,
+    column_offset: usize,
+    width: u16,
+) -> (bool, bool) {
+    let constraints = build_constraints(layout, sticky_len, column_offset);
+    let needed = constraints.iter().map(preferred_width).sum::<u16>()
+        + constraints.len().saturating_sub(1) as u16
+        + HIGHLIGHT_WIDTH;
+    let last_offset = layout
+        .columns
+        .len()
+        .saturating_sub(sticky_len)
+        .saturating_sub(1);
+    (
+        column_offset > 0,
+        column_offset < last_offset && needed > width,
+    )
 }
 
-fn build_row(
-    row: &MetricsRow,
+/// Indices of the columns on screen: the sticky ones, then the scrolled
+/// columns that fit `width` at their preferred widths, at least one.
+fn on_screen_columns(
     layout: &RuntimeLayout,
     sticky_len: usize,
     column_offset: usize,
-) -> Row<'static> {
-    let mut cells = Vec::new();
+    width: u16,
+) -> Vec<usize> {
+    let width_of = |idx: usize| preferred_width(&column_constraint(&layout.columns[idx]));
+    let mut columns = (0..sticky_len).collect::<Vec<_>>();
+    let mut used = HIGHLIGHT_WIDTH + columns.iter().map(|idx| width_of(*idx) + 1).sum::<u16>();
+    for idx in (sticky_len + column_offset).min(layout.columns.len())..layout.columns.len() {
+        // The last column needs no gap after it.
+        if used + width_of(idx) > width && columns.len() > sticky_len {
+            break;
+        }
+        used += width_of(idx) + 1;
+        columns.push(idx);
+    }
+    columns
+}
 
-    for idx in 0..sticky_len.min(layout.columns.len()) {
-        let column = &layout.columns[idx];
-        let value = row.values.get(idx).unwrap_or(&MetricValue::Null);
-        let formatted = format_value(value, &column.format);
-        let text = Text::from(formatted).alignment(column.alignment);
-        cells.push(Cell::from(text));
+/// Room for the selection marker, and the one-cell gap between columns.
+const HIGHLIGHT_WIDTH: u16 = 2;
+
+fn preferred_width(constraint: &Constraint) -> u16 {
+    match constraint {
+        Constraint::Length(width) | Constraint::Max(width) => *width,
+        _ => 0,
     }
+}
 
-    for idx in (sticky_len + column_offset).min(layout.columns.len())..layout.columns.len() {
+fn column_constraint(column: &ColumnConfig) -> Constraint {
+    match column.width {
+        Some(width) => Constraint::Length(width),
+        None => match column.alignment {
+            Alignment::Left => Constraint::Max(30),
+            Alignment::Right | Alignment::Center => Constraint::Max(18),
+        },
+    }
+}
+
+fn build_row(
+    row: &MetricsRow,
+    layout: &RuntimeLayout,
+    sticky_len: usize,
+    column_offset: usize,
+    theme: &Theme,
+    bookmarks: &BTreeSet<String>,
+) -> Row<'static> {
+    let visible = (0..sticky_len.min(layout.columns.len()))
+        .chain((sticky_len + column_offset).min(layout.columns.len())..layout.columns.len());
+    let cells = visible.map(|idx| {
         let column = &layout.columns[idx];
         let value = row.values.get(idx).unwrap_or(&MetricValue::Null);
-        let formatted = format_value(value, &column.format);
-        let text = Text::from(formatted).alignment(column.alignment);
-        cells.push(Cell::from(text));
+        let mut formatted = pmu_data::format_localized(value, &column.format, &locale::current());
+        if Some(idx) == layout.function_column_index
+            && value.as_text().is_some_and(|name| bookmarks.contains(name))
+        {
+            formatted = format!("★ {formatted}");
+        }
+        let mut cell = Cell::from(Text::from(formatted).alignment(column.alignment));
+        if let Some(color) = value_color(column, value, theme) {
+            cell = cell.fg(color);
+        }
+        cell
+    });
+
+    Row::new(cells.collect::<Vec<_>>())
+}
+
+/// GitHub-flavored Markdown table of `rows`, with the `columns` of `layout`
+/// formatted and aligned like on screen.
+fn markdown_table(layout: &RuntimeLayout, columns: &[usize], rows: &[MetricsRow]) -> String {
+    let escape = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+    let mut markdown = line(
+        columns
+            .iter()
+            .map(|idx| escape(&layout.columns[*idx].label))
+            .collect(),
+    );
+    markdown.push_str(&line(
+        columns
+            .iter()
+            .map(|idx| match layout.columns[*idx].alignment {
+                Alignment::Left => ":---",
+                Alignment::Center => ":---:",
+                Alignment::Right => "---:",
+            })
+            .map(str::to_string)
+            .collect(),
+    ));
+    for row in rows {
+        markdown.push_str(&line(
+            columns
+                .iter()
+                .map(|idx| {
+                    let column = &layout.columns[*idx];
+                    let value = row.values.get(*idx).unwrap_or(&MetricValue::Null);
+                    escape(&pmu_data::format_localized(
+                        value,
+                        &column.format,
+                        &locale::current(),
+                    ))
+                })
+                .collect(),
+        ));
     }
+    markdown
+}
 
-    Row::new(cells)
+/// Highlights values that have an established good/bad range.
+fn value_color(column: &ColumnConfig, value: &MetricValue, theme: &Theme) -> Option<Color> {
+    match column.key.as_str() {
+        "ipc" => theme.ipc.color(value.as_float()?),
+        // Tables are sorted hottest first, so growth is a regression.
+        key if key.starts_with("delta_") => match value.as_float()? {
+            delta if delta > 0.0 => Some(Color::Red),
+            delta if delta < 0.0 => Some(Color::Green),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 fn collect_summary(
@@ -1069,53 +1677,492 @@ fn collect_summary(
             continue;
         }
         let value = row.values.get(col_idx).unwrap_or(&MetricValue::Null);
-        summary.push((column.label.clone(), format_value(value, &column.format)));
+        summary.push((
+            column.label.clone(),
+            pmu_data::format_localized(value, &column.format, &locale::current()),
+        ));
     }
 
     Some(summary)
 }
 
-fn format_value(value: &MetricValue, format: &ValueFormat) -> String {
-    match format {
-        ValueFormat::Text => value.as_text().unwrap_or("N/A").to_string(),
-        ValueFormat::Integer => value
-            .as_integer()
-            .map(|v| v.to_formatted_string(&Locale::en))
-            .unwrap_or_else(|| "N/A".to_string()),
-        ValueFormat::Float
-        | ValueFormat::Float1
-        | ValueFormat::Float2
-        | ValueFormat::Float3
-        | ValueFormat::Auto => value
-            .as_float()
-            .map(|v| format_number(v, float_precision(format)))
-            .unwrap_or_else(|| "N/A".to_string()),
-        ValueFormat::Percent
-        | ValueFormat::Percent1
-        | ValueFormat::Percent2
-        | ValueFormat::Percent3 => value
-            .as_float()
-            .map(|v| format!("{}%", format_number(v * 100.0, float_precision(format))))
-            .unwrap_or_else(|| "N/A".to_string()),
+/// Loads the sampled disassembly of `func_name` with per-instruction metrics.
+pub(crate) fn load_assembly(
+    conn: &sqlite::Connection,
+    func_name: &str,
+) -> Result<AssemblyViewState, String> {
+    use sqlite::State;
+
+    let mut module_stmt = conn
+            .prepare(
+                "SELECT proc_map.module_path AS module_path, SUM(pmu_counters.pmu_cycles) AS total_cycles
+                 FROM pmu_counters
+                 INNER JOIN proc_map ON proc_map.ip = pmu_counters.ip
+                 WHERE proc_map.func_name = ?
+                 GROUP BY proc_map.module_path
+                 ORDER BY total_cycles DESC
+                 LIMIT 1;",
+            )
+            .map_err(|err| err.to_string())?;
+    module_stmt
+        .bind((1, func_name))
+        .map_err(|err| err.to_string())?;
+
+    let module_path = match module_stmt.next().map_err(|err| err.to_string())? {
+        State::Row => module_stmt
+            .read::<String, _>(0)
+            .map_err(|err| err.to_string())?,
+        State::Done => {
+            return Err("Assembly data is not available for the selected row".to_string());
+        }
+    };
+
+    let mut stats_stmt = conn
+            .prepare(
+                "SELECT address, samples, cycles, instructions, branch_misses, branch_instructions, llc_misses, llc_references
+                 FROM assembly_address_stats
+                 WHERE module_path = ? AND func_name = ?
+                 ORDER BY address;",
+            )
+            .map_err(|err| err.to_string())?;
+    stats_stmt
+        .bind((1, module_path.as_str()))
+        .map_err(|err| err.to_string())?;
+    stats_stmt
+        .bind((2, func_name))
+        .map_err(|err| err.to_string())?;
+
+    let mut stats_map = HashMap::new();
+    let mut ordered_addresses = Vec::new();
+    let mut total_samples = 0u64;
+
+    while let State::Row = stats_stmt.next().map_err(|err| err.to_string())? {
+        let address = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("address")
+                .map_err(|err| err.to_string())?,
+        );
+        let samples = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("samples")
+                .map_err(|err| err.to_string())?,
+        );
+        let cycles = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("cycles")
+                .map_err(|err| err.to_string())?,
+        );
+        let instructions = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("instructions")
+                .map_err(|err| err.to_string())?,
+        );
+        let branch_misses = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("branch_misses")
+                .map_err(|err| err.to_string())?,
+        );
+        let branch_instructions = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("branch_instructions")
+                .map_err(|err| err.to_string())?,
+        );
+        let llc_misses = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("llc_misses")
+                .map_err(|err| err.to_string())?,
+        );
+        let llc_references = sqlite_u64_from_i64(
+            stats_stmt
+                .read::<i64, _>("llc_references")
+                .map_err(|err| err.to_string())?,
+        );
+
+        stats_map.insert(
+            address,
+            AssemblyStats {
+                samples,
+                cycles,
+                instructions,
+                branch_misses,
+                branch_instructions,
+                llc_misses,
+                llc_references,
+            },
+        );
+        ordered_addresses.push(address);
+
+        total_samples = total_samples.saturating_add(samples);
+    }
+
+    if ordered_addresses.is_empty() {
+        return Err("No assembly information found for the selected function".to_string());
+    }
+
+    // PMU instruction pointers are not guaranteed to equal the first byte of an
+    // instruction. Attribute each one to the closest preceding persisted instruction,
+    // bounded by the maximum x86 instruction length so gaps between selected symbols do
+    // not absorb unrelated samples.
+    const MAX_INSTRUCTION_BYTES: u64 = 15;
+    let mut instruction_stmt = conn
+        .prepare(
+            "SELECT runtime_address, symbol FROM assembly_lines
+                 WHERE module_path = ? AND runtime_address BETWEEN ? AND ?
+                   AND symbol IS NOT NULL
+                 ORDER BY runtime_address DESC LIMIT 1;",
+        )
+        .map_err(|err| err.to_string())?;
+    let mut attributed_stats = HashMap::<u64, AssemblyStats>::new();
+    let mut owner_symbols = Vec::new();
+    let mut unattributed = Vec::new();
+    for address in ordered_addresses {
+        instruction_stmt.reset().map_err(|err| err.to_string())?;
+        instruction_stmt
+            .bind((1, module_path.as_str()))
+            .map_err(|err| err.to_string())?;
+        instruction_stmt
+            .bind((
+                2,
+                sqlite_i64_from_u64(address.saturating_sub(MAX_INSTRUCTION_BYTES)),
+            ))
+            .map_err(|err| err.to_string())?;
+        instruction_stmt
+            .bind((3, sqlite_i64_from_u64(address)))
+            .map_err(|err| err.to_string())?;
+
+        let stats = stats_map[&address];
+        match instruction_stmt.next().map_err(|err| err.to_string())? {
+            State::Row => {
+                let instruction_address = sqlite_u64_from_i64(
+                    instruction_stmt
+                        .read::<i64, _>("runtime_address")
+                        .map_err(|err| err.to_string())?,
+                );
+                let owner = instruction_stmt
+                    .read::<String, _>("symbol")
+                    .map_err(|err| err.to_string())?;
+                attributed_stats
+                    .entry(instruction_address)
+                    .or_default()
+                    .merge(stats);
+                owner_symbols.push(owner);
+            }
+            State::Done => unattributed.push((address, stats)),
+        }
+    }
+    owner_symbols.sort_unstable();
+    owner_symbols.dedup();
+
+    let mut rows = Vec::new();
+    let mut push_line = |lines_stmt: &mut sqlite::Statement| -> Result<(), String> {
+        let address = sqlite_u64_from_i64(
+            lines_stmt
+                .read::<i64, _>("runtime_address")
+                .map_err(|err| err.to_string())?,
+        );
+        let instruction = lines_stmt
+            .read::<String, _>("instruction")
+            .map_err(|err| err.to_string())?;
+        let stats = attributed_stats.get(&address).copied().unwrap_or_default();
+        rows.push(assembly_row(address, instruction, stats, total_samples));
+        Ok(())
+    };
+
+    if owner_symbols.iter().any(|owner| owner == func_name) {
+        let mut lines_stmt = conn
+            .prepare(
+                "SELECT runtime_address, instruction FROM assembly_lines
+                     WHERE module_path = ? AND symbol = ? ORDER BY runtime_address;",
+            )
+            .map_err(|err| err.to_string())?;
+        for owner in &owner_symbols {
+            lines_stmt.reset().map_err(|err| err.to_string())?;
+            lines_stmt
+                .bind((1, module_path.as_str()))
+                .map_err(|err| err.to_string())?;
+            lines_stmt
+                .bind((2, owner.as_str()))
+                .map_err(|err| err.to_string())?;
+            while let State::Row = lines_stmt.next().map_err(|err| err.to_string())? {
+                push_line(&mut lines_stmt)?;
+            }
+        }
+    } else {
+        // Inlining and symbol aliases leave the disassembly under a machine symbol
+        // other than the function the samples resolved to. Dumping those symbols whole
+        // would bury the hot code, so show only the sampled address range.
+        let sampled = attributed_stats
+            .keys()
+            .copied()
+            .chain(unattributed.iter().map(|(address, _)| *address));
+        let (low, high) = sampled.fold((u64::MAX, 0), |(low, high), address| {
+            (low.min(address), high.max(address))
+        });
+        let mut lines_stmt = conn
+            .prepare(
+                "SELECT runtime_address, instruction FROM assembly_lines
+                     WHERE module_path = ? AND runtime_address BETWEEN ? AND ?
+                     GROUP BY runtime_address ORDER BY runtime_address;",
+            )
+            .map_err(|err| err.to_string())?;
+        lines_stmt
+            .bind((1, module_path.as_str()))
+            .map_err(|err| err.to_string())?;
+        lines_stmt
+            .bind((2, sqlite_i64_from_u64(low)))
+            .map_err(|err| err.to_string())?;
+        lines_stmt
+            .bind((3, sqlite_i64_from_u64(high)))
+            .map_err(|err| err.to_string())?;
+        while let State::Row = lines_stmt.next().map_err(|err| err.to_string())? {
+            push_line(&mut lines_stmt)?;
+        }
     }
+
+    // Old or partially postprocessed recordings may not contain the selected machine
+    // symbol. Keep their metrics visible and make the missing instruction explicit.
+    rows.extend(unattributed.into_iter().map(|(address, stats)| {
+        assembly_row(
+            address,
+            "<persisted instruction unavailable>".to_string(),
+            stats,
+            total_samples,
+        )
+    }));
+    rows.sort_unstable_by_key(|row| row.address);
+
+    if rows.is_empty() {
+        return Err("Persisted assembly is not available for the selected function".to_string());
+    }
+
+    let max_samples = rows.iter().map(|row| row.samples).max().unwrap_or(0);
+    let symbol = match owner_symbols.as_slice() {
+        [] => "[instructions unavailable]".to_string(),
+        [owner] => owner.clone(),
+        owners => format!("{} machine symbols", owners.len()),
+    };
+
+    let build_id_warning = module_build_id_warning(conn, &module_path)?;
+
+    Ok(AssemblyViewState {
+        func_name: func_name.to_string(),
+        module_path,
+        symbol,
+        build_id_warning,
+        rows,
+        selected: None,
+        offset: 0,
+        max_samples,
+        range_anchor: None,
+        range: None,
+        instruction_rows: None,
+    })
 }
 
-fn float_precision(format: &ValueFormat) -> usize {
-    match format {
-        ValueFormat::Float1 | ValueFormat::Percent1 => 1,
-        ValueFormat::Float3 | ValueFormat::Percent3 => 3,
-        _ => 2,
+/// Warns when `module_path` was rebuilt after recording, as its disassembly
+/// then describes different code than the one sampled.
+fn module_build_id_warning(
+    conn: &sqlite::Connection,
+    module_path: &str,
+) -> Result<Option<String>, String> {
+    // Recordings whose assembly predates module metadata have nothing to compare.
+    let Ok(mut stmt) = conn.prepare(
+        "SELECT recorded_build_id, current_build_id FROM assembly_module_metadata
+         WHERE module_path = ?;",
+    ) else {
+        return Ok(None);
+    };
+    stmt.bind((1, module_path)).map_err(|err| err.to_string())?;
+    if stmt.next().map_err(|err| err.to_string())? != sqlite::State::Row {
+        return Ok(None);
+    }
+    let recorded = stmt
+        .read::<Option<String>, _>(0)
+        .map_err(|err| err.to_string())?;
+    let current = stmt
+        .read::<Option<String>, _>(1)
+        .map_err(|err| err.to_string())?;
+    if !crate::postprocess::build_id_changed(recorded.as_deref(), current.as_deref()) {
+        return Ok(None);
     }
+    Ok(Some(format!(
+        "Warning: binary changed since recording (build ID {} now {}); hot lines may be wrong",
+        recorded.as_deref().unwrap_or("none"),
+        current.as_deref().unwrap_or("none"),
+    )))
 }
 
-fn format_number(value: f64, precision: usize) -> String {
-    match precision {
-        0 => format!("{:.0}", value),
-        1 => format!("{:.1}", value),
-        2 => format!("{:.2}", value),
-        3 => format!("{:.3}", value),
-        _ => format!("{:.2}", value),
+/// Popup listing every column of the table with whether it is shown.
+fn render_column_chooser(
+    area: Rect,
+    buf: &mut ratatui::prelude::Buffer,
+    layout: &RuntimeLayout,
+    selected: usize,
+) {
+    let height = (layout.choices.len() as u16 + 2).min(area.height);
+    let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+    let horizontal = Layout::horizontal([Constraint::Length(40)]).flex(Flex::Center);
+    let [popup_area] = vertical.areas(area);
+    let [popup_area] = horizontal.areas(popup_area);
+
+    Clear.render(popup_area, buf);
+
+    let rows = layout.choices.iter().map(|choice| {
+        let shown = layout.columns.iter().any(|column| column.key == choice.key);
+        Row::new([
+            if shown { "[x]" } else { "[ ]" }.to_string(),
+            choice.label.clone(),
+        ])
+    });
+    let table = Table::new(rows, [Constraint::Length(3), Constraint::Fill(1)])
+        .row_highlight_style(Style::default().bg(Color::DarkGray))
+        .block(Block::bordered().title(" Columns "));
+    let mut table_state = TableState::default().with_selected(Some(selected));
+    ratatui::widgets::StatefulWidget::render(table, popup_area, buf, &mut table_state);
+}
+
+/// Lists the bookmarks; those without a row in this table are greyed out.
+fn render_bookmark_list(
+    area: Rect,
+    buf: &mut ratatui::prelude::Buffer,
+    bookmarks: &BTreeSet<String>,
+    present: &HashSet<&str>,
+    selected: usize,
+) {
+    let height = (bookmarks.len().max(1) as u16 + 2).min(area.height);
+    let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+    let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
+    let [popup_area] = vertical.areas(area);
+    let [popup_area] = horizontal.areas(popup_area);
+
+    Clear.render(popup_area, buf);
+
+    let block = Block::bordered().title(" Bookmarks ");
+    if bookmarks.is_empty() {
+        Paragraph::new("No bookmarks; press b on a function to add one")
+            .block(block)
+            .render(popup_area, buf);
+        return;
+    }
+    let rows = bookmarks.iter().map(|func_name| {
+        let row = Row::new([func_name.clone()]);
+        if present.contains(func_name.as_str()) {
+            row
+        } else {
+            row.style(Style::new().fg(Color::DarkGray))
+        }
+    });
+    let table = Table::new(rows, [Constraint::Fill(1)])
+        .row_highlight_style(Style::default().bg(Color::DarkGray))
+        .block(block);
+    let mut table_state = TableState::default().with_selected(Some(selected));
+    ratatui::widgets::StatefulWidget::render(table, popup_area, buf, &mut table_state);
+}
+
+/// The expression `view` computes `column` with, as recorded in
+/// `view_columns` when the view was created.
+fn view_column_expression(conn: &sqlite::Connection, view: &str, column: &str) -> Option<String> {
+    let mut statement = conn
+        .prepare("SELECT expression FROM view_columns WHERE view = ? AND name = ?;")
+        .ok()?;
+    statement.bind((1, view)).ok()?;
+    statement.bind((2, column)).ok()?;
+    match statement.next().ok()? {
+        sqlite::State::Row => statement.read::<String, _>("expression").ok(),
+        sqlite::State::Done => None,
+    }
+}
+
+/// The sample count, mean multiplexing confidence and the sum of every
+/// counter column of `pmu_counters` over the samples of `func_name`, as
+/// stored: counters other than cycles and instructions are kept scaled by
+/// their confidence.
+fn raw_counter_sums(
+    conn: &sqlite::Connection,
+    func_name: &str,
+) -> Result<Vec<(String, String)>, String> {
+    let mut statement = conn
+        .prepare("PRAGMA table_info(pmu_counters);")
+        .map_err(|err| err.to_string())?;
+    let mut counters = Vec::new();
+    while let Ok(sqlite::State::Row) = statement.next() {
+        let name = statement
+            .read::<String, _>("name")
+            .map_err(|err| err.to_string())?;
+        if name.starts_with("pmu_") {
+            counters.push(name);
+        }
+    }
+    drop(statement);
+
+    let sums = counters
+        .iter()
+        .map(|name| format!(", SUM(pmu_counters.\"{name}\") AS \"{name}\""))
+        .collect::<String>();
+    let mut statement = conn
+        .prepare(format!(
+            "SELECT COUNT(*) AS samples, AVG(pmu_counters.confidence) AS confidence{sums}
+             FROM pmu_counters
+             INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
+             WHERE proc_map.func_name = ?;"
+        ))
+        .map_err(|err| err.to_string())?;
+    statement
+        .bind((1, func_name))
+        .map_err(|err| err.to_string())?;
+    statement.next().map_err(|err| err.to_string())?;
+
+    let read = |column: &str| match statement.read::<sqlite::Value, _>(column) {
+        Ok(sqlite::Value::Integer(value)) => value.to_formatted_string(&locale::current()),
+        Ok(sqlite::Value::Float(value)) => format!("{value:.3}"),
+        _ => "-".to_string(),
+    };
+    let mut raw = vec![
+        ("samples".to_string(), read("samples")),
+        ("mean confidence".to_string(), read("confidence")),
+    ];
+    raw.extend(counters.iter().map(|name| (name.clone(), read(name))));
+    Ok(raw)
+}
+
+/// Popup with the expression and raw counters behind the selected cell.
+fn render_explanation(
+    area: Rect,
+    buf: &mut ratatui::prelude::Buffer,
+    explanation: &CellExplanation,
+) {
+    let mut lines = vec![
+        Line::from(format!("Value: {}", explanation.value)),
+        Line::from(""),
+        Line::from("Expression:".bold()),
+        Line::from(explanation.expression.clone().unwrap_or_else(|| {
+            "Not recorded; run `mperf postprocess --views-only` to record it".to_string()
+        })),
+        Line::from(""),
+        Line::from("Stored counters:".bold()),
+    ];
+    match &explanation.raw {
+        Ok(raw) => {
+            let width = raw.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            lines.extend(
+                raw.iter()
+                    .map(|(name, value)| Line::from(format!("{name:width$}  {value:>18}"))),
+            );
+        }
+        Err(message) => lines.push(Line::from(message.clone())),
     }
+
+    let vertical =
+        Layout::vertical([Constraint::Length(lines.len() as u16 + 6)]).flex(Flex::Center);
+    let horizontal = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center);
+    let [popup_area] = vertical.areas(area);
+    let [popup_area] = horizontal.areas(popup_area);
+
+    Clear.render(popup_area, buf);
+    Paragraph::new(lines)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .block(Block::bordered().title(format!(" {} ", explanation.title)))
+        .render(popup_area, buf);
 }
 
 fn render_assembly_overlay(
@@ -1162,14 +2209,25 @@ fn render_assembly_overlay(
         return;
     }
 
-    let layout = Layout::vertical([Constraint::Length(4), Constraint::Fill(1)]);
+    let info_height = if view.build_id_warning.is_some() {
+        7
+    } else {
+        6
+    };
+    let layout = Layout::vertical([Constraint::Length(info_height), Constraint::Fill(1)]);
     let [info_area, table_area] = layout.areas(inner_area);
 
-    let mut info_lines = vec![
+    let mut info_lines = Vec::new();
+    if let Some(warning) = view.build_id_warning.as_ref() {
+        info_lines.push(Line::from(warning.as_str()).yellow().bold());
+    }
+    info_lines.extend([
         Line::from(format!("Function: {}", view.func_name)),
         Line::from(format!("Module: {}", view.module_path)),
         Line::from(format!("Symbol: {}", view.symbol)),
-    ];
+        range_summary_line(view),
+        selected_row_line(view),
+    ]);
 
     if let Some(summary) = state.assembly_summary.as_ref() {
         info_lines.push(Line::from("Metrics:"));
@@ -1195,6 +2253,7 @@ fn render_assembly_overlay(
         Cell::from("Assembly"),
         Cell::from(Text::from("Samples").alignment(Alignment::Right)),
         Cell::from(Text::from("Share %").alignment(Alignment::Right)),
+        Cell::from(Text::from("Confidence").alignment(Alignment::Right)),
         Cell::from(Text::from("Cycles").alignment(Alignment::Right)),
         Cell::from(Text::from("Instructions").alignment(Alignment::Right)),
         Cell::from(Text::from("IPC").alignment(Alignment::Right)),
@@ -1219,14 +2278,16 @@ fn render_assembly_overlay(
 
     let header = Row::new(header_cells).style(Style::new().bold());
 
-    let rows_iter = view.rows.iter().map(|row| {
+    let range = view.selected_range();
+    let rows_iter = view.rows.iter().enumerate().map(|(index, row)| {
+        let in_range = range.is_some_and(|(start, end)| (start..=end).contains(&index));
         let heat_cell = Cell::from("  ").style(heat_style(row.samples, view.max_samples));
         let address = format!("0x{:016x}", row.address);
         let asm_text = row.instruction.clone();
-        let samples = row.samples.to_formatted_string(&Locale::en);
+        let samples = row.samples.to_formatted_string(&locale::current());
         let share = format!("{:.2}", row.share * 100.0);
-        let cycles = row.cycles.to_formatted_string(&Locale::en);
-        let instructions = row.instructions.to_formatted_string(&Locale::en);
+        let cycles = row.cycles.to_formatted_string(&locale::current());
+        let instructions = row.instructions.to_formatted_string(&locale::current());
         let ipc = if row.cycles > 0 {
             row.instructions as f64 / row.cycles as f64
         } else {
@@ -1238,6 +2299,7 @@ fn render_assembly_overlay(
             Cell::from(asm_text),
             Cell::from(Text::from(samples).alignment(Alignment::Right)),
             Cell::from(Text::from(share).alignment(Alignment::Right)),
+            Cell::from(Text::from(confidence_label(row.samples)).alignment(Alignment::Right)),
             Cell::from(Text::from(cycles).alignment(Alignment::Right)),
             Cell::from(Text::from(instructions).alignment(Alignment::Right)),
             Cell::from(Text::from(format!("{:.2}", ipc)).alignment(Alignment::Right)),
@@ -1281,7 +2343,16 @@ fn render_assembly_overlay(
             ));
         }
 
-        Row::new(cells)
+        // Too few samples to tell a hot instruction from noise.
+        let mut style = if is_low_confidence(row.samples) {
+            Style::new().fg(Color::DarkGray)
+        } else {
+            Style::new()
+        };
+        if in_range {
+            style = style.bg(RANGE_BACKGROUND);
+        }
+        Row::new(cells).style(style)
     });
 
     let mut widths = vec![
@@ -1290,6 +2361,7 @@ fn render_assembly_overlay(
         Constraint::Length(50),
         Constraint::Length(12),
         Constraint::Length(10),
+        Constraint::Length(11),
         Constraint::Length(12),
         Constraint::Length(16),
         Constraint::Length(10),
@@ -1320,6 +2392,86 @@ fn render_assembly_overlay(
     view.offset = table_state.offset();
 }
 
+fn range_summary_line(view: &AssemblyViewState) -> Line<'static> {
+    let Some((start, end, stats)) = view.range_stats() else {
+        return Line::from("Range: press v to start and v again to end a selection").italic();
+    };
+    let total_samples = view.rows.iter().map(|row| row.samples).sum::<u64>();
+    let share = if total_samples > 0 {
+        stats.samples as f64 / total_samples as f64 * 100.0
+    } else {
+        0.0
+    };
+    let ipc = if stats.cycles > 0 {
+        format!("{:.2}", stats.instructions as f64 / stats.cycles as f64)
+    } else {
+        "N/A".to_string()
+    };
+    let marker = if view.range_anchor.is_some() {
+        " (selecting)"
+    } else {
+        ""
+    };
+    let unit = if view.instruction_rows.is_some() {
+        "blocks"
+    } else {
+        "instructions"
+    };
+    Line::from(format!(
+        "Range{marker}: 0x{:x}-0x{:x} ({} {unit}) samples {} ({share:.2}%), cycles {}, instructions {}, IPC {ipc}",
+        view.rows[start].address,
+        view.rows[end].address,
+        end - start + 1,
+        stats.samples.to_formatted_string(&locale::current()),
+        stats.cycles.to_formatted_string(&locale::current()),
+        stats.instructions.to_formatted_string(&locale::current()),
+    ))
+}
+
+/// Describes the instruction under the cursor and how far its share can be
+/// trusted.
+fn selected_row_line(view: &AssemblyViewState) -> Line<'static> {
+    let Some(row) = view.selected.and_then(|index| view.rows.get(index)) else {
+        return Line::from("");
+    };
+    let mut text = format!(
+        "Selected: 0x{:x} samples {} ({:.2}%)",
+        row.address,
+        row.samples.to_formatted_string(&locale::current()),
+        row.share * 100.0
+    );
+    if row.samples == 0 {
+        text += ", not sampled";
+    } else if is_low_confidence(row.samples) {
+        text += &format!(", low confidence: fewer than {LOW_CONFIDENCE_SAMPLES} samples");
+    } else {
+        text += &format!(", relative error {}", confidence_label(row.samples));
+    }
+    if is_low_confidence(row.samples) {
+        Line::from(text).dark_gray()
+    } else {
+        Line::from(text)
+    }
+}
+
+/// Samples below which an instruction's share is mostly noise.
+const LOW_CONFIDENCE_SAMPLES: u64 = 5;
+
+fn is_low_confidence(samples: u64) -> bool {
+    samples > 0 && samples < LOW_CONFIDENCE_SAMPLES
+}
+
+/// The relative standard error of a share measured with `samples` samples,
+/// which are Poisson distributed, or "low" below [`LOW_CONFIDENCE_SAMPLES`].
+fn confidence_label(samples: u64) -> String {
+    match samples {
+        0 => String::new(),
+        samples if is_low_confidence(samples) => "low".to_string(),
+        samples => format!("±{:.0}%", 100.0 / (samples as f64).sqrt()),
+    }
+}
+
+const RANGE_BACKGROUND: Color = Color::Rgb(32, 48, 96);
 const ASSEMBLY_VIEW_WINDOW_HINT: usize = 20;
 const ASSEMBLY_SCROLL_STEP: usize = 10;
 const HEATMAP_GRADIENT: &[(f64, (u8, u8, u8))] = &[
@@ -1428,12 +2580,457 @@ mod tests {
 
         let config = MetricsTableConfig::from_spec(spec);
         assert_eq!(
-            config.build_query(),
+            config.build_query(false),
             "SELECT * FROM hotspots ORDER BY total DESC LIMIT 50"
         );
+        assert!(config
+            .build_query(true)
+            .starts_with("SELECT * FROM hotspots WHERE func_name IN (SELECT func_name"));
         assert!(config.columns.len() > 5);
     }
 
+    #[test]
+    fn scroll_indicators_follow_hidden_columns() {
+        let column = |key: &str| ColumnConfig {
+            key: key.to_string(),
+            label: key.to_string(),
+            format: ValueFormat::Integer,
+            width: Some(10),
+            sticky: false,
+            optional: false,
+            alignment: Alignment::Right,
+            derived: None,
+        };
+        let layout = RuntimeLayout {
+            columns: ["a", "b", "c", "d"].into_iter().map(column).collect(),
+            sticky_columns: 0,
+            function_column_index: None,
+            choices: Vec::new(),
+        };
+
+        // Four columns need 4 * 10 + 3 gaps + the selection marker.
+        assert_eq!(hidden_columns(&layout, 0, 0, 45), (false, false));
+        assert_eq!(hidden_columns(&layout, 0, 0, 30), (false, true));
+        assert_eq!(hidden_columns(&layout, 0, 1, 30), (true, true));
+        assert_eq!(hidden_columns(&layout, 0, 3, 30), (true, false));
+        assert_eq!(hidden_columns(&layout, 1, 2, 30), (true, false));
+    }
+
+    #[test]
+    fn only_columns_on_screen_are_exported() {
+        let column = |key: &str| ColumnConfig {
+            key: key.to_string(),
+            label: key.to_string(),
+            format: ValueFormat::Integer,
+            width: Some(10),
+            sticky: false,
+            optional: false,
+            alignment: Alignment::Right,
+            derived: None,
+        };
+        let layout = RuntimeLayout {
+            columns: ["a", "b", "c", "d"].into_iter().map(column).collect(),
+            sticky_columns: 1,
+            function_column_index: None,
+            choices: Vec::new(),
+        };
+
+        assert_eq!(on_screen_columns(&layout, 1, 0, 45), [0, 1, 2, 3]);
+        assert_eq!(on_screen_columns(&layout, 1, 0, 30), [0, 1]);
+        assert_eq!(on_screen_columns(&layout, 1, 1, 34), [0, 2, 3]);
+        assert_eq!(on_screen_columns(&layout, 1, 2, 12), [0, 3]);
+    }
+
+    #[test]
+    fn markdown_table_keeps_the_formatting_and_alignment_of_columns() {
+        let column = |key: &str, format, alignment| ColumnConfig {
+            key: key.to_string(),
+            label: key.to_string(),
+            format,
+            width: None,
+            sticky: false,
+            optional: false,
+            alignment,
+            derived: None,
+        };
+        let layout = RuntimeLayout {
+            columns: vec![
+                column("function", ValueFormat::Text, Alignment::Left),
+                column("cycles", ValueFormat::Integer, Alignment::Right),
+            ],
+            sticky_columns: 0,
+            function_column_index: Some(0),
+            choices: Vec::new(),
+        };
+        let rows = [
+            MetricsRow {
+                values: vec![
+                    MetricValue::Text("operator|".to_string()),
+                    MetricValue::Integer(1234567),
+                ],
+            },
+            MetricsRow {
+                values: vec![MetricValue::Text("main".to_string()), MetricValue::Null],
+            },
+        ];
+
+        assert_eq!(
+            markdown_table(&layout, &[0, 1], &rows),
+            "| function | cycles |\n\
+             | :--- | ---: |\n\
+             | operator\\| | 1,234,567 |\n\
+             | main | N/A |\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn source_only_filter_hides_unresolved_functions() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE hotspots (func_name TEXT, total REAL);
+                 CREATE TABLE proc_map (ip INTEGER, func_name TEXT, file_name TEXT);
+                 INSERT INTO hotspots VALUES ('mine', 0.4), ('libc_memcpy', 0.6);
+                 INSERT INTO proc_map VALUES
+                    (1, 'mine', 'src/main.c'), (2, 'mine', 'unknown'),
+                    (3, 'libc_memcpy', 'unknown');",
+            )
+            .unwrap();
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: vec![MetricColumnSpec {
+                key: "func_name".to_string(),
+                label: None,
+                format: ValueFormat::Text,
+                width: None,
+                sticky: true,
+                optional: false,
+            }],
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: Some("func_name".to_string()),
+            enable_assembly: false,
+        };
+        let tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection)));
+
+        tab.state.lock().source_only = true;
+        tab.clone().fetch_data().await;
+
+        let rows = tab.rows.read();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].values[0].as_text(), Some("mine"));
+    }
+
+    #[tokio::test]
+    async fn explanation_shows_the_column_sql_and_raw_counters() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER, confidence REAL,
+                     pmu_cycles INTEGER, pmu_instructions INTEGER);
+                 CREATE TABLE proc_map (ip INTEGER, func_name TEXT);
+                 INSERT INTO pmu_counters VALUES (1, 1.0, 1000, 3000), (1, 0.5, 1000, 1000),
+                     (2, 1.0, 500, 500);
+                 INSERT INTO proc_map VALUES (1, 'work'), (2, 'idle');
+                 CREATE VIEW hotspots AS SELECT proc_map.func_name AS func_name,
+                     SUM(pmu_instructions) * 1.0 / SUM(pmu_cycles) AS ipc
+                     FROM pmu_counters INNER JOIN proc_map ON pmu_counters.ip = proc_map.ip
+                     GROUP BY proc_map.func_name ORDER BY func_name DESC;",
+            )
+            .unwrap();
+        connection.execute(crate::schema::VIEW_COLUMNS).unwrap();
+        connection
+            .execute(
+                "INSERT INTO view_columns VALUES
+                     ('hotspots', 'ipc', 'SUM(pmu_instructions) * 1.0 / SUM(pmu_cycles)');",
+            )
+            .unwrap();
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: ["func_name", "ipc"]
+                .map(|key| MetricColumnSpec {
+                    key: key.to_string(),
+                    label: Some(if key == "ipc" { "IPC" } else { "Function" }.to_string()),
+                    format: if key == "ipc" {
+                        ValueFormat::Float2
+                    } else {
+                        ValueFormat::Text
+                    },
+                    width: None,
+                    sticky: key == "func_name",
+                    optional: false,
+                })
+                .to_vec(),
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: None,
+            enable_assembly: false,
+        };
+        let mut tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection)));
+        tab.clone().fetch_data().await;
+        tab.state.lock().selected = Some(0);
+
+        tab.handle_event(KeyCode::Char('e'));
+        {
+            let state = tab.state.lock();
+            let explanation = state.explanation.as_ref().unwrap();
+            assert_eq!(explanation.title, "IPC of work");
+            assert_eq!(explanation.value, "2.00");
+            assert_eq!(
+                explanation.expression.as_deref(),
+                Some("SUM(pmu_instructions) * 1.0 / SUM(pmu_cycles)")
+            );
+            let raw = explanation.raw.as_ref().unwrap();
+            assert!(raw.contains(&("samples".to_string(), "2".to_string())));
+            assert!(raw.contains(&("mean confidence".to_string(), "0.750".to_string())));
+            assert!(raw.contains(&("pmu_cycles".to_string(), "2,000".to_string())));
+        }
+
+        tab.handle_event(KeyCode::Esc);
+        assert!(tab.state.lock().explanation.is_none());
+    }
+
+    #[tokio::test]
+    async fn bookmarks_persist_and_jump_to_their_function() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE hotspots (func_name TEXT, total REAL);
+                 INSERT INTO hotspots VALUES ('main', 0.5), ('work', 0.3), ('idle', 0.2);",
+            )
+            .unwrap();
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: ["func_name", "total"]
+                .map(|key| MetricColumnSpec {
+                    key: key.to_string(),
+                    label: None,
+                    format: ValueFormat::Auto,
+                    width: None,
+                    sticky: key == "func_name",
+                    optional: false,
+                })
+                .to_vec(),
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: None,
+            enable_assembly: false,
+        };
+        let dir = std::env::temp_dir().join(format!("mperf-bookmarks-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection)))
+            .with_ui_state(UiStateFile::load(&dir));
+        tab.clone().fetch_data().await;
+
+        tab.state.lock().selected = Some(2);
+        tab.handle_event(KeyCode::Char('b'));
+        tab.state.lock().selected = Some(0);
+        tab.handle_event(KeyCode::Char('b'));
+        assert_eq!(
+            UiStateFile::load(&dir).get().bookmarks,
+            BTreeSet::from(["idle".to_string(), "main".to_string()])
+        );
+
+        // The list is sorted by name: "idle" first.
+        tab.handle_event(KeyCode::Char('B'));
+        tab.handle_event(KeyCode::Enter);
+        {
+            let state = tab.state.lock();
+            assert_eq!(state.selected, Some(2));
+            assert_eq!(state.bookmark_list, None);
+        }
+
+        tab.handle_event(KeyCode::Char('B'));
+        tab.handle_event(KeyCode::Char('d'));
+        assert_eq!(
+            UiStateFile::load(&dir).get().bookmarks,
+            BTreeSet::from(["main".to_string()])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn help_lists_the_keys_of_the_focused_view() {
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: vec![],
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: None,
+            enable_assembly: true,
+        };
+        let connection = Connection::open(":memory:").unwrap();
+        let tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection)));
+
+        let keys = |tab: &MetricsTableTab| {
+            tab.help_entries()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert!(keys(&tab).contains(&"<enter>"));
+        assert!(!keys(&tab).contains(&"v"));
+
+        tab.state.lock().focus = MetricsFocus::Assembly;
+        assert!(keys(&tab).contains(&"v"));
+    }
+
+    #[test]
+    fn column_chooser_offers_every_column_of_the_view() {
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: vec![MetricColumnSpec {
+                key: "ipc".to_string(),
+                label: Some("IPC".to_string()),
+                format: ValueFormat::Float2,
+                width: None,
+                sticky: false,
+                optional: false,
+            }],
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: Some("func_name".to_string()),
+            enable_assembly: false,
+        };
+        let config = MetricsTableConfig::from_spec(spec);
+        let available = ["func_name", "ipc", "samples", "cycles"]
+            .into_iter()
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        let keys = |columns: &[ColumnConfig]| {
+            columns
+                .iter()
+                .map(|column| column.key.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let layout = config.build_runtime_layout(&available, None).unwrap();
+        assert_eq!(keys(&layout.columns), ["ipc"]);
+        assert_eq!(keys(&layout.choices), ["ipc", "cycles", "samples"]);
+
+        let visible = ["samples".to_string()];
+        let layout = config
+            .build_runtime_layout(&available, Some(&visible))
+            .unwrap();
+        assert_eq!(keys(&layout.columns), ["samples"]);
+    }
+
+    #[test]
+    fn derived_columns_are_computed_from_their_inputs() {
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: Vec::new(),
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: Some("func_name".to_string()),
+            enable_assembly: false,
+        };
+        let mut config = MetricsTableConfig::from_spec(spec);
+        config.derived_columns = vec![Arc::new(derived_columns::CyclesPerInstruction)];
+        let available = |columns: &[&str]| {
+            columns
+                .iter()
+                .map(|column| column.to_string())
+                .collect::<HashSet<_>>()
+        };
+
+        let visible = ["cycles".to_string()];
+        let layout = config
+            .build_runtime_layout(&available(&["func_name", "cycles"]), Some(&visible))
+            .unwrap();
+        assert!(layout.choices.iter().all(|column| column.key != "cpi"));
+
+        let visible = ["cpi".to_string()];
+        let layout = config
+            .build_runtime_layout(
+                &available(&["func_name", "cycles", "instructions"]),
+                Some(&visible),
+            )
+            .unwrap();
+        let connection = sqlite::open(":memory:").unwrap();
+        let row = connection
+            .prepare("SELECT 'main' AS func_name, 300 AS cycles, 100 AS instructions;")
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_row(&layout, &row).values, [MetricValue::Float(3.0)]);
+    }
+
+    #[test]
+    fn baseline_adds_the_change_of_the_sort_column() {
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: vec![MetricColumnSpec {
+                key: "total".to_string(),
+                label: Some("Total %".to_string()),
+                format: ValueFormat::Percent2,
+                width: None,
+                sticky: false,
+                optional: false,
+            }],
+            order_by: Some(pmu_data::OrderSpec {
+                column: "total".to_string(),
+                direction: SortDirection::Desc,
+            }),
+            limit: None,
+            sticky_columns: None,
+            function_column: Some("func_name".to_string()),
+            enable_assembly: false,
+        };
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE hotspots (func_name TEXT, total REAL);
+                 INSERT INTO hotspots VALUES ('parse', 0.75), ('render', 0.25);
+                 ATTACH DATABASE ':memory:' AS baseline;
+                 CREATE TABLE baseline.hotspots (func_name TEXT, total REAL);
+                 INSERT INTO baseline.hotspots VALUES ('parse', 0.5);",
+            )
+            .unwrap();
+        let tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection))).with_baseline();
+        assert_eq!(
+            tab.config
+                .columns
+                .iter()
+                .map(|column| column.key.as_str())
+                .collect::<Vec<_>>(),
+            ["total", "baseline_total", "delta_total"]
+        );
+
+        let connection = tab.connection.lock();
+        let mut statement = connection.prepare(tab.config.build_query(false)).unwrap();
+        assert_eq!(statement.next().unwrap(), sqlite::State::Row);
+        assert_eq!(statement.read::<String, _>("func_name").unwrap(), "parse");
+        assert_eq!(statement.read::<f64, _>("delta_total").unwrap(), 0.25);
+        assert_eq!(statement.next().unwrap(), sqlite::State::Row);
+        assert_eq!(
+            statement.read::<Option<f64>, _>("delta_total").unwrap(),
+            None
+        );
+    }
+
     #[tokio::test]
     async fn assembly_view_attributes_samples_and_keeps_unavailable_metrics() {
         let connection = Connection::open(":memory:").unwrap();
@@ -1496,4 +3093,152 @@ mod tests {
         );
         assert_eq!(view.max_samples, 11);
     }
+
+    #[tokio::test]
+    async fn assembly_view_shows_sampled_range_when_symbols_disagree() {
+        let connection = Connection::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE pmu_counters (ip INTEGER, pmu_cycles INTEGER);
+                 CREATE TABLE proc_map (ip INTEGER, module_path TEXT, func_name TEXT);
+                 CREATE TABLE assembly_address_stats (
+                    module_path TEXT, func_name TEXT, address INTEGER, samples INTEGER,
+                    cycles INTEGER, instructions INTEGER, branch_misses INTEGER,
+                    branch_instructions INTEGER, llc_misses INTEGER, llc_references INTEGER
+                 );
+                 CREATE TABLE assembly_lines (
+                    module_path TEXT, symbol TEXT, rel_address INTEGER,
+                    runtime_address INTEGER, instruction TEXT
+                 );
+                 INSERT INTO pmu_counters VALUES (8196, 10);
+                 INSERT INTO proc_map VALUES (8196, '/tmp/test', 'inlined');
+                 INSERT INTO assembly_address_stats VALUES
+                    ('/tmp/test', 'inlined', 8196, 2, 20, 40, 0, 0, 0, 0),
+                    ('/tmp/test', 'inlined', 8200, 3, 30, 60, 0, 0, 0, 0);
+                 INSERT INTO assembly_lines VALUES
+                    ('/tmp/test', 'outer', 8192, 8192, 'push %rbp'),
+                    ('/tmp/test', 'outer', 8196, 8196, 'mov (%rdi),%rax'),
+                    ('/tmp/test', 'outer_alias', 8196, 8196, 'mov (%rdi),%rax'),
+                    ('/tmp/test', 'outer', 8200, 8200, 'add %rax,%rbx'),
+                    ('/tmp/test', 'outer', 8204, 8204, 'pop %rbp'),
+                    ('/tmp/test', 'outer', 8205, 8205, 'ret');",
+            )
+            .unwrap();
+        let spec = MetricsTableSpec {
+            view: "hotspots".to_string(),
+            title: None,
+            include_default_columns: false,
+            columns: Vec::new(),
+            order_by: None,
+            limit: None,
+            sticky_columns: None,
+            function_column: Some("func_name".to_string()),
+            enable_assembly: true,
+        };
+        let tab = MetricsTableTab::new(spec, Arc::new(Mutex::new(connection)));
+
+        tab.clone().fetch_assembly("inlined".to_string(), 0).await;
+
+        let state = tab.state.lock();
+        let view = state.assembly.as_ref().unwrap();
+        let addresses = view.rows.iter().map(|row| row.address).collect::<Vec<_>>();
+        assert_eq!(addresses, [8196, 8200]);
+        assert_eq!(view.rows[0].samples, 2);
+        assert_eq!(view.rows[1].samples, 3);
+    }
+
+    #[test]
+    fn basic_blocks_sum_the_samples_of_their_instructions() {
+        let instructions = ["mov %rdi,%rax", "add $0x1,%rax", "jne 1000", "ret"];
+        let rows = instructions
+            .iter()
+            .zip(0u64..)
+            .map(|(instruction, index)| {
+                let stats = AssemblyStats {
+                    samples: index + 1,
+                    ..AssemblyStats::default()
+                };
+                assembly_row(0x1000 + index * 4, instruction.to_string(), stats, 10)
+            })
+            .collect::<Vec<_>>();
+        let mut view = AssemblyViewState {
+            func_name: "f".to_string(),
+            module_path: "/tmp/f".to_string(),
+            symbol: "f".to_string(),
+            build_id_warning: None,
+            rows: rows.clone(),
+            selected: Some(3),
+            offset: 0,
+            max_samples: 4,
+            range_anchor: None,
+            range: None,
+            instruction_rows: None,
+        };
+
+        view.toggle_basic_blocks();
+        let blocks = view
+            .rows
+            .iter()
+            .map(|row| (row.address, row.samples))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [(0x1000, 6), (0x100c, 4)]);
+        assert!((view.rows[0].share - 0.6).abs() < 1e-9);
+        assert_eq!(view.max_samples, 6);
+        assert_eq!(view.selected, None);
+
+        view.toggle_basic_blocks();
+        assert_eq!(view.rows.len(), rows.len());
+        assert_eq!(view.max_samples, 4);
+    }
+
+    #[test]
+    fn range_selection_sums_rows_between_anchor_and_cursor() {
+        let rows = (0..4)
+            .map(|index| {
+                let stats = AssemblyStats {
+                    samples: index + 1,
+                    cycles: (index + 1) * 10,
+                    instructions: (index + 1) * 20,
+                    ..AssemblyStats::default()
+                };
+                assembly_row(0x1000 + index * 4, "nop".to_string(), stats, 10)
+            })
+            .collect();
+        let mut view = AssemblyViewState {
+            func_name: "f".to_string(),
+            module_path: "/tmp/f".to_string(),
+            symbol: "f".to_string(),
+            build_id_warning: None,
+            rows,
+            selected: Some(2),
+            offset: 0,
+            max_samples: 4,
+            range_anchor: None,
+            range: None,
+            instruction_rows: None,
+        };
+
+        view.toggle_range_selection();
+        view.selected = Some(1);
+        assert_eq!(view.selected_range(), Some((1, 2)));
+        view.toggle_range_selection();
+        view.selected = Some(3);
+
+        let (start, end, stats) = view.range_stats().unwrap();
+        assert_eq!((start, end), (1, 2));
+        assert_eq!(stats.samples, 5);
+        assert_eq!(stats.cycles, 50);
+        assert_eq!(stats.instructions, 100);
+    }
+
+    #[test]
+    fn instructions_with_few_samples_are_low_confidence() {
+        assert_eq!(confidence_label(0), "");
+        assert_eq!(confidence_label(4), "low");
+        assert_eq!(confidence_label(25), "±20%");
+        assert_eq!(confidence_label(10_000), "±1%");
+        assert!(!is_low_confidence(0));
+        assert!(is_low_confidence(1));
+        assert!(!is_low_confidence(5));
+    }
 }
@@ -1,4 +1,5 @@
 use std::{
+    collections::{BTreeSet, HashSet},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -15,6 +16,7 @@ use loops::LoopsTab;
 use metrics_table::MetricsTableTab;
 use mperf_data::{RecordInfo, Scenario};
 use parking_lot::{Mutex, RwLock};
+use pmu_data::{OrderSpec, ScenarioUi, TabSpec};
 use ratatui::{
     layout::{Constraint, Flex, Layout},
     style::{palette, Style, Stylize},
@@ -25,16 +27,61 @@ use ratatui::{
 use summary::SummaryTab;
 use tokio::fs::{self};
 use tokio_stream::StreamExt;
+use ui_state::UiStateFile;
 
+mod clipboard;
 mod config;
+mod derived_columns;
 mod flamegraph;
 mod loops;
 mod metrics_table;
 mod summary;
-
-pub async fn tui_main(res_dir: &Path) -> Result<()> {
+mod theme;
+mod ui_state;
+
+pub(crate) use metrics_table::{load_assembly, AssemblyViewState};
+
+/// Keys handled by the app itself, shown at the top of the help overlay.
+const GLOBAL_KEYS: [(&str, &str); 5] = [
+    ("?", "Show/hide this window"),
+    ("q", "Quit miniperf"),
+    ("R", "Reload the results"),
+    ("<tab>", "Next tab"),
+    ("<S-tab>", "Previous tab"),
+];
+
+/// Added to the help window by `--tui-vim-keys`.
+const VIM_KEYS: [(&str, &str); 4] = [
+    ("h/l", "Same as Left/Right"),
+    ("j/k", "Same as Down/Up"),
+    ("gg/G", "Same as Home/End"),
+    ("/", "Search the tab; an empty search repeats the last one"),
+];
+
+/// Shown in place of a view when the recording has nothing to display.
+const NO_SAMPLES_MESSAGE: &str =
+    "No samples collected — try a higher sample frequency or longer run";
+
+/// Opens the results in `res_dir`. With `baseline`, metrics tables also show
+/// how each function changed relative to that recording. `sort` replaces the
+/// initial ordering of the tables whose view has its column. `database`, if
+/// given, is shown instead of `perf.db`, e.g. one built in memory.
+pub async fn tui_main(
+    res_dir: &Path,
+    baseline: Option<&Path>,
+    sort: Option<OrderSpec>,
+    vim_keys: bool,
+    flamegraph_table_limit: Option<usize>,
+    database: Option<sqlite::Connection>,
+) -> Result<()> {
+    derived_columns::register_builtin();
     let terminal = ratatui::init();
-    let app_result = App::new(res_dir).run(terminal).await;
+    let mut app = App::new(res_dir, baseline, sort);
+    app.vim_keys = vim_keys.then(VimKeymap::default);
+    app.tabs.flamegraph_table_limit = flamegraph_table_limit;
+    app.tabs.in_memory = database.is_some();
+    *app.tabs.database.lock() = database;
+    let app_result = app.run(terminal).await;
     ratatui::restore();
     app_result
 }
@@ -45,20 +92,32 @@ struct App {
     show_help: bool,
     tabs: TabsWidget,
     res_dir: PathBuf,
+    baseline: Option<PathBuf>,
+    vim_keys: Option<VimKeymap>,
+    /// Query typed after `/`, until Enter or Esc.
+    search: Option<String>,
+    last_search: Option<String>,
+    /// Shown in the bottom line until the next key, e.g. a failed search.
+    notice: Option<String>,
 }
 
 impl App {
     const FRAMES_PER_SECOND: f32 = 30.0;
 
-    pub fn new(res_dir: &Path) -> Self {
+    pub fn new(res_dir: &Path, baseline: Option<&Path>, sort: Option<OrderSpec>) -> Self {
         App {
             res_dir: res_dir.to_owned(),
+            baseline: baseline.map(Path::to_owned),
+            tabs: TabsWidget {
+                sort,
+                ..Default::default()
+            },
             ..Default::default()
         }
     }
 
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
-        self.tabs.run(&self.res_dir);
+        self.tabs.run(&self.res_dir, self.baseline.as_deref());
 
         let period = Duration::from_secs_f32(1.0 / Self::FRAMES_PER_SECOND);
         let mut interval = tokio::time::interval(period);
@@ -80,11 +139,35 @@ impl App {
         frame.render_widget(title, title_area);
         frame.render_widget(&self.tabs, body_area);
 
+        let prompt = match (&self.search, &self.notice) {
+            (Some(query), _) => Some(format!("/{query}")),
+            (None, notice) => notice.clone(),
+        };
+        if let Some(prompt) = prompt {
+            let [_, prompt_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(body_area);
+            frame.render_widget(Clear, prompt_area);
+            frame.render_widget(Line::from(prompt), prompt_area);
+        }
+
         if self.show_help {
             let block = Block::bordered().title("Help");
 
-            let vertical = Layout::vertical([Constraint::Length(20)]).flex(Flex::Center);
-            let horizontal = Layout::horizontal([Constraint::Length(50)]).flex(Flex::Center);
+            // Global keys first, then whatever the active tab handles.
+            let mut entries = GLOBAL_KEYS.to_vec();
+            if self.vim_keys.is_some() {
+                entries.extend(VIM_KEYS);
+            }
+            let tab_entries = self.tabs.help_entries();
+            if !tab_entries.is_empty() {
+                entries.push(("", ""));
+                entries.extend(tab_entries);
+            }
+
+            // Border, margins and the header row around the entries.
+            let height = entries.len() as u16 + 6;
+            let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+            let horizontal = Layout::horizontal([Constraint::Length(60)]).flex(Flex::Center);
 
             let [area] = vertical.areas(frame.area());
             let [area] = horizontal.areas(area);
@@ -97,17 +180,11 @@ impl App {
                 .collect::<Row>()
                 .style(Style::new().bold());
 
-            let rows = [
-                [Cell::from("?"), Cell::from("Show/hide this window")]
+            let rows = entries.into_iter().map(|(key, action)| {
+                [Cell::from(key), Cell::from(action)]
                     .into_iter()
-                    .collect::<Row>(),
-                [Cell::from("q"), Cell::from("Quit miniperf")]
-                    .into_iter()
-                    .collect::<Row>(),
-                [Cell::from("<tab>"), Cell::from("Switch tabs")]
-                    .into_iter()
-                    .collect::<Row>(),
-            ];
+                    .collect::<Row>()
+            });
 
             let vertical = Layout::vertical_margin(Layout::vertical([Constraint::Fill(1)]), 2);
             let horizontal =
@@ -116,7 +193,7 @@ impl App {
             let [table_area] = vertical.areas(area);
             let [table_area] = horizontal.areas(table_area);
 
-            let widths = [Constraint::Length(8), Constraint::Fill(1)];
+            let widths = [Constraint::Length(10), Constraint::Fill(1)];
             let t = Table::new(rows, widths).header(header);
             frame.render_widget(t, table_area);
         }
@@ -125,7 +202,32 @@ impl App {
     fn handle_event(&mut self, event: &crossterm::event::Event) {
         if let crossterm::event::Event::Key(key) = event {
             if key.kind == KeyEventKind::Press {
-                match key.code {
+                self.notice = None;
+                if let Some(query) = self.search.as_mut() {
+                    match key.code {
+                        KeyCode::Enter => {
+                            let query = std::mem::take(query);
+                            self.search = None;
+                            self.submit_search(query);
+                        }
+                        KeyCode::Esc => self.search = None,
+                        KeyCode::Backspace if query.is_empty() => self.search = None,
+                        KeyCode::Backspace => {
+                            query.pop();
+                        }
+                        KeyCode::Char(c) => query.push(c),
+                        _ => {}
+                    }
+                    return;
+                }
+                let code = match self.vim_keys.as_mut() {
+                    Some(keymap) => match keymap.translate(key.code) {
+                        Some(code) => code,
+                        None => return,
+                    },
+                    None => key.code,
+                };
+                match code {
                     KeyCode::Char('q') => self.should_quit = true,
                     KeyCode::Tab => {
                         if !self.show_help {
@@ -138,9 +240,15 @@ impl App {
                         }
                     }
                     KeyCode::Char('?') => self.show_help = !self.show_help,
+                    KeyCode::Char('/') if self.vim_keys.is_some() && !self.show_help => {
+                        self.search = Some(String::new())
+                    }
+                    KeyCode::Char('R') if !self.show_help => {
+                        self.tabs.reload(&self.res_dir, self.baseline.as_deref())
+                    }
                     _ => {
                         if !self.show_help {
-                            self.tabs.handle_event(key.code);
+                            self.tabs.handle_event(code);
                         }
                     }
                 }
@@ -149,12 +257,78 @@ impl App {
     }
 }
 
+impl App {
+    /// Searches the current tab for `query`, or for the last query when it is
+    /// empty, like vim's `/`.
+    fn submit_search(&mut self, query: String) {
+        let query = match (query.is_empty(), self.last_search.take()) {
+            (true, Some(last)) => last,
+            (true, None) => return,
+            (false, _) => query,
+        };
+        if !self.tabs.search(&query) {
+            self.notice = Some(format!("Pattern not found: {query}"));
+        }
+        self.last_search = Some(query);
+    }
+}
+
+/// Index of the first of `len` rows after `selected` that `matches`, wrapping
+/// around to the top like vim's `/`.
+fn next_match(
+    len: usize,
+    selected: Option<usize>,
+    matches: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let start = selected.map_or(0, |selected| selected + 1);
+    (0..len)
+        .map(|step| (start + step) % len)
+        .find(|&row| matches(row))
+}
+
+/// Translates vim motions to the keys the tabs navigate with.
+#[derive(Default)]
+struct VimKeymap {
+    /// Set by the first `g` of `gg`.
+    pending_g: bool,
+}
+
+impl VimKeymap {
+    /// `None` while waiting for the second key of `gg`.
+    fn translate(&mut self, code: KeyCode) -> Option<KeyCode> {
+        let pending_g = std::mem::take(&mut self.pending_g);
+        Some(match code {
+            KeyCode::Char('h') => KeyCode::Left,
+            KeyCode::Char('j') => KeyCode::Down,
+            KeyCode::Char('k') => KeyCode::Up,
+            KeyCode::Char('l') => KeyCode::Right,
+            KeyCode::Char('g') if pending_g => KeyCode::Home,
+            KeyCode::Char('g') => {
+                self.pending_g = true;
+                return None;
+            }
+            KeyCode::Char('G') => KeyCode::End,
+            code => code,
+        })
+    }
+}
+
 #[derive(Default, Clone)]
 struct TabsWidget {
     cur_tab: usize,
     tabs: Arc<RwLock<Vec<Tab>>>,
     load_started: Arc<AtomicBool>,
     load_error: Arc<RwLock<Option<String>>>,
+    /// Shared by the tabs; swapped for a fresh one on reload.
+    connection: Arc<RwLock<Option<Arc<Mutex<sqlite::Connection>>>>>,
+    /// Ordering from `show --sort`, overriding the scenario's.
+    sort: Option<OrderSpec>,
+    /// Rows of the flamegraph "Top" table rendered at once.
+    flamegraph_table_limit: Option<usize>,
+    /// Database to show instead of `perf.db`, until the tabs take it.
+    database: Arc<Mutex<Option<sqlite::Connection>>>,
+    /// Set when the database only exists in memory, so reloading keeps it.
+    in_memory: bool,
 }
 
 impl Widget for &TabsWidget {
@@ -198,7 +372,7 @@ impl Widget for &TabsWidget {
 
 #[derive(Clone)]
 enum Tab {
-    Summary(SummaryTab),
+    Summary(Box<SummaryTab>),
     MetricsTable(MetricsTableTab),
     Loops(LoopsTab),
     Flamegraph(FlamegraphTab),
@@ -214,6 +388,16 @@ impl Tab {
         }
     }
 
+    /// Keys the tab handles in its current view.
+    fn help_entries(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            Tab::Summary(_) => vec![],
+            Tab::MetricsTable(tab) => tab.help_entries(),
+            Tab::Loops(tab) => tab.help_entries(),
+            Tab::Flamegraph(tab) => tab.help_entries(),
+        }
+    }
+
     fn run(&self) {
         match self {
             Tab::Summary(summary) => summary.run(),
@@ -222,10 +406,19 @@ impl Tab {
             Tab::Flamegraph(fg) => fg.run(),
         }
     }
+
+    fn reload(&mut self) {
+        match self {
+            Tab::Summary(summary) => summary.reload(),
+            Tab::MetricsTable(table) => table.reload(),
+            Tab::Loops(loops) => loops.reload(),
+            Tab::Flamegraph(fg) => fg.reload(),
+        }
+    }
 }
 
 impl TabsWidget {
-    fn run(&self, res_dir: &Path) {
+    fn run(&self, res_dir: &Path, baseline: Option<&Path>) {
         {
             let read_tabs = self.tabs.read();
 
@@ -245,11 +438,12 @@ impl TabsWidget {
             return;
         }
         let this = self.clone();
-        tokio::spawn(this.fetch_data(res_dir.to_owned()));
+        tokio::spawn(this.fetch_data(res_dir.to_owned(), baseline.map(Path::to_owned)));
     }
 
-    async fn fetch_data(self, res_dir: PathBuf) {
-        let (info, connection) = match load_results(&res_dir).await {
+    async fn fetch_data(self, res_dir: PathBuf, baseline: Option<PathBuf>) {
+        let database = self.database.lock().take();
+        let (info, connection) = match load_results(&res_dir, database).await {
             Ok(results) => results,
             Err(error) => {
                 *self.load_error.write() = Some(format!(
@@ -259,30 +453,91 @@ impl TabsWidget {
                 return;
             }
         };
+        if let Some(baseline) = &baseline {
+            if let Err(error) = attach_baseline(&connection, baseline) {
+                *self.load_error.write() = Some(format!(
+                    "Could not open baseline directory '{}':\n\n{error:#}",
+                    baseline.display()
+                ));
+                return;
+            }
+        }
+        let ui = scenario_ui(&info);
+        let sorted_views = match &self.sort {
+            Some(sort) => match sorted_views(&connection, &ui, sort) {
+                Ok(views) => views,
+                Err(error) => {
+                    *self.load_error.write() = Some(error);
+                    return;
+                }
+            },
+            None => HashSet::new(),
+        };
+
         let connection = Arc::new(Mutex::new(connection));
+        *self.connection.write() = Some(connection.clone());
+        let ui_state = UiStateFile::load(&res_dir);
         let mut write_tabs = self.tabs.write();
 
-        let ui = scenario_ui(&info);
-
         for tab in ui.tabs.iter() {
             match tab {
-                pmu_data::TabSpec::Summary => write_tabs.push(Tab::Summary(SummaryTab::new(
+                TabSpec::Summary => write_tabs.push(Tab::Summary(Box::new(SummaryTab::new(
                     info.clone(),
                     connection.clone(),
-                ))),
-                pmu_data::TabSpec::Flamegraph => {
-                    write_tabs.push(Tab::Flamegraph(FlamegraphTab::new(res_dir.clone())))
-                }
-                pmu_data::TabSpec::Loops => {
+                )))),
+                TabSpec::Flamegraph => write_tabs.push(Tab::Flamegraph(
+                    FlamegraphTab::new(res_dir.clone())
+                        .with_ui_state(ui_state.clone())
+                        .with_table_limit(
+                            self.flamegraph_table_limit
+                                .unwrap_or(flamegraph::DEFAULT_TABLE_LIMIT),
+                        ),
+                )),
+                TabSpec::Loops => {
                     if matches!(info.scenario, Scenario::Roofline) {
                         write_tabs.push(Tab::Loops(LoopsTab::new(connection.clone())));
                     }
                 }
-                pmu_data::TabSpec::MetricsTable(spec) => write_tabs.push(Tab::MetricsTable(
-                    MetricsTableTab::new(spec.clone(), connection.clone()),
-                )),
+                TabSpec::MetricsTable(spec) => {
+                    let mut spec = spec.clone();
+                    if sorted_views.contains(&spec.view) {
+                        spec.order_by = self.sort.clone();
+                    }
+                    let mut tab = MetricsTableTab::new(spec.clone(), connection.clone())
+                        .with_ui_state(ui_state.clone())
+                        .with_export_dir(&res_dir);
+                    if baseline.is_some() && baseline_has(&connection.lock(), &spec.view) {
+                        tab = tab.with_baseline();
+                    }
+                    write_tabs.push(Tab::MetricsTable(tab));
+                }
+            }
+        }
+    }
+
+    /// Reopens `perf.db`, which `postprocess` may have replaced, and makes
+    /// every tab load its data again.
+    fn reload(&mut self, res_dir: &Path, baseline: Option<&Path>) {
+        let Some(connection) = self.connection.read().clone() else {
+            return;
+        };
+        if !self.in_memory {
+            match reopen_database(res_dir, baseline) {
+                Ok(fresh) => *connection.lock() = fresh,
+                Err(error) => tracing::warn!("failed to reopen the results: {error:#}"),
             }
         }
+        for tab in self.tabs.write().iter_mut() {
+            tab.reload();
+        }
+    }
+
+    fn help_entries(&self) -> Vec<(&'static str, &'static str)> {
+        self.tabs
+            .read()
+            .get(self.cur_tab)
+            .map(Tab::help_entries)
+            .unwrap_or_default()
     }
 
     fn next_tab(&mut self) {
@@ -306,6 +561,17 @@ impl TabsWidget {
         }
     }
 
+    /// Selects the next match of `query` in the current tab.
+    fn search(&mut self, query: &str) -> bool {
+        let tabs = self.tabs.read();
+        match tabs.get(self.cur_tab) {
+            Some(Tab::MetricsTable(tab)) => tab.search(query),
+            Some(Tab::Flamegraph(tab)) => tab.search(query),
+            Some(Tab::Loops(tab)) => tab.search(query),
+            Some(Tab::Summary(_)) | None => false,
+        }
+    }
+
     fn handle_event(&mut self, code: KeyCode) {
         let mut tabs = self.tabs.write();
         let Some(tab) = tabs.get_mut(self.cur_tab) else {
@@ -314,24 +580,93 @@ impl TabsWidget {
         match tab {
             Tab::MetricsTable(tab) => tab.handle_event(code),
             Tab::Flamegraph(tab) => tab.handle_event(code),
+            Tab::Loops(tab) => tab.handle_event(code),
             _ => {}
         }
     }
 }
 
-async fn load_results(res_dir: &Path) -> Result<(RecordInfo, sqlite::Connection)> {
+/// Reads `info.json` and opens `perf.db`, unless `database` is given.
+async fn load_results(
+    res_dir: &Path,
+    database: Option<sqlite::Connection>,
+) -> Result<(RecordInfo, sqlite::Connection)> {
     let info_path = res_dir.join("info.json");
     let data = fs::read_to_string(&info_path)
         .await
         .with_context(|| format!("failed to read {}", info_path.display()))?;
     let info = parse_record_info(&data)?;
 
-    let db_path = res_dir.join("perf.db");
-    let connection =
-        sqlite::open(&db_path).with_context(|| format!("failed to open {}", db_path.display()))?;
+    let connection = match database {
+        Some(connection) => connection,
+        None => crate::schema::open(&res_dir.join("perf.db"))?,
+    };
     Ok((info, connection))
 }
 
+fn reopen_database(res_dir: &Path, baseline: Option<&Path>) -> Result<sqlite::Connection> {
+    let connection = crate::schema::open(&res_dir.join("perf.db"))?;
+    if let Some(baseline) = baseline {
+        attach_baseline(&connection, baseline)?;
+    }
+    Ok(connection)
+}
+
+/// Attaches the `perf.db` of another results directory as `baseline`.
+fn attach_baseline(connection: &sqlite::Connection, baseline: &Path) -> Result<()> {
+    let db_path = baseline.join("perf.db");
+    // Opening it first rejects newer schemas and upgrades older ones.
+    drop(crate::schema::open(&db_path)?);
+    let mut statement = connection.prepare("ATTACH DATABASE ? AS baseline;")?;
+    statement.bind((1, db_path.to_string_lossy().as_ref()))?;
+    statement.next()?;
+    Ok(())
+}
+
+/// Views of the metrics tables in `ui` that have the column of `sort`. An
+/// error lists the columns to pick from when none has it.
+fn sorted_views(
+    connection: &sqlite::Connection,
+    ui: &ScenarioUi,
+    sort: &OrderSpec,
+) -> Result<HashSet<String>, String> {
+    let mut views = HashSet::new();
+    let mut columns = BTreeSet::new();
+    for tab in &ui.tabs {
+        let TabSpec::MetricsTable(spec) = tab else {
+            continue;
+        };
+        let Ok(statement) = connection.prepare(format!("SELECT * FROM {} LIMIT 0;", spec.view))
+        else {
+            continue;
+        };
+        let view_columns = statement.column_names().to_vec();
+        if view_columns.contains(&sort.column) {
+            views.insert(spec.view.clone());
+        }
+        columns.extend(view_columns);
+    }
+    if views.is_empty() {
+        return Err(format!(
+            "Cannot sort by '{}': no table has that column.\n\nColumns: {}",
+            sort.column,
+            columns.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    Ok(views)
+}
+
+/// Whether the attached baseline has a table or view named `name`.
+fn baseline_has(connection: &sqlite::Connection, name: &str) -> bool {
+    connection
+        .prepare("SELECT 1 FROM baseline.sqlite_master WHERE name = ?;")
+        .and_then(|mut statement| {
+            statement.bind((1, name))?;
+            statement.next()
+        })
+        .is_ok_and(|state| state == sqlite::State::Row)
+}
+
 fn parse_record_info(data: &str) -> Result<RecordInfo> {
     let info: RecordInfo =
         serde_json::from_str(data).context("failed to parse info.json metadata")?;
@@ -345,7 +680,7 @@ impl Widget for &Tab {
         Self: Sized,
     {
         match self {
-            Tab::Summary(tab) => tab.clone().render(area, buf),
+            Tab::Summary(tab) => (**tab).clone().render(area, buf),
             Tab::MetricsTable(tab) => tab.clone().render(area, buf),
             Tab::Loops(tab) => tab.clone().render(area, buf),
             Tab::Flamegraph(tab) => tab.clone().render(area, buf),
@@ -363,6 +698,34 @@ mod tests {
         )
     }
 
+    #[test]
+    fn vim_keys_map_to_the_navigation_keys() {
+        let mut keymap = VimKeymap::default();
+        assert_eq!(keymap.translate(KeyCode::Char('j')), Some(KeyCode::Down));
+        assert_eq!(keymap.translate(KeyCode::Char('l')), Some(KeyCode::Right));
+        assert_eq!(keymap.translate(KeyCode::Char('G')), Some(KeyCode::End));
+        assert_eq!(keymap.translate(KeyCode::Char('g')), None);
+        assert_eq!(keymap.translate(KeyCode::Char('g')), Some(KeyCode::Home));
+        // A lone `g` is dropped; the next key is handled as usual.
+        assert_eq!(keymap.translate(KeyCode::Char('g')), None);
+        assert_eq!(
+            keymap.translate(KeyCode::Char('m')),
+            Some(KeyCode::Char('m'))
+        );
+        assert_eq!(keymap.translate(KeyCode::Char('g')), None);
+    }
+
+    #[test]
+    fn search_wraps_around_from_the_selection() {
+        let rows = ["parse", "render", "parse_args"];
+        let matches = |row: usize| rows[row].contains("parse");
+        assert_eq!(next_match(rows.len(), None, matches), Some(0));
+        assert_eq!(next_match(rows.len(), Some(0), matches), Some(2));
+        assert_eq!(next_match(rows.len(), Some(2), matches), Some(0));
+        assert_eq!(next_match(rows.len(), Some(0), |_| false), None);
+        assert_eq!(next_match(0, None, matches), None);
+    }
+
     #[test]
     fn corrupt_metadata_is_reported() {
         let error = parse_record_info("not json").unwrap_err().to_string();
@@ -378,4 +741,52 @@ mod tests {
         assert!(error.contains(&format!("format version {version}")));
         assert!(error.contains("upgrade mperf"));
     }
+
+    #[test]
+    fn sort_applies_to_views_with_the_column() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection
+            .execute(
+                "CREATE TABLE hotspots (func_name TEXT, total REAL, cache_miss_rate REAL);
+                 CREATE TABLE module_hotspots (module TEXT, total REAL);",
+            )
+            .unwrap();
+        let table = |view: &str| {
+            TabSpec::MetricsTable(pmu_data::MetricsTableSpec {
+                view: view.to_string(),
+                title: None,
+                columns: Vec::new(),
+                include_default_columns: false,
+                sticky_columns: None,
+                order_by: None,
+                limit: None,
+                function_column: None,
+                enable_assembly: false,
+            })
+        };
+        let ui = ScenarioUi {
+            tabs: vec![
+                TabSpec::Summary,
+                table("hotspots"),
+                table("module_hotspots"),
+            ],
+        };
+        let sort = |column: &str| OrderSpec {
+            column: column.to_string(),
+            direction: pmu_data::SortDirection::Desc,
+        };
+
+        assert_eq!(
+            sorted_views(&connection, &ui, &sort("cache_miss_rate")),
+            Ok(HashSet::from(["hotspots".to_string()]))
+        );
+        assert_eq!(
+            sorted_views(&connection, &ui, &sort("total"))
+                .unwrap()
+                .len(),
+            2
+        );
+        let error = sorted_views(&connection, &ui, &sort("ipc")).unwrap_err();
+        assert!(error.contains("cache_miss_rate, func_name, module, total"));
+    }
 }
@@ -6,22 +6,37 @@ use std::{
     },
 };
 
-use mperf_data::RecordInfo;
-use num_format::Locale;
+use mperf_data::{
+    CoreCluster, FrequencyPolicy, RecordInfo, ScenarioInfo, SnapshotInfo, TargetStatus,
+};
 use num_format::ToFormattedString;
 use parking_lot::{Mutex, RwLock};
 use ratatui::{
     layout::{Constraint, Layout},
     style::{Style, Stylize},
-    widgets::{Block, Paragraph, Row, Table, Widget, Wrap},
+    widgets::{Block, Paragraph, Row, Sparkline, Table, Widget, Wrap},
 };
 use sqlite::Connection;
 
+use crate::{
+    locale,
+    postprocess::{parse_cpumask, MappingCoverage},
+    schema::DESCALE_DIVISOR,
+    sqlite_int::sqlite_u64_from_i64,
+};
+
 #[derive(Clone)]
 pub struct SummaryTab {
     record_info: RecordInfo,
     connection: Arc<Mutex<Connection>>,
     stat: Arc<RwLock<Stat>>,
+    threads: Arc<RwLock<Vec<ThreadRow>>>,
+    /// Distinct CPUs that took samples in each time bin of the run.
+    utilization: Arc<RwLock<Vec<u64>>>,
+    /// Share of cycles per core type on a heterogeneous host.
+    core_types: Arc<RwLock<Vec<(String, f64)>>>,
+    /// Samples outside every mapping, as counted by postprocessing.
+    coverage: Arc<RwLock<Option<MappingCoverage>>>,
     load_started: Arc<AtomicBool>,
     load_error: Arc<RwLock<Option<String>>>,
 }
@@ -39,12 +54,33 @@ struct Stat {
     initialized: bool,
 }
 
+#[derive(Debug, Clone)]
+struct ThreadRow {
+    pid: u32,
+    tid: u32,
+    name: Option<String>,
+    /// Arguments of the thread's process.
+    cmdline: Option<String>,
+    samples: u64,
+    total: Option<f64>,
+}
+
+/// Threads shown in the summary, busiest first.
+const MAX_SUMMARY_THREADS: usize = 32;
+
+/// Time bins of the CPU utilization timeline, merged further to fit the screen.
+const UTILIZATION_BINS: u64 = 256;
+
 impl SummaryTab {
     pub fn new(record_info: RecordInfo, connection: Arc<Mutex<Connection>>) -> Self {
         SummaryTab {
             record_info,
             connection,
             stat: Arc::new(RwLock::new(Stat::default())),
+            threads: Arc::new(RwLock::new(Vec::new())),
+            utilization: Arc::new(RwLock::new(Vec::new())),
+            core_types: Arc::new(RwLock::new(Vec::new())),
+            coverage: Arc::new(RwLock::new(None)),
             load_started: Arc::new(AtomicBool::new(false)),
             load_error: Arc::new(RwLock::new(None)),
         }
@@ -62,6 +98,13 @@ impl SummaryTab {
         tokio::spawn(this.fetch_data());
     }
 
+    /// Queries the summary again.
+    pub fn reload(&self) {
+        *self.load_error.write() = None;
+        self.load_started.store(false, Ordering::Release);
+        self.run();
+    }
+
     async fn fetch_data(self) {
         let conn = self.connection.lock();
         let result: Result<Stat, String> = (|| {
@@ -89,12 +132,10 @@ impl SummaryTab {
 
             if has_branch {
                 select_parts.push(
-                "CAST(SUM(pmu_branch_instructions * 1.0 / confidence) AS INTEGER) AS pmu_branch_instructions"
-                    .to_string(),
+                format!("CAST(SUM(pmu_branch_instructions * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_branch_instructions"),
             );
                 select_parts.push(
-                "CAST(SUM(pmu_branch_misses * 1.0 / confidence) AS INTEGER) AS pmu_branch_misses"
-                    .to_string(),
+                format!("CAST(SUM(pmu_branch_misses * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_branch_misses"),
             );
             } else {
                 select_parts.push("0 AS pmu_branch_instructions".to_string());
@@ -103,12 +144,10 @@ impl SummaryTab {
 
             if has_cache {
                 select_parts.push(
-                "CAST(SUM(pmu_llc_references * 1.0 / confidence) AS INTEGER) AS pmu_llc_references"
-                    .to_string(),
+                format!("CAST(SUM(pmu_llc_references * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_llc_references"),
             );
                 select_parts.push(
-                    "CAST(SUM(pmu_llc_misses * 1.0 / confidence) AS INTEGER) AS pmu_llc_misses"
-                        .to_string(),
+                    format!("CAST(SUM(pmu_llc_misses * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_llc_misses"),
                 );
             } else {
                 select_parts.push("0 AS pmu_llc_references".to_string());
@@ -117,12 +156,10 @@ impl SummaryTab {
 
             if has_stalled {
                 select_parts.push(
-                "CAST(SUM(pmu_stalled_cycles_frontend * 1.0 / confidence) AS INTEGER) AS pmu_stalled_cycles_frontend"
-                    .to_string(),
+                format!("CAST(SUM(pmu_stalled_cycles_frontend * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_stalled_cycles_frontend"),
             );
                 select_parts.push(
-                "CAST(SUM(pmu_stalled_cycles_backend * 1.0 / confidence) AS INTEGER) AS pmu_stalled_cycles_backend"
-                    .to_string(),
+                format!("CAST(SUM(pmu_stalled_cycles_backend * 1.0 / {DESCALE_DIVISOR}) AS INTEGER) AS pmu_stalled_cycles_backend"),
             );
             } else {
                 select_parts.push("0 AS pmu_stalled_cycles_frontend".to_string());
@@ -141,7 +178,7 @@ impl SummaryTab {
 
             let read = |name| {
                 row.try_read::<Option<i64>, _>(name)
-                    .map(|value| value.unwrap_or_default() as u64)
+                    .map(|value| sqlite_u64_from_i64(value.unwrap_or_default()))
                     .map_err(|error| error.to_string())
             };
             Ok(Stat {
@@ -162,7 +199,17 @@ impl SummaryTab {
                 initialized: true,
             })
         })();
+        // Databases from before thread-name support have no thread_stats view.
+        let threads = fetch_threads(&conn).unwrap_or_default();
+        // Databases from before per-sample CPUs have no cpu column.
+        let utilization = fetch_utilization(&conn).unwrap_or_default();
+        let core_types = fetch_core_types(&conn, &self.record_info.cores).unwrap_or_default();
+        let coverage = MappingCoverage::load(&conn);
         drop(conn);
+        *self.coverage.write() = coverage;
+        *self.threads.write() = threads;
+        *self.utilization.write() = utilization;
+        *self.core_types.write() = core_types;
 
         match result {
             Ok(stat) => *self.stat.write() = stat,
@@ -178,8 +225,13 @@ impl Widget for SummaryTab {
     where
         Self: Sized,
     {
+        let [area, timeline_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(7)]).areas(area);
+        self.render_utilization(timeline_area, buf);
+
         let horizontal = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]);
-        let [summary_area, _right_area] = horizontal.areas(area);
+        let [summary_area, right_area] = horizontal.areas(area);
+        self.render_threads(right_area, buf);
 
         let vertical = Layout::vertical_margin(
             Layout::vertical([Constraint::Fill(3), Constraint::Fill(1)]),
@@ -267,12 +319,12 @@ impl Widget for SummaryTab {
                 let rows = [
                     Row::new([
                         "Cycles".to_string(),
-                        stat.cycles.to_formatted_string(&Locale::en),
+                        stat.cycles.to_formatted_string(&locale::current()),
                         "".to_string(),
                     ]),
                     Row::new([
                         "Instructions".to_string(),
-                        stat.instructions.to_formatted_string(&Locale::en),
+                        stat.instructions.to_formatted_string(&locale::current()),
                         "".to_string(),
                     ]),
                     Row::new(["IPC".to_string(), ipc, "".to_string()]),
@@ -328,12 +380,52 @@ impl Widget for SummaryTab {
             .unwrap_or(vec!["".to_string()])
             .join(" ");
 
-        let rows = [
-            Row::new(["Scenario", self.record_info.scenario.name()]),
-            Row::new(["Command", command.as_str()]),
-            Row::new(["CPU family", self.record_info.cpu_model.as_str()]),
-            Row::new(["CPU vendor", self.record_info.cpu_vendor.as_str()]),
+        let mut rows = vec![
+            Row::new([
+                "Scenario".to_string(),
+                self.record_info.scenario.name().to_string(),
+            ]),
+            Row::new(["Command".to_string(), command]),
         ];
+        if let Some(directory) = &self.record_info.output_directory {
+            rows.push(Row::new([
+                "Recorded to".to_string(),
+                directory.display().to_string(),
+            ]));
+        }
+        if let Some(target) = &self.record_info.target {
+            rows.extend(target_rows(target).into_iter().map(Row::new));
+        }
+        rows.extend([
+            Row::new(["CPU family".to_string(), self.record_info.cpu_model.clone()]),
+            Row::new([
+                "CPU vendor".to_string(),
+                self.record_info.cpu_vendor.clone(),
+            ]),
+        ]);
+        if let ScenarioInfo::Snapshot(SnapshotInfo {
+            sampling_leader: Some(leader),
+            ..
+        }) = &self.record_info.scenario_info
+        {
+            rows.push(Row::new(["Sampling leader".to_string(), leader.clone()]));
+        }
+        let policies = &self.record_info.frequency_policies;
+        if !policies.is_empty() {
+            rows.push(Row::new(["CPU governor".to_string(), governors(policies)]));
+        }
+        if let Some(warning) = crate::cpufreq::governor_warning(policies) {
+            rows.push(Row::new(["Warning".to_string(), warning]).yellow());
+        }
+        if let Some(warning) = self.coverage.read().and_then(|coverage| coverage.warning()) {
+            rows.push(Row::new(["Warning".to_string(), warning]).yellow());
+        }
+        for (name, share) in self.core_types.read().iter() {
+            rows.push(Row::new([
+                name.clone(),
+                format!("{:.1}% of cycles", share * 100.0),
+            ]));
+        }
         let widths = [Constraint::Percentage(20), Constraint::Percentage(80)];
 
         let vertical = Layout::horizontal_margin(
@@ -347,9 +439,232 @@ impl Widget for SummaryTab {
     }
 }
 
+impl SummaryTab {
+    fn render_utilization(&self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        let utilization = self.utilization.read();
+        let peak = utilization.iter().copied().max().unwrap_or(0);
+        let block = Block::bordered().title(format!("CPU utilization (peak {peak} CPUs)"));
+        if utilization.is_empty() {
+            if self.stat.read().initialized {
+                Paragraph::new("No per-CPU samples")
+                    .block(block)
+                    .render(area, buf);
+            } else {
+                block.render(area, buf);
+            }
+            return;
+        }
+
+        let width = block.inner(area).width as usize;
+        Sparkline::default()
+            .block(block)
+            .data(fit_to_width(&utilization, width))
+            .max(peak)
+            .green()
+            .render(area, buf);
+    }
+}
+
+impl SummaryTab {
+    fn render_threads(&self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        let [area] =
+            Layout::vertical_margin(Layout::vertical([Constraint::Fill(1)]), 1).areas(area);
+        Block::bordered().title("Threads").render(area, buf);
+        let [table_area] = Layout::horizontal_margin(
+            Layout::vertical_margin(Layout::vertical([Constraint::Fill(1)]), 1),
+            2,
+        )
+        .areas(area);
+
+        let threads = self.threads.read();
+        if threads.is_empty() {
+            if self.stat.read().initialized {
+                Paragraph::new("No per-thread samples")
+                    .wrap(Wrap { trim: true })
+                    .render(table_area, buf);
+            }
+            return;
+        }
+
+        let header = Row::new(["PID", "TID", "Name", "Command", "Samples", "Cycles %"]).bold();
+        let rows = threads.iter().map(|thread| {
+            Row::new([
+                thread.pid.to_string(),
+                thread.tid.to_string(),
+                thread.name.clone().unwrap_or_else(|| "-".to_string()),
+                thread.cmdline.clone().unwrap_or_else(|| "-".to_string()),
+                thread.samples.to_formatted_string(&locale::current()),
+                thread
+                    .total
+                    .map(|total| format!("{:.2}%", total * 100.0))
+                    .unwrap_or_else(|| "N/A".to_string()),
+            ])
+        });
+        let widths = [
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Fill(1),
+            Constraint::Length(12),
+            Constraint::Length(9),
+        ];
+        Table::new(rows, widths)
+            .header(header)
+            .column_spacing(1)
+            .render(table_area, buf);
+    }
+}
+
+/// Counts the distinct CPUs seen in each of [`UTILIZATION_BINS`] equal slices
+/// of the sampled time range. Empty when no sample recorded its CPU.
+fn fetch_utilization(conn: &Connection) -> Result<Vec<u64>, sqlite::Error> {
+    let mut range = conn.prepare(
+        "SELECT MIN(timestamp), MAX(timestamp) FROM pmu_counters WHERE cpu IS NOT NULL;",
+    )?;
+    range.next()?;
+    let (Some(start), Some(end)) = (
+        range.read::<Option<i64>, _>(0)?,
+        range.read::<Option<i64>, _>(1)?,
+    ) else {
+        return Ok(Vec::new());
+    };
+    let bin_width = (end - start) as u64 / UTILIZATION_BINS + 1;
+    let bins = (end - start) as u64 / bin_width + 1;
+
+    let mut statement = conn.prepare(format!(
+        "SELECT (timestamp - {start}) / {bin_width} AS bin, COUNT(DISTINCT cpu) AS cpus
+         FROM pmu_counters WHERE cpu IS NOT NULL GROUP BY bin;"
+    ))?;
+    let mut utilization = vec![0; bins as usize];
+    while let sqlite::State::Row = statement.next()? {
+        let bin = statement.read::<i64, _>("bin")? as usize;
+        utilization[bin] = statement.read::<i64, _>("cpus")? as u64;
+    }
+    Ok(utilization)
+}
+
+/// Splits the sampled cycles between the core types of a heterogeneous host,
+/// e.g. P-cores and E-cores. Empty on homogeneous hosts.
+fn fetch_core_types(
+    conn: &Connection,
+    cores: &[CoreCluster],
+) -> Result<Vec<(String, f64)>, sqlite::Error> {
+    if cores.len() <= 1 {
+        return Ok(Vec::new());
+    }
+    let mut statement = conn.prepare(
+        "SELECT cpu, SUM(pmu_cycles) AS cycles FROM pmu_counters
+         WHERE cpu IS NOT NULL GROUP BY cpu;",
+    )?;
+    let ranges = cores
+        .iter()
+        .map(|core| parse_cpumask(&core.cpus))
+        .collect::<Vec<_>>();
+    let mut cycles = vec![0_u64; cores.len()];
+    while let sqlite::State::Row = statement.next()? {
+        let cpu = statement.read::<i64, _>("cpu")? as u32;
+        let value = sqlite_u64_from_i64(statement.read::<Option<i64>, _>("cycles")?.unwrap_or(0));
+        if let Some(index) = ranges.iter().position(|ranges| {
+            ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&cpu))
+        }) {
+            cycles[index] += value;
+        }
+    }
+    let total = cycles.iter().sum::<u64>();
+    if total == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(cores
+        .iter()
+        .zip(cycles)
+        .map(|(core, cycles)| (core.name.clone(), cycles as f64 / total as f64))
+        .collect())
+}
+
+/// Shrinks `bins` to at most `width` values, keeping the peak of each group so
+/// short parallel phases stay visible.
+fn fit_to_width(bins: &[u64], width: usize) -> Vec<u64> {
+    if width == 0 || bins.len() <= width {
+        return bins.to_vec();
+    }
+    let group = bins.len().div_ceil(width);
+    bins.chunks(group)
+        .map(|chunk| chunk.iter().copied().max().unwrap_or(0))
+        .collect()
+}
+
+fn fetch_threads(conn: &Connection) -> Result<Vec<ThreadRow>, sqlite::Error> {
+    let mut statement = conn.prepare(format!(
+        "SELECT thread_stats.pid AS pid, tid, name, cmdline, samples, total
+         FROM thread_stats
+         LEFT JOIN process_cmdlines ON process_cmdlines.pid = thread_stats.pid
+         ORDER BY samples DESC, tid LIMIT {MAX_SUMMARY_THREADS};"
+    ))?;
+    let mut threads = Vec::new();
+    while let sqlite::State::Row = statement.next()? {
+        threads.push(ThreadRow {
+            pid: statement.read::<i64, _>("pid")? as u32,
+            tid: statement.read::<i64, _>("tid")? as u32,
+            name: statement.read::<Option<String>, _>("name")?,
+            cmdline: statement.read::<Option<String>, _>("cmdline")?,
+            samples: statement.read::<i64, _>("samples")? as u64,
+            total: statement.read::<Option<f64>, _>("total")?,
+        });
+    }
+    Ok(threads)
+}
+
+/// Summary rows describing how the profiled command ended.
+fn target_rows(target: &TargetStatus) -> Vec<[String; 2]> {
+    let exit = if target.exit_code > 128 {
+        format!(
+            "{} (killed by signal {})",
+            target.exit_code,
+            target.exit_code - 128
+        )
+    } else {
+        target.exit_code.to_string()
+    };
+    let mut rows = vec![["Exit code".to_string(), exit]];
+    if let Some(bytes) = target.max_rss_bytes {
+        rows.push([
+            "Max RSS".to_string(),
+            format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        ]);
+    }
+    if let (Some(user), Some(system)) = (target.user_time_us, target.system_time_us) {
+        rows.push([
+            "CPU time".to_string(),
+            format!(
+                "{:.2} s user, {:.2} s system",
+                user as f64 / 1e6,
+                system as f64 / 1e6
+            ),
+        ]);
+    }
+    rows
+}
+
+/// Each governor with the CPUs and clock range it applied to.
+fn governors(policies: &[FrequencyPolicy]) -> String {
+    policies
+        .iter()
+        .map(|policy| {
+            let range = match (policy.min_khz, policy.max_khz) {
+                (Some(min), Some(max)) => format!(", {}-{} MHz", min / 1000, max / 1000),
+                _ => String::new(),
+            };
+            format!("{} (CPUs {}{range})", policy.governor, policy.cpus)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 fn format_optional_count(value: Option<u64>) -> String {
     value
-        .map(|v| v.to_formatted_string(&Locale::en))
+        .map(|v| v.to_formatted_string(&locale::current()))
         .unwrap_or_else(|| "N/A".to_string())
 }
 
@@ -359,3 +674,80 @@ fn format_optional_ratio(value: Option<u64>, total: u64) -> String {
         _ => "N/A".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_rows_show_exit_and_usage() {
+        let rows = target_rows(&TargetStatus {
+            exit_code: 137,
+            max_rss_bytes: Some(3 * 1024 * 1024 / 2),
+            user_time_us: Some(1_250_000),
+            system_time_us: Some(40_000),
+        });
+        assert_eq!(
+            rows,
+            [
+                ["Exit code", "137 (killed by signal 9)"],
+                ["Max RSS", "1.5 MiB"],
+                ["CPU time", "1.25 s user, 0.04 s system"],
+            ]
+            .map(|row| row.map(String::from))
+        );
+    }
+
+    #[test]
+    fn utilization_counts_distinct_cpus_per_bin() {
+        let conn = sqlite::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE pmu_counters (timestamp INTEGER NOT NULL, cpu INTEGER);
+             INSERT INTO pmu_counters VALUES (0, 0), (0, 0), (0, 1), (1000, 2), (1000, NULL);",
+        )
+        .unwrap();
+
+        let utilization = fetch_utilization(&conn).unwrap();
+        assert_eq!(utilization.first(), Some(&2));
+        assert_eq!(utilization.last(), Some(&1));
+        assert_eq!(utilization.iter().sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn cycles_are_split_between_core_types() {
+        let conn = sqlite::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE pmu_counters (cpu INTEGER, pmu_cycles INTEGER);
+             INSERT INTO pmu_counters VALUES (0, 300), (3, 300), (4, 200), (9, 1000), (NULL, 50);",
+        )
+        .unwrap();
+        let cores = [
+            CoreCluster {
+                family_id: "cpu_core".to_string(),
+                name: "Intel P-core".to_string(),
+                cpus: "0-3".to_string(),
+            },
+            CoreCluster {
+                family_id: "cpu_atom".to_string(),
+                name: "Intel E-core".to_string(),
+                cpus: "4-7".to_string(),
+            },
+        ];
+
+        let core_types = fetch_core_types(&conn, &cores).unwrap();
+        assert_eq!(
+            core_types,
+            [
+                ("Intel P-core".to_string(), 0.75),
+                ("Intel E-core".to_string(), 0.25)
+            ]
+        );
+        assert!(fetch_core_types(&conn, &cores[..1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn timeline_keeps_peaks_when_narrowed() {
+        assert_eq!(fit_to_width(&[1, 4, 2, 2, 3], 3), [4, 2, 3]);
+        assert_eq!(fit_to_width(&[1, 2], 10), [1, 2]);
+    }
+}
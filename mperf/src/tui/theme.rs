@@ -0,0 +1,57 @@
+use ratatui::style::Color;
+
+/// Colors and thresholds shared by the result tabs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub ipc: IpcThresholds,
+}
+
+/// IPC below `low` is drawn as stall-bound, above `high` as healthy, and
+/// anything in between as a warning.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IpcThresholds {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            // Modern x86 cores retire four or more instructions per cycle;
+            // sustained IPC under one usually means the core waits on memory.
+            ipc: IpcThresholds {
+                low: 1.0,
+                high: 2.0,
+            },
+        }
+    }
+}
+
+impl IpcThresholds {
+    pub fn color(&self, ipc: f64) -> Option<Color> {
+        if !ipc.is_finite() {
+            None
+        } else if ipc < self.low {
+            Some(Color::Red)
+        } else if ipc <= self.high {
+            Some(Color::Yellow)
+        } else {
+            Some(Color::Green)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipc_colors_follow_thresholds() {
+        let ipc = Theme::default().ipc;
+        assert_eq!(ipc.color(0.8), Some(Color::Red));
+        assert_eq!(ipc.color(1.0), Some(Color::Yellow));
+        assert_eq!(ipc.color(2.0), Some(Color::Yellow));
+        assert_eq!(ipc.color(2.5), Some(Color::Green));
+        assert_eq!(ipc.color(f64::NAN), None);
+    }
+}
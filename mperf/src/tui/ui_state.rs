@@ -0,0 +1,94 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use super::flamegraph::FrameNameElision;
+
+/// Viewer choices kept next to the results in `ui_state.json`, so they
+/// survive restarting `mperf show`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UiState {
+    /// Visible columns of each metrics table, keyed by the view it reads.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub columns: BTreeMap<String, Vec<String>>,
+    /// How the flamegraph shortens frame names that do not fit.
+    #[serde(default)]
+    pub frame_names: FrameNameElision,
+    /// Functions bookmarked in the metrics tables, by `func_name`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub bookmarks: BTreeSet<String>,
+}
+
+/// Shared handle to the UI state of one results directory.
+#[derive(Debug, Clone)]
+pub struct UiStateFile {
+    path: PathBuf,
+    state: Arc<Mutex<UiState>>,
+}
+
+impl UiStateFile {
+    /// Loads `ui_state.json` from `res_dir`. A missing or unreadable file
+    /// starts from the defaults.
+    pub fn load(res_dir: &Path) -> Self {
+        let path = res_dir.join("ui_state.json");
+        let state = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| match serde_json::from_str(&data) {
+                Ok(state) => Some(state),
+                Err(err) => {
+                    tracing::warn!("ignoring {}: {err}", path.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+        UiStateFile {
+            path,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn get(&self) -> UiState {
+        self.state.lock().clone()
+    }
+
+    /// Applies `change` and writes the file. A failed write is logged; the
+    /// change still applies for the rest of the session.
+    pub fn update(&self, change: impl FnOnce(&mut UiState)) {
+        let mut state = self.state.lock();
+        change(&mut state);
+        let result = serde_json::to_string_pretty(&*state)
+            .map_err(std::io::Error::other)
+            .and_then(|data| std::fs::write(&self.path, data));
+        if let Err(err) = result {
+            tracing::warn!("failed to save {}: {err}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips_through_the_results_directory() {
+        let dir = std::env::temp_dir().join(format!("mperf-ui-state-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let file = UiStateFile::load(&dir);
+        assert_eq!(file.get(), UiState::default());
+        file.update(|state| {
+            state
+                .columns
+                .insert("hotspots".to_string(), vec!["func_name".to_string()]);
+            state.bookmarks.insert("main".to_string());
+        });
+
+        assert_eq!(UiStateFile::load(&dir).get(), file.get());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
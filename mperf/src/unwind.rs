@@ -64,9 +64,11 @@ impl PostHocUnwinder {
     }
 
     /// Apply the milestone fallback chain: DWARF, sampled callchain, then raw IP.
-    pub(crate) fn unwind_event(&mut self, event: &mut Event) {
+    /// `image` selects the modules, like the `pid` of the mappings passed to
+    /// [`Self::new`].
+    pub(crate) fn unwind_event(&mut self, event: &mut Event, image: u32) {
         if event.user_regs.is_some() {
-            if let Some(stack) = self.unwind(event) {
+            if let Some(stack) = self.unwind(event, image) {
                 event.callstack = stack.into_iter().map(CallFrame::IP).collect();
             } else if event.callstack.is_empty() {
                 if let Some(ip) = event.user_regs.as_ref().and_then(instruction_pointer) {
@@ -81,7 +83,7 @@ impl PostHocUnwinder {
         }
     }
 
-    fn unwind(&mut self, event: &Event) -> Option<Vec<u64>> {
+    fn unwind(&mut self, event: &Event, image: u32) -> Option<Vec<u64>> {
         let regs = event.user_regs.as_ref()?;
         if event.user_stack.is_empty() {
             return None;
@@ -89,8 +91,8 @@ impl PostHocUnwinder {
         let initial_regs = native_regs(regs)?;
         let stack_pointer = native_stack_pointer(regs)?;
         let pc = instruction_pointer(regs)?;
-        let unwinder = self.unwinders.get(&event.process_id)?;
-        let cache = self.caches.entry(event.process_id).or_default();
+        let unwinder = self.unwinders.get(&image)?;
+        let cache = self.caches.entry(image).or_default();
         let stack = &event.user_stack;
         let mut read_stack = |address: u64| -> Result<u64, ()> {
             let offset = address.checked_sub(stack_pointer).ok_or(())? as usize;
@@ -1,6 +1,10 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use mperf_data::{EventType, ProcMapEntry};
 use pmu::Counter;
-use symbolize::{ProcessMap, Resolver};
+use symbolize::{BuildIdCache, ProcessMap, Resolver};
+
+use crate::event_dispatcher::ProcMapBuilder;
 
 pub fn counter_to_event_ty(counter: &Counter) -> EventType {
     match counter {
@@ -16,21 +20,97 @@ pub fn counter_to_event_ty(counter: &Counter) -> EventType {
         Counter::PageFaults => EventType::OsPageFaults,
         Counter::CpuMigrations => EventType::OsCpuMigrations,
         Counter::ContextSwitches => EventType::OsContextSwitches,
+        Counter::RefCycles
+        | Counter::L1DReferences
+        | Counter::L1DMisses
+        | Counter::L1IMisses
+        | Counter::DTLBMisses
+        | Counter::ITLBMisses => EventType::PmuCustom,
         Counter::Custom(_) => EventType::PmuCustom,
         Counter::Internal {
             name: _,
             desc: _,
             code: _,
         } => EventType::PmuCustom,
+        Counter::Raw { .. } => EventType::PmuCustom,
     }
 }
 
-pub fn resolve_proc_maps(proc_maps: &[ProcMapEntry]) -> Resolver {
-    Resolver::new(proc_maps.iter().map(|map| ProcessMap {
+/// Name stored with the events of `counter`, for counters without an event
+/// type of their own; its column is named after it.
+pub fn custom_counter_name(counter: &Counter) -> Option<&str> {
+    (counter_to_event_ty(counter) == EventType::PmuCustom).then(|| counter.name())
+}
+
+/// Name of the perf map that JITs such as the JVM, V8 and .NET write to
+/// `/tmp` with the symbols of the code they generate in `pid`. Recordings
+/// keep a copy under the same name.
+pub fn perf_map_file_name(pid: u32) -> String {
+    format!("perf-{pid}.map")
+}
+
+/// Builds a symbol resolver over `proc_maps`. The entries are replayed in
+/// order, so where mappings overlap the most recent one wins, and the
+/// resolver sees them sorted by address regardless of how they were stored.
+/// Samples in anonymous mappings are looked up in `perf_maps`, keyed like
+/// the entries' pids.
+pub fn resolve_proc_maps(
+    proc_maps: &[ProcMapEntry],
+    debug_dirs: &[PathBuf],
+    perf_maps: &HashMap<u32, PathBuf>,
+) -> Resolver {
+    let mut builder = ProcMapBuilder::default();
+    for entry in proc_maps {
+        builder.insert(entry.clone());
+    }
+    let maps = builder.finish().into_iter().map(|map| ProcessMap {
         pid: map.pid,
         path: map.filename.clone().into(),
         start: map.address as u64,
         end: map.address.saturating_add(map.size) as u64,
         offset: map.offset as u64,
-    }))
+    });
+    let mut resolver = Resolver::with_debug_dirs(maps, BuildIdCache::default(), debug_dirs);
+    for (pid, path) in perf_maps {
+        if let Err(err) = resolver.load_perf_map(*pid, path) {
+            tracing::warn!(
+                "failed to read the JIT symbols in {}: {err}",
+                path.display()
+            );
+        }
+    }
+    resolver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn overlapping_mappings_resolve_to_the_most_recent_one() {
+        let mapping = |filename: &str, address: usize, size: usize| ProcMapEntry {
+            filename: filename.to_string(),
+            address,
+            size,
+            offset: 0,
+            pid: 7,
+            build_id: None,
+            exec_generation: 0,
+        };
+        let maps = [
+            mapping("/tmp/jit-2", 0x2000, 0x1000),
+            mapping("/tmp/jit-1", 0x1000, 0x4000),
+            mapping("/tmp/jit-3", 0x3000, 0x1000),
+        ];
+
+        let resolver = resolve_proc_maps(&maps, &[], &HashMap::new());
+        let module = |ip| resolver.module_path(7, ip);
+        assert_eq!(module(0x1800), Some(Path::new("/tmp/jit-1")));
+        assert_eq!(module(0x2800), Some(Path::new("/tmp/jit-1")));
+        assert_eq!(module(0x3800), Some(Path::new("/tmp/jit-3")));
+        assert_eq!(module(0x4800), Some(Path::new("/tmp/jit-1")));
+        assert_eq!(module(0x5800), None);
+    }
 }
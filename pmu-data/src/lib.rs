@@ -6,6 +6,7 @@ pub mod arith_parser;
 
 use std::collections::{BTreeSet, HashMap};
 
+use num_format::{Locale, ToFormattedString};
 use serde::{de, Deserialize, Serialize};
 
 // Well-known CPU families
@@ -286,6 +287,98 @@ pub enum ValueFormat {
     Percent3,
 }
 
+/// A single metrics-table value, as read from SQLite.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A text value.
+    Text(String),
+    /// An integer value.
+    Integer(i64),
+    /// A floating-point value.
+    Float(f64),
+    /// A missing value.
+    Null,
+}
+
+impl Value {
+    /// The value as text, if it is text.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer. Floats are truncated.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(v) => Some(*v),
+            Value::Float(v) => Some(*v as i64),
+            _ => None,
+        }
+    }
+
+    /// The value as a float.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(v) => Some(*v),
+            Value::Integer(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Renders `value` the way `format` asks for. Integers get thousands
+/// separators; values that do not fit the format render as `N/A`.
+pub fn format(value: &Value, format: &ValueFormat) -> String {
+    format_localized(value, format, &Locale::en)
+}
+
+/// [`format`] with the thousands separator and decimal point of `locale`.
+pub fn format_localized(value: &Value, format: &ValueFormat, locale: &Locale) -> String {
+    let format_number = |value: f64, precision: usize| format_number(value, precision, locale);
+    match format {
+        ValueFormat::Text => value.as_text().unwrap_or("N/A").to_string(),
+        ValueFormat::Integer => value
+            .as_integer()
+            .map(|v| v.to_formatted_string(locale))
+            .unwrap_or_else(|| "N/A".to_string()),
+        ValueFormat::Auto => match value {
+            Value::Text(text) => text.clone(),
+            Value::Integer(value) => value.to_formatted_string(locale),
+            Value::Float(value) => format_number(*value, 2),
+            Value::Null => "N/A".to_string(),
+        },
+        ValueFormat::Float | ValueFormat::Float1 | ValueFormat::Float2 | ValueFormat::Float3 => {
+            value
+                .as_float()
+                .map(|v| format_number(v, format.precision()))
+                .unwrap_or_else(|| "N/A".to_string())
+        }
+        ValueFormat::Percent
+        | ValueFormat::Percent1
+        | ValueFormat::Percent2
+        | ValueFormat::Percent3 => value
+            .as_float()
+            .map(|v| format!("{}%", format_number(v * 100.0, format.precision())))
+            .unwrap_or_else(|| "N/A".to_string()),
+    }
+}
+
+impl ValueFormat {
+    fn precision(&self) -> usize {
+        match self {
+            ValueFormat::Float1 | ValueFormat::Percent1 => 1,
+            ValueFormat::Float3 | ValueFormat::Percent3 => 3,
+            _ => 2,
+        }
+    }
+}
+
+fn format_number(value: f64, precision: usize, locale: &Locale) -> String {
+    format!("{value:.precision$}").replacen('.', locale.decimal(), 1)
+}
+
 const fn default_true() -> bool {
     true
 }
@@ -591,6 +684,34 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn values_are_formatted_by_their_column_format() {
+        assert_eq!(
+            format(&Value::Integer(1_234_567), &ValueFormat::Integer),
+            "1,234,567"
+        );
+        assert_eq!(format(&Value::Float(12.9), &ValueFormat::Integer), "12");
+        assert_eq!(
+            format(&Value::Float(0.12345), &ValueFormat::Percent1),
+            "12.3%"
+        );
+        assert_eq!(format(&Value::Integer(2), &ValueFormat::Float3), "2.000");
+        assert_eq!(format(&Value::Float(1.0 / 3.0), &ValueFormat::Auto), "0.33");
+        assert_eq!(format(&Value::Null, &ValueFormat::Float), "N/A");
+        assert_eq!(format(&Value::Integer(7), &ValueFormat::Text), "N/A");
+    }
+
+    #[test]
+    fn values_use_the_separators_of_the_locale() {
+        let de = |value, format| format_localized(&value, &format, &Locale::de);
+        assert_eq!(
+            de(Value::Integer(1_234_567), ValueFormat::Integer),
+            "1.234.567"
+        );
+        assert_eq!(de(Value::Float(0.12345), ValueFormat::Percent1), "12,3%");
+        assert_eq!(de(Value::Float(-2.5), ValueFormat::Auto), "-2,50");
+    }
+
     #[test]
     fn evaluates_metric_expression_with_precedence_and_parentheses() {
         let values = HashMap::from([
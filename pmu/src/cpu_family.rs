@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use lazy_static::lazy_static;
 use pmu_data::{EventDesc, Metric, TmaScenario};
 
+use crate::{Counter, Error};
+
 #[allow(dead_code)]
 pub struct CPUFamily {
     pub name: String,
@@ -22,6 +24,57 @@ pub fn find_cpu_family(id: &str) -> Option<&CPUFamily> {
     CPU_FAMILIES.get(id)
 }
 
+/// Resolves scenario event names into counters for `family_id`.
+///
+/// Portable counters keep their generic form; any other name must be an event
+/// of the family (case-insensitively) or an alias of one. Resolved events keep
+/// the scenario spelling as their name because metric formulas refer to it.
+pub fn resolve_scenario_events(family_id: &str, names: &[String]) -> Result<Vec<Counter>, Error> {
+    let family = find_cpu_family(family_id);
+    names
+        .iter()
+        .map(|name| {
+            let portable = match name.as_str() {
+                "cycles" => Some(Counter::Cycles),
+                "instructions" => Some(Counter::Instructions),
+                "stalled_cycles_frontend" => Some(Counter::StalledCyclesFrontend),
+                "stalled_cycles_backend" => Some(Counter::StalledCyclesBackend),
+                _ => None,
+            };
+            if let Some(counter) = portable {
+                return Ok(counter);
+            }
+            let event = family.and_then(|family| {
+                family
+                    .events
+                    .get(name)
+                    .or_else(|| {
+                        family
+                            .events
+                            .values()
+                            .find(|event| event.name.eq_ignore_ascii_case(name))
+                    })
+                    .or_else(|| {
+                        family
+                            .aliases
+                            .get(name)
+                            .and_then(|origin| family.events.get(origin))
+                    })
+            });
+            event
+                .map(|event| Counter::Internal {
+                    name: name.clone(),
+                    desc: event.desc.clone(),
+                    code: event.code,
+                })
+                .ok_or_else(|| Error::UnsupportedCounter {
+                    counter: name.clone(),
+                    family: family_id.to_owned(),
+                })
+        })
+        .collect()
+}
+
 /// Returns derived PMU metrics defined for the detected host family.
 pub fn host_metrics() -> Vec<Metric> {
     find_cpu_family(get_host_cpu_family())
@@ -103,6 +156,13 @@ pub fn host_max_counters() -> Option<usize> {
     find_cpu_family(get_host_cpu_family()).and_then(|family| family.max_counters)
 }
 
+/// Whether the host core PMU has the PEBS `MEM_TRANS_RETIRED.LOAD_LATENCY`
+/// event (every Intel core since Nehalem).
+pub fn host_supports_load_latency() -> bool {
+    cfg!(all(target_arch = "x86_64", target_os = "linux"))
+        && find_cpu_family(get_host_cpu_family()).is_some_and(|family| family.vendor == "Intel")
+}
+
 #[cfg(target_arch = "x86_64")]
 pub fn get_host_cpu_family() -> &'static str {
     const EAX_VENDOR_INFO: u32 = 0x1;
@@ -182,6 +242,29 @@ mod x86_tests {
         (model << 4) | (family << 8) | (extended_model << 16) | (extended_family << 20)
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detects_hybrid_core_pmus_from_sysfs() {
+        let devices = std::env::temp_dir().join(format!("mperf-pmus-{}", std::process::id()));
+        for (name, cpus, pmu_type) in [("cpu_core", "0-7\n", "4\n"), ("cpu_atom", "8-15\n", "8\n")]
+        {
+            std::fs::create_dir_all(devices.join(name)).unwrap();
+            std::fs::write(devices.join(name).join("cpus"), cpus).unwrap();
+            std::fs::write(devices.join(name).join("type"), pmu_type).unwrap();
+        }
+
+        let pmus = x86_hybrid_core_pmus(&devices);
+        std::fs::remove_dir_all(&devices).unwrap();
+
+        assert_eq!(pmus.len(), 2);
+        assert_eq!((pmus[0].family_id, pmus[0].pmu_type), (INTEL_P_CORE, 4));
+        assert_eq!(pmus[0].cpus, "0-7");
+        assert_eq!((pmus[1].family_id, pmus[1].pmu_type), (INTEL_E_CORE, 8));
+        assert_eq!(core_family_name(pmus[1].family_id), "Intel E-core");
+
+        assert!(x86_hybrid_core_pmus(&devices).is_empty());
+    }
+
     #[test]
     fn maps_both_tiger_lake_models() {
         assert_eq!(
@@ -194,6 +277,32 @@ mod x86_tests {
         );
     }
 
+    #[test]
+    fn resolves_scenario_events_through_table_and_aliases() {
+        let names = ["cycles", "l1d.replacement", "cache_misses"].map(str::to_owned);
+        let counters = resolve_scenario_events(pmu_data::INTEL_TIGERLAKE, &names).unwrap();
+        assert_eq!(counters[0], Counter::Cycles);
+        assert!(matches!(
+            &counters[1],
+            Counter::Internal { name, code: 0x151, .. } if name == "l1d.replacement"
+        ));
+        let miss_code = find_cpu_family(pmu_data::INTEL_TIGERLAKE).unwrap().events
+            ["LONGEST_LAT_CACHE.MISS"]
+            .code;
+        assert!(matches!(
+            &counters[2],
+            Counter::Internal { name, code, .. } if name == "cache_misses" && *code == miss_code
+        ));
+
+        let error =
+            resolve_scenario_events(pmu_data::INTEL_TIGERLAKE, &["NOT.AN_EVENT".to_owned()])
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "counter 'NOT.AN_EVENT' is not available for CPU family 'tigerlake'"
+        );
+    }
+
     #[test]
     fn tiger_lake_event_table_is_loaded() {
         let family = find_cpu_family(pmu_data::INTEL_TIGERLAKE).unwrap();
@@ -390,15 +499,24 @@ pub fn host_pmu_type() -> Option<u32> {
     None
 }
 
+/// Core type id of the performance cores on an Intel hybrid CPU, named after
+/// their sysfs PMU.
+pub const INTEL_P_CORE: &str = "cpu_core";
+/// Core type id of the efficiency cores on an Intel hybrid CPU.
+pub const INTEL_E_CORE: &str = "cpu_atom";
+
 /// A hardware performance-monitoring unit backing one cluster of cores on a
 /// (possibly heterogeneous) system, together with the CPU family it implements.
 #[derive(Clone, Debug)]
 pub struct CorePmu {
     /// Dynamic `perf_event` PMU `type` id read from sysfs.
-    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    #[cfg(all(
+        any(target_arch = "aarch64", target_arch = "x86_64"),
+        target_os = "linux"
+    ))]
     pub pmu_type: u32,
-    /// Known family id (e.g. `"cortex_a720"`), or `"unknown"` for a cluster we
-    /// have no event data for.
+    /// Known family id (e.g. `"cortex_a720"`), [`INTEL_P_CORE`] or
+    /// [`INTEL_E_CORE`], or `"unknown"` for a cluster we have no event data for.
     pub family_id: &'static str,
     /// sysfs cpumask string for display, e.g. `"0,5-11"`.
     pub cpus: String,
@@ -452,11 +570,57 @@ pub fn host_core_pmus() -> Vec<CorePmu> {
     pmus
 }
 
-#[cfg(not(all(target_arch = "aarch64", target_os = "linux")))]
+/// Intel hybrid CPUs (Alder Lake and later) expose the P-cores and E-cores
+/// as two PMUs, `cpu_core` and `cpu_atom`, each with its own `perf_event`
+/// type. Other x86 CPUs have a single `cpu` PMU and get an empty vector.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub fn host_core_pmus() -> Vec<CorePmu> {
+    x86_hybrid_core_pmus(std::path::Path::new("/sys/bus/event_source/devices"))
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn x86_hybrid_core_pmus(devices: &std::path::Path) -> Vec<CorePmu> {
+    let mut pmus = Vec::new();
+    for family_id in [INTEL_P_CORE, INTEL_E_CORE] {
+        let path = devices.join(family_id);
+        let (Ok(cpus), Ok(type_str)) = (
+            std::fs::read_to_string(path.join("cpus")),
+            std::fs::read_to_string(path.join("type")),
+        ) else {
+            continue;
+        };
+        let Ok(pmu_type) = type_str.trim().parse::<u32>() else {
+            continue;
+        };
+        pmus.push(CorePmu {
+            pmu_type,
+            family_id,
+            cpus: cpus.trim().to_string(),
+        });
+    }
+    pmus
+}
+
+#[cfg(not(all(
+    any(target_arch = "aarch64", target_arch = "x86_64"),
+    target_os = "linux"
+)))]
 pub fn host_core_pmus() -> Vec<CorePmu> {
     Vec::new()
 }
 
+/// Display name of a core cluster's family, e.g. `"ARM Cortex-A720"` or
+/// `"Intel P-core"`. Unknown families keep their id.
+pub fn core_family_name(family_id: &str) -> String {
+    match family_id {
+        INTEL_P_CORE => "Intel P-core".to_owned(),
+        INTEL_E_CORE => "Intel E-core".to_owned(),
+        _ => find_cpu_family(family_id)
+            .map(|family| family.name.clone())
+            .unwrap_or_else(|| family_id.to_owned()),
+    }
+}
+
 /// Return a `(vendor, model)` description of the host CPU for display and for
 /// recording in profile metadata. On heterogeneous systems the model lists each
 /// distinct core cluster, e.g. `"ARM Cortex-A720 + ARM Cortex-A520"`.
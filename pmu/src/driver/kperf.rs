@@ -13,8 +13,9 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use crate::driver::{
-    CounterEntry, CounterResult, CounterValue, CountingDriver, MeasurementQuality, Record, Sample,
-    SamplingCallback, SamplingDriver,
+    join_reader, reader_status, CounterEntry, CounterResult, CounterValue, CountingDriver,
+    Heartbeat, MeasurementQuality, ReaderStatus, Record, Sample, SamplingCallback, SamplingDriver,
+    TaskScope,
 };
 use crate::{Counter, Error};
 
@@ -274,6 +275,7 @@ pub struct KPerfSamplingDriver {
     capture_callstacks: bool,
     running: Arc<AtomicBool>,
     thread_handle: Option<thread::JoinHandle<()>>,
+    heartbeat: Arc<Heartbeat>,
 }
 
 #[derive(Clone)]
@@ -677,6 +679,9 @@ impl CountingDriver for KPerfCountingDriver {
                             value,
                             scaling: 1.0,
                             quality,
+                            raw: value,
+                            time_enabled: 0,
+                            time_running: 0,
                         },
                     });
                     continue;
@@ -689,6 +694,9 @@ impl CountingDriver for KPerfCountingDriver {
                             value,
                             scaling,
                             quality: MeasurementQuality::Estimated,
+                            raw: value,
+                            time_enabled: 0,
+                            time_running: 0,
                         },
                     });
                 }
@@ -732,6 +740,10 @@ impl CountingDriver for KPerfCountingDriver {
                     } else {
                         MeasurementQuality::Exact
                     },
+                    // kpc reports neither the unscaled count nor timings.
+                    raw: value,
+                    time_enabled: 0,
+                    time_running: 0,
                 },
             });
         }
@@ -1082,6 +1094,7 @@ impl KPerfSamplingDriver {
             capture_callstacks,
             running: Arc::new(AtomicBool::new(false)),
             thread_handle: None,
+            heartbeat: Heartbeat::new(),
         })
     }
 }
@@ -1125,6 +1138,8 @@ impl SamplingDriver for KPerfSamplingDriver {
 
         self.running.store(true, Ordering::SeqCst);
         let running = self.running.clone();
+        let heartbeat = self.heartbeat.clone();
+        heartbeat.beat();
         let ctx = self.ctx.clone();
         let programs = self.programs.clone();
         let mut handles = programs
@@ -1144,6 +1159,7 @@ impl SamplingDriver for KPerfSamplingDriver {
             let mut last_switch = Instant::now();
 
             loop {
+                heartbeat.beat();
                 let is_running = running.load(Ordering::SeqCst);
                 let mut records = vec![KdBuf::default(); 4096];
                 match read_kdebug_records(&mut records) {
@@ -1225,11 +1241,24 @@ impl SamplingDriver for KPerfSamplingDriver {
         let _ = sysctl_kdebug_set(KERN_KDENABLE, 0);
         self.running.store(false, Ordering::SeqCst);
         if let Some(handle) = self.thread_handle.take() {
-            handle.join().map_err(|_| Error::EnableFailed)?;
+            // Tear the buffer down even when the reader panicked.
+            let joined = join_reader(handle);
+            teardown_kdebug_buffer();
+            return joined;
         }
         teardown_kdebug_buffer();
         Ok(())
     }
+
+    fn reader_status(&self) -> ReaderStatus {
+        reader_status(self.thread_handle.as_ref(), &self.heartbeat)
+    }
+
+    fn task_scope(&self) -> TaskScope {
+        // kperf filters the system-wide trace by pid, so every thread of the
+        // target is seen but forked children carry their own pid.
+        TaskScope::Process
+    }
 }
 
 struct BuiltConfig {
@@ -2162,6 +2191,7 @@ fn emit_pending_sample(
             callstack: callstack.iter().copied().collect(),
             user_regs: None,
             user_stack: Vec::new(),
+            weight: 0,
         }));
     }
 }
@@ -2479,7 +2509,7 @@ mod tests {
             .iter()
             .map(|record| match record {
                 Record::Sample(sample) => sample.value,
-                Record::ProcAddr(_) => panic!("expected sample"),
+                _ => panic!("expected sample"),
             })
             .collect();
         assert_eq!(values, [50]);
@@ -4,20 +4,31 @@ mod perf;
 #[cfg(target_os = "macos")]
 mod kperf;
 
+#[cfg(all(target_os = "windows", feature = "windows"))]
+mod windows;
+
 #[cfg(target_os = "linux")]
 use perf::{PerfCountingDriver, PerfSamplingDriver};
 
 #[cfg(target_os = "macos")]
 use kperf::{KPerfCountingDriver, KPerfSamplingDriver};
 
+#[cfg(all(target_os = "windows", feature = "windows"))]
+use windows::WindowsCountingDriver;
+
 use itertools::chain;
 use smallvec::SmallVec;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use crate::{cpu_family, Counter, Error, Process};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 /// Operating-system backend used to access performance counters.
 pub enum DriverKind {
     /// Select the native backend automatically.
@@ -26,6 +37,9 @@ pub enum DriverKind {
     Perf,
     /// Apple kperf backend.
     KPerf,
+    /// Windows per-process accounting backend (counting only, behind the
+    /// `windows` feature).
+    Windows,
 }
 
 /// Strategy used to collect user-space call stacks.
@@ -38,6 +52,24 @@ pub enum UnwindMode {
     Dwarf,
     /// Intel Last Branch Record call stacks. Falls back to DWARF when unsupported.
     Lbr,
+    /// No call stacks: each sample holds only the interrupted instruction.
+    /// The cheapest mode, for flat profiles.
+    None,
+}
+
+/// Clock that stamps [`Sample::time`] and the task records of a sampling
+/// driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleClock {
+    /// The kernel's perf clock. Cheapest, but on some hosts not comparable
+    /// with any clock user space can read.
+    #[default]
+    Perf,
+    /// `CLOCK_MONOTONIC`, as read by `clock_gettime`.
+    Monotonic,
+    /// `CLOCK_MONOTONIC_RAW`, which is not slewed by NTP. The collector stamps
+    /// instrumentation events with it.
+    MonotonicRaw,
 }
 
 /// Register state captured by `PERF_SAMPLE_REGS_USER`.
@@ -60,6 +92,13 @@ pub struct CounterValue {
     pub scaling: f64,
     /// Reliability of the value after multiplexing or estimation.
     pub quality: MeasurementQuality,
+    /// Count as read from the hardware, before scaling.
+    pub raw: u64,
+    /// Nanoseconds the counter was enabled. Zero when the platform does not
+    /// report it.
+    pub time_enabled: u64,
+    /// Nanoseconds the counter was actually counting on a PMU.
+    pub time_running: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -101,7 +140,100 @@ pub trait SamplingDriver {
     fn start(&mut self, callback: Arc<dyn SamplingCallback>) -> Result<(), Error>;
 
     /// Stops sampling, drains pending records, and joins the reader thread.
+    ///
+    /// Returns [`Error::WorkerPanicked`] when the reader thread panicked.
     fn stop(&mut self) -> Result<(), Error>;
+
+    /// Liveness of the reader thread, for watchdogs polling during a run.
+    fn reader_status(&self) -> ReaderStatus;
+
+    /// Tasks of the target that are actually sampled. This can be narrower
+    /// than requested with [`SamplingDriverBuilder::follow_forks`] when the
+    /// kernel cannot inherit the sampling group.
+    fn task_scope(&self) -> TaskScope;
+}
+
+/// Which tasks of a profiled process are covered by its events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TaskScope {
+    /// Only the thread the events were opened on.
+    Thread,
+    /// The process and every thread it creates.
+    Process,
+    /// The process, the processes it forks, and all of their threads.
+    ProcessTree,
+}
+
+/// Liveness of a sampling driver's reader thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReaderStatus {
+    /// No reader is running: sampling was not started or is already stopped.
+    Idle,
+    /// The reader is polling the sample buffers.
+    Running {
+        /// Time since the reader last finished a pass over the buffers.
+        since_heartbeat: Duration,
+    },
+    /// The reader thread ended before `stop`, usually because it panicked.
+    /// `stop` reports the panic.
+    Exited,
+}
+
+/// Progress marker a reader thread bumps after every pass over its buffers.
+#[derive(Debug)]
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) struct Heartbeat {
+    origin: Instant,
+    last_nanos: AtomicU64,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl Heartbeat {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            origin: Instant::now(),
+            last_nanos: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn beat(&self) {
+        let nanos = u64::try_from(self.origin.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.last_nanos.store(nanos, Ordering::Relaxed);
+    }
+
+    fn since_last(&self) -> Duration {
+        self.origin.elapsed().saturating_sub(Duration::from_nanos(
+            self.last_nanos.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) fn reader_status(
+    handle: Option<&JoinHandle<()>>,
+    heartbeat: &Heartbeat,
+) -> ReaderStatus {
+    match handle {
+        None => ReaderStatus::Idle,
+        Some(handle) if handle.is_finished() => ReaderStatus::Exited,
+        Some(_) => ReaderStatus::Running {
+            since_heartbeat: heartbeat.since_last(),
+        },
+    }
+}
+
+/// Joins a reader thread, turning a panic into [`Error::WorkerPanicked`] with
+/// the panic message.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) fn join_reader(handle: JoinHandle<()>) -> Result<(), Error> {
+    handle.join().map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+        Error::WorkerPanicked(message)
+    })
 }
 
 /// Identifies the core cluster a counter value was measured on, on a
@@ -142,12 +274,26 @@ pub enum Record {
     Sample(Sample),
     /// A process address-space mapping.
     ProcAddr(ProcAddr),
+    /// A thread name assigned by `exec` or `prctl(PR_SET_NAME)`.
+    ThreadName(ThreadName),
+    /// A process or thread created by a sampled task.
+    Fork(TaskEvent),
+    /// A sampled process or thread exited.
+    Exit(TaskEvent),
+    /// A sampled process replaced its image with `exec`. Mappings reported
+    /// before it belong to the previous image.
+    Exec(ExecEvent),
+    /// Number of records the kernel dropped because a sampling ring buffer
+    /// was full. Raising the buffer size or lowering the sample rate helps.
+    Lost(u64),
 }
 
 /// A structure that represents a single sample
 #[derive(Debug)]
 pub struct Sample {
-    /// Unique ID shared by all samples of the event
+    /// Unique ID shared by all samples of the event. A hardware sample of a
+    /// counter group is delivered as one `Sample` per counter, consecutively
+    /// and with the same `event_id`.
     pub event_id: u128,
     /// Instruction pointer
     pub ip: u64,
@@ -160,7 +306,7 @@ pub struct Sample {
     /// Family id of the core cluster this sample came from (e.g.
     /// `"cortex_a720"`), on a heterogeneous system. `None` on homogeneous hosts.
     pub core: Option<String>,
-    /// Timestamp
+    /// Timestamp in nanoseconds on the driver's [`SampleClock`].
     pub time: u64,
     /// Time for which the event was enabled.
     pub time_enabled: u64,
@@ -176,6 +322,47 @@ pub struct Sample {
     pub user_regs: Option<UserRegs>,
     /// User stack bytes beginning at the sampled stack pointer.
     pub user_stack: Vec<u8>,
+    /// `PERF_SAMPLE_WEIGHT` of the sample, e.g. the load latency in core cycles
+    /// for memory-latency sampling. Zero when not requested.
+    pub weight: u64,
+}
+
+#[derive(Debug)]
+/// A thread name reported by `PERF_RECORD_COMM`.
+pub struct ThreadName {
+    /// Process identifier.
+    pub pid: u32,
+    /// Thread identifier.
+    pub tid: u32,
+    /// Kernel `comm` string, at most 15 bytes on Linux.
+    pub name: String,
+}
+
+#[derive(Debug)]
+/// Task lifetime change reported by `PERF_RECORD_FORK` or `PERF_RECORD_EXIT`.
+/// A new process has `pid != ppid`; a new thread shares its parent's `pid`.
+pub struct TaskEvent {
+    /// Process identifier.
+    pub pid: u32,
+    /// Parent process identifier.
+    pub ppid: u32,
+    /// Thread identifier.
+    pub tid: u32,
+    /// Parent thread identifier.
+    pub ptid: u32,
+    /// Timestamp, on the same clock as [`Sample::time`].
+    pub time: u64,
+}
+
+#[derive(Debug)]
+/// An `exec` reported by `PERF_RECORD_COMM` with `PERF_RECORD_MISC_COMM_EXEC`.
+pub struct ExecEvent {
+    /// Process identifier.
+    pub pid: u32,
+    /// Thread identifier.
+    pub tid: u32,
+    /// Timestamp, on the same clock as [`Sample::time`].
+    pub time: u64,
 }
 
 #[derive(Debug)]
@@ -189,8 +376,9 @@ pub struct ProcAddr {
     pub len: u64,
     /// File offset backing the mapping.
     pub pgoff: u64,
-    /// Path of the mapped file.
-    pub filename: String,
+    /// Path of the mapped file, byte for byte: Linux paths need not be
+    /// UTF-8.
+    pub filename: PathBuf,
 }
 
 /// Builder for a counting driver.
@@ -198,6 +386,7 @@ pub struct CountingDriverBuilder {
     counters: Vec<Counter>,
     pid: Option<i32>,
     kind: DriverKind,
+    follow_forks: bool,
 }
 
 /// Builder for a sampling driver.
@@ -210,8 +399,22 @@ pub struct SamplingDriverBuilder {
     unwind_mode: UnwindMode,
     stack_dump_size: u32,
     precise_ip: bool,
+    mmap_pages: usize,
+    memory_latency: bool,
+    follow_forks: bool,
+    cpus: Vec<u32>,
+    leader: Option<Counter>,
+    clock: SampleClock,
+    thread_only: bool,
 }
 
+/// Default number of data pages in each sampling ring buffer.
+pub const DEFAULT_MMAP_PAGES: usize = 512;
+
+/// Minimum latency, in core cycles, of loads sampled by
+/// [`SamplingDriverBuilder::memory_latency`].
+pub const LOAD_LATENCY_THRESHOLD: u64 = 32;
+
 impl<F: Fn(Record) + Send + Sync> SamplingCallback for F {
     fn call(&self, record: Record) {
         self(record)
@@ -229,6 +432,10 @@ pub fn list_supported_counters(driver: DriverKind) -> Vec<Counter> {
             if driver == DriverKind::Default || driver == DriverKind::KPerf {
                 return kperf::list_supported_counters();
             }
+        } else if #[cfg(all(target_os="windows", feature = "windows"))] {
+            if driver == DriverKind::Default || driver == DriverKind::Windows {
+                return windows::list_supported_counters();
+            }
         }
     }
 
@@ -242,6 +449,7 @@ impl CountingDriverBuilder {
             counters: vec![],
             pid: None,
             kind: DriverKind::Default,
+            follow_forks: true,
         }
     }
 
@@ -268,17 +476,32 @@ impl CountingDriverBuilder {
         self
     }
 
+    /// Also counts processes the target forks, like `perf stat` (the
+    /// default). When `false`, only the target and its threads are counted.
+    pub fn follow_forks(mut self, follow_forks: bool) -> Self {
+        self.follow_forks = follow_forks;
+        self
+    }
+
     /// Opens the configured counters and returns the native driver.
     pub fn build(self) -> Result<Box<dyn CountingDriver>, Error> {
         cfg_if::cfg_if! {
             if #[cfg(target_os="linux")] {
                 if self.kind == DriverKind::Default || self.kind == DriverKind::Perf {
-                    return Ok(Box::new(PerfCountingDriver::new(self.counters, self.pid)?));
+                    return Ok(Box::new(PerfCountingDriver::new(
+                        self.counters,
+                        self.pid,
+                        self.follow_forks,
+                    )?));
                 }
             } else if #[cfg(target_os="macos")] {
                 if self.kind == DriverKind::Default || self.kind == DriverKind::KPerf {
                     return Ok(Box::new(KPerfCountingDriver::new(self.counters, self.pid)?));
                 }
+            } else if #[cfg(all(target_os="windows", feature = "windows"))] {
+                if self.kind == DriverKind::Default || self.kind == DriverKind::Windows {
+                    return Ok(Box::new(WindowsCountingDriver::new(self.counters, self.pid)?));
+                }
             }
         }
 
@@ -306,6 +529,13 @@ impl SamplingDriverBuilder {
             unwind_mode: UnwindMode::Dwarf,
             stack_dump_size: 8 * 1024,
             precise_ip: false,
+            mmap_pages: DEFAULT_MMAP_PAGES,
+            memory_latency: false,
+            follow_forks: true,
+            cpus: vec![],
+            leader: None,
+            clock: SampleClock::Perf,
+            thread_only: false,
         }
     }
 
@@ -363,19 +593,120 @@ impl SamplingDriverBuilder {
         self
     }
 
+    /// Number of data pages in each ring buffer (Linux only). Larger buffers
+    /// lose fewer records at high sample rates; must be a power of two.
+    pub fn mmap_pages(mut self, pages: usize) -> Self {
+        self.mmap_pages = pages;
+        self
+    }
+
+    /// Leads each sampling group with the PEBS load-latency event, so samples
+    /// land on retired loads slower than [`LOAD_LATENCY_THRESHOLD`] cycles and
+    /// carry the load's latency in [`Sample::weight`]. Intel only.
+    pub fn memory_latency(mut self) -> Self {
+        self.memory_latency = true;
+        self
+    }
+
+    /// Also samples processes the target forks, like `perf record` (the
+    /// default). When `false`, only the target and its threads are sampled.
+    /// Check [`SamplingDriver::task_scope`] for what the kernel granted.
+    pub fn follow_forks(mut self, follow_forks: bool) -> Self {
+        self.follow_forks = follow_forks;
+        self
+    }
+
+    /// Samples only the thread the driver is attached to (Linux only): the
+    /// main thread of a launched process, or the thread whose ID is passed to
+    /// [`pid`](Self::pid). Threads and processes it creates are not sampled.
+    /// Takes precedence over [`follow_forks`](Self::follow_forks).
+    pub fn thread_only(mut self, thread_only: bool) -> Self {
+        self.thread_only = thread_only;
+        self
+    }
+
+    /// Restricts sampling to the listed CPUs (Linux only): one sampling group
+    /// is opened per CPU and execution elsewhere is not sampled. Empty, the
+    /// default, samples on every CPU.
+    pub fn cpus(mut self, cpus: &[u32]) -> Self {
+        self.cpus = cpus.to_vec();
+        self
+    }
+
+    /// Makes `leader` the only event that interrupts the target (Linux only),
+    /// instead of every counter sampling on its own, so samples land where it
+    /// occurs, e.g. on cache misses. The other counters are read with each of
+    /// its samples. It is added to the counters if they lack it.
+    pub fn leader(mut self, leader: Counter) -> Self {
+        self.leader = Some(leader);
+        self
+    }
+
+    /// Stamps samples and task records with `clock` instead of the kernel's
+    /// perf clock (Linux only), so they line up with timestamps taken in user
+    /// space, such as those of the collector.
+    pub fn clock(mut self, clock: SampleClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Prefers raw CPU-family event encodings over generic perf aliases.
     pub fn prefer_raw_events(mut self) -> Self {
         self.prefer_raw_events = true;
         self
     }
 
+    /// The tasks the events should cover.
+    #[cfg(target_os = "linux")]
+    fn requested_scope(&self) -> TaskScope {
+        if self.thread_only {
+            TaskScope::Thread
+        } else if self.follow_forks {
+            TaskScope::ProcessTree
+        } else {
+            TaskScope::Process
+        }
+    }
+
+    /// The counters with the leader, if it is not one of them.
+    #[cfg(target_os = "linux")]
+    fn sampled_counters(&self) -> Vec<Counter> {
+        let mut counters = self.counters.clone();
+        if let Some(leader) = self
+            .leader
+            .as_ref()
+            .filter(|leader| !counters.contains(leader))
+        {
+            counters.insert(0, leader.clone());
+        }
+        counters
+    }
+
     /// Opens events and creates the native sampling driver.
     pub fn build(self) -> Result<Box<dyn SamplingDriver>, Error> {
+        if !self.mmap_pages.is_power_of_two() {
+            return Err(Error::InvalidConfiguration(format!(
+                "mmap page count must be a power of two, got {}",
+                self.mmap_pages
+            )));
+        }
+        if self.memory_latency && !cpu_family::host_supports_load_latency() {
+            return Err(Error::InvalidConfiguration(
+                "memory latency sampling requires an Intel CPU with PEBS load-latency support"
+                    .to_owned(),
+            ));
+        }
+        if self.memory_latency && self.leader.is_some() {
+            return Err(Error::InvalidConfiguration(
+                "memory latency sampling is led by the load-latency event".to_owned(),
+            ));
+        }
+
         cfg_if::cfg_if! {
             if #[cfg(target_os="linux")] {
                 if self.kind == DriverKind::Default || self.kind == DriverKind::Perf {
                     let driver = sampling_with_fallback(
-                        self.counters,
+                        self.sampled_counters(),
                         self.unwind_mode,
                         |counters, unwind_mode| PerfSamplingDriver::new(
                             counters,
@@ -385,12 +716,38 @@ impl SamplingDriverBuilder {
                             unwind_mode,
                             self.stack_dump_size,
                             self.precise_ip,
+                            self.mmap_pages,
+                            self.memory_latency,
+                            self.requested_scope(),
+                            &self.cpus,
+                            self.leader.as_ref(),
+                            self.clock,
                         ),
                     )?;
                     return Ok(Box::new(driver));
                 }
             } else if #[cfg(target_os="macos")] {
                 if self.kind == DriverKind::Default || self.kind == DriverKind::KPerf {
+                    if !self.cpus.is_empty() {
+                        return Err(Error::InvalidConfiguration(
+                            "kperf cannot restrict sampling to specific CPUs".to_owned(),
+                        ));
+                    }
+                    if self.leader.is_some() {
+                        return Err(Error::InvalidConfiguration(
+                            "kperf cannot choose the sampling leader".to_owned(),
+                        ));
+                    }
+                    if self.thread_only {
+                        return Err(Error::InvalidConfiguration(
+                            "kperf cannot sample a single thread".to_owned(),
+                        ));
+                    }
+                    if self.clock != SampleClock::Perf {
+                        return Err(Error::InvalidConfiguration(
+                            "kperf cannot choose the sample clock".to_owned(),
+                        ));
+                    }
                     return Ok(Box::new(KPerfSamplingDriver::new(
                         &self.counters,
                         self.sample_freq,
@@ -453,6 +810,25 @@ where
     }
 }
 
+/// Drops repeated counters, keeping the first occurrence so the group leader
+/// stays in front. Each duplicate would otherwise take a hardware counter and
+/// be summed twice into [`CounterResult::get`].
+#[cfg(target_os = "linux")]
+pub(crate) fn dedup_counters(counters: &[Counter]) -> Vec<Counter> {
+    let mut unique: Vec<Counter> = Vec::with_capacity(counters.len());
+    for counter in counters {
+        if unique.contains(counter) {
+            eprintln!(
+                "warning: counter '{}' is listed more than once; opening it once",
+                counter.name()
+            );
+        } else {
+            unique.push(counter.clone());
+        }
+    }
+    unique
+}
+
 impl Default for SamplingDriverBuilder {
     fn default() -> Self {
         Self::new()
@@ -477,11 +853,18 @@ impl CounterResult {
 
         let value = matching.iter().map(|e| e.value.value).sum();
         let scaling = matching.iter().map(|e| e.value.scaling).sum::<f64>() / matching.len() as f64;
+        let raw = matching.iter().map(|e| e.value.raw).sum();
+        // Per-core instances run side by side, so their times are not additive.
+        let time_enabled = matching.iter().map(|e| e.value.time_enabled).max();
+        let time_running = matching.iter().map(|e| e.value.time_running).max();
 
         Some(CounterValue {
             value,
             scaling,
             quality: MeasurementQuality::Exact,
+            raw,
+            time_enabled: time_enabled.unwrap_or_default(),
+            time_running: time_running.unwrap_or_default(),
         })
     }
 
@@ -556,4 +939,55 @@ mod tests {
             "hardware-only sampling must become a cpu-clock-only group"
         );
     }
+
+    #[test]
+    fn duplicate_counters_are_opened_once_keeping_the_leader() {
+        let counters = [
+            Counter::Cycles,
+            Counter::Instructions,
+            Counter::Cycles,
+            Counter::Instructions,
+            Counter::BranchMisses,
+        ];
+        assert_eq!(
+            dedup_counters(&counters),
+            [
+                Counter::Cycles,
+                Counter::Instructions,
+                Counter::BranchMisses
+            ]
+        );
+    }
+
+    #[test]
+    fn thread_only_overrides_following_forks() {
+        let builder = SamplingDriverBuilder::new();
+        assert_eq!(builder.requested_scope(), TaskScope::ProcessTree);
+        let builder = builder.follow_forks(false);
+        assert_eq!(builder.requested_scope(), TaskScope::Process);
+        let builder = builder.follow_forks(true).thread_only(true);
+        assert_eq!(builder.requested_scope(), TaskScope::Thread);
+    }
+}
+
+#[cfg(test)]
+mod reader_tests {
+    use super::*;
+
+    #[test]
+    fn reader_panic_is_reported_with_its_message() {
+        let heartbeat = Heartbeat::new();
+        let handle = std::thread::spawn(|| panic!("malformed record"));
+        while !handle.is_finished() {
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            reader_status(Some(&handle), &heartbeat),
+            ReaderStatus::Exited
+        );
+
+        let error = join_reader(handle).unwrap_err();
+        assert!(matches!(&error, Error::WorkerPanicked(message) if message == "malformed record"));
+        assert_eq!(reader_status(None, &heartbeat), ReaderStatus::Idle);
+    }
 }
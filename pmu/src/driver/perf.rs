@@ -17,19 +17,21 @@ use perf_event_open_sys::bindings::{
     perf_event_attr, PERF_SAMPLE_BRANCH_CALL_STACK, PERF_SAMPLE_BRANCH_STACK,
     PERF_SAMPLE_BRANCH_USER, PERF_SAMPLE_CALLCHAIN, PERF_SAMPLE_CPU, PERF_SAMPLE_ID,
     PERF_SAMPLE_IP, PERF_SAMPLE_READ, PERF_SAMPLE_REGS_USER, PERF_SAMPLE_STACK_USER,
-    PERF_SAMPLE_TID, PERF_SAMPLE_TIME,
+    PERF_SAMPLE_TID, PERF_SAMPLE_TIME, PERF_SAMPLE_WEIGHT,
 };
 use perf_event_open_sys::{self as sys, bindings::PERF_SAMPLE_IDENTIFIER};
 use smallvec::SmallVec;
 
-use crate::driver::{ProcAddr, Sample, UnwindMode};
+use crate::driver::{
+    ExecEvent, ProcAddr, Sample, SampleClock, TaskEvent, TaskScope, ThreadName, UnwindMode,
+};
 use crate::{Counter, Error, Record};
 
 pub use events::list_supported_counters;
 
 use super::{
-    CoreId, CounterEntry, CounterResult, CounterValue, CountingDriver, MeasurementQuality,
-    SamplingCallback, SamplingDriver,
+    join_reader, reader_status, CoreId, CounterEntry, CounterResult, CounterValue, CountingDriver,
+    Heartbeat, MeasurementQuality, ReaderStatus, SamplingCallback, SamplingDriver,
 };
 
 /// Counting driver is used for simple collection of system's performance counters values. On Linux,
@@ -42,14 +44,18 @@ pub struct PerfCountingDriver {
 /// interrupted and counters values are recorded for future post processing.
 pub struct PerfSamplingDriver {
     native_handles: Vec<NativeCounterHandle>,
-    mmaps: Vec<UnsafeMmap>,
-    page_size: usize,
-    mmap_pages: usize,
+    /// Shared with the reader thread, so the buffers stay mapped until it
+    /// has exited even if the driver is dropped first.
+    mmaps: Arc<Vec<RingBuffer>>,
     running: Arc<AtomicBool>,
     thread_handle: Option<thread::JoinHandle<()>>,
+    heartbeat: Arc<Heartbeat>,
     enable_on_start: bool,
     sample_regs_user: u64,
     sample_branch_stack: bool,
+    sample_callchain: bool,
+    sample_weight: bool,
+    task_scope: TaskScope,
 }
 
 #[derive(Debug, Clone)]
@@ -63,24 +69,83 @@ struct NativeCounterHandle {
     pub leader: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct UnsafeMmap {
+/// A perf ring buffer mapped into memory, unmapped when dropped.
+#[derive(Debug)]
+struct RingBuffer {
     ptr: *mut u8,
+    length: usize,
+}
+
+// SAFETY: the mapping is owned, not aliased by other Rust values. The data
+// pages are only written by the kernel and read by the single reader thread,
+// which accesses the control page atomically.
+unsafe impl Send for RingBuffer {}
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    /// Maps `length` bytes of the ring buffer of perf event `fd`.
+    fn map(fd: i32, length: usize) -> std::io::Result<Self> {
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                length,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(RingBuffer {
+            ptr: ptr.cast(),
+            length,
+        })
+    }
+
+    fn records(
+        &self,
+        sample_regs_user: u64,
+        sample_branch_stack: bool,
+        sample_callchain: bool,
+        sample_weight: bool,
+    ) -> Records {
+        Records::from_ptr(
+            self.ptr,
+            sample_regs_user,
+            sample_branch_stack,
+            sample_callchain,
+            sample_weight,
+        )
+    }
 }
 
-unsafe impl Send for UnsafeMmap {}
-unsafe impl Sync for UnsafeMmap {}
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr.cast(), self.length) };
+    }
+}
 
 impl PerfCountingDriver {
-    pub fn new(counters: Vec<Counter>, pid: Option<i32>) -> Result<Self, Error> {
+    pub fn new(
+        counters: Vec<Counter>,
+        pid: Option<i32>,
+        follow_forks: bool,
+    ) -> Result<Self, Error> {
+        let counters = super::dedup_counters(&counters);
+
         // On a heterogeneous (big.LITTLE) host we open every hardware counter on
         // each cluster's PMU so a migrating task is faithfully counted wherever
         // it runs. `host_core_pmus` returns more than one entry only in that
         // case; otherwise fall through to the single-PMU path below.
         let core_pmus = crate::cpu_family::host_core_pmus();
         if core_pmus.len() > 1 {
-            #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
-            return Self::new_per_core(counters, pid, &core_pmus);
+            #[cfg(all(
+                any(target_arch = "aarch64", target_arch = "x86_64"),
+                target_os = "linux"
+            ))]
+            return Self::new_per_core(counters, pid, follow_forks, &core_pmus);
         }
 
         let mut attrs = get_native_counters(&counters, true)?;
@@ -89,6 +154,7 @@ impl PerfCountingDriver {
             attr.set_exclude_kernel(1);
             attr.set_exclude_hv(1);
             attr.set_inherit(1);
+            attr.set_inherit_thread((!follow_forks).into());
             attr.set_exclusive(0);
             attr.sample_type = PERF_SAMPLE_IDENTIFIER as u64;
             if pid.is_some() {
@@ -96,7 +162,39 @@ impl PerfCountingDriver {
             }
         }
 
-        let native_handles = binding::direct(&counters, &mut attrs, pid)?;
+        // A group is read in one syscall, so its counters do not skew against
+        // each other. It is only scheduled as a whole, though, so counters
+        // that have to be multiplexed stay independent events.
+        let hardware = counters.iter().filter(|cntr| !cntr.is_software()).count();
+        let fits_group =
+            hardware > 1 && crate::host_max_counters().is_some_and(|max| hardware <= max);
+        let native_handles = if fits_group {
+            for (cntr, attr) in counters.iter().zip(&mut attrs) {
+                // Only a group leader may be pinned.
+                attr.set_pinned(0);
+                if !cntr.is_software() {
+                    attr.set_disabled(0);
+                }
+            }
+            if let Some(attr) = counters
+                .iter()
+                .zip(&mut attrs)
+                .find_map(|(cntr, attr)| (!cntr.is_software()).then_some(attr))
+            {
+                attr.set_disabled(1);
+            }
+            match binding::counting_group(&counters, &mut attrs, pid) {
+                Ok(handles) => handles,
+                Err(_) => {
+                    for attr in &mut attrs {
+                        attr.set_disabled(1);
+                    }
+                    binding::direct(&counters, &mut attrs, pid)?
+                }
+            }
+        } else {
+            binding::direct(&counters, &mut attrs, pid)?
+        };
 
         Ok(PerfCountingDriver { native_handles })
     }
@@ -104,16 +202,22 @@ impl PerfCountingDriver {
     /// Open each PMU counter once per core cluster (faithful per-core counting).
     /// Software counters, which are not PMU-specific, are opened a single time.
     /// A counter that a cluster's family does not implement is skipped there.
-    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    #[cfg(all(
+        any(target_arch = "aarch64", target_arch = "x86_64"),
+        target_os = "linux"
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn new_per_core(
         counters: Vec<Counter>,
         pid: Option<i32>,
+        follow_forks: bool,
         core_pmus: &[crate::cpu_family::CorePmu],
     ) -> Result<Self, Error> {
         let apply_flags = |attr: &mut perf_event_attr| {
             attr.set_exclude_kernel(1);
             attr.set_exclude_hv(1);
             attr.set_inherit(1);
+            attr.set_inherit_thread((!follow_forks).into());
             attr.set_exclusive(0);
             attr.sample_type = PERF_SAMPLE_IDENTIFIER as u64;
             if pid.is_some() {
@@ -157,7 +261,7 @@ impl PerfCountingDriver {
             }
 
             for pmu in core_pmus {
-                let Some(resolved) = resolve_counter_for_family(cntr, pmu.family_id, true) else {
+                let Some(resolved) = resolve_for_core_pmu(cntr, pmu) else {
                     continue; // this cluster's family does not implement it
                 };
 
@@ -187,19 +291,47 @@ impl PerfCountingDriver {
 
 /// Build a display-friendly [`CoreId`] for a core PMU, resolving the family's
 /// human readable name where known.
-#[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+#[cfg(all(
+    any(target_arch = "aarch64", target_arch = "x86_64"),
+    target_os = "linux"
+))]
 fn core_id_of(pmu: &crate::cpu_family::CorePmu) -> CoreId {
-    let name = crate::cpu_family::find_cpu_family(pmu.family_id)
-        .map(|f| f.name.clone())
-        .unwrap_or_else(|| pmu.family_id.to_string());
-
     CoreId {
         family_id: pmu.family_id.to_string(),
-        name,
+        name: crate::cpu_family::core_family_name(pmu.family_id),
         cpus: pmu.cpus.clone(),
     }
 }
 
+/// Resolves `counter` for one core PMU of a heterogeneous host. Returns
+/// `None` when that core type does not implement the event.
+#[cfg(all(
+    any(target_arch = "aarch64", target_arch = "x86_64"),
+    target_os = "linux"
+))]
+fn resolve_for_core_pmu(counter: &Counter, pmu: &crate::cpu_family::CorePmu) -> Option<Counter> {
+    #[cfg(target_arch = "aarch64")]
+    {
+        resolve_counter_for_family(counter, pmu.family_id, true)
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        match counter {
+            // Intel's event tables describe the P-cores, whose codes mean
+            // something else on the E-cores. E-cores only get the generic
+            // hardware and software events.
+            Counter::Internal { .. } | Counter::Custom(_) | Counter::Raw { .. } => {
+                if pmu.family_id == crate::cpu_family::INTEL_P_CORE {
+                    process_counter(counter, true).ok()
+                } else {
+                    None
+                }
+            }
+            _ => Some(counter.clone()),
+        }
+    }
+}
+
 impl CountingDriver for PerfCountingDriver {
     fn start(&mut self) -> Result<(), Error> {
         for handle in &self.native_handles {
@@ -240,12 +372,19 @@ impl CountingDriver for PerfCountingDriver {
     }
 
     fn counters(&mut self) -> Result<CounterResult, std::io::Error> {
-        let read_size = std::mem::size_of::<ReadFormat>() + (std::mem::size_of::<EventValue>());
+        let read_size = std::mem::size_of::<ReadFormat>()
+            + std::mem::size_of::<EventValue>() * self.native_handles.len();
 
         let mut buffer = vec![0_u8; read_size];
         let mut entries = SmallVec::<[CounterEntry; 16]>::with_capacity(self.native_handles.len());
 
+        // With PERF_FORMAT_GROUP one read returns every member of the handle's
+        // group, all sampled at once. Ungrouped handles are groups of one.
+        let mut reads = HashMap::<u64, (u64, ReadFormat)>::new();
         for handle in self.native_handles.iter() {
+            if reads.contains_key(&handle.id) {
+                continue;
+            }
             let result = unsafe {
                 libc::read(
                     handle.fd,
@@ -258,17 +397,14 @@ impl CountingDriver for PerfCountingDriver {
                 return Err(std::io::Error::last_os_error());
             }
 
-            let header = unsafe { &*(buffer.as_ptr() as *const ReadFormat) };
-
-            let values = unsafe {
-                std::slice::from_raw_parts(
-                    buffer.as_ptr().add(std::mem::size_of::<ReadFormat>()) as *const EventValue,
-                    header.nr as usize,
-                )
-            };
+            let (header, values) = mmap::group_values(&buffer, result as usize);
+            for value in values {
+                reads.insert(value.id, (value.value, header));
+            }
+        }
 
-            // For now it is guaranteed there's exactly 1 event
-            let value = &values[0];
+        for handle in self.native_handles.iter() {
+            let (raw, header) = reads.get(&handle.id).copied().unwrap_or_default();
 
             let scaling_factor = if header.time_running > 0 {
                 (header.time_enabled as f64) / (header.time_running as f64)
@@ -286,11 +422,11 @@ impl CountingDriver for PerfCountingDriver {
             // Homogeneous and software counters keep the usual multiplexing
             // extrapolation, where enabled/running reflects real time-sharing.
             let reported_value = if handle.core.is_some() {
-                value.value
+                raw
             } else if header.time_running > 0 {
-                (value.value as f64 * scaling_factor) as u64
+                (raw as f64 * scaling_factor) as u64
             } else {
-                value.value
+                raw
             };
 
             entries.push(CounterEntry {
@@ -304,6 +440,9 @@ impl CountingDriver for PerfCountingDriver {
                     } else {
                         MeasurementQuality::Exact
                     },
+                    raw,
+                    time_enabled: header.time_enabled,
+                    time_running: header.time_running,
                 },
             });
         }
@@ -339,10 +478,14 @@ impl SamplingDriver for PerfSamplingDriver {
         self.running.store(true, Ordering::SeqCst);
 
         let running = self.running.clone();
+        let heartbeat = self.heartbeat.clone();
+        heartbeat.beat();
         let mmaps = self.mmaps.clone();
         let native_handles = self.native_handles.clone();
         let sample_regs_user = self.sample_regs_user;
         let sample_branch_stack = self.sample_branch_stack;
+        let sample_callchain = self.sample_callchain;
+        let sample_weight = self.sample_weight;
 
         #[derive(Clone, Default)]
         struct LastSample {
@@ -355,9 +498,13 @@ impl SamplingDriver for PerfSamplingDriver {
             let mut last_samples_map = HashMap::<(usize, u32, u32, u32, u64), LastSample>::new();
 
             loop {
-                for (idx, &mmap) in mmaps.iter().enumerate() {
-                    let records =
-                        Records::from_ptr(mmap.ptr, sample_regs_user, sample_branch_stack);
+                for (idx, mmap) in mmaps.iter().enumerate() {
+                    let records = mmap.records(
+                        sample_regs_user,
+                        sample_branch_stack,
+                        sample_callchain,
+                        sample_weight,
+                    );
 
                     for record in records.into_iter() {
                         match record {
@@ -373,6 +520,7 @@ impl SamplingDriver for PerfSamplingDriver {
                                 callstack,
                                 user_regs,
                                 user_stack,
+                                weight,
                             } => {
                                 let uid = uuid::Uuid::now_v7();
                                 let mut user_regs = user_regs;
@@ -407,6 +555,7 @@ impl SamplingDriver for PerfSamplingDriver {
                                         // reuses its result for the sibling counter events.
                                         user_regs: user_regs.take(),
                                         user_stack: user_stack.take().unwrap_or_default(),
+                                        weight,
                                     });
 
                                     last_samples_map.insert(
@@ -436,11 +585,36 @@ impl SamplingDriver for PerfSamplingDriver {
                                     filename,
                                 }));
                             }
+                            mmap::MmapRecord::Comm {
+                                pid,
+                                tid,
+                                comm,
+                                exec_time,
+                            } => {
+                                if let Some(time) = exec_time {
+                                    callback.call(Record::Exec(ExecEvent { pid, tid, time }));
+                                }
+                                callback.call(Record::ThreadName(ThreadName {
+                                    pid,
+                                    tid,
+                                    name: comm,
+                                }));
+                            }
+                            mmap::MmapRecord::Fork(task) => {
+                                callback.call(Record::Fork(task_event(&task)));
+                            }
+                            mmap::MmapRecord::Exit(task) => {
+                                callback.call(Record::Exit(task_event(&task)));
+                            }
+                            mmap::MmapRecord::Lost(count) => {
+                                callback.call(Record::Lost(count));
+                            }
                             mmap::MmapRecord::Unknown => {}
                         }
                     }
                 }
 
+                heartbeat.beat();
                 if !running.load(Ordering::SeqCst) {
                     break;
                 }
@@ -470,11 +644,29 @@ impl SamplingDriver for PerfSamplingDriver {
         self.running.store(false, Ordering::SeqCst);
 
         if let Some(handle) = self.thread_handle.take() {
-            handle.join().map_err(|_| Error::WorkerPanicked)?;
+            join_reader(handle)?;
         }
 
         Ok(())
     }
+
+    fn reader_status(&self) -> ReaderStatus {
+        reader_status(self.thread_handle.as_ref(), &self.heartbeat)
+    }
+
+    fn task_scope(&self) -> TaskScope {
+        self.task_scope
+    }
+}
+
+fn task_event(task: &mmap::TaskRecord) -> TaskEvent {
+    TaskEvent {
+        pid: task.pid,
+        ppid: task.ppid,
+        tid: task.tid,
+        ptid: task.ptid,
+        time: task.time,
+    }
 }
 
 /// Apply the sampling-specific attribute flags shared by every counter.
@@ -485,11 +677,11 @@ fn apply_sampling_flags(
     stack_dump_size: u32,
     enable_on_exec: bool,
     precise_ip: bool,
+    sample_weight: bool,
 ) {
     attr.set_exclude_kernel(1);
     attr.set_exclude_user(0);
     attr.set_exclusive(0);
-    attr.set_inherit(0);
     attr.set_enable_on_exec(enable_on_exec.into());
     if precise_ip {
         attr.set_precise_ip(2);
@@ -503,8 +695,11 @@ fn apply_sampling_flags(
         | PERF_SAMPLE_TIME
         | PERF_SAMPLE_ID
         | PERF_SAMPLE_CPU
-        | PERF_SAMPLE_READ
-        | PERF_SAMPLE_CALLCHAIN) as u64;
+        | PERF_SAMPLE_READ) as u64;
+
+    if unwind_mode != UnwindMode::None {
+        sample_type |= PERF_SAMPLE_CALLCHAIN as u64;
+    }
 
     if unwind_mode == UnwindMode::Dwarf {
         let regs = dwarf_register_mask();
@@ -518,12 +713,86 @@ fn apply_sampling_flags(
         sample_type |= PERF_SAMPLE_BRANCH_STACK as u64;
         attr.branch_sample_type = (PERF_SAMPLE_BRANCH_CALL_STACK | PERF_SAMPLE_BRANCH_USER) as u64;
     }
+    if sample_weight {
+        sample_type |= PERF_SAMPLE_WEIGHT as u64;
+    }
     attr.sample_type = sample_type;
 
     attr.set_mmap(1);
+    attr.set_comm(1);
+    attr.set_comm_exec(1);
+    attr.set_task(1);
+    // Gives COMM records a time, so samples can be placed before or after
+    // an exec.
+    attr.set_sample_id_all(1);
+}
+
+/// Selects the clock of `PERF_SAMPLE_TIME` and the record timestamps. The
+/// kernel only accepts NMI-safe clocks for hardware events, which both
+/// monotonic clocks are.
+fn apply_clock(attr: &mut perf_event_attr, clock: SampleClock) {
+    let clockid = match clock {
+        SampleClock::Perf => return,
+        SampleClock::Monotonic => libc::CLOCK_MONOTONIC,
+        SampleClock::MonotonicRaw => libc::CLOCK_MONOTONIC_RAW,
+    };
+    attr.set_use_clockid(1);
+    attr.clockid = clockid;
+}
+
+/// Moves `leader` in front of `counters`. Fallbacks drop unsupported events
+/// before the driver is opened again, so a missing leader was not supported.
+fn leader_first(counters: &[Counter], leader: &Counter) -> Result<Vec<Counter>, Error> {
+    if !counters.contains(leader) {
+        return Err(Error::InvalidConfiguration(format!(
+            "sampling leader {} is not supported on this host",
+            leader.name()
+        )));
+    }
+    Ok(std::iter::once(leader.clone())
+        .chain(
+            counters
+                .iter()
+                .filter(|counter| *counter != leader)
+                .cloned(),
+        )
+        .collect())
+}
+
+/// Makes group members counters that are only read with the leader's
+/// samples, instead of interrupting on their own.
+fn stop_sampling(attrs: &mut [perf_event_attr]) {
+    for attr in attrs {
+        attr.set_freq(0);
+        attr.sample_freq = 0;
+    }
+}
+
+/// How sampling events propagate to tasks the target creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Inherit {
+    Off,
+    Threads,
+    All,
+}
+
+impl Inherit {
+    fn apply(self, attr: &mut perf_event_attr) {
+        attr.set_inherit((self != Inherit::Off).into());
+        attr.set_inherit_thread((self == Inherit::Threads).into());
+    }
+
+    fn scope(self) -> TaskScope {
+        match self {
+            Inherit::Off => TaskScope::Thread,
+            Inherit::Threads => TaskScope::Process,
+            Inherit::All => TaskScope::ProcessTree,
+        }
+    }
 }
 
 impl PerfSamplingDriver {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         counters: &[Counter],
         sample_freq: u64,
@@ -532,24 +801,69 @@ impl PerfSamplingDriver {
         unwind_mode: UnwindMode,
         stack_dump_size: u32,
         precise_ip: bool,
+        mmap_pages: usize,
+        memory_latency: bool,
+        scope: TaskScope,
+        cpus: &[u32],
+        leader: Option<&Counter>,
+        clock: SampleClock,
     ) -> Result<PerfSamplingDriver, Error> {
-        // On a heterogeneous (big.LITTLE) host, open a sampling group on each
-        // cluster's PMU so the profile captures execution wherever the task
-        // runs, not just on one cluster.
+        let counters = &super::dedup_counters(counters)[..];
+        // A chosen leader goes first and is the only event that interrupts;
+        // the rest of its group is read with each of its samples.
+        let counters = match leader {
+            Some(leader) => leader_first(counters, leader)?,
+            None => counters.to_vec(),
+        };
+        let counters = counters.as_slice();
+
+        let cpus = cpus.iter().map(|&cpu| cpu as i32).collect::<Vec<_>>();
+        if !cpus.is_empty() {
+            let online = binding::online_cpus();
+            if let Some(cpu) = cpus.iter().find(|cpu| !online.contains(cpu)) {
+                return Err(Error::InvalidConfiguration(format!(
+                    "CPU {cpu} is not online"
+                )));
+            }
+        }
+
+        // On a heterogeneous (big.LITTLE or Intel hybrid) host, open a
+        // sampling group on each cluster's PMU so the profile captures
+        // execution wherever the task runs, not just on one cluster. PEBS
+        // load-latency sampling is a P-core feature and keeps the single PMU.
         let core_pmus = crate::cpu_family::host_core_pmus();
-        if core_pmus.len() > 1 {
-            #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+        if core_pmus.len() > 1 && !memory_latency {
+            #[cfg(all(
+                any(target_arch = "aarch64", target_arch = "x86_64"),
+                target_os = "linux"
+            ))]
             return Self::new_per_core(
                 counters,
                 sample_freq,
                 pid,
                 &core_pmus,
+                &cpus,
                 unwind_mode,
                 stack_dump_size,
                 precise_ip,
+                mmap_pages,
+                leader.is_some(),
+                clock,
             );
         }
 
+        // Memory-latency sampling puts the load-latency event in front as the
+        // group leader: only its PEBS records carry a latency weight, and the
+        // rest of the group is read alongside it.
+        let counters = if memory_latency {
+            std::iter::once(load_latency_counter())
+                .chain(counters.iter().cloned())
+                .collect::<Vec<_>>()
+        } else {
+            counters.to_vec()
+        };
+        let counters = counters.as_slice();
+
         let mut attrs = get_native_counters(counters, prefer_raw_events)?;
 
         for attr in &mut attrs {
@@ -560,50 +874,162 @@ impl PerfSamplingDriver {
                 stack_dump_size,
                 pid.is_some(),
                 precise_ip,
+                memory_latency,
             );
+            apply_clock(attr, clock);
+        }
+        if leader.is_some() {
+            stop_sampling(&mut attrs[1..]);
+        }
+        if memory_latency {
+            // ldlat lives in config1; PEBS is mandatory for this event.
+            attrs[0].config1 = crate::driver::LOAD_LATENCY_THRESHOLD;
+            attrs[0].set_precise_ip(2);
         }
 
-        let native_handles = if pid.is_none() {
-            binding::grouped_all(counters, &mut attrs, pid)?
-        } else if counters.contains(&Counter::Cycles) {
-            binding::grouped(counters, &mut attrs, pid)?
+        // perf refuses to mmap an inherited event that is not bound to a CPU,
+        // so following the target's children takes one group per online CPU.
+        // Inheriting a group read with PERF_SAMPLE_READ needs Linux 6.12;
+        // older kernels fall back to the target thread alone.
+        let requested = match (pid, scope) {
+            (Some(_), TaskScope::ProcessTree) => Inherit::All,
+            (Some(_), TaskScope::Process) => Inherit::Threads,
+            (Some(_), TaskScope::Thread) | (None, _) => Inherit::Off,
+        };
+        // `--sample-cpu` style restrictions bind every group to its CPU, so
+        // only execution on those CPUs is sampled.
+        let (any_cpu, inherit_cpus) = if cpus.is_empty() {
+            (vec![-1], binding::online_cpus())
         } else {
-            binding::grouped_software(counters, &mut attrs, pid)?
+            (cpus.clone(), cpus)
+        };
+        let first_leads = memory_latency || leader.is_some();
+        let (native_handles, inherit) = match requested {
+            Inherit::Off => (
+                Self::open_groups(counters, &attrs, pid, first_leads, Inherit::Off, &any_cpu)?,
+                Inherit::Off,
+            ),
+            inherit => {
+                match Self::open_groups(counters, &attrs, pid, first_leads, inherit, &inherit_cpus)
+                {
+                    Ok(handles) => (handles, inherit),
+                    Err(_) => (
+                        Self::open_groups(
+                            counters,
+                            &attrs,
+                            pid,
+                            first_leads,
+                            Inherit::Off,
+                            &any_cpu,
+                        )?,
+                        Inherit::Off,
+                    ),
+                }
+            }
         };
 
-        Self::from_handles(
+        let mut driver = Self::from_handles(
             native_handles,
             dwarf_mask_for_mode(unwind_mode),
             unwind_mode == UnwindMode::Lbr,
+            unwind_mode != UnwindMode::None,
+            memory_latency,
             pid.is_none(),
-        )
+            mmap_pages,
+        )?;
+        driver.task_scope = inherit.scope();
+        Ok(driver)
+    }
+
+    /// Open one sampling group per entry of `cpus` (`-1` for any CPU). With
+    /// `first_leads`, the first counter leads every group.
+    fn open_groups(
+        counters: &[Counter],
+        attrs: &[perf_event_attr],
+        pid: Option<i32>,
+        first_leads: bool,
+        inherit: Inherit,
+        cpus: &[i32],
+    ) -> Result<Vec<NativeCounterHandle>, Error> {
+        let mut native_handles = Vec::new();
+        for &cpu in cpus {
+            let mut attrs = attrs.to_vec();
+            for attr in &mut attrs {
+                inherit.apply(attr);
+            }
+            let handles = if pid.is_none() || first_leads {
+                binding::grouped_all(counters, &mut attrs, pid, cpu)
+            } else if counters.contains(&Counter::Cycles) {
+                binding::grouped(counters, &mut attrs, pid, cpu)
+            } else {
+                binding::grouped_software(counters, &mut attrs, pid, cpu)
+            };
+            match handles {
+                Ok(handles) => native_handles.extend(handles),
+                Err(error) => {
+                    binding::close_handles(&native_handles);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(native_handles)
     }
 
     /// Faithful per-core sampling: open a sampling group on every cluster's PMU
     /// (each with that cluster's event codes), so no cluster is invisible in the
     /// profile. Each handle is tagged with the cluster it samples so downstream
     /// consumers can attribute samples per core.
-    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    #[cfg(all(
+        any(target_arch = "aarch64", target_arch = "x86_64"),
+        target_os = "linux"
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn new_per_core(
         counters: &[Counter],
         sample_freq: u64,
         pid: Option<i32>,
         core_pmus: &[crate::cpu_family::CorePmu],
+        cpus: &[i32],
         unwind_mode: UnwindMode,
         stack_dump_size: u32,
         precise_ip: bool,
+        mmap_pages: usize,
+        first_leads: bool,
+        clock: SampleClock,
     ) -> Result<PerfSamplingDriver, Error> {
         let mut native_handles: Vec<NativeCounterHandle> = Vec::new();
+        let leader = counters.first().filter(|_| first_leads);
 
         for pmu in core_pmus {
             let core = core_id_of(pmu);
 
-            let mut attrs: Vec<perf_event_attr> = counters
+            // Events a core type does not implement are left out of its group.
+            let pmu_counters = counters
                 .iter()
-                .map(|cntr| {
-                    let resolved = resolve_counter_for_family(cntr, pmu.family_id, true)
-                        .unwrap_or_else(|| cntr.clone());
-                    let mut attr = build_pmu_attr(&resolved, pmu.pmu_type)?;
+                .filter_map(|cntr| {
+                    let resolved = resolve_for_core_pmu(cntr, pmu);
+                    #[cfg(target_arch = "aarch64")]
+                    let resolved = resolved.or_else(|| Some(cntr.clone()));
+                    resolved.map(|resolved| (cntr.clone(), resolved))
+                })
+                .collect::<Vec<_>>();
+            let (counters, resolved): (Vec<Counter>, Vec<Counter>) =
+                pmu_counters.into_iter().unzip();
+            if counters.is_empty() {
+                continue;
+            }
+            if leader.is_some_and(|leader| counters.first() != Some(leader)) {
+                return Err(Error::InvalidConfiguration(format!(
+                    "sampling leader {} is not available on {}",
+                    leader.map(Counter::name).unwrap_or_default(),
+                    core.name
+                )));
+            }
+
+            let mut attrs: Vec<perf_event_attr> = resolved
+                .iter()
+                .map(|resolved| {
+                    let mut attr = build_pmu_attr(resolved, pmu.pmu_type)?;
                     apply_sampling_flags(
                         &mut attr,
                         sample_freq,
@@ -611,28 +1037,50 @@ impl PerfSamplingDriver {
                         stack_dump_size,
                         pid.is_some(),
                         precise_ip,
+                        false,
                     );
+                    apply_clock(&mut attr, clock);
                     Ok(attr)
                 })
                 .collect::<Result<Vec<_>, Error>>()?;
+            if first_leads {
+                stop_sampling(&mut attrs[1..]);
+            }
 
-            let mut handles = if pid.is_none() {
-                binding::grouped_all(counters, &mut attrs, pid)?
+            // A CPU restriction keeps only this cluster's listed CPUs.
+            let pmu_cpus = if cpus.is_empty() {
+                vec![-1]
             } else {
-                binding::grouped(counters, &mut attrs, pid)?
+                binding::parse_cpu_list(&pmu.cpus)
+                    .into_iter()
+                    .filter(|cpu| cpus.contains(cpu))
+                    .collect()
             };
-            for handle in &mut handles {
-                handle.core = Some(core.clone());
-            }
+            for cpu in pmu_cpus {
+                let mut attrs = attrs.clone();
+                let mut handles = if pid.is_none() || first_leads {
+                    binding::grouped_all(&counters, &mut attrs, pid, cpu)?
+                } else if counters.contains(&Counter::Cycles) {
+                    binding::grouped(&counters, &mut attrs, pid, cpu)?
+                } else {
+                    binding::grouped_software(&counters, &mut attrs, pid, cpu)?
+                };
+                for handle in &mut handles {
+                    handle.core = Some(core.clone());
+                }
 
-            native_handles.extend(handles);
+                native_handles.extend(handles);
+            }
         }
 
         Self::from_handles(
             native_handles,
             dwarf_mask_for_mode(unwind_mode),
             unwind_mode == UnwindMode::Lbr,
+            unwind_mode != UnwindMode::None,
+            false,
             pid.is_none(),
+            mmap_pages,
         )
     }
 
@@ -641,30 +1089,35 @@ impl PerfSamplingDriver {
         native_handles: Vec<NativeCounterHandle>,
         sample_regs_user: u64,
         sample_branch_stack: bool,
+        sample_callchain: bool,
+        sample_weight: bool,
         enable_on_start: bool,
+        mmap_pages: usize,
     ) -> Result<PerfSamplingDriver, Error> {
         let page_size = unsafe { sysconf(libc::_SC_PAGE_SIZE) } as usize;
-        let mmap_pages = 512;
 
-        let length = page_size * (mmap_pages + 1);
-        let mut mmaps: Vec<UnsafeMmap> = Vec::new();
+        let length = page_size.saturating_mul(mmap_pages.saturating_add(1));
+        let leaders = native_handles.iter().filter(|handle| handle.leader).count();
+        let available = unsafe { sysconf(libc::_SC_AVPHYS_PAGES) };
+        if available > 0 && length.saturating_mul(leaders) > page_size * available as usize {
+            for native_handle in &native_handles {
+                unsafe { close(native_handle.fd) };
+            }
+            return Err(Error::InvalidConfiguration(format!(
+                "{leaders} ring buffers of {mmap_pages} pages need {} MiB, more than the {} MiB of available memory",
+                length.saturating_mul(leaders) >> 20,
+                (page_size * available as usize) >> 20
+            )));
+        }
+        let mut mmaps = Vec::new();
         for handle in native_handles.iter().filter(|handle| handle.leader) {
-            let ptr = unsafe {
-                let ptr = mmap(
-                    std::ptr::null_mut(),
-                    length,
-                    PROT_READ | PROT_WRITE,
-                    MAP_SHARED,
-                    handle.fd,
-                    0,
-                ) as *mut u8;
-                if ptr as *mut libc::c_void == MAP_FAILED {
-                    let source = std::io::Error::last_os_error();
-                    for mmap in &mmaps {
-                        munmap(mmap.ptr.cast(), length);
-                    }
+            match RingBuffer::map(handle.fd, length) {
+                Ok(mmap) => mmaps.push(mmap),
+                Err(source) => {
+                    // Unmap the buffers before closing their events.
+                    drop(mmaps);
                     for native_handle in &native_handles {
-                        close(native_handle.fd);
+                        unsafe { close(native_handle.fd) };
                     }
                     return Err(Error::PerfMmap {
                         counter: handle.kind.name().to_owned(),
@@ -672,25 +1125,35 @@ impl PerfSamplingDriver {
                         source,
                     });
                 }
-                ptr
-            };
-            mmaps.push(UnsafeMmap { ptr });
+            }
         }
 
         Ok(PerfSamplingDriver {
             native_handles,
-            mmaps,
-            page_size,
-            mmap_pages,
+            mmaps: Arc::new(mmaps),
             running: Arc::new(AtomicBool::new(false)),
             thread_handle: None,
+            heartbeat: Heartbeat::new(),
             sample_regs_user,
             sample_branch_stack,
+            sample_callchain,
+            sample_weight,
             enable_on_start,
+            task_scope: TaskScope::Thread,
         })
     }
 }
 
+/// `MEM_TRANS_RETIRED.LOAD_LATENCY`: event 0xcd, umask 0x01. The latency
+/// threshold is programmed separately through `config1`.
+fn load_latency_counter() -> Counter {
+    Counter::Internal {
+        name: "MEM_TRANS_RETIRED.LOAD_LATENCY".to_owned(),
+        desc: "Retired loads slower than the load-latency threshold".to_owned(),
+        code: 0x01cd,
+    }
+}
+
 fn dwarf_mask_for_mode(mode: UnwindMode) -> u64 {
     if mode == UnwindMode::Dwarf {
         dwarf_register_mask()
@@ -719,14 +1182,13 @@ fn dwarf_register_mask() -> u64 {
 
 impl Drop for PerfSamplingDriver {
     fn drop(&mut self) {
-        for &mmap in &self.mmaps {
-            unsafe {
-                munmap(
-                    mmap.ptr as *mut std::ffi::c_void,
-                    self.page_size * (self.mmap_pages + 1),
-                );
-            }
+        // Without `stop` the reader is still running; it must not outlive the
+        // events it reads. A panic it exited with has nowhere to go here.
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = join_reader(handle);
         }
+        // The ring buffers are unmapped when `mmaps` is dropped after this.
         for handle in &self.native_handles {
             unsafe { close(handle.fd) };
         }
@@ -750,13 +1212,17 @@ fn base_counter_attr() -> perf_event_attr {
 }
 
 /// Map a resolved counter to its `(type_, config)` pair using the legacy
-/// generic encodings (`PERF_TYPE_HARDWARE`/`SOFTWARE`/`RAW`).
+/// generic encodings (`PERF_TYPE_HARDWARE`/`HW_CACHE`/`SOFTWARE`/`RAW`).
 fn counter_type_config(cntr: &Counter) -> Result<(u32, u64), Error> {
     Ok(match cntr {
         Counter::Cycles => (
             sys::bindings::PERF_TYPE_HARDWARE,
             sys::bindings::PERF_COUNT_HW_CPU_CYCLES as u64,
         ),
+        Counter::RefCycles => (
+            sys::bindings::PERF_TYPE_HARDWARE,
+            sys::bindings::PERF_COUNT_HW_REF_CPU_CYCLES as u64,
+        ),
         Counter::Instructions => (
             sys::bindings::PERF_TYPE_HARDWARE,
             sys::bindings::PERF_COUNT_HW_INSTRUCTIONS as u64,
@@ -785,6 +1251,26 @@ fn counter_type_config(cntr: &Counter) -> Result<(u32, u64), Error> {
             sys::bindings::PERF_TYPE_HARDWARE,
             sys::bindings::PERF_COUNT_HW_STALLED_CYCLES_BACKEND as u64,
         ),
+        Counter::L1DReferences => crate::hw_cache_read(
+            sys::bindings::PERF_COUNT_HW_CACHE_L1D,
+            sys::bindings::PERF_COUNT_HW_CACHE_RESULT_ACCESS,
+        ),
+        Counter::L1DMisses => crate::hw_cache_read(
+            sys::bindings::PERF_COUNT_HW_CACHE_L1D,
+            sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+        ),
+        Counter::L1IMisses => crate::hw_cache_read(
+            sys::bindings::PERF_COUNT_HW_CACHE_L1I,
+            sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+        ),
+        Counter::DTLBMisses => crate::hw_cache_read(
+            sys::bindings::PERF_COUNT_HW_CACHE_DTLB,
+            sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+        ),
+        Counter::ITLBMisses => crate::hw_cache_read(
+            sys::bindings::PERF_COUNT_HW_CACHE_ITLB,
+            sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+        ),
         Counter::CpuClock => (
             sys::bindings::PERF_TYPE_SOFTWARE,
             sys::bindings::PERF_COUNT_SW_CPU_CLOCK as u64,
@@ -802,6 +1288,7 @@ fn counter_type_config(cntr: &Counter) -> Result<(u32, u64), Error> {
             sys::bindings::PERF_COUNT_SW_PAGE_FAULTS as u64,
         ),
         Counter::Internal { code, .. } => (sys::bindings::PERF_TYPE_RAW, *code),
+        Counter::Raw { type_, config, .. } => (*type_, *config),
         Counter::Custom(name) => {
             return Err(Error::InvalidConfiguration(format!(
                 "custom counter '{name}' was not resolved"
@@ -854,7 +1341,10 @@ fn get_native_counters(
 /// PMU (`pmu_type`). Hardware and raw events are routed to that PMU so they
 /// count only while the task runs on that cluster; software events are left on
 /// the generic software PMU.
-#[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+#[cfg(all(
+    any(target_arch = "aarch64", target_arch = "x86_64"),
+    target_os = "linux"
+))]
 fn build_pmu_attr(resolved: &Counter, pmu_type: u32) -> Result<perf_event_attr, Error> {
     let mut attrs = base_counter_attr();
     let (type_, config) = counter_type_config(resolved)?;
@@ -863,6 +1353,7 @@ fn build_pmu_attr(resolved: &Counter, pmu_type: u32) -> Result<perf_event_attr,
         attrs.type_ = pmu_type;
         attrs.config = config;
     } else if type_ == sys::bindings::PERF_TYPE_HARDWARE {
+        #[cfg(target_arch = "aarch64")]
         if let Some(code) = aarch64_hw_event_code(config) {
             attrs.type_ = pmu_type;
             attrs.config = code;
@@ -870,6 +1361,21 @@ fn build_pmu_attr(resolved: &Counter, pmu_type: u32) -> Result<perf_event_attr,
             attrs.type_ = type_;
             attrs.config = config;
         }
+        // Hybrid x86 PMUs take generic hardware events with the PMU type in
+        // the upper half of `config` (`PERF_PMU_TYPE_SHIFT`).
+        #[cfg(target_arch = "x86_64")]
+        {
+            attrs.type_ = type_;
+            attrs.config = config | ((pmu_type as u64) << 32);
+        }
+    } else if type_ == sys::bindings::PERF_TYPE_HW_CACHE {
+        // Generic cache events take the PMU type the same way on hybrid x86.
+        attrs.type_ = type_;
+        attrs.config = config;
+        #[cfg(target_arch = "x86_64")]
+        {
+            attrs.config |= (pmu_type as u64) << 32;
+        }
     } else {
         attrs.type_ = type_;
         attrs.config = config;
@@ -900,3 +1406,85 @@ fn aarch64_hw_event_code(config: u64) -> Option<u64> {
     };
     Some(code)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ring buffer backed by a zeroed memfd, which holds no records.
+    fn empty_ring_buffer() -> RingBuffer {
+        let length = 2 * unsafe { sysconf(libc::_SC_PAGE_SIZE) } as usize;
+        let fd = unsafe { libc::memfd_create(c"ring".as_ptr(), 0) };
+        assert!(fd >= 0);
+        assert_eq!(unsafe { libc::ftruncate(fd, length as i64) }, 0);
+        let ring = RingBuffer::map(fd, length).unwrap();
+        unsafe { close(fd) };
+        ring
+    }
+
+    #[test]
+    fn dropping_a_running_driver_stops_the_reader_before_unmapping() {
+        let mut driver = PerfSamplingDriver {
+            native_handles: vec![],
+            mmaps: Arc::new(vec![empty_ring_buffer(), empty_ring_buffer()]),
+            running: Arc::new(AtomicBool::new(false)),
+            thread_handle: None,
+            heartbeat: Heartbeat::new(),
+            enable_on_start: false,
+            sample_regs_user: 0,
+            sample_branch_stack: false,
+            sample_callchain: false,
+            sample_weight: false,
+            task_scope: TaskScope::Thread,
+        };
+        let callback: Arc<dyn SamplingCallback> = Arc::new(|_: Record| {});
+        driver.start(callback.clone()).unwrap();
+        let mmaps = Arc::downgrade(&driver.mmaps);
+        assert_eq!(mmaps.strong_count(), 2);
+
+        drop(driver);
+
+        // The reader let go of both the buffers and the callback, and the
+        // buffers were unmapped with the driver.
+        assert_eq!(Arc::strong_count(&callback), 1);
+        assert!(mmaps.upgrade().is_none());
+    }
+
+    #[test]
+    fn the_sampling_leader_leads_the_counters() {
+        let counters = [Counter::Cycles, Counter::Instructions, Counter::LLCMisses];
+        assert_eq!(
+            leader_first(&counters, &Counter::LLCMisses).unwrap(),
+            [Counter::LLCMisses, Counter::Cycles, Counter::Instructions]
+        );
+        assert!(leader_first(&counters, &Counter::BranchMisses).is_err());
+    }
+
+    #[test]
+    fn only_user_space_clocks_set_a_clockid() {
+        let mut attr = base_counter_attr();
+        apply_clock(&mut attr, SampleClock::Perf);
+        assert_eq!(attr.use_clockid(), 0);
+
+        apply_clock(&mut attr, SampleClock::MonotonicRaw);
+        assert_eq!(attr.use_clockid(), 1);
+        assert_eq!(attr.clockid, libc::CLOCK_MONOTONIC_RAW);
+    }
+
+    #[test]
+    fn cache_counters_use_the_generic_cache_encoding() {
+        // perf's L1-dcache-loads, dTLB-load-misses and iTLB-load-misses.
+        assert_eq!(
+            counter_type_config(&Counter::L1DReferences).unwrap(),
+            (sys::bindings::PERF_TYPE_HW_CACHE, 0x0)
+        );
+        assert_eq!(
+            counter_type_config(&Counter::DTLBMisses).unwrap(),
+            (sys::bindings::PERF_TYPE_HW_CACHE, 0x10003)
+        );
+        assert_eq!(
+            counter_type_config(&Counter::ITLBMisses).unwrap(),
+            (sys::bindings::PERF_TYPE_HW_CACHE, 0x10004)
+        );
+    }
+}
@@ -57,10 +57,44 @@ pub fn direct(
     Ok(handles)
 }
 
+/// Opens the hardware counters of a counting driver as one group, so a
+/// single read of any member returns all of them sampled at the same
+/// instant. Software counters are opened on their own. The group must fit
+/// the PMU: a group is only ever scheduled as a whole.
+pub fn counting_group(
+    counters: &[Counter],
+    attrs: &mut [perf_event_attr],
+    pid: Option<i32>,
+) -> Result<Vec<NativeCounterHandle>, Error> {
+    let mut handles: Vec<NativeCounterHandle> = vec![];
+    let mut leader_fd = -1;
+
+    for (cntr, attr) in std::iter::zip(counters, attrs) {
+        let group_fd = if cntr.is_software() { -1 } else { leader_fd };
+        let new_fd = unsafe {
+            sys::perf_event_open(
+                &mut *attr as *mut perf_event_attr,
+                pid.unwrap_or(0),
+                -1,
+                group_fd,
+                0,
+            )
+        };
+        let leader = !cntr.is_software() && leader_fd < 0;
+        push_handle(&mut handles, new_fd, cntr.clone(), leader)?;
+        if leader {
+            leader_fd = new_fd;
+        }
+    }
+
+    Ok(handles)
+}
+
 pub fn grouped(
     counters: &[Counter],
     attrs: &mut [perf_event_attr],
     pid: Option<i32>,
+    cpu: i32,
 ) -> Result<Vec<NativeCounterHandle>, Error> {
     // TMA passes one complete group after another, each beginning with cycles.
     // Do not flatten these into the historical arbitrary chunks: doing so
@@ -86,6 +120,7 @@ pub fn grouped(
                 &counters[*start..end],
                 &mut attrs[*start..end],
                 pid,
+                cpu,
             )?);
         }
         return Ok(handles);
@@ -156,7 +191,7 @@ pub fn grouped(
                 Error::InvalidConfiguration("configured sampling leader is missing".to_owned())
             })?;
             let leader_fd =
-                unsafe { sys::perf_event_open(&mut leader_attr, pid.unwrap_or(0), -1, -1, 0) };
+                unsafe { sys::perf_event_open(&mut leader_attr, pid.unwrap_or(0), cpu, -1, 0) };
             push_handle(&mut handles, leader_fd, leader_counter.clone(), true)?;
             leader_fd
         } else {
@@ -164,7 +199,13 @@ pub fn grouped(
         };
 
         let cycles_fd = unsafe {
-            sys::perf_event_open(&mut cycles_attrs, pid.unwrap_or(0), -1, cycles_leader_fd, 0)
+            sys::perf_event_open(
+                &mut cycles_attrs,
+                pid.unwrap_or(0),
+                cpu,
+                cycles_leader_fd,
+                0,
+            )
         };
 
         let leader_fd = if leader.is_some() {
@@ -176,19 +217,19 @@ pub fn grouped(
         push_handle(&mut handles, cycles_fd, Counter::Cycles, leader.is_none())?;
 
         let instr_fd =
-            unsafe { sys::perf_event_open(&mut instr_attrs, pid.unwrap_or(0), -1, leader_fd, 0) };
+            unsafe { sys::perf_event_open(&mut instr_attrs, pid.unwrap_or(0), cpu, leader_fd, 0) };
 
         push_handle(&mut handles, instr_fd, Counter::Instructions, false)?;
 
         for (cntr, attrs) in chunk {
             let new_fd =
-                unsafe { sys::perf_event_open(&mut *attrs, pid.unwrap_or(0), -1, leader_fd, 0) };
+                unsafe { sys::perf_event_open(&mut *attrs, pid.unwrap_or(0), cpu, leader_fd, 0) };
             push_handle(&mut handles, new_fd, cntr.clone(), false)?;
         }
 
         for (cntr, attrs) in &mut sw_counters {
             let new_fd =
-                unsafe { sys::perf_event_open(&mut *attrs, pid.unwrap_or(0), -1, leader_fd, 0) };
+                unsafe { sys::perf_event_open(&mut *attrs, pid.unwrap_or(0), cpu, leader_fd, 0) };
             push_handle(&mut handles, new_fd, cntr.clone(), false)?;
         }
     }
@@ -203,6 +244,7 @@ pub fn grouped_software(
     counters: &[Counter],
     attrs: &mut [perf_event_attr],
     pid: Option<i32>,
+    cpu: i32,
 ) -> Result<Vec<NativeCounterHandle>, Error> {
     let Some(leader_index) = counters
         .iter()
@@ -215,14 +257,14 @@ pub fn grouped_software(
 
     let mut handles = Vec::with_capacity(counters.len());
     let leader_fd =
-        unsafe { sys::perf_event_open(&mut attrs[leader_index], pid.unwrap_or(0), -1, -1, 0) };
+        unsafe { sys::perf_event_open(&mut attrs[leader_index], pid.unwrap_or(0), cpu, -1, 0) };
     push_handle(&mut handles, leader_fd, Counter::CpuClock, true)?;
 
     for (index, (counter, attr)) in zip(counters, attrs).enumerate() {
         if index == leader_index {
             continue;
         }
-        let fd = unsafe { sys::perf_event_open(attr, pid.unwrap_or(0), -1, leader_fd, 0) };
+        let fd = unsafe { sys::perf_event_open(attr, pid.unwrap_or(0), cpu, leader_fd, 0) };
         push_handle(&mut handles, fd, counter.clone(), false)?;
     }
 
@@ -235,6 +277,7 @@ pub fn grouped_all(
     counters: &[Counter],
     attrs: &mut [perf_event_attr],
     pid: Option<i32>,
+    cpu: i32,
 ) -> Result<Vec<NativeCounterHandle>, Error> {
     if counters.is_empty() || counters.len() != attrs.len() {
         return Err(Error::InvalidConfiguration(
@@ -243,10 +286,10 @@ pub fn grouped_all(
     }
 
     let mut handles = Vec::with_capacity(counters.len());
-    let leader_fd = unsafe { sys::perf_event_open(&mut attrs[0], pid.unwrap_or(0), -1, -1, 0) };
+    let leader_fd = unsafe { sys::perf_event_open(&mut attrs[0], pid.unwrap_or(0), cpu, -1, 0) };
     push_handle(&mut handles, leader_fd, counters[0].clone(), true)?;
     for (counter, attr) in zip(&counters[1..], &mut attrs[1..]) {
-        let fd = unsafe { sys::perf_event_open(attr, pid.unwrap_or(0), -1, leader_fd, 0) };
+        let fd = unsafe { sys::perf_event_open(attr, pid.unwrap_or(0), cpu, leader_fd, 0) };
         push_handle(&mut handles, fd, counter.clone(), false)?;
     }
     Ok(handles)
@@ -294,8 +337,40 @@ fn get_native_handle(fd: i32, cntr: Counter, leader: bool) -> Result<NativeCount
     })
 }
 
-fn close_handles(handles: &[NativeCounterHandle]) {
+pub fn close_handles(handles: &[NativeCounterHandle]) {
     for handle in handles {
         unsafe { libc::close(handle.fd) };
     }
 }
+
+/// Online CPUs from `/sys/devices/system/cpu/online`, e.g. `"0-3,6"`.
+/// Inherited sampling events need one ring buffer per CPU.
+pub fn online_cpus() -> Vec<i32> {
+    std::fs::read_to_string("/sys/devices/system/cpu/online")
+        .map(|list| parse_cpu_list(&list))
+        .unwrap_or_default()
+}
+
+/// Parses a sysfs CPU list such as `"0-3,6"`.
+pub fn parse_cpu_list(list: &str) -> Vec<i32> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        if let (Ok(start), Ok(end)) = (start.parse::<i32>(), end.parse::<i32>()) {
+            cpus.extend(start..=end);
+        }
+    }
+    cpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cpu_list;
+
+    #[test]
+    fn parses_online_cpu_ranges() {
+        assert_eq!(parse_cpu_list("0-3,6\n"), vec![0, 1, 2, 3, 6]);
+        assert_eq!(parse_cpu_list("0"), vec![0]);
+        assert!(parse_cpu_list("").is_empty());
+    }
+}
@@ -3,11 +3,17 @@ use crate::{cpu_family, Counter};
 pub fn list_supported_counters() -> Vec<Counter> {
     let mut counters = vec![
         Counter::Cycles,
+        Counter::RefCycles,
         Counter::Instructions,
         Counter::BranchInstructions,
         Counter::BranchMisses,
         Counter::LLCMisses,
         Counter::LLCReferences,
+        Counter::L1DReferences,
+        Counter::L1DMisses,
+        Counter::L1IMisses,
+        Counter::DTLBMisses,
+        Counter::ITLBMisses,
         Counter::CpuClock,
         Counter::PageFaults,
         Counter::CpuMigrations,
@@ -69,7 +75,7 @@ pub fn resolve_counter_for_family(
 
         // Already a concrete raw event: assume the caller knows it is valid for
         // this family (it originates from this family's event table).
-        Counter::Internal { .. } => Some(counter.clone()),
+        Counter::Internal { .. } | Counter::Raw { .. } => Some(counter.clone()),
 
         // Generic hardware counters: remap to this family's architectural event
         // via the alias table when possible, otherwise keep the generic form.
@@ -1,7 +1,13 @@
-use std::{ffi::CStr, sync::atomic::AtomicU64};
+use std::{
+    ffi::{CStr, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    sync::atomic::AtomicU64,
+};
 
 use perf_event_open_sys::bindings::{
-    perf_event_header, perf_event_mmap_page, PERF_RECORD_MMAP, PERF_RECORD_SAMPLE,
+    perf_event_header, perf_event_mmap_page, PERF_RECORD_COMM, PERF_RECORD_EXIT, PERF_RECORD_FORK,
+    PERF_RECORD_LOST, PERF_RECORD_MISC_COMM_EXEC, PERF_RECORD_MMAP, PERF_RECORD_SAMPLE,
 };
 use smallvec::{SmallVec, ToSmallVec};
 
@@ -11,6 +17,8 @@ pub struct Records {
     metadata: *mut perf_event_mmap_page,
     sample_regs_user: u64,
     sample_branch_stack: bool,
+    sample_callchain: bool,
+    sample_weight: bool,
 }
 
 #[repr(C)]
@@ -43,14 +51,26 @@ pub enum MmapRecord {
         callstack: SmallVec<[u64; 8]>,
         user_regs: Option<UserRegs>,
         user_stack: Vec<u8>,
+        weight: u64,
     },
     Address {
         pid: u32,
         start: u64,
         len: u64,
         offset: u64,
-        filename: String,
+        filename: PathBuf,
     },
+    Comm {
+        pid: u32,
+        tid: u32,
+        comm: String,
+        /// Time of the `exec` that set the name; `None` for a rename.
+        exec_time: Option<u64>,
+    },
+    Fork(TaskRecord),
+    Exit(TaskRecord),
+    /// The kernel dropped this many records because the ring buffer was full.
+    Lost(u64),
     Unknown,
 }
 
@@ -69,12 +89,64 @@ pub struct SampleFormat {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct ReadFormat {
     pub nr: u64,
     pub time_enabled: u64,
     pub time_running: u64,
 }
 
+/// Decodes a `PERF_FORMAT_GROUP | PERF_FORMAT_ID` read: the shared times
+/// and the value of every group member, tagged with its id. `len` is the
+/// number of bytes `read` returned.
+pub(crate) fn group_values(buffer: &[u8], len: usize) -> (ReadFormat, Vec<EventValue>) {
+    let header_size = std::mem::size_of::<ReadFormat>();
+    if len < header_size {
+        return (ReadFormat::default(), Vec::new());
+    }
+    let header = unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const ReadFormat) };
+    let available = (len - header_size) / std::mem::size_of::<EventValue>();
+    let values = (0..(header.nr as usize).min(available))
+        .map(|index| unsafe {
+            std::ptr::read_unaligned(
+                buffer
+                    .as_ptr()
+                    .add(header_size + index * std::mem::size_of::<EventValue>())
+                    as *const EventValue,
+            )
+        })
+        .collect();
+    (header, values)
+}
+
+#[repr(C)]
+struct ProcComm {
+    header: perf_event_header,
+    pid: u32,
+    tid: u32,
+    // NUL-terminated comm, padded to 8 bytes
+}
+
+/// Shared layout of `PERF_RECORD_FORK` and `PERF_RECORD_EXIT`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TaskRecord {
+    header: perf_event_header,
+    pub pid: u32,
+    pub ppid: u32,
+    pub tid: u32,
+    pub ptid: u32,
+    pub time: u64,
+}
+
+/// Layout of `PERF_RECORD_LOST`.
+#[repr(C)]
+struct LostRecord {
+    header: perf_event_header,
+    id: u64,
+    lost: u64,
+}
+
 #[repr(C)]
 struct ProcMmap {
     header: perf_event_header,
@@ -87,11 +159,19 @@ struct ProcMmap {
 }
 
 impl Records {
-    pub fn from_ptr(ptr: *mut u8, sample_regs_user: u64, sample_branch_stack: bool) -> Records {
+    pub fn from_ptr(
+        ptr: *mut u8,
+        sample_regs_user: u64,
+        sample_branch_stack: bool,
+        sample_callchain: bool,
+        sample_weight: bool,
+    ) -> Records {
         Records {
             metadata: ptr as *mut perf_event_mmap_page,
             sample_regs_user,
             sample_branch_stack,
+            sample_callchain,
+            sample_weight,
         }
     }
 
@@ -202,7 +282,11 @@ impl Iterator for Records {
             PERF_RECORD_SAMPLE => match SampleFormat::read_from_bytes(&record_buf) {
                 Some(sample_format) => {
                     let values = sample_format.read_values(&record_buf);
-                    let mut callstack = sample_format.read_callchain(&record_buf);
+                    let mut callstack = if self.sample_callchain {
+                        sample_format.read_callchain(&record_buf)
+                    } else {
+                        smallvec::smallvec![sample_format.ip]
+                    };
                     if self.sample_branch_stack {
                         let lbr_callstack = sample_format.read_branch_callstack(&record_buf);
                         if lbr_callstack.len() > 1 {
@@ -214,6 +298,18 @@ impl Iterator for Records {
                         self.sample_regs_user,
                         self.sample_branch_stack,
                     );
+                    let weight = if self.sample_weight {
+                        sample_format
+                            .read_weight(
+                                &record_buf,
+                                self.sample_regs_user,
+                                self.sample_branch_stack,
+                                self.sample_callchain,
+                            )
+                            .unwrap_or_default()
+                    } else {
+                        0
+                    };
 
                     MmapRecord::Sample {
                         ip: sample_format.ip,
@@ -227,6 +323,7 @@ impl Iterator for Records {
                         callstack,
                         user_regs,
                         user_stack,
+                        weight,
                     }
                 }
                 None => MmapRecord::Unknown,
@@ -244,6 +341,23 @@ impl Iterator for Records {
                 }
                 None => MmapRecord::Unknown,
             },
+            PERF_RECORD_COMM => match ProcComm::read_from_bytes(&record_buf) {
+                Some(comm_record) => MmapRecord::Comm {
+                    pid: comm_record.pid,
+                    tid: comm_record.tid,
+                    comm: read_c_string(&record_buf, std::mem::size_of::<ProcComm>()),
+                    exec_time: (u32::from(header.misc) & PERF_RECORD_MISC_COMM_EXEC != 0)
+                        .then(|| sample_id_time(&record_buf))
+                        .flatten(),
+                },
+                None => MmapRecord::Unknown,
+            },
+            PERF_RECORD_FORK => TaskRecord::read_from_bytes(&record_buf)
+                .map_or(MmapRecord::Unknown, MmapRecord::Fork),
+            PERF_RECORD_EXIT => TaskRecord::read_from_bytes(&record_buf)
+                .map_or(MmapRecord::Unknown, MmapRecord::Exit),
+            PERF_RECORD_LOST => LostRecord::read_from_bytes(&record_buf)
+                .map_or(MmapRecord::Unknown, |lost| MmapRecord::Lost(lost.lost)),
             _ => MmapRecord::Unknown,
         };
 
@@ -313,9 +427,13 @@ impl SampleFormat {
         }
     }
 
+    fn values_end(&self) -> Option<usize> {
+        std::mem::size_of::<SampleFormat>()
+            .checked_add((self.read.nr as usize).checked_mul(std::mem::size_of::<EventValue>())?)
+    }
+
     fn callchain_end(&self, record: &[u8]) -> Option<usize> {
-        let base = std::mem::size_of::<SampleFormat>()
-            .checked_add((self.read.nr as usize).checked_mul(std::mem::size_of::<EventValue>())?)?;
+        let base = self.values_end()?;
         let nr = read_u64(record, base)? as usize;
         base.checked_add(std::mem::size_of::<u64>())?
             .checked_add(nr.checked_mul(std::mem::size_of::<u64>())?)
@@ -407,6 +525,38 @@ impl SampleFormat {
         let dynamic_size = dynamic_size.min(stack.len());
         (regs, stack[..dynamic_size].to_vec())
     }
+
+    /// `PERF_SAMPLE_WEIGHT` follows every field this crate requests, so its
+    /// offset depends on the size of the variable-length user state.
+    fn read_weight(
+        &self,
+        record: &[u8],
+        mask: u64,
+        sample_branch_stack: bool,
+        sample_callchain: bool,
+    ) -> Option<u64> {
+        let mut offset = if sample_branch_stack {
+            self.branch_stack_end(record)?
+        } else if sample_callchain {
+            self.callchain_end(record)?
+        } else {
+            self.values_end()?
+        };
+        if mask != 0 {
+            let abi = read_u64(record, offset)?;
+            offset = offset.checked_add(8)?;
+            if abi != 0 {
+                offset = offset.checked_add((mask.count_ones() as usize).checked_mul(8)?)?;
+            }
+            let stack_size = read_u64(record, offset)? as usize;
+            offset = offset.checked_add(8)?.checked_add(stack_size)?;
+            // dyn_size is only present after a non-empty stack dump.
+            if stack_size != 0 {
+                offset = offset.checked_add(8)?;
+            }
+        }
+        read_u64(record, offset)
+    }
 }
 
 fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
@@ -424,22 +574,115 @@ impl ProcMmap {
         Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
     }
 
-    fn filename(bytes: &[u8]) -> String {
-        let start = std::mem::size_of::<Self>();
-        if bytes.len() <= start {
-            return String::new();
+    fn filename(bytes: &[u8]) -> PathBuf {
+        read_c_path(bytes, std::mem::size_of::<Self>())
+    }
+}
+
+impl TaskRecord {
+    fn read_from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < std::mem::size_of::<Self>() {
+            return None;
         }
 
-        match CStr::from_bytes_until_nul(&bytes[start..]) {
-            Ok(cstr) => cstr.to_string_lossy().into_owned(),
-            Err(_) => String::new(),
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+impl LostRecord {
+    fn read_from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < std::mem::size_of::<Self>() {
+            return None;
+        }
+
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+impl ProcComm {
+    fn read_from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < std::mem::size_of::<Self>() {
+            return None;
         }
+
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+/// Size of the `sample_id` trailer of non-sample records. Sampling events set
+/// `sample_id_all` and always sample `TID | TIME | ID | CPU`, so the trailer
+/// is `{ pid, tid }`, `time`, `id` and `{ cpu, res }`.
+const SAMPLE_ID_SIZE: usize = 32;
+
+/// The `time` of a non-sample record's `sample_id` trailer.
+fn sample_id_time(record: &[u8]) -> Option<u64> {
+    let start = record.len().checked_sub(SAMPLE_ID_SIZE)? + 8;
+    let bytes = record.get(start..start + 8)?;
+    Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+fn read_c_string(bytes: &[u8], start: usize) -> String {
+    if bytes.len() <= start {
+        return String::new();
     }
+
+    match CStr::from_bytes_until_nul(&bytes[start..]) {
+        Ok(cstr) => cstr.to_string_lossy().into_owned(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Reads a NUL-terminated path as the kernel reports it: bytes that need
+/// not be UTF-8.
+fn read_c_path(bytes: &[u8], start: usize) -> PathBuf {
+    let raw = bytes.get(start..).unwrap_or_default();
+    let raw = CStr::from_bytes_until_nul(raw).map_or(&[][..], CStr::to_bytes);
+    PathBuf::from(OsStr::from_bytes(raw))
 }
 
 #[cfg(test)]
 mod test {
-    use super::Records;
+    use super::{group_values, MmapRecord, Records};
+
+    /// Decodes `records` from a fake ring buffer: a metadata page followed by
+    /// the data area.
+    fn decode(records: &[u8]) -> Vec<MmapRecord> {
+        use perf_event_open_sys::bindings::perf_event_mmap_page;
+
+        let page_size = std::mem::size_of::<perf_event_mmap_page>();
+        let data_size = records.len().next_power_of_two().max(64);
+        // u64 storage keeps the metadata page suitably aligned.
+        let mut buffer = vec![0u64; (page_size + data_size) / 8];
+        let bytes = buffer.as_mut_ptr() as *mut u8;
+        let metadata = bytes as *mut perf_event_mmap_page;
+        unsafe {
+            std::ptr::copy_nonoverlapping(records.as_ptr(), bytes.add(page_size), records.len());
+            (*metadata).data_offset = page_size as u64;
+            (*metadata).data_size = data_size as u64;
+            (*metadata).data_head = records.len() as u64;
+        }
+        Records::from_ptr(bytes, 0, false, true, false).collect()
+    }
+
+    #[test]
+    fn group_reads_list_every_member() {
+        let words: [u64; 7] = [2, 1_000, 500, 42, 7, 84, 8];
+        let buffer = words
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect::<Vec<_>>();
+
+        let (header, values) = group_values(&buffer, buffer.len());
+        assert_eq!((header.time_enabled, header.time_running), (1_000, 500));
+        let values = values
+            .iter()
+            .map(|value| (value.id, value.value))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [(7, 42), (8, 84)]);
+
+        // A short read never yields members past its end.
+        assert_eq!(group_values(&buffer, 5 * 8).1.len(), 1);
+    }
 
     #[test]
     fn basic_test() {
@@ -748,12 +991,156 @@ mod test {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        let records = Records::from_ptr(test_data.as_mut_ptr(), 0, false);
+        let records = Records::from_ptr(test_data.as_mut_ptr(), 0, false, true, false);
         let decoded = records.into_iter().collect::<Vec<_>>();
 
         insta::assert_debug_snapshot!(decoded);
     }
 
+    #[test]
+    fn comm_record_is_decoded() {
+        use perf_event_open_sys::bindings::{perf_event_header, PERF_RECORD_COMM};
+
+        let mut record = Vec::new();
+        let header = perf_event_header {
+            type_: PERF_RECORD_COMM,
+            misc: 0,
+            size: 32,
+        };
+        record.extend_from_slice(&header.type_.to_ne_bytes());
+        record.extend_from_slice(&header.misc.to_ne_bytes());
+        record.extend_from_slice(&header.size.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&42_u32.to_ne_bytes());
+        record.extend_from_slice(b"gc-worker\0\0\0\0\0\0\0");
+
+        let decoded = decode(&record);
+        assert!(matches!(
+            decoded.as_slice(),
+            [MmapRecord::Comm { pid: 41, tid: 42, comm, exec_time: None }]
+                if comm == "gc-worker"
+        ));
+    }
+
+    #[test]
+    fn mmap_record_keeps_non_utf8_filenames() {
+        use perf_event_open_sys::bindings::{perf_event_header, PERF_RECORD_MMAP};
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        // Latin-1 "libcafé.so", which is not valid UTF-8.
+        let filename = b"/opt/libcaf\xe9.so\0\0\0\0\0\0\0\0";
+        let mut record = Vec::new();
+        let header = perf_event_header {
+            type_: PERF_RECORD_MMAP,
+            misc: 0,
+            size: (40 + filename.len()) as u16,
+        };
+        record.extend_from_slice(&header.type_.to_ne_bytes());
+        record.extend_from_slice(&header.misc.to_ne_bytes());
+        record.extend_from_slice(&header.size.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&0x1000_u64.to_ne_bytes());
+        record.extend_from_slice(&0x2000_u64.to_ne_bytes());
+        record.extend_from_slice(&0_u64.to_ne_bytes());
+        record.extend_from_slice(filename);
+
+        let decoded = decode(&record);
+        let expected = Path::new(OsStr::from_bytes(b"/opt/libcaf\xe9.so"));
+        assert!(matches!(
+            decoded.as_slice(),
+            [MmapRecord::Address { pid: 41, start: 0x1000, len: 0x2000, filename, .. }]
+                if filename == expected
+        ));
+    }
+
+    #[test]
+    fn exec_comm_record_has_its_time() {
+        use perf_event_open_sys::bindings::{
+            perf_event_header, PERF_RECORD_COMM, PERF_RECORD_MISC_COMM_EXEC,
+        };
+
+        let mut record = Vec::new();
+        let header = perf_event_header {
+            type_: PERF_RECORD_COMM,
+            misc: PERF_RECORD_MISC_COMM_EXEC as u16,
+            size: 56,
+        };
+        record.extend_from_slice(&header.type_.to_ne_bytes());
+        record.extend_from_slice(&header.misc.to_ne_bytes());
+        record.extend_from_slice(&header.size.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(b"app\0\0\0\0\0");
+        // sample_id: pid/tid, time, id, cpu/res.
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&41_u32.to_ne_bytes());
+        record.extend_from_slice(&1234_u64.to_ne_bytes());
+        record.extend_from_slice(&7_u64.to_ne_bytes());
+        record.extend_from_slice(&3_u64.to_ne_bytes());
+
+        let decoded = decode(&record);
+        assert!(matches!(
+            decoded.as_slice(),
+            [MmapRecord::Comm { pid: 41, comm, exec_time: Some(1234), .. }]
+                if comm == "app"
+        ));
+    }
+
+    #[test]
+    fn fork_and_exit_records_are_decoded() {
+        use perf_event_open_sys::bindings::{
+            perf_event_header, PERF_RECORD_EXIT, PERF_RECORD_FORK,
+        };
+
+        let mut records = Vec::new();
+        for (type_, pid, ppid, time) in
+            [(PERF_RECORD_FORK, 51, 50, 7), (PERF_RECORD_EXIT, 51, 50, 9)]
+        {
+            let header = perf_event_header {
+                type_,
+                misc: 0,
+                size: 32,
+            };
+            records.extend_from_slice(&header.type_.to_ne_bytes());
+            records.extend_from_slice(&header.misc.to_ne_bytes());
+            records.extend_from_slice(&header.size.to_ne_bytes());
+            for id in [pid, ppid, pid, ppid] {
+                records.extend_from_slice(&(id as u32).to_ne_bytes());
+            }
+            records.extend_from_slice(&(time as u64).to_ne_bytes());
+        }
+
+        let decoded = decode(&records);
+        assert!(matches!(
+            decoded.as_slice(),
+            [
+                MmapRecord::Fork(fork),
+                MmapRecord::Exit(exit),
+            ] if fork.pid == 51 && fork.ppid == 50 && fork.time == 7 && exit.time == 9
+        ));
+    }
+
+    #[test]
+    fn lost_record_is_decoded() {
+        use perf_event_open_sys::bindings::{perf_event_header, PERF_RECORD_LOST};
+
+        let mut record = Vec::new();
+        let header = perf_event_header {
+            type_: PERF_RECORD_LOST,
+            misc: 0,
+            size: 24,
+        };
+        record.extend_from_slice(&header.type_.to_ne_bytes());
+        record.extend_from_slice(&header.misc.to_ne_bytes());
+        record.extend_from_slice(&header.size.to_ne_bytes());
+        record.extend_from_slice(&3_u64.to_ne_bytes());
+        record.extend_from_slice(&17_u64.to_ne_bytes());
+
+        let decoded = decode(&record);
+        assert!(matches!(decoded.as_slice(), [MmapRecord::Lost(17)]));
+    }
+
     #[test]
     fn dwarf_user_state_fixture() {
         use super::{ReadFormat, SampleFormat};
@@ -817,6 +1204,51 @@ mod test {
         "###);
     }
 
+    #[test]
+    fn weight_follows_user_state() {
+        use super::{ReadFormat, SampleFormat};
+
+        let sample = SampleFormat {
+            header: perf_event_open_sys::bindings::perf_event_header::default(),
+            ip: 0x1234,
+            pid: 7,
+            tid: 8,
+            time: 9,
+            id: 10,
+            cpu: 11,
+            _res: 0,
+            read: ReadFormat {
+                nr: 0,
+                time_enabled: 12,
+                time_running: 13,
+            },
+        };
+        let sample_bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&sample as *const SampleFormat).cast::<u8>(),
+                std::mem::size_of::<SampleFormat>(),
+            )
+        };
+        let mut flat = sample_bytes.to_vec();
+        flat.extend_from_slice(&93_u64.to_ne_bytes());
+        assert_eq!(sample.read_weight(&flat, 0, false, false), Some(93));
+
+        let mut plain = sample_bytes.to_vec();
+        plain.extend_from_slice(&1_u64.to_ne_bytes());
+        plain.extend_from_slice(&0x1234_u64.to_ne_bytes());
+        let mut dwarf = plain.clone();
+        plain.extend_from_slice(&187_u64.to_ne_bytes());
+        assert_eq!(sample.read_weight(&plain, 0, false, true), Some(187));
+
+        dwarf.extend_from_slice(&2_u64.to_ne_bytes()); // PERF_SAMPLE_REGS_ABI_64
+        dwarf.extend_from_slice(&0xaa_u64.to_ne_bytes());
+        dwarf.extend_from_slice(&8_u64.to_ne_bytes());
+        dwarf.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        dwarf.extend_from_slice(&8_u64.to_ne_bytes());
+        dwarf.extend_from_slice(&412_u64.to_ne_bytes());
+        assert_eq!(sample.read_weight(&dwarf, 0b1, false, true), Some(412));
+    }
+
     #[test]
     fn lbr_call_stack_fixture() {
         use super::{ReadFormat, SampleFormat};
@@ -0,0 +1,275 @@
+//! Counting on Windows through the per-process accounting kept by the kernel.
+//!
+//! `QueryProcessCycleTime` reports the cycles charged to every thread of a
+//! process, `GetProcessTimes` its CPU time and `GetProcessMemoryInfo` its page
+//! faults. None of them need administrator rights. The cycle time is read
+//! from the time-stamp counter at context switches, so it is reported as
+//! [`Counter::RefCycles`], not core cycles. Core cycles, retired instructions
+//! and other hardware events are only exposed through ETW PMU sessions, which
+//! need administrator rights and are not implemented.
+
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+
+use smallvec::SmallVec;
+
+use crate::{Counter, Error};
+
+use super::{CounterEntry, CounterResult, CounterValue, CountingDriver, MeasurementQuality};
+
+type Handle = *mut c_void;
+type Bool = i32;
+
+const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+const PROCESS_VM_READ: u32 = 0x0010;
+
+#[repr(C)]
+#[derive(Default)]
+struct FileTime {
+    low: u32,
+    high: u32,
+}
+
+impl FileTime {
+    /// `FILETIME` durations are in 100 ns units.
+    fn as_nanos(&self) -> u64 {
+        (((self.high as u64) << 32) | self.low as u64) * 100
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProcessMemoryCounters {
+    cb: u32,
+    page_fault_count: u32,
+    peak_working_set_size: usize,
+    working_set_size: usize,
+    quota_peak_paged_pool_usage: usize,
+    quota_paged_pool_usage: usize,
+    quota_peak_non_paged_pool_usage: usize,
+    quota_non_paged_pool_usage: usize,
+    pagefile_usage: usize,
+    peak_pagefile_usage: usize,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetCurrentProcess() -> Handle;
+    fn OpenProcess(desired_access: u32, inherit_handle: Bool, process_id: u32) -> Handle;
+    fn CloseHandle(handle: Handle) -> Bool;
+    fn QueryProcessCycleTime(process: Handle, cycle_time: *mut u64) -> Bool;
+    fn GetProcessTimes(
+        process: Handle,
+        creation_time: *mut FileTime,
+        exit_time: *mut FileTime,
+        kernel_time: *mut FileTime,
+        user_time: *mut FileTime,
+    ) -> Bool;
+    fn K32GetProcessMemoryInfo(
+        process: Handle,
+        counters: *mut ProcessMemoryCounters,
+        cb: u32,
+    ) -> Bool;
+}
+
+pub fn list_supported_counters() -> Vec<Counter> {
+    vec![Counter::RefCycles, Counter::CpuClock, Counter::PageFaults]
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Snapshot {
+    ref_cycles: u64,
+    cpu_time: u64,
+    page_faults: u64,
+}
+
+impl Snapshot {
+    fn since(self, earlier: Snapshot) -> Snapshot {
+        Snapshot {
+            ref_cycles: self.ref_cycles.saturating_sub(earlier.ref_cycles),
+            cpu_time: self.cpu_time.saturating_sub(earlier.cpu_time),
+            page_faults: self.page_faults.saturating_sub(earlier.page_faults),
+        }
+    }
+
+    fn add(self, other: Snapshot) -> Snapshot {
+        Snapshot {
+            ref_cycles: self.ref_cycles + other.ref_cycles,
+            cpu_time: self.cpu_time + other.cpu_time,
+            page_faults: self.page_faults + other.page_faults,
+        }
+    }
+}
+
+pub struct WindowsCountingDriver {
+    process: Handle,
+    /// Whether `process` came from `OpenProcess` and must be closed.
+    owned: bool,
+    counters: Vec<Counter>,
+    started: Option<(Instant, Snapshot)>,
+    accumulated: Snapshot,
+    enabled: Duration,
+}
+
+impl WindowsCountingDriver {
+    /// Counts the process `pid`, or the calling process when `None`.
+    pub fn new(counters: Vec<Counter>, pid: Option<i32>) -> Result<Self, Error> {
+        if let Some(counter) = counters
+            .iter()
+            .find(|counter| !list_supported_counters().contains(*counter))
+        {
+            return Err(Error::InvalidConfiguration(format!(
+                "counter '{}' is not supported by the Windows driver, which counts only {}",
+                counter.name(),
+                list_supported_counters()
+                    .iter()
+                    .map(Counter::name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+
+        let (process, owned) = match pid {
+            Some(pid) => {
+                let handle = unsafe {
+                    OpenProcess(
+                        PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ,
+                        0,
+                        pid as u32,
+                    )
+                };
+                if handle.is_null() {
+                    return Err(Error::InvalidConfiguration(format!(
+                        "cannot open process {pid}: {}",
+                        std::io::Error::last_os_error()
+                    )));
+                }
+                (handle, true)
+            }
+            None => (unsafe { GetCurrentProcess() }, false),
+        };
+
+        Ok(WindowsCountingDriver {
+            process,
+            owned,
+            counters,
+            started: None,
+            accumulated: Snapshot::default(),
+            enabled: Duration::ZERO,
+        })
+    }
+
+    fn snapshot(&self) -> std::io::Result<Snapshot> {
+        let mut ref_cycles = 0_u64;
+        if unsafe { QueryProcessCycleTime(self.process, &mut ref_cycles) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut creation = FileTime::default();
+        let mut exit = FileTime::default();
+        let mut kernel = FileTime::default();
+        let mut user = FileTime::default();
+        if unsafe {
+            GetProcessTimes(
+                self.process,
+                &mut creation,
+                &mut exit,
+                &mut kernel,
+                &mut user,
+            )
+        } == 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut memory = ProcessMemoryCounters {
+            cb: std::mem::size_of::<ProcessMemoryCounters>() as u32,
+            ..Default::default()
+        };
+        if unsafe { K32GetProcessMemoryInfo(self.process, &mut memory, memory.cb) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Snapshot {
+            ref_cycles,
+            cpu_time: kernel.as_nanos() + user.as_nanos(),
+            page_faults: memory.page_fault_count as u64,
+        })
+    }
+}
+
+impl CountingDriver for WindowsCountingDriver {
+    fn start(&mut self) -> Result<(), Error> {
+        if self.started.is_none() {
+            let snapshot = self.snapshot().map_err(|_| Error::EnableFailed)?;
+            self.started = Some((Instant::now(), snapshot));
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        if let Some((since, start)) = self.started.take() {
+            let snapshot = self.snapshot().map_err(|_| Error::EnableFailed)?;
+            self.accumulated = self.accumulated.add(snapshot.since(start));
+            self.enabled += since.elapsed();
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Error> {
+        self.accumulated = Snapshot::default();
+        self.enabled = Duration::ZERO;
+        if self.started.is_some() {
+            let snapshot = self.snapshot().map_err(|_| Error::EnableFailed)?;
+            self.started = Some((Instant::now(), snapshot));
+        }
+        Ok(())
+    }
+
+    fn counters(&mut self) -> Result<CounterResult, std::io::Error> {
+        let mut total = self.accumulated;
+        let mut enabled = self.enabled;
+        if let Some((since, start)) = self.started {
+            total = total.add(self.snapshot()?.since(start));
+            enabled += since.elapsed();
+        }
+        let enabled = u64::try_from(enabled.as_nanos()).unwrap_or(u64::MAX);
+
+        let entries = self
+            .counters
+            .iter()
+            .map(|counter| {
+                let (value, quality) = match counter {
+                    Counter::RefCycles => (total.ref_cycles, MeasurementQuality::Exact),
+                    Counter::CpuClock => (total.cpu_time, MeasurementQuality::Exact),
+                    Counter::PageFaults => (total.page_faults, MeasurementQuality::Exact),
+                    _ => unreachable!("unsupported counters are rejected in new()"),
+                };
+                CounterEntry {
+                    core: None,
+                    counter: counter.clone(),
+                    value: CounterValue {
+                        value,
+                        scaling: 1.0,
+                        quality,
+                        raw: value,
+                        time_enabled: enabled,
+                        time_running: enabled,
+                    },
+                }
+            })
+            .collect::<SmallVec<_>>();
+
+        Ok(CounterResult::from_entries(entries))
+    }
+}
+
+impl Drop for WindowsCountingDriver {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                CloseHandle(self.process);
+            }
+        }
+    }
+}
@@ -654,6 +654,10 @@ mod backend {
                 sys::bindings::PERF_TYPE_HARDWARE,
                 sys::bindings::PERF_COUNT_HW_CPU_CYCLES as u64,
             ),
+            Counter::RefCycles => (
+                sys::bindings::PERF_TYPE_HARDWARE,
+                sys::bindings::PERF_COUNT_HW_REF_CPU_CYCLES as u64,
+            ),
             Counter::Instructions => (
                 sys::bindings::PERF_TYPE_HARDWARE,
                 sys::bindings::PERF_COUNT_HW_INSTRUCTIONS as u64,
@@ -682,6 +686,26 @@ mod backend {
                 sys::bindings::PERF_TYPE_HARDWARE,
                 sys::bindings::PERF_COUNT_HW_STALLED_CYCLES_BACKEND as u64,
             ),
+            Counter::L1DReferences => crate::hw_cache_read(
+                sys::bindings::PERF_COUNT_HW_CACHE_L1D,
+                sys::bindings::PERF_COUNT_HW_CACHE_RESULT_ACCESS,
+            ),
+            Counter::L1DMisses => crate::hw_cache_read(
+                sys::bindings::PERF_COUNT_HW_CACHE_L1D,
+                sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+            ),
+            Counter::L1IMisses => crate::hw_cache_read(
+                sys::bindings::PERF_COUNT_HW_CACHE_L1I,
+                sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+            ),
+            Counter::DTLBMisses => crate::hw_cache_read(
+                sys::bindings::PERF_COUNT_HW_CACHE_DTLB,
+                sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+            ),
+            Counter::ITLBMisses => crate::hw_cache_read(
+                sys::bindings::PERF_COUNT_HW_CACHE_ITLB,
+                sys::bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
+            ),
             Counter::CpuClock => (
                 sys::bindings::PERF_TYPE_SOFTWARE,
                 sys::bindings::PERF_COUNT_SW_CPU_CLOCK as u64,
@@ -699,6 +723,7 @@ mod backend {
                 sys::bindings::PERF_COUNT_SW_CPU_MIGRATIONS as u64,
             ),
             Counter::Internal { code, .. } => (sys::bindings::PERF_TYPE_RAW, *code),
+            Counter::Raw { type_, config, .. } => (*type_, *config),
             Counter::Custom(name) => {
                 return Err(Error::InvalidConfiguration(format!(
                     "custom counter '{name}' must be resolved before use with EventTimer"
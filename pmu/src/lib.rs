@@ -19,8 +19,9 @@ pub use cpu_family::{host_cpu_description, host_metrics};
 pub use criterion_measurement::CriterionCounter;
 pub use driver::{
     list_supported_counters, CoreId, CounterEntry, CounterResult, CounterValue, CountingDriver,
-    CountingDriverBuilder, DriverKind, MeasurementQuality, Record, Sample, SamplingDriver,
-    SamplingDriverBuilder, UnwindMode, UserRegs,
+    CountingDriverBuilder, DriverKind, ExecEvent, MeasurementQuality, ReaderStatus, Record, Sample,
+    SampleClock, SamplingDriver, SamplingDriverBuilder, TaskEvent, TaskScope, UnwindMode, UserRegs,
+    DEFAULT_MMAP_PAGES, LOAD_LATENCY_THRESHOLD,
 };
 #[cfg(feature = "criterion")]
 pub use event_timer::CounterCheckpoint;
@@ -29,7 +30,7 @@ pub use event_timer::{
     Measurements, ReadCost, ReadMethod,
 };
 pub use pmu_data::{Metric, MetricError, MetricExpression};
-pub use process::Process;
+pub use process::{Process, ResourceUsage};
 #[cfg(feature = "symbolize")]
 pub use quick::{top_symbols, SymbolCount};
 pub use quick::{QuickSampler, SampleBatch};
@@ -39,6 +40,16 @@ pub fn host_tma_scenario() -> Option<pmu_data::TmaScenario> {
     cpu_family::host_tma_scenario()
 }
 
+/// Resolves scenario event names for the host CPU family into counters.
+///
+/// Names other than the portable `cycles`, `instructions` and stall counters
+/// become [`Counter::Internal`] events with their raw codes, looked up in the
+/// family's event and alias tables. Unknown names are rejected with
+/// [`Error::UnsupportedCounter`].
+pub fn host_scenario_counters(names: &[String]) -> Result<Vec<Counter>, Error> {
+    cpu_family::resolve_scenario_events(cpu_family::get_host_cpu_family(), names)
+}
+
 /// Maximum number of events in one coherent group on the host PMU, when known.
 pub fn host_max_counters() -> Option<usize> {
     cpu_family::host_max_counters()
@@ -54,26 +65,37 @@ pub fn host_core_clusters() -> Vec<CoreId> {
     }
 
     pmus.iter()
-        .map(|pmu| {
-            let name = cpu_family::find_cpu_family(pmu.family_id)
-                .map(|f| f.name.clone())
-                .unwrap_or_else(|| pmu.family_id.to_string());
-            CoreId {
-                family_id: pmu.family_id.to_string(),
-                name,
-                cpus: pmu.cpus.clone(),
-            }
+        .map(|pmu| CoreId {
+            family_id: pmu.family_id.to_string(),
+            name: cpu_family::core_family_name(pmu.family_id),
+            cpus: pmu.cpus.clone(),
         })
         .collect()
 }
 
 use thiserror::Error;
 
+/// `(type_, config)` of the generic cache event counting reads of `cache`
+/// with `result`. `PERF_TYPE_HW_CACHE` encodes the cache, the operation and
+/// the result in one byte each.
+#[cfg(target_os = "linux")]
+pub(crate) fn hw_cache_read(cache: u32, result: u32) -> (u32, u64) {
+    use perf_event_open_sys::bindings::{PERF_COUNT_HW_CACHE_OP_READ, PERF_TYPE_HW_CACHE};
+    let op = PERF_COUNT_HW_CACHE_OP_READ;
+    (
+        PERF_TYPE_HW_CACHE,
+        cache as u64 | (op as u64) << 8 | (result as u64) << 16,
+    )
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// A hardware, software, or platform-specific performance counter.
 pub enum Counter {
     /// CPU cycles.
     Cycles,
+    /// CPU cycles at the constant reference (TSC) rate, which does not follow
+    /// frequency scaling.
+    RefCycles,
     /// Retired instructions.
     Instructions,
     /// Last-level-cache references.
@@ -88,6 +110,16 @@ pub enum Counter {
     StalledCyclesFrontend,
     /// Cycles stalled by the processor backend.
     StalledCyclesBackend,
+    /// Level 1 data cache reads.
+    L1DReferences,
+    /// Level 1 data cache read misses.
+    L1DMisses,
+    /// Level 1 instruction cache misses.
+    L1IMisses,
+    /// Data TLB misses of loads.
+    DTLBMisses,
+    /// Instruction TLB misses.
+    ITLBMisses,
     /// Software CPU-clock time.
     CpuClock,
     /// Page faults.
@@ -107,9 +139,23 @@ pub enum Counter {
         /// Raw perf event encoding.
         code: u64,
     },
+    /// An event given by its raw encoding on the command line, like perf's
+    /// `r412e`, without a descriptor in the event tables.
+    Raw {
+        /// The name it was given as, e.g. `r412e`.
+        name: String,
+        /// `perf_event_attr.type`; [`PERF_TYPE_RAW`] for core PMU events.
+        type_: u32,
+        /// `perf_event_attr.config`.
+        config: u64,
+    },
 }
 
+/// perf's event type for raw core PMU encodings.
+pub const PERF_TYPE_RAW: u32 = 4;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 /// Errors produced while configuring or reading performance events.
 pub enum Error {
     /// The kernel rejected `perf_event_open` for a counter.
@@ -170,9 +216,9 @@ pub enum Error {
     /// The requested counter or sampler configuration is invalid.
     #[error("invalid counter configuration: {0}")]
     InvalidConfiguration(String),
-    /// A sampling reader thread panicked.
-    #[error("sampling worker thread panicked")]
-    WorkerPanicked,
+    /// A sampling reader thread panicked, e.g. on a malformed record.
+    #[error("sampling reader thread panicked: {0}")]
+    WorkerPanicked(String),
     /// A grouped counter read failed.
     #[error("failed to read perf counter group: {source}")]
     PerfRead {
@@ -273,6 +319,7 @@ impl Counter {
     pub fn name(&self) -> &str {
         match self {
             Counter::Cycles => "cycles",
+            Counter::RefCycles => "ref_cycles",
             Counter::Instructions => "instructions",
             Counter::LLCReferences => "llc_references",
             Counter::LLCMisses => "llc_misses",
@@ -280,6 +327,11 @@ impl Counter {
             Counter::BranchMisses => "branch_misses",
             Counter::StalledCyclesFrontend => "stalled_cycles_frontend",
             Counter::StalledCyclesBackend => "stalled_cycles_backend",
+            Counter::L1DReferences => "l1d_references",
+            Counter::L1DMisses => "l1d_misses",
+            Counter::L1IMisses => "l1i_misses",
+            Counter::DTLBMisses => "dtlb_misses",
+            Counter::ITLBMisses => "itlb_misses",
             Counter::CpuClock => "cpu_clock",
             Counter::PageFaults => "page_faults",
             Counter::ContextSwitches => "context_switches",
@@ -290,6 +342,7 @@ impl Counter {
                 desc: _,
                 code: _,
             } => name,
+            Counter::Raw { name, .. } => name,
         }
     }
 
@@ -297,6 +350,7 @@ impl Counter {
     pub fn description(&self) -> &str {
         match self {
             Counter::Cycles => "Number of CPU cycles",
+            Counter::RefCycles => "Number of CPU cycles at the constant reference rate",
             Counter::Instructions => "Number of instructions retired",
             Counter::LLCReferences => "Last level cache references",
             Counter::LLCMisses => "Last level cache misses",
@@ -306,6 +360,11 @@ impl Counter {
                 "Number of cycles stalled due to frontend bottlenecks"
             }
             Counter::StalledCyclesBackend => "Number of cycles stalled due to backend bottlenecks",
+            Counter::L1DReferences => "Level 1 data cache reads",
+            Counter::L1DMisses => "Level 1 data cache read misses",
+            Counter::L1IMisses => "Level 1 instruction cache misses",
+            Counter::DTLBMisses => "Data TLB misses of loads",
+            Counter::ITLBMisses => "Instruction TLB misses",
             Counter::CpuClock => "A high-resolution per-CPU timer",
             Counter::PageFaults => "Number of page faults",
             Counter::ContextSwitches => "Number of context switches",
@@ -316,9 +375,37 @@ impl Counter {
                 desc,
                 code: _,
             } => desc,
+            Counter::Raw { .. } => "Raw PMU event",
         }
     }
 
+    /// Parses a raw event: `r` and the hex config as perf spells it
+    /// (`r412e`: umask 0x41, event 0x2e), or `r` with the event and umask
+    /// apart (`r2e:41`). Returns `None` for any other name.
+    pub fn parse_raw(name: &str) -> Option<Counter> {
+        let hex = name.strip_prefix('r')?;
+        let parse = |digits: &str| {
+            (!digits.is_empty() && digits.len() <= 16)
+                .then(|| u64::from_str_radix(digits, 16).ok())
+                .flatten()
+        };
+        let config = match hex.split_once(':') {
+            Some((event, umask)) => {
+                let (event, umask) = (parse(event)?, parse(umask)?);
+                if event > 0xff || umask > 0xff {
+                    return None;
+                }
+                (umask << 8) | event
+            }
+            None => parse(hex)?,
+        };
+        Some(Counter::Raw {
+            name: name.to_owned(),
+            type_: PERF_TYPE_RAW,
+            config,
+        })
+    }
+
     /// Returns whether the counter is implemented by perf's software PMU.
     pub fn is_software(&self) -> bool {
         matches!(
@@ -329,6 +416,31 @@ impl Counter {
                 | Counter::CpuMigrations
         )
     }
+
+    /// Returns whether the counter measures elapsed CPU time in nanoseconds
+    /// rather than counting events.
+    pub fn is_time(&self) -> bool {
+        matches!(self, Counter::CpuClock)
+    }
+}
+
+#[cfg(test)]
+mod counter_tests {
+    use super::{Counter, PERF_TYPE_RAW};
+
+    #[test]
+    fn raw_events_parse_in_both_spellings() {
+        let raw = |name: &str| Counter::Raw {
+            name: name.to_owned(),
+            type_: PERF_TYPE_RAW,
+            config: 0x412e,
+        };
+        assert_eq!(Counter::parse_raw("r412e"), Some(raw("r412e")));
+        assert_eq!(Counter::parse_raw("r2e:41"), Some(raw("r2e:41")));
+        for name in ["r2e:141", "rx", "r", "cycles"] {
+            assert_eq!(Counter::parse_raw(name), None, "{name}");
+        }
+    }
 }
 
 #[cfg(test)]
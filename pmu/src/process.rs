@@ -1,11 +1,13 @@
 use std::cell::Cell;
+#[cfg(unix)]
 use std::ffi::CString;
+use std::time::Duration;
 
 #[derive(Debug)]
 /// A child process suspended before `execve` so counters can be attached.
 pub struct Process {
     pid: i32,
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(all(unix, not(target_os = "macos")))]
     write_fd: i32,
     /// Set once the child has been observed to exit (via `wait`). Until the
     /// process is reaped it lingers as a zombie, which keeps its accounting
@@ -13,6 +15,19 @@ pub struct Process {
     /// driver's `stop()` even though the child has already finished.
     exited: Cell<bool>,
     reaped: Cell<bool>,
+    exit_code: Cell<Option<i32>>,
+    usage: Cell<Option<ResourceUsage>>,
+}
+
+/// Resources a child used over its lifetime, read when it is reaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Time spent running in user mode.
+    pub user_time: Duration,
+    /// Time the kernel spent on behalf of the child.
+    pub system_time: Duration,
+    /// Peak resident set size, in bytes.
+    pub max_rss_bytes: u64,
 }
 
 impl Process {
@@ -23,10 +38,21 @@ impl Process {
             Self::new_macos_suspended(args, env)
         }
 
-        #[cfg(not(target_os = "macos"))]
+        #[cfg(all(unix, not(target_os = "macos")))]
         {
             Self::new_fork_gated(args, env)
         }
+
+        // Counting on Windows attaches to a running process by PID; there is
+        // no suspended-launch path yet, so no `Process` is ever constructed.
+        #[cfg(windows)]
+        {
+            let _ = (args, env);
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "launching a suspended process is not supported on Windows",
+            ))
+        }
     }
 
     #[cfg(target_os = "macos")]
@@ -95,10 +121,12 @@ impl Process {
             pid,
             exited: Cell::new(false),
             reaped: Cell::new(false),
+            exit_code: Cell::new(None),
+            usage: Cell::new(None),
         })
     }
 
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(all(unix, not(target_os = "macos")))]
     fn new_fork_gated(args: &[String], env: &[(String, String)]) -> Result<Self, std::io::Error> {
         let mut pipe_fds: [libc::c_int; 2] = [-1; 2];
         if unsafe { libc::pipe(pipe_fds.as_mut_ptr()) } == -1 {
@@ -149,6 +177,8 @@ impl Process {
             write_fd: pipe_fds[1],
             exited: Cell::new(false),
             reaped: Cell::new(false),
+            exit_code: Cell::new(None),
+            usage: Cell::new(None),
         })
     }
 
@@ -164,7 +194,7 @@ impl Process {
             libc::kill(self.pid, libc::SIGCONT);
         }
 
-        #[cfg(not(target_os = "macos"))]
+        #[cfg(all(unix, not(target_os = "macos")))]
         unsafe {
             libc::write(self.write_fd, &[1u8] as *const u8 as *const libc::c_void, 1);
             libc::close(self.write_fd);
@@ -173,6 +203,7 @@ impl Process {
 
     /// Block until the child exits, but leave it unreaped (a zombie) so that its
     /// final resource accounting stays queryable. Reaping happens on drop.
+    #[cfg(unix)]
     pub fn wait(&self) -> Result<(), std::io::Error> {
         unsafe {
             let mut info: libc::siginfo_t = std::mem::zeroed();
@@ -185,12 +216,67 @@ impl Process {
             {
                 return Err(std::io::Error::last_os_error());
             }
+            self.exit_code.set(Some(exit_code_of(&info)));
         }
         self.exited.set(true);
         Ok(())
     }
 
+    /// Returns whether the child has exited, without blocking. Like
+    /// [`Process::wait`], this leaves the child unreaped.
+    #[cfg(unix)]
+    pub fn try_wait(&self) -> Result<bool, std::io::Error> {
+        if self.exited.get() {
+            return Ok(true);
+        }
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let exited_pid = unsafe {
+            if libc::waitid(
+                libc::P_PID,
+                self.pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+            ) == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            #[cfg(target_os = "macos")]
+            {
+                info.si_pid
+            }
+            #[cfg(all(unix, not(target_os = "macos")))]
+            {
+                info.si_pid()
+            }
+        };
+        // With WNOHANG, a still-running child leaves siginfo zeroed.
+        if exited_pid == 0 {
+            return Ok(false);
+        }
+        self.exit_code.set(Some(exit_code_of(&info)));
+        self.exited.set(true);
+        Ok(true)
+    }
+
+    /// Exit code of the child once [`Process::wait`] or [`Process::try_wait`]
+    /// saw it exit. A child killed by a signal reports 128 plus the signal
+    /// number, like shells do.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code.get()
+    }
+
+    /// Reaps the exited child and returns what it used. Reaping releases the
+    /// zombie, so stop counting drivers of the process first. `None` until
+    /// the child has been seen to exit.
+    pub fn resource_usage(&self) -> Option<ResourceUsage> {
+        if self.exited.get() {
+            self.reap();
+        }
+        self.usage.get()
+    }
+
     /// Reap the child if it has exited, releasing the zombie. Idempotent.
+    #[cfg(unix)]
     fn reap(&self) {
         if self.reaped.get() {
             return;
@@ -204,15 +290,92 @@ impl Process {
             }
         }
         let mut status: libc::c_int = 0;
-        let rc = unsafe { libc::waitpid(self.pid, &mut status, 0) };
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let rc = unsafe { libc::wait4(self.pid, &mut status, 0, &mut usage) };
+        if rc == self.pid {
+            self.usage.set(Some(resource_usage_of(&usage)));
+        }
         if rc == self.pid || rc == -1 {
             self.reaped.set(true);
         }
     }
 }
 
+#[cfg(unix)]
+fn resource_usage_of(usage: &libc::rusage) -> ResourceUsage {
+    let duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // Linux reports the peak RSS in kilobytes, macOS in bytes.
+    let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    ResourceUsage {
+        user_time: duration(usage.ru_utime),
+        system_time: duration(usage.ru_stime),
+        max_rss_bytes: usage.ru_maxrss as u64 * rss_unit,
+    }
+}
+
+#[cfg(unix)]
+fn exit_code_of(info: &libc::siginfo_t) -> i32 {
+    #[cfg(target_os = "macos")]
+    let status = info.si_status;
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let status = unsafe { info.si_status() };
+    if info.si_code == libc::CLD_EXITED {
+        status
+    } else {
+        128 + status
+    }
+}
+
+// No `Process` is constructed on Windows (see [`Process::new`]), so there is
+// never a child to wait for or reap.
+#[cfg(windows)]
+impl Process {
+    /// Block until the child exits.
+    pub fn wait(&self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// Returns whether the child has exited, without blocking.
+    pub fn try_wait(&self) -> Result<bool, std::io::Error> {
+        Ok(self.exited.get())
+    }
+
+    fn reap(&self) {
+        self.reaped.set(true);
+    }
+}
+
 impl Drop for Process {
     fn drop(&mut self) {
         self.reap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exited_child_reports_its_status_and_usage() {
+        let process = Process::new(
+            &[
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                "exit 3".to_string(),
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(process.resource_usage(), None);
+
+        process.cont();
+        process.wait().unwrap();
+
+        assert_eq!(process.exit_code(), Some(3));
+        let usage = process.resource_usage().unwrap();
+        assert!(usage.max_rss_bytes > 0);
+        assert_eq!(process.resource_usage(), Some(usage));
+    }
+}
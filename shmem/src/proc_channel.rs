@@ -6,6 +6,7 @@ use std::{
     marker::PhantomData,
     mem::MaybeUninit,
     sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
 use crate::{platform, utils::blocker};
@@ -208,27 +209,70 @@ impl<T: Sendable> Sender<T> {
     /// Sends a message, or drops it and increments the backpressure counter if full.
     pub fn send_sync(&self, object: T) -> Result<(), Error> {
         let data = object.as_raw_bytes();
-        let word = std::mem::size_of::<usize>();
-        let padded_len = data
-            .len()
-            .checked_add(word - 1)
-            .map(|len| len & !(word - 1));
-        let record_len = padded_len.and_then(|len| len.checked_add(word));
+        match self.record_len(&data) {
+            Some(record_len) if self.has_space(record_len) => self.publish(&data, record_len),
+            _ => {
+                self.inner.dropped().fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+    }
 
-        let Some(record_len) = record_len else {
+    /// Sends a message, waiting for the receiver to free ring space instead of dropping.
+    ///
+    /// If the receiver makes no progress for `timeout`, the message is dropped, the
+    /// backpressure counter is incremented and `ErrorKind::TimedOut` is returned, so a
+    /// producer never hangs on a receiver that has gone away.
+    pub fn send_blocking(&self, object: T, timeout: Duration) -> Result<(), Error> {
+        let data = object.as_raw_bytes();
+        let Some(record_len) = self.record_len(&data).filter(|len| *len <= self.inner.size) else {
             self.inner.dropped().fetch_add(1, Ordering::Relaxed);
-            return Ok(());
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "message does not fit into the shared-memory ring",
+            ));
         };
+
+        let mut head = self.inner.head().load(Ordering::Acquire);
+        let mut last_progress = Instant::now();
+        while !self.has_space(record_len) {
+            let current = self.inner.head().load(Ordering::Acquire);
+            if current != head {
+                head = current;
+                last_progress = Instant::now();
+            } else if last_progress.elapsed() >= timeout {
+                self.inner.dropped().fetch_add(1, Ordering::Relaxed);
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "shared-memory receiver did not keep up",
+                ));
+            }
+            std::thread::yield_now();
+        }
+
+        self.publish(&data, record_len)
+    }
+
+    fn record_len(&self, data: &[u8]) -> Option<usize> {
+        let word = std::mem::size_of::<usize>();
+        data.len()
+            .checked_add(word - 1)
+            .map(|len| len & !(word - 1))
+            .and_then(|len| len.checked_add(word))
+    }
+
+    fn has_space(&self, record_len: usize) -> bool {
         let tail = self.inner.tail().load(Ordering::Relaxed);
         let head = self.inner.head().load(Ordering::Acquire);
-        if record_len > self.inner.size || tail.wrapping_sub(head) > self.inner.size - record_len {
-            self.inner.dropped().fetch_add(1, Ordering::Relaxed);
-            return Ok(());
-        }
+        record_len <= self.inner.size && tail.wrapping_sub(head) <= self.inner.size - record_len
+    }
 
+    fn publish(&self, data: &[u8], record_len: usize) -> Result<(), Error> {
+        let word = std::mem::size_of::<usize>();
+        let tail = self.inner.tail().load(Ordering::Relaxed);
         unsafe {
             self.inner.write_wrapped(tail, &data.len().to_ne_bytes());
-            self.inner.write_wrapped(tail.wrapping_add(word), &data);
+            self.inner.write_wrapped(tail.wrapping_add(word), data);
         }
         // Publish only after the entire record is in shared memory.
         self.inner
@@ -374,7 +418,7 @@ mod tests {
     use super::{Receiver, Sender};
     use std::{
         sync::atomic::{AtomicUsize, Ordering},
-        time::Instant,
+        time::{Duration, Instant},
     };
 
     static NEXT_NAME: AtomicUsize = AtomicUsize::new(0);
@@ -431,6 +475,38 @@ mod tests {
         assert_eq!(receiver.recv_sync(), Some(2));
     }
 
+    #[test]
+    fn blocking_send_waits_for_receiver() {
+        let name = name("blocking");
+        let sender = Sender::<u64>::new(&name, 32).unwrap();
+        let receiver = Receiver::<u64>::attach(&name, 32).unwrap();
+        sender.send_sync(1).unwrap();
+        sender.send_sync(2).unwrap();
+
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            (0..3).map(|_| receiver.recv_sync()).collect::<Vec<_>>()
+        });
+        sender.send_blocking(3, Duration::from_secs(10)).unwrap();
+
+        assert_eq!(consumer.join().unwrap(), vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(sender.dropped_count(), 0);
+    }
+
+    #[test]
+    fn blocking_send_times_out_on_stalled_receiver() {
+        let name = name("blocking_timeout");
+        let sender = Sender::<u64>::new(&name, 32).unwrap();
+        sender.send_sync(1).unwrap();
+        sender.send_sync(2).unwrap();
+
+        let err = sender
+            .send_blocking(3, Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(sender.dropped_count(), 1);
+    }
+
     #[test]
     fn concurrent_spsc_stress_preserves_order() {
         const COUNT: usize = 100_000;
@@ -16,6 +16,9 @@ use std::{
 use addr2line::Loader;
 use object::{Object, ObjectSegment};
 
+/// Module of the frames resolved from a perf map.
+pub const JIT_MODULE: &str = "[jit]";
+
 /// A mapped object in one sampled process.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ProcessMap {
@@ -41,7 +44,8 @@ pub struct Frame {
     pub file: Option<String>,
     /// One-based source line, when available.
     pub line: Option<u32>,
-    /// Mapped object or perf-map path that supplied the frame.
+    /// Mapped object that supplied the frame, or [`JIT_MODULE`] for a perf
+    /// map symbol.
     pub module: Option<PathBuf>,
 }
 
@@ -148,6 +152,17 @@ impl Resolver {
 
     /// Creates a resolver using an explicit build-id cache.
     pub fn with_cache(maps: impl IntoIterator<Item = ProcessMap>, cache: BuildIdCache) -> Self {
+        Self::with_debug_dirs(maps, cache, &[])
+    }
+
+    /// Creates a resolver that searches `debug_dirs` for separate debug files
+    /// before the default locations. Each directory is laid out like
+    /// `/usr/lib/debug`.
+    pub fn with_debug_dirs(
+        maps: impl IntoIterator<Item = ProcessMap>,
+        cache: BuildIdCache,
+        debug_dirs: &[PathBuf],
+    ) -> Self {
         let maps = maps.into_iter().collect::<Vec<_>>();
         let mut loaders = Vec::new();
         let mut loader_by_path = HashMap::<PathBuf, Option<usize>>::new();
@@ -157,7 +172,8 @@ impl Resolver {
         for map in maps {
             pids.insert(map.pid);
             let loader = *loader_by_path.entry(map.path.clone()).or_insert_with(|| {
-                let debug_path = find_debug_file(&map.path, &cache);
+                let debug_path = find_separate_debug_file(&map.path, &cache, debug_dirs)
+                    .unwrap_or_else(|| map.path.clone());
                 Loader::new(debug_path).ok().map(|loader| {
                     let index = loaders.len();
                     loaders.push(loader);
@@ -190,6 +206,13 @@ impl Resolver {
         }
     }
 
+    /// Loads the perf map of `pid` from `path` instead of
+    /// `/tmp/perf-<pid>.map`, e.g. a copy saved with a recording.
+    pub fn load_perf_map(&mut self, pid: u32, path: &Path) -> Result<(), std::io::Error> {
+        self.perf_maps.insert(pid, PerfMap::load(path)?);
+        Ok(())
+    }
+
     /// Returns whether any native or JIT mapping is known for `pid`.
     pub fn has_process(&self, pid: u32) -> bool {
         self.modules.contains_key(&pid) || self.perf_maps.contains_key(&pid)
@@ -198,25 +221,26 @@ impl Resolver {
     /// Resolves one runtime instruction pointer to logical inline frames,
     /// ordered from innermost callee to outermost caller.
     pub fn resolve(&self, pid: u32, ip: u64) -> Vec<Frame> {
-        if let Some(symbol) = self
-            .perf_maps
-            .get(&pid)
-            .and_then(|perf_map| perf_map.resolve(ip))
-        {
-            return vec![Frame {
-                function: symbol.to_owned(),
-                file: None,
-                line: None,
-                module: Some(PathBuf::from(format!("/tmp/perf-{pid}.map"))),
-            }];
-        }
-
-        let Some(module) = self.modules.get(&pid).and_then(|modules| {
+        let module = self.modules.get(&pid).and_then(|modules| {
             modules
                 .iter()
                 .find(|module| ip >= module.map.start && ip < module.map.end)
-        }) else {
-            return Vec::new();
+        });
+        // JITs emit code into anonymous mappings; an address in a mapped
+        // file is never theirs, even where a stale perf map claims it.
+        let Some(module) = module.filter(|module| !is_anonymous(&module.map.path)) else {
+            return self
+                .perf_maps
+                .get(&pid)
+                .and_then(|perf_map| perf_map.resolve(ip))
+                .map(|symbol| Frame {
+                    function: symbol.to_owned(),
+                    file: None,
+                    line: None,
+                    module: Some(PathBuf::from(JIT_MODULE)),
+                })
+                .into_iter()
+                .collect();
         };
         let Some(loader) = module.loader.and_then(|index| self.loaders.get(index)) else {
             return Vec::new();
@@ -334,19 +358,57 @@ fn resolve_loader(loader: &Loader, address: u64, module: &Path) -> Vec<Frame> {
     resolved
 }
 
-fn find_debug_file(object_path: &Path, cache: &BuildIdCache) -> PathBuf {
-    let Ok(bytes) = fs::read(object_path) else {
-        return object_path.to_path_buf();
-    };
-    let Ok(object) = object::File::parse(bytes.as_slice()) else {
-        return object_path.to_path_buf();
-    };
+/// Returns the hex-encoded GNU build ID of the object at `path`, if it has one.
+pub fn build_id(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    let object = object::File::parse(bytes.as_slice()).ok()?;
+    object.build_id().ok().flatten().map(hex)
+}
+
+/// Finds separate debug information for `object_path`.
+///
+/// Each of `debug_dirs` is searched first, by build ID
+/// (`<dir>/.build-id/ab/cdef….debug`) and by `.gnu_debuglink` name, then the
+/// default lookup order applies. Returns `None` when the object itself is the
+/// best source of debug information.
+pub fn find_separate_debug_file(
+    object_path: &Path,
+    cache: &BuildIdCache,
+    debug_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    let bytes = fs::read(object_path).ok()?;
+    let object = object::File::parse(bytes.as_slice()).ok()?;
+    let build_id = object.build_id().ok().flatten();
+    let debuglink = object.gnu_debuglink().ok().flatten().map(|(name, crc)| {
+        (
+            OsStr::new(std::str::from_utf8(name).unwrap_or_default()),
+            crc,
+        )
+    });
+    let relative_object = object_path.strip_prefix("/").unwrap_or(object_path);
 
-    if let Ok(Some((name, expected_crc))) = object.gnu_debuglink() {
-        let name = OsStr::new(std::str::from_utf8(name).unwrap_or_default());
+    for root in debug_dirs {
+        if let Some(candidate) = build_id.and_then(|id| build_id_path(root, id)) {
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        if let Some((name, expected_crc)) = debuglink {
+            for candidate in [
+                root.join(name),
+                root.join(relative_object).with_file_name(name),
+            ] {
+                if debuglink_matches(&candidate, expected_crc) {
+                    return Some(candidate);
+                }
+            }
+        }
+    }
+
+    if let Some((name, expected_crc)) = debuglink {
         let parent = object_path.parent().unwrap_or_else(|| Path::new("."));
         let absolute_debug = Path::new("/usr/lib/debug")
-            .join(object_path.strip_prefix("/").unwrap_or(object_path))
+            .join(relative_object)
             .with_file_name(name);
         for candidate in [
             parent.join(name),
@@ -354,35 +416,40 @@ fn find_debug_file(object_path: &Path, cache: &BuildIdCache) -> PathBuf {
             absolute_debug,
         ] {
             if debuglink_matches(&candidate, expected_crc) {
-                return candidate;
+                return Some(candidate);
             }
         }
     }
 
-    if let Ok(Some(build_id)) = object.build_id() {
+    if let Some(build_id) = build_id {
         let cached = cache.path_for(build_id);
         if cached.is_file() {
-            return cached;
+            return Some(cached);
         }
-        let id = hex(build_id);
-        if id.len() > 2 {
-            let system = Path::new("/usr/lib/debug/.build-id")
-                .join(&id[..2])
-                .join(format!("{}.debug", &id[2..]));
+        if let Some(system) = build_id_path(Path::new("/usr/lib/debug"), build_id) {
             if system.is_file() {
-                return system;
+                return Some(system);
             }
         }
         if debuginfod_enabled() {
-            if let Some(downloaded) = debuginfod_find(&id) {
+            if let Some(downloaded) = debuginfod_find(&hex(build_id)) {
                 if let Ok(cached) = cache.store(build_id, &downloaded) {
-                    return cached;
+                    return Some(cached);
                 }
-                return downloaded;
+                return Some(downloaded);
             }
         }
     }
-    object_path.to_path_buf()
+    None
+}
+
+fn build_id_path(root: &Path, build_id: &[u8]) -> Option<PathBuf> {
+    let id = hex(build_id);
+    (id.len() > 2).then(|| {
+        root.join(".build-id")
+            .join(&id[..2])
+            .join(format!("{}.debug", &id[2..]))
+    })
 }
 
 fn debuglink_matches(path: &Path, expected_crc: u32) -> bool {
@@ -425,6 +492,17 @@ fn hex(bytes: &[u8]) -> String {
     bytes.iter().map(|byte| format!("{byte:02x}")).collect()
 }
 
+/// Whether `path` names an anonymous mapping rather than a file, as
+/// `/proc/<pid>/maps` and perf `MMAP` records report them.
+fn is_anonymous(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.is_empty()
+        || path.starts_with("//anon")
+        || path.starts_with("[anon")
+        || path.starts_with("/memfd:")
+        || path == "[heap]"
+}
+
 #[derive(Default)]
 struct PerfMap {
     entries: Vec<PerfMapEntry>,
@@ -492,7 +570,8 @@ pub fn current_process_symbol(_ip: u64) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{BuildIdCache, PerfMap};
+    use super::{BuildIdCache, Frame, PerfMap, ProcessMap, Resolver, JIT_MODULE};
+    use object::Object;
 
     #[test]
     fn perf_map_resolves_ranges_and_names_with_spaces() {
@@ -503,6 +582,42 @@ mod tests {
         assert_eq!(map.resolve(0x2005), Some("second"));
     }
 
+    #[test]
+    fn perf_map_resolves_only_anonymous_mappings() {
+        let dir = std::env::temp_dir().join(format!(
+            "miniperf-symbolize-perf-map-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let perf_map = dir.join("perf-7.map");
+        std::fs::write(&perf_map, "1000 100 LazyCompile:*run\n5000 100 stale\n").unwrap();
+        let mapping = |path: &str, start| ProcessMap {
+            pid: 7,
+            path: path.into(),
+            start,
+            end: start + 0x1000,
+            offset: 0,
+        };
+        let mut resolver = Resolver::new([
+            mapping("//anon", 0x1000),
+            mapping("/nonexistent/libstale.so", 0x5000),
+        ]);
+        resolver.load_perf_map(7, &perf_map).unwrap();
+
+        assert_eq!(
+            resolver.resolve(7, 0x1010),
+            [Frame {
+                function: "LazyCompile:*run".to_owned(),
+                file: None,
+                line: None,
+                module: Some(JIT_MODULE.into()),
+            }]
+        );
+        assert!(resolver.resolve(7, 0x5010).is_empty());
+        assert!(resolver.resolve(8, 0x1010).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn cache_path_is_stable_and_namespaced() {
         let cache = BuildIdCache::new("/tmp/example-cache");
@@ -527,6 +642,44 @@ mod tests {
     }
 
     #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_build_id_as_hex() {
+        let executable = std::env::current_exe().unwrap();
+        let id = super::build_id(&executable).expect("test binary has a build ID");
+        assert!(!id.is_empty());
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(super::build_id(std::path::Path::new("/nonexistent")), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_build_id_debug_file_in_explicit_directory() {
+        let root = std::env::temp_dir().join(format!(
+            "miniperf-symbolize-debug-dir-{}",
+            std::process::id()
+        ));
+        let executable = std::env::current_exe().unwrap();
+        let bytes = std::fs::read(&executable).unwrap();
+        let object = object::File::parse(bytes.as_slice()).unwrap();
+        let build_id = object
+            .build_id()
+            .unwrap()
+            .expect("test binary has a build ID");
+        let debug = super::build_id_path(&root, build_id).unwrap();
+        std::fs::create_dir_all(debug.parent().unwrap()).unwrap();
+        std::fs::copy(&executable, &debug).unwrap();
+
+        assert_eq!(
+            super::find_separate_debug_file(
+                &executable,
+                &BuildIdCache::new(root.join("cache")),
+                std::slice::from_ref(&root)
+            ),
+            Some(debug)
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
     #[test]
     fn follows_valid_gnu_debuglink() {
         let root = std::env::temp_dir().join(format!(
@@ -552,8 +705,8 @@ mod tests {
             .unwrap();
         assert!(link.success());
         assert_eq!(
-            super::find_debug_file(&binary, &BuildIdCache::new(root.join("cache"))),
-            debug
+            super::find_separate_debug_file(&binary, &BuildIdCache::new(root.join("cache")), &[]),
+            Some(debug)
         );
         std::fs::remove_dir_all(root).unwrap();
     }
@@ -129,7 +129,7 @@ struct MiniperfInstr : PassInfoMixin<MiniperfInstr> {
     Function *NotifyEnd =
         F.getParent()->getFunction("mperf_roofline_internal_notify_loop_end");
     if (!NotifyEnd) {
-      auto FuncTy = FunctionType::get(Type::getVoidTy(F.getContext()),
+      auto FuncTy = FunctionType::get(Type::getInt32Ty(F.getContext()),
                                       {PointerType::get(F.getContext(), 0)}, 0);
       NotifyEnd = Function::Create(FuncTy, llvm::GlobalValue::ExternalLinkage,
                                    "mperf_roofline_internal_notify_loop_end",
@@ -139,7 +139,7 @@ struct MiniperfInstr : PassInfoMixin<MiniperfInstr> {
     Function *NotifyStats =
         F.getParent()->getFunction("mperf_roofline_internal_notify_loop_stats");
     if (!NotifyStats) {
-      auto FuncTy = FunctionType::get(Type::getVoidTy(F.getContext()),
+      auto FuncTy = FunctionType::get(Type::getInt32Ty(F.getContext()),
                                       {PointerType::get(F.getContext(), 0),
                                        PointerType::get(F.getContext(), 0)},
                                       0);
@@ -240,7 +240,7 @@ struct MiniperfInstr : PassInfoMixin<MiniperfInstr> {
 
       Builder.SetInsertPoint(DispatchBB);
       Value *IsEnabled = Builder.CreateCall(IsInstrEnabled);
-      Value *Cmp = Builder.CreateCmp(
+      Value *EnabledCmp = Builder.CreateCmp(
           CmpInst::ICMP_NE, IsEnabled,
           ConstantInt::get(Type::getInt32Ty(F.getContext()), 0));
 
@@ -261,6 +261,10 @@ struct MiniperfInstr : PassInfoMixin<MiniperfInstr> {
           ConstantInt::get(Type::getInt32Ty(F.getContext()), LineNo),
           LineNoPtr);
       Value *LoopHandle = Builder.CreateCall(NotifyBegin, {InfoMem});
+      // A null handle means the collector failed to record the loop start (or
+      // profiling is off). Run the uninstrumented copy and skip the end event.
+      Value *HasHandle = Builder.CreateIsNotNull(LoopHandle);
+      Value *Cmp = Builder.CreateAnd(EnabledCmp, HasHandle);
 
       Builder.CreateCondBr(Cmp, InstrBB, CallBB);
 
@@ -273,9 +277,20 @@ struct MiniperfInstr : PassInfoMixin<MiniperfInstr> {
         V->replaceUsesOutsideBlock(PHI, LandingPadBB);
       }
 
+      BasicBlock *ExitBB = CallBB->getSingleSuccessor();
+      auto NotifyEndBB = BasicBlock::Create(F.getContext(), "", &F, ExitBB);
+      auto JoinBB = BasicBlock::Create(F.getContext(), "", &F, ExitBB);
+      ExitBB->replacePhiUsesWith(LandingPadBB, JoinBB);
+      Builder.CreateCondBr(HasHandle, NotifyEndBB, JoinBB);
+
+      // Only a recorded start gets an end event. A failed end or stats call
+      // loses that event; the collector logs it, and the program goes on.
+      Builder.SetInsertPoint(NotifyEndBB);
       Builder.CreateCall(NotifyEnd, {LoopHandle});
+      Builder.CreateBr(JoinBB);
 
-      Builder.CreateBr(CallBB->getSingleSuccessor());
+      Builder.SetInsertPoint(JoinBB);
+      Builder.CreateBr(ExitBB);
 
       cast<BranchInst>(CallBB->getTerminator())->setSuccessor(0, LandingPadBB);
       cast<BranchInst>(InstrBB->getTerminator())->setSuccessor(0, LandingPadBB);